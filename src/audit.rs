@@ -0,0 +1,115 @@
+//! Audit events told by the entries of the Cloud Controller (API component), e.g. an app being
+//! stopped or scaled
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{CfAppLogEntry, Component, ComponentInfoValid};
+
+/// Change made through the API, e.g. `Updated app with guid 79bb... ({"state"=>"STOPPED"})`
+#[derive(Debug, PartialEq)]
+pub struct AuditEvent<'a> {
+    /// lower case verb, e.g. `updated` or `created`
+    pub action: String,
+    /// lower case resource, e.g. `app` or `process web`
+    pub target: String,
+    pub guid: &'a str,
+    /// user who made the change, when told
+    pub actor: Option<&'a str>,
+    /// fields changed and their new value, quotes removed, e.g. `("state", "STOPPED")`
+    pub changes: Vec<(&'a str, &'a str)>,
+}
+
+struct Patterns {
+    event: Regex,
+    change: Regex,
+    actor: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        event: Regex::new(
+            r"^([A-Z][a-z]+(?:ed|ing)) ([a-z][\w ]*?) with guid ([0-9a-fA-F-]+)(?:[: ]+(.*))?$",
+        )
+        .unwrap(),
+        // `"key"=>"value"` Ruby hashes, `"key":"value"` JSON or `key: value`
+        change: Regex::new(r#""?(\w+)"?\s*(?:=>|:)\s*("[^"]*"|[^,\s})]+)"#).unwrap(),
+        actor: Regex::new(r#"(?:\bby (?:user )?|"(?:actor|user_name)"\s*(?:=>|:)\s*")([\w.@-]+)"#)
+            .unwrap(),
+    })
+}
+
+/// Audit event told by an API entry about a resource, `None` for other entries, e.g. crashes
+/// (see `crash`)
+pub fn audit_event<'a>(entry: &CfAppLogEntry<'a>) -> Option<AuditEvent<'a>> {
+    match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::API => {}
+        _ => return None,
+    }
+    let message = entry.message?.trim();
+    let patterns = patterns();
+    let captures = patterns.event.captures(message)?;
+    let details = captures.get(4).map_or("", |details| details.as_str());
+    let actor = patterns
+        .actor
+        .captures(details)
+        .and_then(|captures| captures.get(1))
+        .map(|actor| actor.as_str());
+    let changes = patterns
+        .change
+        .captures_iter(details)
+        .filter_map(|change| {
+            let key = change.get(1)?.as_str();
+            let value = change.get(2)?.as_str().trim_matches('"');
+            Some((key, value))
+        })
+        .filter(|(key, _)| !["actor", "user_name"].contains(key))
+        .collect();
+    Some(AuditEvent {
+        action: captures.get(1)?.as_str().to_lowercase(),
+        target: captures.get(2)?.as_str().to_lowercase(),
+        guid: captures.get(3)?.as_str(),
+        actor,
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_audit_event() {
+        let (_, entry) = parse_cf_app_log(r#"2021-09-28T17:00:00.00+0900 [API/0] OUT Updated app with guid 79bb58ab-3737-43be-ac70-39a2843b5177 ({"state"=>"STOPPED", "instances"=>3})"#).unwrap();
+        assert_eq!(
+            audit_event(&entry),
+            Some(AuditEvent {
+                action: "updated".to_string(),
+                target: "app".to_string(),
+                guid: "79bb58ab-3737-43be-ac70-39a2843b5177",
+                actor: None,
+                changes: vec![("state", "STOPPED"), ("instances", "3")],
+            })
+        );
+
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:00.00+0900 [API/1] OUT Updated app with guid 79bb58ab (state: STARTED) by user admin").unwrap();
+        let event = audit_event(&entry).unwrap();
+        assert_eq!(event.actor, Some("admin"));
+        assert_eq!(event.changes, vec![("state", "STARTED")]);
+
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:00.00+0900 [API/1] OUT Creating droplet for app with guid 79bb58ab",
+        )
+        .unwrap();
+        let event = audit_event(&entry).unwrap();
+        assert_eq!(
+            (event.action.as_str(), event.target.as_str(), event.guid),
+            ("creating", "droplet for app", "79bb58ab")
+        );
+
+        let (_, entry) = parse_cf_app_log(r#"2021-09-28T17:00:00.00+0900 [API/1] OUT App instance exited with guid 79bb58ab payload: {"index"=>0}"#).unwrap();
+        assert_eq!(audit_event(&entry), None);
+    }
+}
@@ -0,0 +1,64 @@
+//! Spread of the load of an app over its instances, one of them getting most of it pointing to
+//! sticky sessions or routing problems
+
+use std::collections::BTreeMap;
+
+/// Fewest lines or requests to tell an imbalance from chance
+pub const MIN_SAMPLE: u64 = 20;
+
+/// Instance getting much more than its share of the load
+#[derive(Debug, PartialEq)]
+pub struct Imbalance {
+    pub index: u32,
+    /// of the load, from 0 to 1
+    pub share: f64,
+    /// instances sharing the load
+    pub instances: usize,
+}
+
+/// The instance getting more than halfway from its fair share to the whole load, e.g. over 75%
+/// of it with 2 instances, given the load of each instance index
+pub fn imbalance(load: &BTreeMap<u32, u64>) -> Option<Imbalance> {
+    let total: u64 = load.values().sum();
+    if load.len() < 2 || total < MIN_SAMPLE {
+        return None;
+    }
+    // the busiest instance, the lowest index on ties
+    let (index, busiest) = load
+        .iter()
+        .max_by_key(|&(index, count)| (count, std::cmp::Reverse(index)))?;
+    let share = *busiest as f64 / total as f64;
+    let fair = 1.0 / load.len() as f64;
+    if share > (fair + 1.0) / 2.0 {
+        Some(Imbalance {
+            index: *index,
+            share,
+            instances: load.len(),
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imbalance() {
+        let load = |counts: &[u64]| (0..).zip(counts.iter().copied()).collect();
+        assert_eq!(
+            imbalance(&load(&[10, 90])),
+            Some(Imbalance {
+                index: 1,
+                share: 0.9,
+                instances: 2,
+            })
+        );
+        assert_eq!(imbalance(&load(&[30, 70])), None);
+        assert_eq!(imbalance(&load(&[80, 10, 10])).map(|i| i.index), Some(0));
+        // too few to tell
+        assert_eq!(imbalance(&load(&[1, 9])), None);
+        assert_eq!(imbalance(&load(&[100])), None);
+    }
+}
@@ -0,0 +1,102 @@
+//! ANSI colors of the entries written to a terminal, the way the cf CLI colors `cf logs`
+
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+use crate::{Channel, ChannelValid, Component};
+
+pub const DIM: &str = "2";
+pub const RED: &str = "31";
+pub const GREEN: &str = "32";
+pub const YELLOW: &str = "33";
+pub const BLUE: &str = "34";
+pub const MAGENTA: &str = "35";
+pub const CYAN: &str = "36";
+
+/// When to color the output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// on terminals, unless `NO_COLOR` is set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("unknown color choice: {}", s)),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Whether to color an output, given whether it is a terminal and the value of the
+    /// `NO_COLOR` environment variable, which disables colors unless empty (https://no-color.org)
+    pub fn enabled(self, is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(OsStr::is_empty),
+        }
+    }
+}
+
+/// `text` in the SGR `style`, e.g. `RED`, the style being reset after it
+pub fn paint(text: &str, style: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", style, text)
+}
+
+/// Color of the envelopes of a component, those of the app itself keeping the default one
+pub fn component_color(component: &Component) -> Option<&'static str> {
+    match component {
+        Component::ROUTER => Some(BLUE),
+        Component::STAGING => Some(CYAN),
+        Component::API => Some(MAGENTA),
+        Component::CELL => Some(YELLOW),
+        Component::SSH => Some(GREEN),
+        Component::LOGGREGATOR => Some(RED),
+        Component::APPLICATION | Component::INVALID => None,
+    }
+}
+
+/// Color of the channel and the message of an entry, stderr standing out
+pub fn channel_color(channel: &ChannelValid) -> Option<&'static str> {
+    match channel {
+        ChannelValid::Valid(Channel::STDERR) => Some(RED),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(paint("RTR/0", BLUE), "\x1b[34mRTR/0\x1b[0m");
+        assert_eq!(component_color(&Component::APPLICATION), None);
+        assert_eq!(
+            channel_color(&ChannelValid::Valid(Channel::STDERR)),
+            Some(RED)
+        );
+        assert_eq!(channel_color(&ChannelValid::Valid(Channel::STDOUT)), None);
+    }
+
+    #[test]
+    fn test_color_choice() {
+        let auto: ColorChoice = "auto".parse().unwrap();
+        assert!(auto.enabled(true, None));
+        assert!(auto.enabled(true, Some(OsStr::new(""))));
+        assert!(!auto.enabled(true, Some(OsStr::new("1"))));
+        assert!(!auto.enabled(false, None));
+        assert!(ColorChoice::Always.enabled(false, Some(OsStr::new("1"))));
+        assert!(!ColorChoice::Never.enabled(true, None));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
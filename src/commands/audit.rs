@@ -0,0 +1,80 @@
+use chrono::SecondsFormat;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+
+use cf_app_log_detector::audit::audit_event;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("audit")
+        .about("Write the changes made through the API told by API entries, e.g. an app being stopped or scaled")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let json = matches.value_of("format") == Some("json");
+    let filter = entry_filter(matches);
+    for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        let entry = match parse_cf_app_log(&line) {
+            Ok((_, entry)) if filter.matches(&entry) => entry,
+            _ => return true,
+        };
+        let event = match audit_event(&entry) {
+            Some(event) => event,
+            None => return true,
+        };
+        let timestamp = entry
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Millis, false);
+        // a failed write means stdout got closed, e.g. piped into head
+        if json {
+            let changes = event
+                .changes
+                .iter()
+                .map(|(key, value)| (key.to_string(), json!(value)))
+                .collect::<Map<String, Value>>();
+            writeln!(
+                out,
+                "{}",
+                json!({
+                    "timestamp": timestamp,
+                    "action": event.action,
+                    "target": event.target,
+                    "guid": event.guid,
+                    "actor": event.actor,
+                    "changes": changes,
+                })
+            )
+            .is_ok()
+        } else {
+            let mut line = format!(
+                "{} {} {} {}",
+                timestamp, event.action, event.target, event.guid
+            );
+            for (key, value) in &event.changes {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            if let Some(actor) = event.actor {
+                line.push_str(&format!(" by {}", actor));
+            }
+            writeln!(out, "{}", line).is_ok()
+        }
+    })
+}
@@ -0,0 +1,71 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::rtr::rtr_access_log;
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("check")
+        .about("Exit with 1 when the CF application log entries go over a threshold, e.g. to gate a smoke test")
+        .arg(
+            Arg::with_name("max_5xx_rate")
+                .value_name("PERCENTAGE")
+                .long("max-5xx-rate")
+                .help("Fail when more than PERCENTAGE of the requests of the Gorouter access logs got a 5xx response, e.g. 1%")
+                .takes_value(true)
+                .required(true)
+                .validator(|v| parse_percentage(&v).map(|_| ())),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Parse a percentage between 0 and 100, the `%` suffix being optional
+fn parse_percentage(percentage: &str) -> Result<f64, String> {
+    percentage
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .ok_or_else(|| format!("invalid percentage: {}", percentage))
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let max_rate = parse_percentage(matches.value_of("max_5xx_rate").unwrap()).unwrap();
+    let filter = entry_filter(matches);
+    let mut requests = 0u64;
+    let mut errors = 0u64;
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if let (true, Some(log)) = (filter.matches(&entry), rtr_access_log(&entry)) {
+                requests += 1;
+                if log.status >= 500 {
+                    errors += 1;
+                }
+            }
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    // no request at all is not a failure, the app may not be routed
+    let rate = if requests == 0 {
+        0.0
+    } else {
+        errors as f64 * 100.0 / requests as f64
+    };
+    let verdict = if rate > max_rate { "FAIL" } else { "OK" };
+    println!(
+        "{}: 5xx rate {:.2}% ({} of {} requests), maximum {}%",
+        verdict, rate, errors, requests, max_rate
+    );
+    if rate > max_rate {
+        1
+    } else {
+        0
+    }
+}
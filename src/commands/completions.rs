@@ -0,0 +1,24 @@
+use clap::{value_t, App, Arg, ArgMatches, Shell, SubCommand};
+use std::io::{self, Write};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("completions")
+        .about("Write the completion script of a shell, generated from the options of every subcommand")
+        .arg(
+            Arg::with_name("shell")
+                .value_name("SHELL")
+                .help("Shell to complete the command line of")
+                .required(true)
+                .possible_values(&Shell::variants()),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let shell = value_t!(matches, "shell", Shell).unwrap_or_else(|e| e.exit());
+    // clap panics when it fails to write, e.g. piped into head
+    let mut script = Vec::new();
+    crate::app().gen_completions_to("cf-app-log-detector", shell, &mut script);
+    // nothing to report when stdout got closed
+    let _ = io::stdout().write_all(&script);
+    0
+}
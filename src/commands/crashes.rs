@@ -0,0 +1,102 @@
+use chrono::SecondsFormat;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+use std::io::{self, Write};
+
+use cf_app_log_detector::crash::crash;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::{parse_cf_app_log, ComponentInfoValid};
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("crashes")
+        .about("Write the app instances which crashed or exited, told by API, CELL and APP entries")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let json = matches.value_of("format") == Some("json");
+    let filter = entry_filter(matches);
+    let mut crashes = 0;
+    let mut out_of_memory = 0;
+    let code = for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        let entry = match parse_cf_app_log(&line) {
+            Ok((_, entry)) if filter.matches(&entry) => entry,
+            _ => return true,
+        };
+        let crash = match crash(&entry) {
+            Some(crash) => crash,
+            None => return true,
+        };
+        crashes += 1;
+        let is_out_of_memory = crash.is_out_of_memory();
+        if is_out_of_memory {
+            out_of_memory += 1;
+        }
+        let timestamp = entry
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Millis, false);
+        let source = match &entry.component {
+            ComponentInfoValid::Valid(info) => info.instance(),
+            ComponentInfoValid::Invalid(raw) => raw.clone(),
+        };
+        // a failed write means stdout got closed, e.g. piped into head
+        let written = if json {
+            writeln!(
+                out,
+                "{}",
+                json!({
+                    "timestamp": timestamp,
+                    "source": source,
+                    "index": crash.index,
+                    "exit_status": crash.exit_status,
+                    "reason": crash.reason,
+                    "description": crash.description,
+                    "out_of_memory": is_out_of_memory,
+                })
+            )
+        } else {
+            let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            // without a reason, the description is the message of the instance itself
+            let reason = match (crash.reason, crash.description) {
+                (Some(reason), Some(description)) => format!(" {} ({})", reason, description),
+                (Some(reason), None) => format!(" {}", reason),
+                (None, _) => String::new(),
+            };
+            writeln!(
+                out,
+                "{} {} instance {} exit status {}{}{}",
+                timestamp,
+                source,
+                optional(crash.index.map(|index| index.to_string())),
+                optional(crash.exit_status.map(|status| status.to_string())),
+                reason,
+                if is_out_of_memory {
+                    " [out of memory]"
+                } else {
+                    ""
+                }
+            )
+        };
+        written.is_ok()
+    });
+    if code == 0 && !json {
+        // same as above, nothing to report when stdout got closed
+        let _ = writeln!(out, "{} crashes, {} out of memory", crashes, out_of_memory);
+    }
+    code
+}
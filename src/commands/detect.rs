@@ -0,0 +1,420 @@
+use clap::{value_t, App, Arg, ArgMatches, SubCommand};
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, IsTerminal};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use cf_app_log_detector::detector::{CfAppLogDetector, SampleStrategy};
+use cf_app_log_detector::input::{expand_inputs, is_binary};
+use cf_app_log_detector::metrics::Metrics;
+
+use super::{log_format, parse_size, timestamp_format_arg};
+
+const WATCH_DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+const METRICS_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("detect")
+       .about("Try to detect log outputted by CF cli (default when no subcommand is given)")
+       .arg(Arg::with_name("percentage_matching")
+          .short("p")
+          .long("percentage-matching")
+          .value_name("PERCENTAGE_MATCHING")
+          .help("Percentage of line matching expected format for the file to be considered an application log")
+          .takes_value(true)
+          .default_value("90"))
+        .arg(Arg::with_name("one_line_match")
+          .value_name("ONE_LINE_MATCH")
+          .long("one-line-match")
+          .help("Consider the file to be CF app log if a single line matches expected format")
+          .takes_value(false))
+        .arg(Arg::with_name("log_format")
+          .value_name("NAME")
+          .long("log-format")
+          .help("Format the file is expected to be in, cf for the CF application log written by the cf CLI, or the name of an envelope of the configuration file")
+          .takes_value(true)
+          .default_value("cf"))
+        .arg(timestamp_format_arg())
+        .arg(Arg::with_name("follow")
+          .value_name("FOLLOW")
+          .long("follow")
+          .short("f")
+          .help("Keep reading the file as it grows and print verdict updates")
+          .takes_value(false))
+        .arg(Arg::with_name("watch")
+          .value_name("DIR")
+          .long("watch")
+          .help("Watch a directory and classify files as they are created or modified")
+          .takes_value(true)
+          .conflicts_with_all(&["follow", "log"]))
+        .arg(Arg::with_name("sample")
+          .value_name("N")
+          .long("sample")
+          .help("Only parse N lines of the file to produce the verdict")
+          .takes_value(true)
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("sample_strategy")
+          .value_name("STRATEGY")
+          .long("sample-strategy")
+          .help("How sampled lines are picked")
+          .takes_value(true)
+          .possible_values(&["head", "random", "reservoir"])
+          .default_value("head"))
+        .arg(Arg::with_name("max_lines")
+          .value_name("N")
+          .long("max-lines")
+          .help("Stop reading the file after N lines")
+          .takes_value(true)
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("max_bytes")
+          .value_name("SIZE")
+          .long("max-bytes")
+          .help("Stop reading the file after SIZE bytes (K, M and G suffixes are supported)")
+          .takes_value(true)
+          .validator(|v| parse_size(&v).map(|_| ()))
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("skip_lines")
+          .value_name("N")
+          .long("skip-lines")
+          .help("Ignore the first N lines of the file (cf CLI banner lines are always ignored)")
+          .takes_value(true))
+        .arg(Arg::with_name("start_offset")
+          .value_name("BYTES")
+          .long("start-offset")
+          .help("Start reading the file at this byte offset")
+          .takes_value(true)
+          .conflicts_with_all(&["follow", "watch", "checkpoint"]))
+        .arg(Arg::with_name("emit_offset")
+          .value_name("EMIT_OFFSET")
+          .long("emit-offset")
+          .help("Print the byte offset where reading stopped, to resume from it with --start-offset")
+          .takes_value(false)
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("checkpoint")
+          .value_name("FILE")
+          .long("checkpoint")
+          .help("Resume from the offset saved in FILE and save the new offset into it")
+          .takes_value(true)
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("max_line_length")
+          .value_name("SIZE")
+          .long("max-line-length")
+          .help("Truncate lines longer than SIZE bytes, they are classified on what is kept")
+          .takes_value(true)
+          .validator(|v| parse_size(&v).map(|_| ()))
+          .default_value("1M"))
+        .arg(Arg::with_name("mmap")
+          .value_name("MMAP")
+          .long("mmap")
+          .help("Read the file through a memory mapping (done automatically for files above 64M)")
+          .takes_value(false)
+          .conflicts_with_all(&["follow", "watch"]))
+        .arg(Arg::with_name("no_progress")
+          .value_name("NO_PROGRESS")
+          .long("no-progress")
+          .help("Do not display a progress bar, only displayed when stderr is a terminal anyway")
+          .takes_value(false))
+        .arg(Arg::with_name("no_early_exit")
+          .value_name("NO_EARLY_EXIT")
+          .long("no-early-exit")
+          .help("Read the whole file even once the verdict can no longer change")
+          .takes_value(false))
+        .arg(Arg::with_name("fail_on_loss")
+          .value_name("FAIL_ON_LOSS")
+          .long("fail-on-loss")
+          .help("Exit with 3 when Loggregator warns that messages were dropped, the log is incomplete")
+          .takes_value(false))
+        .arg(Arg::with_name("metrics_addr")
+          .value_name("ADDR")
+          .long("metrics-addr")
+          .help("With --follow or --watch, serve /metrics and /healthz over HTTP on ADDR (e.g. 127.0.0.1:9187)")
+          .takes_value(true))
+        .arg(Arg::with_name("debug")
+          .value_name("DEBUG")
+          .long("debug")
+          .short("d")
+          .help("Enable debugging")
+          .takes_value(false))
+        .arg(Arg::with_name("log")
+          .value_name("LOG")
+          .help("Log file, or directory to scan recursively")
+          .index(1)
+          .multiple(true)
+          .required_unless("watch")
+          .takes_value(true))
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let debug = matches.is_present("debug");
+    let metrics = Arc::new(Metrics::new());
+    if let Some(address) = matches.value_of("metrics_addr") {
+        if !matches.is_present("follow") && !matches.is_present("watch") {
+            eprintln!("--metrics-addr is only supported with --follow or --watch");
+            return 2;
+        }
+        if let Err(e) = serve_metrics(address, metrics.clone()) {
+            eprintln!("Failed serving metrics on {}, message: {}", address, e);
+            return 2;
+        }
+    }
+    let format = match log_format(matches) {
+        Some(format) => format,
+        None => return 2,
+    };
+    let new_detector = || {
+        let mut detector = CfAppLogDetector::new(
+            value_t!(matches, "percentage_matching", usize).unwrap_or_else(|e| e.exit()),
+            matches.is_present("one_line_match"),
+        )
+        .with_format(format.clone())
+        .with_early_exit(!matches.is_present("no_early_exit"))
+        .with_fail_on_loss(matches.is_present("fail_on_loss"))
+        .with_mmap(matches.is_present("mmap"))
+        .with_max_line_length(
+            parse_size(matches.value_of("max_line_length").unwrap()).unwrap() as usize,
+        )
+        .with_progress(!matches.is_present("no_progress") && io::stderr().is_terminal());
+        if matches.is_present("sample") {
+            detector = detector.with_sample(
+                value_t!(matches, "sample", usize).unwrap_or_else(|e| e.exit()),
+                value_t!(matches, "sample_strategy", SampleStrategy).unwrap(),
+            );
+        }
+        if matches.is_present("skip_lines") {
+            let skip_lines = value_t!(matches, "skip_lines", usize).unwrap_or_else(|e| e.exit());
+            detector = detector.with_skip_lines(skip_lines);
+        }
+        if matches.is_present("max_lines") {
+            let max_lines = value_t!(matches, "max_lines", usize).unwrap_or_else(|e| e.exit());
+            detector = detector.with_max_lines(max_lines);
+        }
+        if let Some(max_bytes) = matches.value_of("max_bytes") {
+            detector = detector.with_max_bytes(parse_size(max_bytes).unwrap());
+        }
+        if matches.is_present("metrics_addr") {
+            detector = detector.with_metrics(metrics.clone());
+        }
+        detector
+    };
+
+    if let Some(dir) = matches.value_of("watch") {
+        if let Err(e) = watch_directory(dir, debug, new_detector) {
+            eprintln!("Failed watching directory: {}, message: {}", dir, e);
+            return 2;
+        }
+        return 0;
+    }
+
+    let inputs: Vec<&str> = matches.values_of("log").unwrap().collect();
+    if inputs.len() > 1 || Path::new(inputs[0]).is_dir() {
+        for option in &["follow", "start_offset", "emit_offset", "checkpoint"] {
+            if matches.is_present(option) {
+                eprintln!(
+                    "--{} is only supported with a single file",
+                    option.replace('_', "-")
+                );
+                return 2;
+            }
+        }
+        return detect_many(&inputs, debug, new_detector);
+    }
+
+    let mut detector = new_detector();
+    if matches.is_present("start_offset") {
+        let offset = value_t!(matches, "start_offset", u64).unwrap_or_else(|e| e.exit());
+        detector = detector.with_start_offset(offset);
+    }
+    let checkpoint = matches.value_of("checkpoint");
+    if let Some(checkpoint) = checkpoint {
+        match read_checkpoint(checkpoint) {
+            Ok(offset) => detector = detector.with_start_offset(offset),
+            Err(e) => {
+                eprintln!("Failed reading checkpoint: {}, message: {}", checkpoint, e);
+                return 2;
+            }
+        }
+    }
+
+    let filename = inputs[0];
+    let result = if matches.is_present("follow") {
+        detector.follow_file(filename, debug)
+    } else {
+        detector.process_file(filename)
+    };
+    match result {
+        Ok(()) => (),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("File {} not found", filename);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("Failed parsing file: {}, message: {}", filename, e);
+            return 2;
+        }
+    }
+
+    if matches.is_present("emit_offset") {
+        println!("{}", detector.offset());
+    }
+    if let Some(checkpoint) = checkpoint {
+        if let Err(e) = fs::write(checkpoint, format!("{}\n", detector.offset())) {
+            eprintln!("Failed writing checkpoint: {}, message: {}", checkpoint, e);
+            return 2;
+        }
+    }
+
+    detector.show_results(filename, debug)
+}
+
+/// Classify several files, directories are walked recursively and the binary files found
+/// in them skipped. Exits with 0 when at least one file is a CF application log, with 3 when
+/// one of them lost messages and `--fail-on-loss` is given.
+fn detect_many<F>(inputs: &[&str], debug: bool, new_detector: F) -> i32
+where
+    F: Fn() -> CfAppLogDetector,
+{
+    let files = match expand_inputs(inputs) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Failed reading directory, message: {}", e);
+            return 2;
+        }
+    };
+
+    let mut code = 1;
+    for (path, found_in_directory) in files {
+        let filename = path.to_string_lossy();
+        if found_in_directory {
+            match is_binary(&path) {
+                Ok(true) => {
+                    eprintln!("{} skipped: binary", filename);
+                    continue;
+                }
+                Ok(false) => (),
+                Err(e) => {
+                    eprintln!("Failed parsing file: {}, message: {}", filename, e);
+                    continue;
+                }
+            }
+        }
+
+        let mut detector = new_detector();
+        match detector.process_file(&filename) {
+            Ok(()) => match detector.show_results(&filename, debug) {
+                0 if code != 3 => code = 0,
+                3 => code = 3,
+                _ => (),
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!("File {} not found", filename)
+            }
+            Err(e) => eprintln!("Failed parsing file: {}, message: {}", filename, e),
+        }
+    }
+    code
+}
+
+/// Offset saved by a previous run, a missing checkpoint file means starting from scratch
+fn read_checkpoint(path: &str) -> io::Result<u64> {
+    match fs::read_to_string(path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Classify every file created or modified in `dir`, printing one verdict per file event
+fn watch_directory<F>(dir: &str, debug: bool, new_detector: F) -> notify::Result<()>
+where
+    F: Fn() -> CfAppLogDetector,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(Path::new(dir), RecursiveMode::NonRecursive)?;
+    eprintln!("Watching {} for log files", dir);
+
+    while let Ok(event) = rx.recv() {
+        // a single write usually triggers a burst of events, wait for it to settle
+        let mut events = vec![event];
+        while let Ok(event) = rx.recv_timeout(WATCH_DEBOUNCE_DELAY) {
+            events.push(event);
+        }
+
+        let mut paths = Vec::new();
+        for event in events {
+            match event {
+                Ok(event) => {
+                    if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+                        for path in event.paths {
+                            if path.is_file() && !paths.contains(&path) {
+                                paths.push(path);
+                            }
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Watch error: {}", e),
+            }
+        }
+
+        for path in paths {
+            let filename = path.to_string_lossy();
+            let mut detector = new_detector();
+            match detector.process_file(&filename) {
+                Ok(()) => {
+                    detector.show_results(&filename, debug);
+                }
+                Err(e) => eprintln!("Failed parsing file: {}, message: {}", filename, e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Answer HTTP requests for `/metrics` and `/healthz` from a background thread
+fn serve_metrics(address: &str, metrics: Arc<Metrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // nothing to do about a client going away
+            let _ = respond(stream, &metrics);
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(METRICS_READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, nothing in them changes the response
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", "text/plain; version=0.0.4", metrics.render()),
+        Some("/healthz") => ("200 OK", "text/plain", "ok\n".to_string()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
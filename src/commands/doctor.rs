@@ -0,0 +1,492 @@
+use chrono::{DateTime, Duration, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+use cf_app_log_detector::balance::imbalance;
+use cf_app_log_detector::crash::{container_start, crash, out_of_memory, restart_loops};
+use cf_app_log_detector::filter::parse_duration;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::level::{level, Level};
+use cf_app_log_detector::loggregator::loss_warning;
+use cf_app_log_detector::rtr::rtr_access_log;
+use cf_app_log_detector::timeline::{gaps, Regressions};
+use cf_app_log_detector::{
+    is_cf_cli_banner, parse_cf_app_log, CfAppLogEntry, Component, ComponentInfoValid,
+};
+
+use super::stats::skew::SkewStats;
+use super::{for_each_numbered_line, input_args, is_count};
+
+/// Starts within `RESTART_WINDOW` making a restart loop, same as the defaults of `restarts`
+const RESTART_MAX: usize = 3;
+const RESTART_WINDOW_MINUTES: i64 = 10;
+/// Shares of 5xx responses, in percents, making a warning and a critical finding
+const WARNING_5XX_RATE: f64 = 1.0;
+const CRITICAL_5XX_RATE: f64 = 10.0;
+/// Share of APP entries telling an error, in percents, making a warning
+const WARNING_ERROR_RATE: f64 = 5.0;
+/// Median clock skew, in seconds, making a warning
+const WARNING_CLOCK_SKEW: f64 = 1.0;
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("doctor")
+        .about("Run the detection and every analysis, and report what needs attention, the most severe first")
+        .args(&doctor_args())
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes a single object")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&input_args())
+}
+
+/// Arguments of `diagnose`
+pub fn doctor_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("percentage_matching")
+            .value_name("PERCENTAGE")
+            .short("p")
+            .long("percentage-matching")
+            .help("Percentage of lines which must be entries for the input to be considered an application log")
+            .takes_value(true)
+            .validator(is_count)
+            .default_value("90"),
+        Arg::with_name("gap")
+            .value_name("DURATION")
+            .long("gap")
+            .help("Flag the app instances logging nothing for longer than DURATION")
+            .takes_value(true)
+            .validator(|v| parse_duration(&v).map(|_| ()))
+            .default_value("5m"),
+    ]
+}
+
+/// How much a finding needs attention
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Critical,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+}
+
+/// What one of the heuristics, `check`, found out
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+}
+
+/// Outcome of the detection and of the heuristics over the inputs
+pub struct Diagnosis {
+    pub lines: u64,
+    pub entries: u64,
+    /// whether enough lines are entries for the inputs to be CF application logs
+    pub is_log: bool,
+    /// the most severe first
+    pub findings: Vec<Finding>,
+}
+
+/// Seconds, with milliseconds
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+fn timestamp(timestamp: &DateTime<FixedOffset>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+/// Detection and heuristics, fed with the lines of the inputs in order
+pub struct Doctor {
+    percentage_matching: u64,
+    longest_gap: Duration,
+    lines: u64,
+    entries: u64,
+    crashes: u64,
+    out_of_memory_entries: u64,
+    starts: BTreeMap<u32, Vec<DateTime<FixedOffset>>>,
+    loss_warnings: u64,
+    dropped: u64,
+    requests: u64,
+    server_errors: u64,
+    app_entries: u64,
+    app_errors: u64,
+    /// timestamps of the entries of each app instance
+    app_timestamps: BTreeMap<String, Vec<DateTime<FixedOffset>>>,
+    skew: SkewStats,
+    app_load: BTreeMap<u32, u64>,
+    request_load: BTreeMap<u32, u64>,
+    file_offsets: BTreeMap<String, BTreeSet<i32>>,
+    regressions: Regressions,
+    /// the cf CLI banner starting an input is not accounted for, same as by `detect`
+    in_header: bool,
+}
+
+impl Doctor {
+    /// Configured with the `doctor_args`
+    pub fn new(matches: &ArgMatches) -> Doctor {
+        Doctor {
+            percentage_matching: matches
+                .value_of("percentage_matching")
+                .unwrap()
+                .parse()
+                .unwrap(),
+            longest_gap: parse_duration(matches.value_of("gap").unwrap()).unwrap(),
+            lines: 0,
+            entries: 0,
+            crashes: 0,
+            out_of_memory_entries: 0,
+            starts: BTreeMap::new(),
+            loss_warnings: 0,
+            dropped: 0,
+            requests: 0,
+            server_errors: 0,
+            app_entries: 0,
+            app_errors: 0,
+            app_timestamps: BTreeMap::new(),
+            skew: SkewStats::default(),
+            app_load: BTreeMap::new(),
+            request_load: BTreeMap::new(),
+            file_offsets: BTreeMap::new(),
+            regressions: Regressions::default(),
+            in_header: true,
+        }
+    }
+
+    /// Account for a line, ANSI escapes stripped, returning its entry when it is one
+    pub fn record<'a>(
+        &mut self,
+        filename: &str,
+        line_number: u64,
+        line: &'a str,
+    ) -> Option<CfAppLogEntry<'a>> {
+        if line_number == 1 {
+            self.in_header = true;
+        }
+        if self.in_header && is_cf_cli_banner(line) {
+            return None;
+        }
+        self.in_header = false;
+        self.lines += 1;
+        let (_, entry) = parse_cf_app_log(line).ok()?;
+        self.entries += 1;
+        let timestamp = entry.timestamp;
+        self.file_offsets
+            .entry(filename.to_string())
+            .or_default()
+            .insert(timestamp.offset().local_minus_utc());
+        self.regressions.record(filename, line_number, timestamp);
+        if crash(&entry).is_some() {
+            self.crashes += 1;
+        }
+        if out_of_memory(&entry) {
+            self.out_of_memory_entries += 1;
+        }
+        if let Some(index) = container_start(&entry) {
+            self.starts.entry(index).or_default().push(timestamp);
+        }
+        if let Some(warning) = loss_warning(&entry) {
+            self.loss_warnings += 1;
+            self.dropped += warning.dropped.unwrap_or(0);
+        }
+        if let Some(log) = rtr_access_log(&entry) {
+            self.requests += 1;
+            if log.status >= 500 {
+                self.server_errors += 1;
+            }
+            if let Some(index) = log.app_index() {
+                *self.request_load.entry(index).or_insert(0) += 1;
+            }
+            self.skew.record_request(&log, timestamp);
+        }
+        if let ComponentInfoValid::Valid(info) = &entry.component {
+            if info.name == Component::APPLICATION {
+                self.app_entries += 1;
+                if level(&entry).is_some_and(|level| level >= Level::Error) {
+                    self.app_errors += 1;
+                }
+                let instance = info.instance();
+                self.skew
+                    .record_app_entry(&instance, timestamp, entry.message.unwrap_or(""));
+                self.app_timestamps
+                    .entry(instance)
+                    .or_default()
+                    .push(timestamp);
+                if info
+                    .process_type
+                    .as_deref()
+                    .is_none_or(|process| process == "web")
+                {
+                    *self.app_load.entry(info.index).or_insert(0) += 1;
+                }
+            }
+        }
+        Some(entry)
+    }
+
+    /// Findings over the lines recorded
+    pub fn diagnosis(self) -> Diagnosis {
+        let Doctor {
+            percentage_matching,
+            longest_gap,
+            lines,
+            entries,
+            crashes,
+            out_of_memory_entries,
+            mut starts,
+            loss_warnings,
+            dropped,
+            requests,
+            server_errors,
+            app_entries,
+            app_errors,
+            mut app_timestamps,
+            skew,
+            app_load,
+            request_load,
+            file_offsets,
+            regressions,
+            ..
+        } = self;
+
+        let mut findings = Vec::new();
+        let mut find = |severity, check, message| {
+            findings.push(Finding {
+                severity,
+                check,
+                message,
+            })
+        };
+        let percentage = (entries * 100).checked_div(lines).unwrap_or(0);
+        let is_log = lines > 0 && percentage >= percentage_matching;
+        if is_log {
+            find(
+                Severity::Info,
+                "detection",
+                format!(
+                    "{}% of the {} lines are CF application log entries",
+                    percentage, lines
+                ),
+            );
+        } else {
+            find(
+                Severity::Critical,
+                "detection",
+                format!(
+                    "only {}% of the {} lines are CF application log entries, {}% being expected",
+                    percentage, lines, percentage_matching
+                ),
+            );
+        }
+        if crashes > 0 {
+            find(
+                Severity::Critical,
+                "crashes",
+                format!("{} app instance crashes", crashes),
+            );
+        }
+        if out_of_memory_entries > 0 {
+            find(
+                Severity::Critical,
+                "out-of-memory",
+                format!(
+                    "{} entries tell an app instance ran out of memory",
+                    out_of_memory_entries
+                ),
+            );
+        }
+        for (index, starts) in &mut starts {
+            // inputs are not necessarily in order, e.g. several captures
+            starts.sort();
+            let window = Duration::minutes(RESTART_WINDOW_MINUTES);
+            for restart_loop in restart_loops(starts, RESTART_MAX, window) {
+                find(
+                    Severity::Critical,
+                    "restarts",
+                    format!(
+                        "instance {} restarted in a loop, {} starts from {} to {}",
+                        index,
+                        restart_loop.starts,
+                        timestamp(&restart_loop.first),
+                        timestamp(&restart_loop.last)
+                    ),
+                );
+            }
+        }
+        if requests > 0 {
+            let rate = server_errors as f64 * 100.0 / requests as f64;
+            let message = format!(
+                "{:.1}% of the {} requests got a 5xx response",
+                rate, requests
+            );
+            if rate > CRITICAL_5XX_RATE {
+                find(Severity::Critical, "5xx", message);
+            } else if rate > WARNING_5XX_RATE {
+                find(Severity::Warning, "5xx", message);
+            }
+        }
+        if app_entries > 0 {
+            let rate = app_errors as f64 * 100.0 / app_entries as f64;
+            if rate > WARNING_ERROR_RATE {
+                find(
+                    Severity::Warning,
+                    "errors",
+                    format!(
+                        "{:.1}% of the {} APP entries tell an error",
+                        rate, app_entries
+                    ),
+                );
+            }
+        }
+        if loss_warnings > 0 {
+            find(
+                Severity::Warning,
+                "loss",
+                format!(
+                    "{} Loggregator warnings of lost messages, {} messages dropped",
+                    loss_warnings, dropped
+                ),
+            );
+        }
+        for (instance, timestamps) in &mut app_timestamps {
+            timestamps.sort();
+            let found = gaps(timestamps, longest_gap);
+            if let Some(gap) = found.iter().max_by_key(|gap| gap.duration()) {
+                find(
+                    Severity::Warning,
+                    "gaps",
+                    format!(
+                        "{} logged nothing for {}s from {} to {}, {} gaps over {}s",
+                        instance,
+                        seconds(gap.duration()),
+                        timestamp(&gap.start),
+                        timestamp(&gap.end),
+                        found.len(),
+                        seconds(longest_gap)
+                    ),
+                );
+            }
+        }
+        if let Some((median, joined)) = skew.median() {
+            if median.abs() >= WARNING_CLOCK_SKEW {
+                find(
+                    Severity::Warning,
+                    "clock-skew",
+                    format!(
+                        "the clocks of the cells are {}s {} the ones of the routers, median of {} requests",
+                        median.abs(),
+                        if median > 0.0 { "ahead of" } else { "behind" },
+                        joined
+                    ),
+                );
+            }
+        }
+        for (load, counts) in &[("APP lines", &app_load), ("requests", &request_load)] {
+            if let Some(imbalance) = imbalance(counts) {
+                find(
+                    Severity::Warning,
+                    "load",
+                    format!(
+                        "instance {} got {:.1}% of the {} of {} instances",
+                        imbalance.index,
+                        imbalance.share * 100.0,
+                        load,
+                        imbalance.instances
+                    ),
+                );
+            }
+        }
+        for (filename, offsets) in &file_offsets {
+            if offsets.len() > 1 {
+                let offsets: Vec<_> = offsets
+                    .iter()
+                    .map(|offset| FixedOffset::east_opt(*offset).unwrap().to_string())
+                    .collect();
+                find(
+                    Severity::Warning,
+                    "utc-offsets",
+                    format!("{} mixes UTC offsets: {}", filename, offsets.join(", ")),
+                );
+            }
+        }
+        if let Some(largest) = &regressions.largest {
+            find(
+                Severity::Warning,
+                "timestamps",
+                format!(
+                    "{} entries go back in time, up to {}s at {}:{}",
+                    regressions.count,
+                    seconds(largest.duration),
+                    largest.filename,
+                    largest.line_number
+                ),
+            );
+        }
+        // stable, the findings of a severity keep the order of the checks
+        findings.sort_by_key(|finding| finding.severity);
+        Diagnosis {
+            lines,
+            entries,
+            is_log,
+            findings,
+        }
+    }
+}
+
+/// Run the detection and the heuristics over the inputs, the exit code of reading them on error
+pub fn diagnose(matches: &ArgMatches) -> Result<Diagnosis, i32> {
+    let mut doctor = Doctor::new(matches);
+    let code = for_each_numbered_line(matches, |filename, line_number, line| {
+        doctor.record(filename, line_number, &strip_ansi(line));
+        true
+    });
+    if code != 0 {
+        return Err(code);
+    }
+    Ok(doctor.diagnosis())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let diagnosis = match diagnose(matches) {
+        Ok(diagnosis) => diagnosis,
+        Err(code) => return code,
+    };
+    if matches.value_of("format") == Some("json") {
+        println!(
+            "{}",
+            json!({
+                "lines": diagnosis.lines,
+                "entries": diagnosis.entries,
+                "is_log": diagnosis.is_log,
+                "findings": diagnosis
+                    .findings
+                    .iter()
+                    .map(|finding| json!({
+                        "severity": finding.severity.as_str(),
+                        "check": finding.check,
+                        "message": finding.message,
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        );
+        return 0;
+    }
+    for finding in &diagnosis.findings {
+        println!("{}: {}", finding.severity.as_str(), finding.message);
+    }
+    0
+}
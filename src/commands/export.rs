@@ -0,0 +1,79 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+#[cfg(feature = "sqlite")]
+use std::io;
+
+#[cfg(feature = "sqlite")]
+use cf_app_log_detector::input::strip_ansi;
+#[cfg(feature = "sqlite")]
+use cf_app_log_detector::output::{EntryWriter, SqliteWriter};
+#[cfg(feature = "sqlite")]
+use cf_app_log_detector::parse_cf_app_log;
+
+#[cfg(feature = "sqlite")]
+use super::for_each_line;
+use super::input_args;
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("export")
+        .about("Store the lines parsed as CF application log entries in a database")
+        .arg(
+            Arg::with_name("sqlite")
+                .value_name("DB")
+                .long("sqlite")
+                .help("SQLite database to add the entries to, in an entries table")
+                .takes_value(true)
+                .required(true),
+        )
+        .args(&input_args())
+}
+
+#[cfg(feature = "sqlite")]
+pub fn run(matches: &ArgMatches) -> i32 {
+    let path = matches.value_of("sqlite").unwrap();
+    let writer = rusqlite::Connection::open(path)
+        .map_err(|error| error.to_string())
+        .and_then(|connection| {
+            SqliteWriter::new(connection, "entries").map_err(|error| error.to_string())
+        });
+    let mut writer = match writer {
+        Ok(writer) => writer,
+        Err(error) => {
+            eprintln!("Could not open {}: {}", path, error);
+            return 2;
+        }
+    };
+    match insert_entries(matches, &mut writer) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Could not write to {}: {}", path, error);
+            2
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn run(_matches: &ArgMatches) -> i32 {
+    eprintln!("SQLite export is not available, rebuild with --features sqlite");
+    2
+}
+
+/// Write the entries of every input given on the command line and commit them, returns the
+/// exit code of reading the inputs
+#[cfg(feature = "sqlite")]
+pub fn insert_entries(matches: &ArgMatches, writer: &mut SqliteWriter) -> io::Result<i32> {
+    let mut failure = None;
+    let code = for_each_line(matches, |filename, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            writer.set_source(filename);
+            if let Err(error) = writer.write_entry(&entry) {
+                failure = Some(error);
+                return false;
+            }
+        }
+        true
+    });
+    match failure {
+        Some(error) => Err(error),
+        None => writer.finish().map(|_| code),
+    }
+}
@@ -0,0 +1,296 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::output::pretty_json;
+use cf_app_log_detector::time::{rewrite_relative, rewrite_timestamp};
+use cf_app_log_detector::{parse_cf_app_log, ComponentInfoValid};
+
+use super::pager::{output, pager_arg};
+use super::{
+    count_lines, entry_filter, entry_filter_args, for_each_numbered_line, input_args, is_count,
+    origin_args, reassemble_arg, relative_arg, timezone, timezone_args, write_failed,
+};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("filter")
+        .about("Only write the lines which are CF application log entries")
+        .arg(
+            Arg::with_name("invert")
+                .short("v")
+                .long("invert")
+                .visible_alias("show-nonmatching")
+                .help("Write the lines which are not CF application log entries, or not selected, instead"),
+        )
+        .arg(
+            Arg::with_name("strip_ansi")
+                .long("strip-ansi")
+                .help("Remove ANSI escape sequences (colors) from the lines written"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .short("c")
+                .long("count")
+                .help("Only write the number of lines which would be written, per file when several are given")
+                .conflicts_with_all(&["after_context", "before_context", "context"]),
+        )
+        .arg(
+            Arg::with_name("after_context")
+                .short("A")
+                .long("after-context")
+                .value_name("NUM")
+                .help("Also write the NUM entries following each selected entry")
+                .takes_value(true)
+                .validator(is_count)
+                .conflicts_with("invert"),
+        )
+        .arg(
+            Arg::with_name("before_context")
+                .short("B")
+                .long("before-context")
+                .value_name("NUM")
+                .help("Also write the NUM entries preceding each selected entry")
+                .takes_value(true)
+                .validator(is_count)
+                .conflicts_with("invert"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .short("C")
+                .long("context")
+                .value_name("NUM")
+                .help("Also write the NUM entries preceding and following each selected entry")
+                .takes_value(true)
+                .validator(is_count)
+                .conflicts_with("invert"),
+        )
+        .arg(
+            Arg::with_name("dedup_consecutive")
+                .long("dedup-consecutive")
+                .help("Write runs of entries of the same component instance with the same message once, with their repeat count")
+                .conflicts_with_all(&["invert", "count", "after_context", "before_context", "context"]),
+        )
+        .arg(
+            Arg::with_name("pretty_json")
+                .long("pretty-json")
+                .help("Re-indent the messages which are JSON objects over several lines")
+                .conflicts_with("dedup_consecutive"),
+        )
+        .args(&origin_args())
+        .args(&timezone_args())
+        .arg(relative_arg())
+        .arg(reassemble_arg())
+        .arg(pager_arg())
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Entries written around the selected ones, counted in entries: lines which are not entries
+/// are never written as context
+struct Context {
+    before: usize,
+    after: usize,
+    /// last entries not written, up to `before` of them, with their position and annotated
+    /// as context
+    preceding: VecDeque<(u64, String)>,
+    /// entries still to write after the last selected one
+    following: usize,
+    /// position of the current entry, and of the last written one
+    position: u64,
+    last_written: Option<u64>,
+}
+
+impl Context {
+    fn new(before: usize, after: usize) -> Context {
+        Context {
+            before,
+            after,
+            preceding: VecDeque::with_capacity(before + 1),
+            following: 0,
+            position: 0,
+            last_written: None,
+        }
+    }
+
+    /// Context does not span inputs
+    fn next_input(&mut self) {
+        self.preceding.clear();
+        self.following = 0;
+        // forces a separator between the groups of two inputs
+        self.position += 1;
+    }
+
+    /// Write the entry if it is selected or in the context of a selected one, groups of entries
+    /// which are not contiguous are separated by a `--` line like grep does. `annotate` renders
+    /// the line with the separator of its origin annotations, `:` when selected, `-` otherwise.
+    fn write<W, A>(&mut self, out: &mut W, annotate: A, selected: bool) -> io::Result<()>
+    where
+        W: Write,
+        A: Fn(char) -> String,
+    {
+        self.position += 1;
+        if selected {
+            let first = self
+                .preceding
+                .front()
+                .map_or(self.position, |(position, _)| *position);
+            let gap = self.last_written.is_some_and(|last| last + 1 != first);
+            if gap && self.before + self.after > 0 {
+                writeln!(out, "--")?;
+            }
+            for (_, preceding) in self.preceding.drain(..) {
+                writeln!(out, "{}", preceding)?;
+            }
+            self.following = self.after;
+        } else if self.following > 0 {
+            self.following -= 1;
+        } else {
+            if self.before > 0 {
+                if self.preceding.len() == self.before {
+                    self.preceding.pop_front();
+                }
+                self.preceding.push_back((self.position, annotate('-')));
+            }
+            return Ok(());
+        }
+        self.last_written = Some(self.position);
+        writeln!(out, "{}", annotate(if selected { ':' } else { '-' }))
+    }
+}
+
+/// Consecutive selected entries of a component instance sharing their message, written as the
+/// first one followed by how many times it got repeated
+struct Run {
+    component: ComponentInfoValid,
+    message: Option<String>,
+    line: String,
+    count: u64,
+}
+
+impl Run {
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.count > 1 {
+            writeln!(out, "{} (repeated {} times)", self.line, self.count)
+        } else {
+            writeln!(out, "{}", self.line)
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let keep_ansi = !matches.is_present("strip_ansi");
+    let invert = matches.is_present("invert");
+    let filter = entry_filter(matches);
+    if matches.is_present("count") {
+        return count_lines(matches, |line| {
+            let selected = match parse_cf_app_log(&strip_ansi(line)) {
+                Ok((_, entry)) => filter.matches(&entry),
+                Err(_) => false,
+            };
+            selected != invert
+        });
+    }
+    let mut out = io::BufWriter::new(output(matches));
+    let count = |name| matches.value_of(name).map(|v| v.parse::<usize>().unwrap());
+    let context = count("context").unwrap_or(0);
+    let mut context = Context::new(
+        count("before_context").unwrap_or(context),
+        count("after_context").unwrap_or(context),
+    );
+    let with_filename = matches.is_present("with_filename");
+    let line_numbers = matches.is_present("line_numbers");
+    let tz = timezone(matches);
+    let relative = matches.is_present("relative");
+    let pretty = matches.is_present("pretty_json");
+    // first entry read, whether it gets selected or not
+    let mut start = None;
+    let dedup = matches.is_present("dedup_consecutive");
+    let mut repeats: Option<Run> = None;
+    let mut write_code = None;
+
+    let code = for_each_numbered_line(matches, |filename, line_number, line| {
+        if line_number == 1 {
+            context.next_input();
+        }
+        let stripped = strip_ansi(line);
+        let entry = parse_cf_app_log(&stripped).ok().map(|(_, entry)| entry);
+        if let (true, None, Some(entry)) = (relative, start, &entry) {
+            start = Some(entry.timestamp);
+        }
+        let line = if keep_ansi { line } else { &stripped };
+        let line = match (tz, start) {
+            (Some(tz), _) => rewrite_timestamp(line, tz),
+            (_, Some(start)) => rewrite_relative(line, start),
+            _ => line.into(),
+        };
+        // the message ends the line, whatever its timestamp became
+        let pretty_message = match (pretty, entry.as_ref().and_then(|entry| entry.message)) {
+            (true, Some(message)) if line.ends_with(message) => {
+                pretty_json(message).map(|pretty| (message.len(), pretty))
+            }
+            _ => None,
+        };
+        let line: Cow<str> = match pretty_message {
+            Some((length, pretty)) => format!("{}{}", &line[..line.len() - length], pretty).into(),
+            None => line,
+        };
+        // like grep: `FILE:NUMBER:LINE`, `-` replacing `:` for context lines
+        let annotate = |separator| {
+            let mut annotated = String::new();
+            if with_filename {
+                annotated.push_str(filename);
+                annotated.push(separator);
+            }
+            if line_numbers {
+                annotated.push_str(&line_number.to_string());
+                annotated.push(separator);
+            }
+            annotated.push_str(&line);
+            annotated
+        };
+        let written = match entry {
+            Some(entry) if dedup => {
+                if !filter.matches(&entry) {
+                    return true;
+                }
+                if let Some(run) = &mut repeats {
+                    if run.component == entry.component && run.message.as_deref() == entry.message {
+                        run.count += 1;
+                        return true;
+                    }
+                }
+                let previous = repeats.replace(Run {
+                    component: entry.component,
+                    message: entry.message.map(String::from),
+                    line: annotate(':'),
+                    count: 1,
+                });
+                previous.map_or(Ok(()), |run| run.write(&mut out))
+            }
+            Some(entry) if !invert => context.write(&mut out, annotate, filter.matches(&entry)),
+            Some(entry) if filter.matches(&entry) => Ok(()),
+            None if !invert => Ok(()),
+            _ => writeln!(out, "{}", annotate(':')),
+        };
+        match written {
+            Ok(()) => true,
+            Err(error) => {
+                write_code = Some(write_failed(&error, None));
+                false
+            }
+        }
+    });
+    if let Some(write_code) = write_code {
+        return write_code;
+    }
+    let written = match repeats {
+        Some(run) => run.write(&mut out),
+        None => Ok(()),
+    };
+    match written.and_then(|()| out.flush()) {
+        Ok(()) => code,
+        Err(error) => write_failed(&error, None),
+    }
+}
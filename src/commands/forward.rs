@@ -0,0 +1,280 @@
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::output::{
+    EntryWriter, FluentdWriter, GelfUdpWriter, GelfWriter, KafkaWriter, OtlpWriter,
+    SyslogUdpWriter, SyslogWriter,
+};
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::time::in_timezone;
+
+use super::{
+    for_each_line, input_args, severity_mapping, severity_mapping_arg, timezone, timezone_args,
+};
+
+const FLUENTD_SCHEMES: &[&str] = &["tcp"];
+#[cfg(feature = "tls")]
+const OTLP_SCHEMES: &[&str] = &["http", "https"];
+#[cfg(not(feature = "tls"))]
+const OTLP_SCHEMES: &[&str] = &["http"];
+const GELF_SCHEMES: &[&str] = &["udp", "tcp"];
+#[cfg(feature = "tls")]
+const SYSLOG_SCHEMES: &[&str] = &["udp", "tcp", "tls"];
+#[cfg(not(feature = "tls"))]
+const SYSLOG_SCHEMES: &[&str] = &["udp", "tcp"];
+
+pub fn app() -> App<'static, 'static> {
+    let app = SubCommand::with_name("forward")
+        .about("Send the lines parsed as CF application log entries to a log server")
+        .arg(
+            Arg::with_name("gelf")
+                .value_name("URL")
+                .long("gelf")
+                .help("Graylog GELF input, udp://host:port or tcp://host:port")
+                .takes_value(true)
+                .validator(|v| parse_endpoint(&v, GELF_SCHEMES).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("syslog")
+                .value_name("URL")
+                .long("syslog")
+                .help(
+                    "Syslog server receiving RFC 5424 messages, udp://, tcp:// or tls://host:port",
+                )
+                .takes_value(true)
+                .validator(|v| parse_endpoint(&v, SYSLOG_SCHEMES).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("fluentd")
+                .value_name("URL")
+                .long("fluentd")
+                .help("Fluentd or Fluent Bit forward input, tcp://host:port")
+                .takes_value(true)
+                .validator(|v| parse_endpoint(&v, FLUENTD_SCHEMES).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("kafka")
+                .value_name("BROKER")
+                .long("kafka")
+                .help(
+                    "Kafka broker to bootstrap from, host:port (plaintext, without authentication)",
+                )
+                .takes_value(true)
+                .requires("topic"),
+        )
+        .arg(
+            Arg::with_name("topic")
+                .value_name("TOPIC")
+                .long("topic")
+                .help("Kafka topic the entries are produced to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("otlp")
+                .value_name("URL")
+                .long("otlp")
+                .help("OpenTelemetry collector OTLP/HTTP endpoint, e.g. http://localhost:4318")
+                .takes_value(true)
+                .validator(|v| {
+                    if OTLP_SCHEMES
+                        .iter()
+                        .any(|scheme| v.starts_with(&format!("{}://", scheme)))
+                    {
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "{} should be an {} URL",
+                            v,
+                            OTLP_SCHEMES.join(" or ")
+                        ))
+                    }
+                }),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .value_name("TAG")
+                .long("tag")
+                .help("Tag of the events sent to fluentd")
+                .takes_value(true)
+                .default_value("cf.app"),
+        )
+        .group(
+            ArgGroup::with_name("sink")
+                .args(&["gelf", "syslog", "fluentd", "kafka", "otlp"])
+                .required(true),
+        );
+    #[cfg(feature = "tls")]
+    let app = app.arg(
+        Arg::with_name("tls_ca")
+            .value_name("FILE")
+            .long("tls-ca")
+            .help("PEM certificates trusted for tls:// instead of the usual root authorities")
+            .takes_value(true),
+    );
+    app.arg(severity_mapping_arg())
+        .args(&timezone_args())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let (sink, url) = ["gelf", "syslog", "fluentd", "kafka", "otlp"]
+        .iter()
+        .find_map(|sink| matches.value_of(sink).map(|url| (*sink, url)))
+        .unwrap();
+    let mut writer = match connect(matches, sink, url) {
+        Ok(writer) => writer,
+        Err(error) => {
+            eprintln!("Could not connect to {}: {}", url, error);
+            return 2;
+        }
+    };
+    writer.set_severity_mapping(severity_mapping(matches));
+
+    let mut failure = None;
+    let tz = timezone(matches);
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, mut entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if let Some(tz) = tz {
+                entry.timestamp = in_timezone(&entry.timestamp, tz);
+            }
+            if let Err(error) = writer.write_entry(&entry) {
+                failure = Some(error);
+                return false;
+            }
+        }
+        true
+    });
+    if let Some(error) = failure.or_else(|| writer.finish().err()) {
+        eprintln!("Could not send to {}: {}", url, error);
+        return 2;
+    }
+    code
+}
+
+/// Writer sending the entries to `url` as they come, streams are not buffered and Kafka and OTLP
+/// batches are not held back longer than a second, so that entries piped in from a live
+/// `cf logs` are forwarded right away
+fn connect(matches: &ArgMatches, sink: &str, url: &str) -> io::Result<Box<dyn EntryWriter>> {
+    if sink == "kafka" {
+        let topic = matches.value_of("topic").unwrap();
+        return Ok(Box::new(KafkaWriter::connect(url, topic)?));
+    }
+    if sink == "otlp" {
+        return Ok(Box::new(OtlpWriter::new(url)));
+    }
+    let (scheme, address) = parse_endpoint(url, &["udp", "tcp", "tls"]).unwrap();
+    Ok(match (sink, scheme) {
+        ("gelf", "udp") => Box::new(GelfUdpWriter::new(udp_socket(address)?)),
+        ("gelf", _) => Box::new(GelfWriter::null_delimited(TcpStream::connect(resolve(
+            address,
+        )?)?)),
+        ("fluentd", _) => Box::new(FluentdWriter::new(
+            TcpStream::connect(resolve(address)?)?,
+            matches.value_of("tag").unwrap(),
+        )),
+        ("syslog", "udp") => Box::new(SyslogUdpWriter::new(udp_socket(address)?)),
+        #[cfg(feature = "tls")]
+        ("syslog", "tls") => Box::new(SyslogWriter::octet_counted(tls::connect(
+            address,
+            matches.value_of("tls_ca"),
+        )?)),
+        _ => Box::new(SyslogWriter::octet_counted(TcpStream::connect(resolve(
+            address,
+        )?)?)),
+    })
+}
+
+fn udp_socket(address: &str) -> io::Result<UdpSocket> {
+    let address = resolve(address)?;
+    let local: SocketAddr = if address.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    Ok(socket)
+}
+
+/// Split `scheme://host:port`, the scheme being one of `schemes`
+fn parse_endpoint<'a>(url: &'a str, schemes: &[&str]) -> Result<(&'a str, &'a str), String> {
+    match url.find("://") {
+        Some(position) if schemes.contains(&&url[..position]) => {
+            let address = &url[position + 3..];
+            if address
+                .rsplit_once(':')
+                .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+            {
+                Ok((&url[..position], address))
+            } else {
+                Err(format!("missing port in {}", url))
+            }
+        }
+        _ => Err(format!(
+            "{} should start with one of {}",
+            url,
+            schemes
+                .iter()
+                .map(|scheme| format!("{}://", scheme))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn resolve(address: &str) -> io::Result<SocketAddr> {
+    address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no address found for {}", address),
+        )
+    })
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::{self, BufReader};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    use super::resolve;
+
+    /// TLS stream to `address`, the server certificate being checked against the certificates
+    /// in the `ca` PEM file when given, the Mozilla root authorities otherwise
+    pub fn connect(
+        address: &str,
+        ca: Option<&str>,
+    ) -> io::Result<StreamOwned<ClientConnection, TcpStream>> {
+        let mut roots = RootCertStore::empty();
+        match ca {
+            Some(ca) => {
+                for certificate in rustls_pemfile::certs(&mut BufReader::new(File::open(ca)?)) {
+                    roots.add(certificate?).map_err(io::Error::other)?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        let config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(io::Error::other)?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        let host = address.rsplit_once(':').unwrap().0;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let connection = ClientConnection::new(Arc::new(config), name).map_err(io::Error::other)?;
+        Ok(StreamOwned::new(
+            connection,
+            TcpStream::connect(resolve(address)?)?,
+        ))
+    }
+}
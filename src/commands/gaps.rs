@@ -0,0 +1,121 @@
+use chrono::{DateTime, Duration, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use cf_app_log_detector::filter::parse_duration;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::timeline::gaps;
+use cf_app_log_detector::{parse_cf_app_log, ComponentInfoValid};
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("gaps")
+        .about("List the periods without entries, e.g. to spot an instance which went quiet before crashing")
+        .arg(
+            Arg::with_name("longer_than")
+                .value_name("DURATION")
+                .long("longer-than")
+                .help("Only list the gaps longer than DURATION, e.g. 30s")
+                .takes_value(true)
+                .validator(|v| parse_duration(&v).map(|_| ()))
+                .default_value("1m"),
+        )
+        .arg(
+            Arg::with_name("by")
+                .value_name("GROUP")
+                .long("by")
+                .help("Look for gaps between the entries of each component or instance, instead of all of them")
+                .takes_value(true)
+                .possible_values(&["component", "instance"]),
+        )
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per gap (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Seconds, with milliseconds
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let longer_than = parse_duration(matches.value_of("longer_than").unwrap()).unwrap();
+    let by = matches.value_of("by");
+    let filter = entry_filter(matches);
+    // timestamps of the entries of each group, a single unnamed one unless --by is given
+    let mut timestamps: BTreeMap<String, Vec<DateTime<FixedOffset>>> = BTreeMap::new();
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if !filter.matches(&entry) {
+                return true;
+            }
+            let group = match (by, &entry.component) {
+                (None, _) => String::new(),
+                (Some("component"), ComponentInfoValid::Valid(info)) => {
+                    info.name.as_str().to_string()
+                }
+                (Some(_), ComponentInfoValid::Valid(info)) => info.instance(),
+                (Some(_), ComponentInfoValid::Invalid(raw)) => raw.clone(),
+            };
+            timestamps.entry(group).or_default().push(entry.timestamp);
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let json = matches.value_of("format") == Some("json");
+    let format =
+        |timestamp: &DateTime<FixedOffset>| timestamp.to_rfc3339_opts(SecondsFormat::Millis, false);
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    for (group, timestamps) in &mut timestamps {
+        // inputs are not necessarily in order, e.g. several captures
+        timestamps.sort();
+        let found = gaps(timestamps, longer_than);
+        if found.is_empty() {
+            continue;
+        }
+        if !json && by.is_some() && writeln!(out, "{}:", group).is_err() {
+            return 0;
+        }
+        for gap in found {
+            let written = if json {
+                let mut object = Map::new();
+                if let Some(by) = by {
+                    object.insert(by.to_string(), json!(group));
+                }
+                object.insert("start".to_string(), json!(format(&gap.start)));
+                object.insert("end".to_string(), json!(format(&gap.end)));
+                object.insert("seconds".to_string(), json!(seconds(gap.duration())));
+                writeln!(out, "{}", Value::Object(object))
+            } else {
+                writeln!(
+                    out,
+                    "{}{}s from {} to {}",
+                    if by.is_some() { "  " } else { "" },
+                    seconds(gap.duration()),
+                    format(&gap.start),
+                    format(&gap.end)
+                )
+            };
+            // a failed write means stdout got closed, e.g. piped into head
+            if written.is_err() {
+                return 0;
+            }
+        }
+    }
+    0
+}
@@ -0,0 +1,112 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map};
+
+use cf_app_log_detector::formats::Tally;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::is_cf_cli_banner;
+
+use super::{for_each_input_line, input_args, registry, timestamp_format_arg};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("identify")
+        .about("Tell which of the known log formats each file is in, with the share of its lines matching each of them")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes an object per file")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .arg(timestamp_format_arg())
+        .args(&input_args())
+}
+
+/// Lines of a file in each format
+struct Identification<'a> {
+    filename: &'a str,
+    tally: Tally,
+    /// still reading the cf CLI banner, not accounted for
+    in_header: bool,
+}
+
+impl Identification<'_> {
+    /// Percentages of the lines in each format, the most matching first
+    fn ranking(&self) -> Vec<(&str, &str, f64)> {
+        let mut ranking: Vec<(&str, &str, f64)> = self
+            .tally
+            .percentages()
+            .into_iter()
+            .map(|(format, percentage)| (format.name(), format.description(), percentage.floor()))
+            .collect();
+        // stable, the formats registered first win ties
+        ranking.sort_by(|a, b| b.2.total_cmp(&a.2));
+        ranking
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let registry = registry(matches);
+    let mut identifications: Vec<Identification> = matches
+        .values_of("log")
+        .unwrap()
+        .map(|filename| Identification {
+            filename,
+            tally: registry.tally(),
+            in_header: true,
+        })
+        .collect();
+    let code = for_each_input_line(matches, |input, _, line| {
+        let identification = &mut identifications[input];
+        if identification.in_header && is_cf_cli_banner(line) {
+            return true;
+        }
+        identification.in_header = false;
+        identification.tally.line(&strip_ansi(line));
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let json = matches.value_of("format") == Some("json");
+    let mut code = 0;
+    for identification in &identifications {
+        let ranking = identification.ranking();
+        // the best format only counts when some lines are in it
+        let best = ranking
+            .first()
+            .filter(|(_, _, percentage)| *percentage > 0.0);
+        if best.is_none() {
+            code = 1;
+        }
+        if json {
+            let formats: Map<String, serde_json::Value> = ranking
+                .iter()
+                .map(|(name, _, percentage)| (name.to_string(), json!(percentage)))
+                .collect();
+            println!(
+                "{}",
+                json!({
+                    "file": identification.filename,
+                    "format": best.map(|(name, _, _)| name),
+                    "lines": identification.tally.lines(),
+                    "formats": formats,
+                })
+            );
+        } else {
+            let shares: Vec<String> = ranking
+                .iter()
+                .map(|(_, description, percentage)| format!("{}% {}", percentage, description))
+                .collect();
+            println!(
+                "{}: {} ({})",
+                identification.filename,
+                best.map_or("unknown format", |(_, description, _)| description),
+                shares.join(", ")
+            );
+        }
+    }
+    code
+}
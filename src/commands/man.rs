@@ -0,0 +1,109 @@
+use clap::{crate_version, App, ArgMatches, ErrorKind, SubCommand};
+use std::io::{self, Write};
+
+use cf_app_log_detector::config::ENV_PREFIX;
+
+/// Width of the help texts in the manual page, the usual one of terminals
+const WIDTH: usize = 80;
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("man")
+        .about("Write the manual page in roff, generated from the options of every subcommand, e.g. for packagers")
+}
+
+/// Help text of `args`, e.g. `["parse", "--help"]`
+fn help(args: &[&str]) -> String {
+    let mut command_line = vec!["cf-app-log-detector"];
+    command_line.extend_from_slice(args);
+    match crate::app()
+        .set_term_width(WIDTH)
+        .get_matches_from_safe(command_line)
+    {
+        Err(error) if error.kind == ErrorKind::HelpDisplayed => error.message,
+        _ => unreachable!("--help always displays the help"),
+    }
+}
+
+/// `text` as roff, dashes being minus signs so that options can be searched and copied
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lines of a help text from its usage, the name and description being in other sections
+fn usage_and_options(help: &str) -> &str {
+    help.find("USAGE:").map_or(help, |start| &help[start..])
+}
+
+fn page() -> String {
+    let mut page = format!(
+        ".TH CF\\-APP\\-LOG\\-DETECTOR 1 \"\" \"cf\\-app\\-log\\-detector {}\" \"User Commands\"\n",
+        escape(crate_version!())
+    );
+    page.push_str(".SH NAME\ncf\\-app\\-log\\-detector \\- detect and analyze Cloud Foundry application logs\n");
+    page.push_str(".SH SYNOPSIS\n.B cf\\-app\\-log\\-detector\n[\\fIOPTIONS\\fR] \\fISUBCOMMAND\\fR [\\fIOPTIONS\\fR] [\\fILOG\\fR]...\n");
+    page.push_str(
+        ".SH DESCRIPTION\nTell whether files are logs written by the cf CLI (\\fBcf logs\\fR), and \
+         parse, filter, count and analyze their entries. \\fBcf\\-app\\-log\\-detector\\fR \
+         [\\fIOPTIONS\\fR] \\fILOG\\fR is an alias of \\fBcf\\-app\\-log\\-detector detect\\fR.\n",
+    );
+    page.push_str(".PP\n.nf\n");
+    page.push_str(&escape(usage_and_options(&help(&["--help"]))));
+    page.push_str("\n.fi\n.SH SUBCOMMANDS\n");
+    for subcommand in crate::SUBCOMMANDS
+        .iter()
+        .filter(|name| !name.starts_with('-') && **name != "help")
+    {
+        let help = help(&[subcommand, "--help"]);
+        // the first line names the subcommand, the description follows, wrapped
+        let about: Vec<&str> = help
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        page.push_str(&format!(
+            ".SS {}\n{}\n.PP\n.nf\n{}\n.fi\n",
+            escape(subcommand),
+            escape(&about.join(" ")),
+            escape(usage_and_options(&help))
+        ));
+    }
+    page.push_str(&format!(
+        ".SH ENVIRONMENT\n.TP\n.B {}*\nSet options the same way as the top level keys of the \
+         configuration file, e.g. \\fB{}PERCENTAGE_MATCHING=80\\fR.\n.TP\n.B {}CONFIG\n\
+         Configuration file, like \\fB\\-\\-config\\fR.\n.TP\n.B NO_COLOR\n\
+         Disable colors unless \\fB\\-\\-color always\\fR is given.\n.TP\n.B PAGER\n\
+         Pager of the output of \\fBparse\\fR and \\fBfilter\\fR on terminals, \\fBless\\fR by default.\n",
+        escape(ENV_PREFIX),
+        escape(ENV_PREFIX),
+        escape(ENV_PREFIX)
+    ));
+    page.push_str(
+        ".SH FILES\n.TP\n.I ~/.config/cf\\-app\\-log\\-detector/config.toml\n\
+         Defaults of the options, keys being long option names, tables applying to a single \
+         subcommand.\n",
+    );
+    page.push_str(
+        ".SH EXIT STATUS\n.TP\n.B 0\nThe log is a CF application log.\n.TP\n.B 1\n\
+         The log does not look like a CF application log.\n.TP\n.B 2\n\
+         The input could not be read.\n.TP\n.B 3\n\
+         The log is a CF application log in which messages were lost, with \
+         \\fB\\-\\-fail\\-on\\-loss\\fR.\n",
+    );
+    page
+}
+
+pub fn run(_: &ArgMatches) -> i32 {
+    // nothing to report when stdout got closed
+    let _ = io::stdout().write_all(page().as_bytes());
+    0
+}
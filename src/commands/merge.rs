@@ -0,0 +1,54 @@
+use clap::{App, ArgMatches, SubCommand};
+use std::io::{self, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::time::{rewrite_relative, rewrite_timestamp};
+
+use super::{for_each_line, input_args, relative_arg, timezone, timezone_args, write_failed};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("merge")
+        .about("Combine several logs into a single one ordered by timestamp")
+        .args(&timezone_args())
+        .arg(relative_arg())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let mut entries = Vec::new();
+
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            entries.push((entry.timestamp, line.to_string()));
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+    // stable, entries sharing a timestamp keep the order of the inputs
+    entries.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let tz = timezone(matches);
+    let start = match entries.first() {
+        Some((timestamp, _)) if matches.is_present("relative") => Some(*timestamp),
+        _ => None,
+    };
+    for (_, line) in entries {
+        let line = match (tz, start) {
+            (Some(tz), _) => rewrite_timestamp(&line, tz),
+            (_, Some(start)) => rewrite_relative(&line, start),
+            _ => line.into(),
+        };
+        if let Err(error) = writeln!(out, "{}", line) {
+            return write_failed(&error, None);
+        }
+    }
+    match out.flush() {
+        Ok(()) => 0,
+        Err(error) => write_failed(&error, None),
+    }
+}
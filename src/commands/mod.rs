@@ -0,0 +1,487 @@
+//! Subcommands of the CLI, each one exposes its clap definition and a `run` function
+//! returning the process exit code
+
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use clap::{value_t, Arg, ArgMatches};
+use regex::Regex;
+use std::env;
+use std::io;
+use std::sync::Arc;
+
+use cf_app_log_detector::color::ColorChoice;
+use cf_app_log_detector::filter::{parse_instances, parse_time, EntryFilter, CHANNELS, COMPONENTS};
+use cf_app_log_detector::formats::{LogFormatDetector, Registry};
+use cf_app_log_detector::input::{self, Lines};
+use cf_app_log_detector::is_timestamp_format;
+use cf_app_log_detector::level::{SeverityMapping, LEVELS};
+use cf_app_log_detector::loggregator::Reassembler;
+
+pub mod audit;
+pub mod check;
+pub mod completions;
+pub mod crashes;
+pub mod detect;
+pub mod doctor;
+pub mod export;
+pub mod filter;
+pub mod forward;
+pub mod gaps;
+pub mod identify;
+pub mod man;
+pub mod merge;
+mod pager;
+pub mod parse;
+pub mod query;
+pub mod redact;
+pub mod report;
+pub mod requests;
+pub mod restarts;
+pub mod split;
+pub mod ssh;
+pub mod staging;
+pub mod stats;
+pub mod tail;
+pub mod trace;
+pub mod view;
+
+/// Parse a size in bytes with an optional K, M or G (power of 1024) suffix
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match size.chars().last() {
+        Some('K') | Some('k') => (&size[..size.len() - 1], 1024),
+        Some('M') | Some('m') => (&size[..size.len() - 1], 1024 * 1024),
+        Some('G') | Some('g') => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size: {}", size))
+}
+
+/// Validate a non negative number, e.g. a count of entries
+pub fn is_count(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("invalid number: {}", value))
+}
+
+/// Arguments shared by the subcommands reading log entries
+pub fn input_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("max_line_length")
+            .value_name("SIZE")
+            .long("max-line-length")
+            .help("Truncate lines longer than SIZE bytes")
+            .takes_value(true)
+            .validator(|v| parse_size(&v).map(|_| ()))
+            .default_value("1M"),
+        Arg::with_name("log")
+            .value_name("LOG")
+            .help("Log files, - reads stdin")
+            .multiple(true)
+            .default_value("-")
+            .takes_value(true),
+    ]
+}
+
+/// Arguments of the subcommands writing lines or entries telling where they come from
+pub fn origin_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("line_numbers")
+            .short("n")
+            .long("line-numbers")
+            .help("Tell the number of the line each output comes from"),
+        Arg::with_name("with_filename")
+            .short("H")
+            .long("with-filename")
+            .help("Tell the file each output comes from"),
+    ]
+}
+
+/// Arguments of the subcommands writing entry timestamps, see `timezone`
+pub fn timezone_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("utc")
+            .long("utc")
+            .help("Write timestamps in UTC, same as --tz UTC"),
+        Arg::with_name("tz")
+            .value_name("TZ")
+            .long("tz")
+            .help("Write timestamps in this IANA time zone, e.g. Asia/Tokyo")
+            .takes_value(true)
+            .conflicts_with("utc")
+            .validator(|v| {
+                v.parse::<Tz>()
+                    .map(|_| ())
+                    .map_err(|_| format!("unknown time zone: {}", v))
+            }),
+    ]
+}
+
+/// Argument of the subcommands writing lines, rewriting their timestamp relative to the first
+/// entry
+pub fn relative_arg() -> Arg<'static, 'static> {
+    Arg::with_name("relative")
+        .long("relative")
+        .help("Write timestamps as the time elapsed since the first entry, e.g. +00:03:12.480")
+        .conflicts_with_all(&["utc", "tz"])
+}
+
+/// Argument of the subcommands writing lines, joining back the messages Loggregator split
+pub fn reassemble_arg() -> Arg<'static, 'static> {
+    Arg::with_name("reassemble")
+        .long("reassemble")
+        .help("Join back the messages split by Loggregator over consecutive entries (over 60K)")
+}
+
+/// Argument of the subcommands writing colors, see `color`
+pub fn color_arg() -> Arg<'static, 'static> {
+    Arg::with_name("color")
+        .value_name("WHEN")
+        .long("color")
+        .help("Color the output, auto doing it on terminals unless the NO_COLOR environment variable is set")
+        .takes_value(true)
+        .possible_values(&["auto", "always", "never"])
+        .default_value("auto")
+}
+
+/// Whether to color an output given `color_arg` and whether the output is a terminal
+pub fn color(matches: &ArgMatches, is_terminal: bool) -> bool {
+    value_t!(matches, "color", ColorChoice)
+        .unwrap()
+        .enabled(is_terminal, env::var_os("NO_COLOR").as_deref())
+}
+
+/// Argument of the subcommands writing formats with a severity, e.g. syslog
+pub fn severity_mapping_arg() -> Arg<'static, 'static> {
+    Arg::with_name("severity_mapping")
+        .value_name("MAPPING")
+        .long("severity-mapping")
+        .help("Override how severities are told, e.g. err=info,debug=info: out and err for the entries without a level, level names for the levels of APP messages")
+        .takes_value(true)
+        .validator(|v| v.parse::<SeverityMapping>().map(|_| ()))
+}
+
+/// Mapping given by `severity_mapping_arg`, the default one otherwise
+pub fn severity_mapping(matches: &ArgMatches) -> SeverityMapping {
+    matches
+        .value_of("severity_mapping")
+        .map_or_else(SeverityMapping::default, |mapping| mapping.parse().unwrap())
+}
+
+/// Argument of the subcommands telling the log format of lines, see `registry`
+pub fn timestamp_format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("timestamp_format")
+        .value_name("FORMAT")
+        .long("timestamp-format")
+        .help("Also take the lines whose timestamp is written in FORMAT (strftime syntax, e.g. \"%Y-%m-%d %H:%M:%S%.f %z\", UTC without %z) as entries")
+        .takes_value(true)
+        .validator(|format| is_timestamp_format(&format))
+}
+
+/// Formats lines can be in, the CF ones also taking the timestamp format given by
+/// `timestamp_format_arg`, followed by the envelopes of the configuration file
+pub fn registry(matches: &ArgMatches) -> Registry {
+    let mut registry = Registry::default();
+    if let Some(format) = matches.value_of("timestamp_format") {
+        registry = registry.with_timestamp_format(format);
+    }
+    if let Some((_, config)) = crate::read_config(matches) {
+        for envelope in config.envelopes() {
+            registry.register(envelope.clone());
+        }
+    }
+    registry
+}
+
+/// Format given by `--log-format`, `None` after telling the formats known when there is none of
+/// that name
+pub fn log_format(matches: &ArgMatches) -> Option<Arc<dyn LogFormatDetector>> {
+    let registry = registry(matches);
+    let name = matches.value_of("log_format").unwrap();
+    let format = registry.get(name);
+    if format.is_none() {
+        eprintln!(
+            "Unknown log format {}, known formats: {}",
+            name,
+            registry.names().join(", ")
+        );
+    }
+    format
+}
+
+/// Time zone timestamps are written in, the one of each entry when `None`
+pub fn timezone(matches: &ArgMatches) -> Option<Tz> {
+    if matches.is_present("utc") {
+        return Some(Tz::UTC);
+    }
+    matches.value_of("tz").map(|tz| tz.parse().unwrap())
+}
+
+/// Arguments of the subcommands selecting entries on their fields
+pub fn entry_filter_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("component")
+        .value_name("COMPONENTS")
+        .long("component")
+        .help(
+            "Only keep the entries of these comma separated components, other matches unknown ones",
+        )
+        .takes_value(true)
+        .use_delimiter(true)
+        .case_insensitive(true)
+        .possible_values(COMPONENTS),
+        Arg::with_name("channel")
+            .value_name("CHANNELS")
+            .long("channel")
+            .help("Only keep the entries written to these comma separated channels, e.g. err")
+            .takes_value(true)
+            .use_delimiter(true)
+            .case_insensitive(true)
+            .possible_values(CHANNELS),
+        Arg::with_name("instance")
+            .value_name("INSTANCES")
+            .long("instance")
+            .help("Only keep the entries of these instance indexes, e.g. 3 or 0-2,5")
+            .takes_value(true)
+            .validator(|v| parse_instances(&v).map(|_| ())),
+        Arg::with_name("match")
+            .value_name("REGEX")
+            .long("match")
+            .help("Only keep the entries whose message matches REGEX")
+            .takes_value(true)
+            .validator(|v| Regex::new(&v).map(|_| ()).map_err(|e| e.to_string())),
+        Arg::with_name("exclude")
+            .value_name("REGEX")
+            .long("exclude")
+            .help("Leave out the entries whose message matches REGEX")
+            .takes_value(true)
+            .validator(|v| Regex::new(&v).map(|_| ()).map_err(|e| e.to_string())),
+        Arg::with_name("since")
+            .value_name("TIME")
+            .long("since")
+            .help("Only keep the entries logged from TIME, a RFC 3339 date or a duration ago, e.g. 15m")
+            .takes_value(true)
+            .validator(|v| parse_time(&v, now()).map(|_| ())),
+        Arg::with_name("until")
+            .value_name("TIME")
+            .long("until")
+            .help("Only keep the entries logged up to TIME, a RFC 3339 date or a duration ago, e.g. 1h")
+            .takes_value(true)
+            .validator(|v| parse_time(&v, now()).map(|_| ())),
+        Arg::with_name("min_level")
+            .value_name("LEVEL")
+            .long("min-level")
+            .help("Only keep the APP entries whose message tells a level at least as severe, e.g. warn")
+            .takes_value(true)
+            .possible_values(LEVELS)
+            .case_insensitive(true),
+        Arg::with_name("drop_health_checks")
+            .long("drop-health-checks")
+            .help("Leave out the health checks: requests of health checkers or to health endpoints, e.g. /health, and the app entries telling them"),
+    ]
+}
+
+fn now() -> DateTime<FixedOffset> {
+    Utc::now().into()
+}
+
+/// Filter built from the arguments of `entry_filter_args`
+pub fn entry_filter(matches: &ArgMatches) -> EntryFilter {
+    let mut filter = EntryFilter::new();
+    if let Some(components) = matches.values_of("component") {
+        filter = filter.with_components(&components.collect::<Vec<_>>());
+    }
+    if let Some(channels) = matches.values_of("channel") {
+        filter = filter.with_channels(&channels.collect::<Vec<_>>());
+    }
+    if let Some(instances) = matches.value_of("instance") {
+        filter = filter.with_instances(parse_instances(instances).unwrap());
+    }
+    if let Some(regex) = matches.value_of("match") {
+        filter = filter.with_match(Regex::new(regex).unwrap());
+    }
+    if let Some(regex) = matches.value_of("exclude") {
+        filter = filter.with_exclude(Regex::new(regex).unwrap());
+    }
+    let now = now();
+    if let Some(since) = matches.value_of("since") {
+        filter = filter.with_since(parse_time(since, now).unwrap());
+    }
+    if let Some(until) = matches.value_of("until") {
+        filter = filter.with_until(parse_time(until, now).unwrap());
+    }
+    if let Some(min_level) = matches.value_of("min_level") {
+        filter = filter.with_min_level(min_level.parse().unwrap());
+    }
+    if matches.is_present("drop_health_checks") {
+        filter = filter.with_drop_health_checks();
+    }
+    filter
+}
+
+/// Call `f` with the name of the input and each of its lines, for every input given on the
+/// command line. `f` returns false to stop reading (e.g. stdout got closed). Returns the exit
+/// code: 0 when every input could be read.
+pub fn for_each_line<F>(matches: &ArgMatches, mut f: F) -> i32
+where
+    F: FnMut(&str, &str) -> bool,
+{
+    for_each_numbered_line(matches, |filename, _, line| f(filename, line))
+}
+
+/// Same as `for_each_line`, `f` also getting the number of the line in its input, from 1
+pub fn for_each_numbered_line<F>(matches: &ArgMatches, mut f: F) -> i32
+where
+    F: FnMut(&str, u64, &str) -> bool,
+{
+    let filenames: Vec<&str> = matches.values_of("log").unwrap().collect();
+    for_each_input_line(matches, |input, line_number, line| {
+        f(filenames[input], line_number, line)
+    })
+}
+
+/// Same as `for_each_numbered_line`, the input being given by its position on the command line,
+/// telling apart an input given twice
+pub fn for_each_input_line<F>(matches: &ArgMatches, mut f: F) -> i32
+where
+    F: FnMut(usize, u64, &str) -> bool,
+{
+    let max_line_length =
+        parse_size(matches.value_of("max_line_length").unwrap()).unwrap() as usize;
+
+    let reassemble = matches.is_present("reassemble");
+
+    for (input, filename) in matches.values_of("log").unwrap().enumerate() {
+        let mut line_number = 0;
+        let mut reassembler = Reassembler::default();
+        let mut emit = |line_number, line: &str| f(input, line_number, line);
+        let read = read_lines(filename, max_line_length, &mut |line| {
+            line_number += 1;
+            if reassemble {
+                reassembler.push(line_number, line, &mut emit)
+            } else {
+                emit(line_number, line)
+            }
+        });
+        // the message of the last line may have been truncated
+        let read = read.map(|more| more && reassembler.finish(&mut emit));
+        match read {
+            Ok(true) => {}
+            Ok(false) => return 0,
+            Err(code) => return code,
+        }
+    }
+    0
+}
+
+/// Print how many lines `counted` returns true for, per input as `NAME:COUNT` when several are
+/// given. Returns the exit code like `for_each_line`.
+pub fn count_lines<F>(matches: &ArgMatches, mut counted: F) -> i32
+where
+    F: FnMut(&str) -> bool,
+{
+    let max_line_length =
+        parse_size(matches.value_of("max_line_length").unwrap()).unwrap() as usize;
+    let inputs: Vec<_> = matches.values_of("log").unwrap().collect();
+
+    let reassemble = matches.is_present("reassemble");
+
+    for filename in &inputs {
+        let mut count = 0;
+        let mut reassembler = Reassembler::default();
+        let mut emit = |_, line: &str| {
+            if counted(line) {
+                count += 1;
+            }
+            true
+        };
+        let mut line_number = 0;
+        let read = read_lines(filename, max_line_length, &mut |line| {
+            line_number += 1;
+            if reassemble {
+                reassembler.push(line_number, line, &mut emit)
+            } else {
+                emit(line_number, line)
+            }
+        });
+        reassembler.finish(&mut emit);
+        if let Err(code) = read {
+            return code;
+        }
+        if inputs.len() > 1 {
+            println!("{}:{}", filename, count);
+        } else {
+            println!("{}", count);
+        }
+    }
+    0
+}
+
+/// Exit code of a failed write, nothing to report when stdout got closed, e.g. piped into head
+pub fn write_failed(error: &io::Error, output: Option<&str>) -> i32 {
+    match output {
+        None if error.kind() == io::ErrorKind::BrokenPipe => 0,
+        None => {
+            eprintln!("Could not write to stdout: {}", error);
+            2
+        }
+        Some(path) => {
+            eprintln!("Could not write to {}: {}", path, error);
+            2
+        }
+    }
+}
+
+/// Call `f` with each line of the input until it returns false, which is returned. Failures
+/// are reported on stderr and turned into an exit code.
+fn read_lines<F>(filename: &str, max_line_length: usize, f: &mut F) -> Result<bool, i32>
+where
+    F: FnMut(&str) -> bool,
+{
+    let reader = match input::open(filename) {
+        Ok(reader) => reader,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("File {} not found", filename);
+            return Err(1);
+        }
+        Err(e) => {
+            eprintln!("Failed opening file: {}, message: {}", filename, e);
+            return Err(2);
+        }
+    };
+    for line in Lines::new(reader, max_line_length) {
+        match line {
+            Ok(line) => {
+                if !f(&line) {
+                    return Ok(false);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed reading file: {}, message: {}", filename, e);
+                return Err(2);
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4k"), Ok(4 * 1024));
+        assert_eq!(parse_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1.5M"), Err("invalid size: 1.5M".to_string()));
+        assert_eq!(
+            parse_size("99999999999G"),
+            Err("invalid size: 99999999999G".to_string())
+        );
+    }
+}
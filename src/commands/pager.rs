@@ -0,0 +1,67 @@
+use clap::{Arg, ArgMatches};
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+/// Argument of the subcommands writing many lines, see `output`
+pub fn pager_arg() -> Arg<'static, 'static> {
+    Arg::with_name("no_pager")
+        .long("no-pager")
+        .help("Write to the terminal directly instead of going through $PAGER")
+}
+
+/// Standard output, or the input of the pager it got piped into
+pub struct Output {
+    pager: Option<Child>,
+}
+
+/// Like git: on a terminal, unless `--no-pager` is given, pipe the output through `$PAGER`,
+/// `less` by default, with `LESS=FRX` when unset so that short outputs are written as is. An
+/// empty `$PAGER` or `cat` disables it.
+pub fn output(matches: &ArgMatches) -> Output {
+    if matches.is_present("no_pager") || !io::stdout().is_terminal() {
+        return Output { pager: None };
+    }
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let mut words = pager.split_whitespace();
+    let program = match words.next() {
+        Some(program) if program != "cat" => program,
+        _ => return Output { pager: None },
+    };
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    // writing to the terminal directly is better than nothing
+    Output {
+        pager: command.spawn().ok(),
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.pager.as_mut().and_then(|pager| pager.stdin.as_mut()) {
+            Some(stdin) => stdin.write(buf),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.pager.as_mut().and_then(|pager| pager.stdin.as_mut()) {
+            Some(stdin) => stdin.flush(),
+            None => io::stdout().flush(),
+        }
+    }
+}
+
+impl Drop for Output {
+    /// Closing the input of the pager tells it the output is complete, the user then reads it
+    /// until quitting the pager
+    fn drop(&mut self) {
+        if let Some(pager) = &mut self.pager {
+            drop(pager.stdin.take());
+            let _ = pager.wait();
+        }
+    }
+}
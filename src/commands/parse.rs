@@ -0,0 +1,285 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map};
+use std::fs::File;
+use std::io::{self, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::level::level;
+#[cfg(feature = "parquet")]
+use cf_app_log_detector::output::ParquetWriter;
+use cf_app_log_detector::output::{
+    json_column, json_fields, rtr_fields, trace_fields, ApacheCombinedWriter, CefWriter, CsvWriter,
+    EntryWriter, EsBulkWriter, GelfWriter, JsonWriter, LogfmtWriter, SyslogWriter, COLUMNS,
+    RTR_COLUMNS, TRACE_COLUMNS,
+};
+use cf_app_log_detector::rtr::rtr_access_log;
+use cf_app_log_detector::time::in_timezone;
+use cf_app_log_detector::trace::trace_context;
+
+use super::pager::{output, pager_arg};
+use super::{
+    entry_filter, entry_filter_args, for_each_numbered_line, input_args, log_format, origin_args,
+    reassemble_arg, severity_mapping, severity_mapping_arg, timestamp_format_arg, timezone,
+    timezone_args, write_failed,
+};
+
+/// Formats able to add fields to entries, see `EntryWriter::set_origin` and `set_fields`
+const COLUMN_FORMATS: &[&str] = &["json", "csv", "logfmt", "es-bulk"];
+
+#[cfg(feature = "parquet")]
+const FORMATS: &[&str] = &[
+    "json",
+    "csv",
+    "logfmt",
+    "gelf",
+    "cef",
+    "syslog",
+    "es-bulk",
+    "apache-combined",
+    "parquet",
+];
+#[cfg(not(feature = "parquet"))]
+const FORMATS: &[&str] = &[
+    "json",
+    "csv",
+    "logfmt",
+    "gelf",
+    "cef",
+    "syslog",
+    "es-bulk",
+    "apache-combined",
+];
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("parse")
+        .about("Write the lines parsed as CF application log entries in a structured format")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per line (NDJSON)")
+                .takes_value(true)
+                .possible_values(FORMATS)
+                .default_value("json"),
+        )
+        .arg(
+            Arg::with_name("log_format")
+                .value_name("NAME")
+                .long("log-format")
+                .help("Parse the lines in this format instead of the cf CLI one, e.g. docker for the json-file logs of Docker or the name of an envelope of the configuration file")
+                .takes_value(true)
+                .default_value("cf"),
+        )
+        .arg(timestamp_format_arg())
+        .arg(
+            Arg::with_name("columns")
+                .value_name("COLUMNS")
+                .long("columns")
+                .help("Comma separated fields to output, in that order")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(COLUMNS),
+        )
+        .arg(
+            Arg::with_name("index")
+                .value_name("INDEX")
+                .long("index")
+                .help("Elasticsearch index of the es-bulk format")
+                .takes_value(true)
+                .default_value("cf-logs"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .value_name("FILE")
+                .short("o")
+                .long("output")
+                .help("Write to FILE instead of the standard output, required by parquet")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rtr_fields")
+                .long("rtr-fields")
+                .help("Add the fields of the Gorouter access logs of RTR entries, e.g. status and response_time"),
+        )
+        .arg(
+            Arg::with_name("trace_fields")
+                .long("trace-fields")
+                .help("Add the trace_id and span_id written in messages, by Spring Cloud Sleuth or as a W3C traceparent"),
+        )
+        .arg(
+            Arg::with_name("level_field")
+                .long("level-field")
+                .help("Add the level written in the messages of APP entries, e.g. warn, null when there is none"),
+        )
+        .arg(
+            Arg::with_name("json_keys")
+                .value_name("KEYS")
+                .long("json-keys")
+                .help("Add the comma separated top level keys of JSON messages as fields, named json.KEY")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .args(&origin_args())
+        .args(&timezone_args())
+        .arg(severity_mapping_arg())
+        .arg(pager_arg())
+        .arg(reassemble_arg())
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let format = matches.value_of("format").unwrap();
+    let with_filename = matches.is_present("with_filename");
+    let line_numbers = matches.is_present("line_numbers");
+    let with_rtr_fields = matches.is_present("rtr_fields");
+    let with_trace_fields = matches.is_present("trace_fields");
+    let with_level_field = matches.is_present("level_field");
+    let json_keys: Vec<&str> = matches
+        .values_of("json_keys")
+        .map_or_else(Vec::new, |keys| keys.collect());
+    if (with_filename
+        || line_numbers
+        || with_rtr_fields
+        || with_trace_fields
+        || with_level_field
+        || !json_keys.is_empty())
+        && !COLUMN_FORMATS.contains(&format)
+    {
+        eprintln!(
+            "--with-filename, --line-numbers, --rtr-fields, --trace-fields, --level-field and --json-keys only apply to the {} formats",
+            COLUMN_FORMATS.join(", ")
+        );
+        return 2;
+    }
+    let mut columns: Vec<String> = Vec::new();
+    if with_filename {
+        columns.push("file".to_string());
+    }
+    if line_numbers {
+        columns.push("line".to_string());
+    }
+    match matches.values_of("columns") {
+        Some(selected) => columns.extend(selected.map(String::from)),
+        None => columns.extend(COLUMNS.iter().map(|column| column.to_string())),
+    }
+    if with_rtr_fields {
+        columns.extend(RTR_COLUMNS.iter().map(|column| column.to_string()));
+    }
+    if with_trace_fields {
+        columns.extend(TRACE_COLUMNS.iter().map(|column| column.to_string()));
+    }
+    if with_level_field {
+        columns.push("level".to_string());
+    }
+    columns.extend(json_keys.iter().map(|key| json_column(key)));
+    let file = match matches.value_of("output") {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(file),
+            Err(error) => {
+                eprintln!("Could not create {}: {}", path, error);
+                return 2;
+            }
+        },
+        None if format == "parquet" => {
+            eprintln!("The parquet format needs a file to write to, use --output");
+            return 2;
+        }
+        None => None,
+    };
+    let mut writer: Box<dyn EntryWriter> = match format {
+        "csv" => match CsvWriter::new(buffered(file, matches), columns) {
+            Ok(writer) => Box::new(writer),
+            Err(error) => return write_failed(&error, matches.value_of("output")),
+        },
+        #[cfg(feature = "parquet")]
+        "parquet" => match ParquetWriter::new(file.unwrap(), columns) {
+            Ok(writer) => Box::new(writer),
+            Err(error) => {
+                eprintln!("Could not write parquet: {}", error);
+                return 2;
+            }
+        },
+        "logfmt" => Box::new(LogfmtWriter::new(buffered(file, matches), columns)),
+        "gelf" => Box::new(GelfWriter::new(buffered(file, matches))),
+        "cef" => Box::new(CefWriter::new(buffered(file, matches))),
+        "syslog" => Box::new(SyslogWriter::new(buffered(file, matches))),
+        "apache-combined" => Box::new(ApacheCombinedWriter::new(buffered(file, matches))),
+        "es-bulk" => Box::new(EsBulkWriter::new(
+            buffered(file, matches),
+            matches.value_of("index").unwrap(),
+            columns,
+        )),
+        _ => Box::new(JsonWriter::new(buffered(file, matches), columns)),
+    };
+    writer.set_severity_mapping(severity_mapping(matches));
+
+    let log_format = match log_format(matches) {
+        Some(log_format) => log_format,
+        None => return 2,
+    };
+    let filter = entry_filter(matches);
+    let tz = timezone(matches);
+    let mut write_code = None;
+    let code = for_each_numbered_line(matches, |filename, line_number, line| {
+        let line = strip_ansi(line);
+        let owned = log_format.parse_owned(&line);
+        let parsed = match &owned {
+            Some(owned) => Some(owned.entry()),
+            None => log_format.parse(&line),
+        };
+        match parsed {
+            Some(mut entry) if filter.matches(&entry) => {
+                if let Some(tz) = tz {
+                    entry.timestamp = in_timezone(&entry.timestamp, tz);
+                }
+                writer.set_origin(filename, line_number);
+                let mut fields = Map::new();
+                if with_rtr_fields {
+                    if let Some(log) = rtr_access_log(&entry) {
+                        fields.extend(rtr_fields(&log));
+                    }
+                }
+                if with_trace_fields {
+                    if let Some(context) = entry.message.and_then(trace_context) {
+                        fields.extend(trace_fields(&context));
+                    }
+                }
+                if with_level_field {
+                    let level = level(&entry).map(|level| level.as_str());
+                    fields.insert("level".to_string(), json!(level));
+                }
+                if !json_keys.is_empty() {
+                    if let Some(message) = entry.message {
+                        fields.extend(json_fields(message, &json_keys));
+                    }
+                }
+                writer.set_fields(fields);
+                match writer.write_entry(&entry) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        write_code = Some(write_failed(&error, matches.value_of("output")));
+                        false
+                    }
+                }
+            }
+            _ => true,
+        }
+    });
+    if let Some(write_code) = write_code {
+        return write_code;
+    }
+    // finishing writes what is still buffered, e.g. the parquet footer
+    match writer.finish() {
+        Ok(()) => code,
+        Err(error) => write_failed(&error, matches.value_of("output")),
+    }
+}
+
+/// The output file when one was given, the standard output or the pager otherwise
+fn buffered(file: Option<File>, matches: &ArgMatches) -> io::BufWriter<Box<dyn Write>> {
+    match file {
+        Some(file) => io::BufWriter::new(Box::new(file)),
+        None => io::BufWriter::new(Box::new(output(matches))),
+    }
+}
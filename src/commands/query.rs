@@ -0,0 +1,95 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use super::input_args;
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("query")
+        .about("Run a SQL query over the lines parsed as CF application log entries")
+        .after_help(
+            "The entries are in a log table with the columns file, timestamp (UTC, RFC 3339), \
+             component, \"index\", process_type, channel and message.",
+        )
+        .arg(
+            Arg::with_name("query")
+                .value_name("QUERY")
+                .help("SQL query, e.g. SELECT component, count(*) FROM log GROUP BY 1")
+                .required(true),
+        )
+        .args(&input_args())
+}
+
+/// Rows are written tab separated after a header line, NULL values as empty fields
+#[cfg(feature = "sqlite")]
+pub fn run(matches: &ArgMatches) -> i32 {
+    use cf_app_log_detector::output::SqliteWriter;
+    use rusqlite::types::ValueRef;
+    use rusqlite::Connection;
+    use std::io::{self, Write};
+
+    let connection = Connection::open_in_memory().expect("in-memory database");
+    let mut writer = SqliteWriter::new(connection, "log").expect("in-memory table");
+    match super::export::insert_entries(matches, &mut writer) {
+        Ok(0) => (),
+        Ok(code) => return code,
+        Err(error) => {
+            eprintln!("Could not load the entries: {}", error);
+            return 2;
+        }
+    }
+    let connection = writer.into_connection();
+
+    let query = matches.value_of("query").unwrap();
+    let mut statement = match connection.prepare(query) {
+        Ok(statement) => statement,
+        Err(error) => {
+            eprintln!("Invalid query: {}", error);
+            return 2;
+        }
+    };
+    let header = statement.column_names().join("\t");
+    let column_count = statement.column_count();
+    let mut rows = match statement.query([]) {
+        Ok(rows) => rows,
+        Err(error) => {
+            eprintln!("Query failed: {}", error);
+            return 2;
+        }
+    };
+
+    let mut out = io::BufWriter::new(io::stdout());
+    // a failed write means stdout got closed, e.g. piped into head
+    if writeln!(out, "{}", header).is_err() {
+        return 0;
+    }
+    loop {
+        let row = match rows.next() {
+            Ok(Some(row)) => row,
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!("Query failed: {}", error);
+                return 2;
+            }
+        };
+        let fields: Vec<String> = (0..column_count)
+            .map(|i| match row.get_ref_unwrap(i) {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(value) => value.to_string(),
+                ValueRef::Real(value) => value.to_string(),
+                ValueRef::Text(value) | ValueRef::Blob(value) => {
+                    String::from_utf8_lossy(value).into_owned()
+                }
+            })
+            .collect();
+        if writeln!(out, "{}", fields.join("\t")).is_err() {
+            return 0;
+        }
+    }
+    let _ = out.flush();
+    0
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn run(_matches: &ArgMatches) -> i32 {
+    eprintln!("SQL queries are not available, rebuild with --features sqlite");
+    2
+}
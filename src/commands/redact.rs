@@ -0,0 +1,100 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs;
+use std::io::{self, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::redact::{parse_rules, Redactor, Rule, RULES};
+
+use super::{for_each_line, input_args, write_failed};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("redact")
+        .about("Write the lines with IPs, emails, GUIDs, tokens and card numbers masked in messages, e.g. to attach them to a public ticket")
+        .arg(
+            Arg::with_name("keep")
+                .value_name("RULES")
+                .long("keep")
+                .help("Comma separated kinds of values not to mask, e.g. ip")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(RULES),
+        )
+        .arg(
+            Arg::with_name("redact_config")
+                .value_name("FILE")
+                .long("redact-config")
+                .help("Also mask what the NAME=REGEX=>REPLACEMENT lines of FILE match")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("redact_pattern")
+                .value_name("NAME=REGEX=>REPLACEMENT")
+                .long("redact-pattern")
+                .help("Also mask what REGEX matches with REPLACEMENT, which may refer to its groups, e.g. 'employee=EMP-\\d{6}=><employee>'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| v.parse::<Rule>().map(|_| ())),
+        )
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let mut redactor = Redactor::new();
+    for name in matches.values_of("keep").into_iter().flatten() {
+        redactor = redactor.without(name);
+    }
+    if let Some(path) = matches.value_of("redact_config") {
+        let rules = fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| parse_rules(&content));
+        match rules {
+            Ok(rules) => {
+                for rule in rules {
+                    redactor = redactor.with_rule(rule);
+                }
+            }
+            Err(error) => {
+                eprintln!("Could not read the redaction rules of {}: {}", path, error);
+                return 2;
+            }
+        }
+    }
+    for spec in matches.values_of("redact_pattern").into_iter().flatten() {
+        redactor = redactor.with_rule(spec.parse().unwrap());
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut write_code = None;
+    let code = for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        // the envelope of entries is kept as is, lines which are not entries are masked whole
+        let message = match parse_cf_app_log(&line) {
+            Ok((_, entry)) => entry.message.filter(|message| line.ends_with(message)),
+            Err(_) => Some(&line[..]),
+        };
+        let written = match message {
+            Some(message) => {
+                let envelope = &line[..line.len() - message.len()];
+                writeln!(out, "{}{}", envelope, redactor.redact(message))
+            }
+            None => writeln!(out, "{}", line),
+        };
+        match written {
+            Ok(()) => true,
+            Err(error) => {
+                write_code = Some(write_failed(&error, None));
+                false
+            }
+        }
+    });
+    if let Some(write_code) = write_code {
+        return write_code;
+    }
+    match out.flush() {
+        Ok(()) => code,
+        Err(error) => write_failed(&error, None),
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+
+use cf_app_log_detector::crash::{container_start, crash, out_of_memory};
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::level::{level, Level};
+use cf_app_log_detector::loggregator::loss_warning;
+use cf_app_log_detector::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+mod html;
+mod markdown;
+
+use super::doctor::{doctor_args, Doctor};
+use super::{for_each_numbered_line, input_args};
+
+/// Bucket lengths of the histogram in seconds, the shortest one giving at most
+/// `MAX_BUCKETS` buckets being used
+const BUCKETS: &[i64] = &[1, 10, 60, 300, 900, 3600, 21600, 86400];
+const MAX_BUCKETS: i64 = 60;
+/// Most events kept for the timeline, the first ones
+const MAX_EVENTS: usize = 500;
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("report")
+        .about("Write the stats and the findings of doctor as a report, e.g. to attach to an incident review")
+        .arg(
+            Arg::with_name("html")
+                .value_name("FILE")
+                .long("html")
+                .help("Write the report to FILE as a standalone HTML page")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("markdown")
+                .long("markdown")
+                .help("Write the verdict and the findings as Markdown, e.g. to paste into a ticket comment"),
+        )
+        .group(
+            ArgGroup::with_name("output")
+                .args(&["html", "markdown"])
+                .multiple(true)
+                .required(true),
+        )
+        .args(&doctor_args())
+        .args(&input_args())
+}
+
+/// Entry telling something happened to an instance
+pub struct Event {
+    pub timestamp: DateTime<FixedOffset>,
+    /// instance, e.g. `APP/web/0`
+    pub source: String,
+    /// e.g. `crash` or `start`
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Counts of the entries, and the events they tell
+#[derive(Default)]
+pub struct Summary {
+    /// timestamps of the first and last entries
+    pub period: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    pub components: BTreeMap<String, u64>,
+    pub channels: BTreeMap<String, u64>,
+    pub instances: BTreeMap<String, u64>,
+    pub levels: BTreeMap<Level, u64>,
+    /// entries per second since the epoch
+    per_second: HashMap<i64, u64>,
+    /// in the order of the inputs, up to `MAX_EVENTS`
+    pub events: Vec<Event>,
+    /// events left out of `events`
+    pub more_events: u64,
+}
+
+impl Summary {
+    fn record(&mut self, entry: &CfAppLogEntry) {
+        let (component, source) = match &entry.component {
+            ComponentInfoValid::Valid(info) => (info.name.as_str().to_string(), info.instance()),
+            ComponentInfoValid::Invalid(raw) => (raw.clone(), raw.clone()),
+        };
+        *self.components.entry(component).or_insert(0) += 1;
+        *self.instances.entry(source.clone()).or_insert(0) += 1;
+        let channel = match &entry.channel {
+            ChannelValid::Valid(channel) => channel.as_str().to_string(),
+            ChannelValid::Invalid(raw) => raw.clone(),
+        };
+        *self.channels.entry(channel).or_insert(0) += 1;
+        if let Some(level) = level(entry) {
+            *self.levels.entry(level).or_insert(0) += 1;
+        }
+        let timestamp = entry.timestamp;
+        self.period = Some(match self.period {
+            Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+            None => (timestamp, timestamp),
+        });
+        *self
+            .per_second
+            .entry(entry.timestamp.timestamp())
+            .or_insert(0) += 1;
+
+        let kind = if out_of_memory(entry) {
+            "out of memory"
+        } else if crash(entry).is_some() {
+            "crash"
+        } else if container_start(entry).is_some() {
+            "start"
+        } else if loss_warning(entry).is_some() {
+            "message loss"
+        } else {
+            return;
+        };
+        if self.events.len() >= MAX_EVENTS {
+            self.more_events += 1;
+            return;
+        }
+        self.events.push(Event {
+            timestamp: entry.timestamp,
+            source,
+            kind,
+            message: entry.message.unwrap_or("").to_string(),
+        });
+    }
+
+    /// Entries per bucket, by the start of the bucket in seconds since the epoch, empty buckets
+    /// included, and the length of the buckets
+    pub fn histogram(&self) -> (Vec<(i64, u64)>, i64) {
+        let (first, last) = match (self.per_second.keys().min(), self.per_second.keys().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return (Vec::new(), BUCKETS[0]),
+        };
+        let bucket = BUCKETS
+            .iter()
+            .copied()
+            .find(|bucket| (last - first) / bucket < MAX_BUCKETS)
+            .unwrap_or(BUCKETS[BUCKETS.len() - 1]);
+        let mut counts: BTreeMap<i64, u64> = BTreeMap::new();
+        for (second, count) in &self.per_second {
+            *counts
+                .entry(second.div_euclid(bucket) * bucket)
+                .or_insert(0) += count;
+        }
+        let start = first.div_euclid(bucket) * bucket;
+        let histogram = (start..=last)
+            .step_by(bucket as usize)
+            .map(|start| (start, counts.get(&start).copied().unwrap_or(0)))
+            .collect();
+        (histogram, bucket)
+    }
+}
+
+pub fn timestamp(timestamp: &DateTime<FixedOffset>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let mut doctor = Doctor::new(matches);
+    let mut summary = Summary::default();
+    let code = for_each_numbered_line(matches, |filename, line_number, line| {
+        let line = strip_ansi(line);
+        if let Some(entry) = doctor.record(filename, line_number, &line) {
+            summary.record(&entry);
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+    let diagnosis = doctor.diagnosis();
+    let inputs: Vec<&str> = matches.values_of("log").unwrap().collect();
+
+    if let Some(path) = matches.value_of("html") {
+        if let Err(error) = fs::write(path, html::render(&inputs, &diagnosis, &summary)) {
+            eprintln!("Could not write {}: {}", path, error);
+            return 2;
+        }
+    }
+    if matches.is_present("markdown") {
+        // nothing to report when stdout got closed
+        let _ = io::stdout().write_all(markdown::render(&inputs, &diagnosis, &summary).as_bytes());
+    }
+    0
+}
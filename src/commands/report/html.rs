@@ -0,0 +1,174 @@
+//! Standalone HTML page of `report --html`, styles included so that it can be attached as is
+
+use chrono::{TimeZone, Utc};
+use std::fmt::Write;
+
+use super::super::doctor::Diagnosis;
+use super::{timestamp, Summary};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+th,td{border:1px solid #ccc;padding:.25em .6em;text-align:left;vertical-align:top}\
+td.count{text-align:right}\
+.critical{background:#fdd}.warning{background:#ffd}.info{background:#eef}\
+.bar{background:#4a7fc1;height:1em}\
+.tables{display:flex;flex-wrap:wrap;gap:2em}\
+code{white-space:pre-wrap;word-break:break-all}";
+
+/// `text` with the characters special to HTML escaped
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Table of the entries counted per `title`, e.g. per component
+fn write_counts<'a>(
+    html: &mut String,
+    title: &str,
+    counts: impl Iterator<Item = (String, &'a u64)>,
+) {
+    let _ = write!(
+        html,
+        "<table><tr><th>{}</th><th>entries</th></tr>",
+        escape(title)
+    );
+    for (key, count) in counts {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"count\">{}</td></tr>",
+            escape(&key),
+            count
+        );
+    }
+    html.push_str("</table>");
+}
+
+pub fn render(inputs: &[&str], diagnosis: &Diagnosis, summary: &Summary) -> String {
+    // writing to a String does not fail
+    let mut html = String::new();
+    let title = format!("CF application log report: {}", inputs.join(", "));
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>\n<h1>{}</h1>\n",
+        escape(&title),
+        STYLE,
+        escape(&title)
+    );
+    let _ = write!(
+        html,
+        "<p>{} entries of {} lines",
+        diagnosis.entries, diagnosis.lines
+    );
+    if let Some((first, last)) = &summary.period {
+        let _ = write!(
+            html,
+            ", from {} to {}",
+            escape(&timestamp(first)),
+            escape(&timestamp(last))
+        );
+    }
+    html.push_str(".</p>\n");
+
+    html.push_str(
+        "<h2>Findings</h2>\n<table><tr><th>severity</th><th>check</th><th>finding</th></tr>",
+    );
+    for finding in &diagnosis.findings {
+        let _ = write!(
+            html,
+            "<tr class=\"{severity}\"><td>{severity}</td><td>{}</td><td>{}</td></tr>",
+            escape(finding.check),
+            escape(&finding.message),
+            severity = finding.severity.as_str()
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Entries</h2>\n<div class=\"tables\">");
+    write_counts(
+        &mut html,
+        "component",
+        summary.components.iter().map(|(k, v)| (k.clone(), v)),
+    );
+    write_counts(
+        &mut html,
+        "channel",
+        summary.channels.iter().map(|(k, v)| (k.clone(), v)),
+    );
+    write_counts(
+        &mut html,
+        "instance",
+        summary.instances.iter().map(|(k, v)| (k.clone(), v)),
+    );
+    if !summary.levels.is_empty() {
+        write_counts(
+            &mut html,
+            "level",
+            summary.levels.iter().map(|(k, v)| (k.to_string(), v)),
+        );
+    }
+    html.push_str("</div>\n");
+
+    let (histogram, bucket) = summary.histogram();
+    if !histogram.is_empty() {
+        let highest = histogram
+            .iter()
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let _ = write!(
+            html,
+            "<h2>Entries per {}s</h2>\n<table><tr><th>start (UTC)</th><th>entries</th><th style=\"width:50%\"></th></tr>",
+            bucket
+        );
+        for (start, count) in &histogram {
+            let start = Utc
+                .timestamp_opt(*start, 0)
+                .unwrap()
+                .format("%Y-%m-%dT%H:%M:%SZ");
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td class=\"count\">{}</td><td><div class=\"bar\" style=\"width:{:.1}%\"></div></td></tr>",
+                start,
+                count,
+                *count as f64 * 100.0 / highest as f64
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Timeline</h2>\n");
+    if summary.events.is_empty() {
+        html.push_str("<p>No crash, restart or message loss.</p>\n");
+    } else {
+        let mut events: Vec<_> = summary.events.iter().collect();
+        events.sort_by_key(|event| event.timestamp);
+        html.push_str("<table><tr><th>time</th><th>source</th><th>event</th><th>message</th></tr>");
+        for event in events {
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>",
+                escape(&timestamp(&event.timestamp)),
+                escape(&event.source),
+                escape(event.kind),
+                escape(&event.message)
+            );
+        }
+        html.push_str("</table>\n");
+        if summary.more_events > 0 {
+            let _ = writeln!(html, "<p>{} more events left out.</p>", summary.more_events);
+        }
+    }
+    html.push_str("</body></html>\n");
+    html
+}
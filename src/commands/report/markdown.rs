@@ -0,0 +1,84 @@
+//! Summary of `report --markdown`, to paste into a GitHub or Jira comment
+
+use std::fmt::Write;
+
+use super::super::doctor::Diagnosis;
+use super::{timestamp, Summary};
+
+/// Most events listed, the earliest ones, a ticket comment being no place for the whole timeline
+const MAX_EVENTS: usize = 10;
+
+/// `text` fit for a table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// `text` fit for an inline code span, which has no escapes
+fn code(text: &str) -> String {
+    text.replace('`', "'")
+}
+
+pub fn render(inputs: &[&str], diagnosis: &Diagnosis, summary: &Summary) -> String {
+    // writing to a String does not fail
+    let mut markdown = String::from("## CF application log report\n\n");
+    let _ = writeln!(markdown, "- **Inputs:** {}", inputs.join(", "));
+    let _ = writeln!(
+        markdown,
+        "- **Verdict:** {}, {} of {} lines are entries",
+        if diagnosis.is_log {
+            "CF application log"
+        } else {
+            "not a CF application log"
+        },
+        diagnosis.entries,
+        diagnosis.lines
+    );
+    if let Some((first, last)) = &summary.period {
+        let _ = writeln!(
+            markdown,
+            "- **Period:** {} to {}",
+            timestamp(first),
+            timestamp(last)
+        );
+    }
+    if !summary.components.is_empty() {
+        let components: Vec<_> = summary
+            .components
+            .iter()
+            .map(|(component, count)| format!("{} {}", component, count))
+            .collect();
+        let _ = writeln!(markdown, "- **Entries:** {}", components.join(", "));
+    }
+
+    markdown.push_str("\n| severity | check | finding |\n| --- | --- | --- |\n");
+    for finding in &diagnosis.findings {
+        let _ = writeln!(
+            markdown,
+            "| {} | {} | {} |",
+            finding.severity.as_str(),
+            finding.check,
+            cell(&finding.message)
+        );
+    }
+
+    if !summary.events.is_empty() {
+        let mut events: Vec<_> = summary.events.iter().collect();
+        events.sort_by_key(|event| event.timestamp);
+        markdown.push_str("\n### Timeline\n\n");
+        for event in events.iter().take(MAX_EVENTS) {
+            let _ = writeln!(
+                markdown,
+                "- `{}` {} {}: `{}`",
+                timestamp(&event.timestamp),
+                event.source,
+                event.kind,
+                code(&event.message)
+            );
+        }
+        let more = (events.len() - events.len().min(MAX_EVENTS)) as u64 + summary.more_events;
+        if more > 0 {
+            let _ = writeln!(markdown, "- and {} more events", more);
+        }
+    }
+    markdown
+}
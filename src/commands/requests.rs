@@ -0,0 +1,182 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use regex::Regex;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::rtr::rtr_access_log;
+use cf_app_log_detector::trace::trace_context;
+use cf_app_log_detector::{parse_cf_app_log, Channel, ChannelValid, Component, ComponentInfoValid};
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("requests")
+        .about("Write one summary per HTTP request, joining its Gorouter access log with the app entries of its trace")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::with_name("errors")
+                .long("errors")
+                .help("Only write the requests answered with a 5xx response, or whose app entries tell an error"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// App entries sharing a trace id
+struct Span {
+    first: DateTime<FixedOffset>,
+    last: DateTime<FixedOffset>,
+    entries: u64,
+    errors: u64,
+}
+
+/// Request of a Gorouter access log
+struct Request {
+    timestamp: DateTime<FixedOffset>,
+    method: String,
+    path: String,
+    status: u16,
+    response_time: Option<f64>,
+    vcap_request_id: Option<String>,
+    trace_id: Option<String>,
+}
+
+/// App messages telling an error, besides the ones written to ERR
+fn is_error(message: &str) -> bool {
+    static ERROR: OnceLock<Regex> = OnceLock::new();
+    ERROR
+        .get_or_init(|| Regex::new(r"\b(ERROR|FATAL)\b|Exception\b").unwrap())
+        .is_match(message)
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let filter = entry_filter(matches);
+    let mut requests = Vec::new();
+    let mut spans: HashMap<String, Span> = HashMap::new();
+    let code = for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        let entry = match parse_cf_app_log(&line) {
+            Ok((_, entry)) if filter.matches(&entry) => entry,
+            _ => return true,
+        };
+        if let Some(log) = rtr_access_log(&entry) {
+            requests.push(Request {
+                timestamp: entry.timestamp,
+                method: log.method.to_string(),
+                path: log.path.to_string(),
+                status: log.status,
+                response_time: log.response_time(),
+                vcap_request_id: log.vcap_request_id().map(String::from),
+                trace_id: log.b3_trace_id().map(str::to_ascii_lowercase),
+            });
+            return true;
+        }
+        match &entry.component {
+            ComponentInfoValid::Valid(info) if info.name == Component::APPLICATION => {}
+            _ => return true,
+        }
+        let message = entry.message.unwrap_or("");
+        if let Some(context) = trace_context(message) {
+            let error =
+                matches!(entry.channel, ChannelValid::Valid(Channel::STDERR)) || is_error(message);
+            let span = spans.entry(context.trace_id.to_string()).or_insert(Span {
+                first: entry.timestamp,
+                last: entry.timestamp,
+                entries: 0,
+                errors: 0,
+            });
+            span.first = span.first.min(entry.timestamp);
+            span.last = span.last.max(entry.timestamp);
+            span.entries += 1;
+            span.errors += error as u64;
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let json = matches.value_of("format") == Some("json");
+    let errors_only = matches.is_present("errors");
+    for request in &requests {
+        let span = request.trace_id.as_ref().and_then(|id| spans.get(id));
+        let mut flags = Vec::new();
+        if request.status >= 500 {
+            flags.push("5xx");
+        }
+        if span.is_some_and(|span| span.errors > 0) {
+            flags.push("app errors");
+        }
+        if errors_only && flags.is_empty() {
+            continue;
+        }
+        let app_span = span.map(|span| (span.last - span.first).num_milliseconds() as f64 / 1000.0);
+        let written = if json {
+            writeln!(
+                out,
+                "{}",
+                json!({
+                    "timestamp": request.timestamp.to_rfc3339_opts(SecondsFormat::Millis, false),
+                    "method": request.method,
+                    "path": request.path,
+                    "status": request.status,
+                    "response_time": request.response_time,
+                    "app_entries": span.map_or(0, |span| span.entries),
+                    "app_errors": span.map_or(0, |span| span.errors),
+                    "app_span": app_span,
+                    "vcap_request_id": request.vcap_request_id,
+                    "trace_id": request.trace_id,
+                    "flags": flags,
+                })
+            )
+        } else {
+            let response_time = request
+                .response_time
+                .map_or("-".to_string(), |time| format!("{}s", time));
+            let app = match (span, app_span) {
+                (Some(span), Some(app_span)) => {
+                    format!("{} app entries over {}s", span.entries, app_span)
+                }
+                _ => "no app entries".to_string(),
+            };
+            let flags = if flags.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", flags.join(", "))
+            };
+            writeln!(
+                out,
+                "{} {} {} {} {}, {}{} {}",
+                request
+                    .timestamp
+                    .to_rfc3339_opts(SecondsFormat::Millis, false),
+                request.method,
+                request.path,
+                request.status,
+                response_time,
+                app,
+                flags,
+                request.vcap_request_id.as_deref().unwrap_or("-")
+            )
+        };
+        // a failed write means stdout got closed, e.g. piped into head
+        if written.is_err() {
+            break;
+        }
+    }
+    0
+}
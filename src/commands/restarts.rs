@@ -0,0 +1,106 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use cf_app_log_detector::crash::{container_start, restart_loops};
+use cf_app_log_detector::filter::parse_duration;
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args, is_count};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("restarts")
+        .about("Count the restarts of each app instance told by CELL entries, and flag the restart loops")
+        .arg(
+            Arg::with_name("max")
+                .value_name("N")
+                .long("max")
+                .help("Flag the instances starting more than N times within --window")
+                .takes_value(true)
+                .validator(is_count)
+                .default_value("3"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .value_name("DURATION")
+                .long("window")
+                .help("Period the starts of a restart loop fall in, e.g. 10m")
+                .takes_value(true)
+                .validator(|v| parse_duration(&v).map(|_| ()))
+                .default_value("10m"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per instance and line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let max = matches.value_of("max").unwrap().parse::<usize>().unwrap();
+    let window = parse_duration(matches.value_of("window").unwrap()).unwrap();
+    let filter = entry_filter(matches);
+    // times each instance started at
+    let mut starts = BTreeMap::new();
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if let (true, Some(index)) = (filter.matches(&entry), container_start(&entry)) {
+                starts
+                    .entry(index)
+                    .or_insert_with(Vec::new)
+                    .push(entry.timestamp);
+            }
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let json = matches.value_of("format") == Some("json");
+    let format =
+        |timestamp: &DateTime<FixedOffset>| timestamp.to_rfc3339_opts(SecondsFormat::Millis, false);
+    for (index, starts) in &mut starts {
+        // inputs are not necessarily in order, e.g. several captures
+        starts.sort();
+        let loops = restart_loops(starts, max, window);
+        // the first start is not a restart
+        let restarts = starts.len() - 1;
+        if json {
+            println!(
+                "{}",
+                json!({
+                    "index": index,
+                    "restarts": restarts,
+                    "loops": loops
+                        .iter()
+                        .map(|restart_loop| json!({
+                            "first": format(&restart_loop.first),
+                            "last": format(&restart_loop.last),
+                            "starts": restart_loop.starts,
+                        }))
+                        .collect::<Vec<_>>(),
+                })
+            );
+            continue;
+        }
+        println!("instance {}: {} restarts", index, restarts);
+        for restart_loop in loops {
+            println!(
+                "  restart loop: {} starts from {} to {}",
+                restart_loop.starts,
+                format(&restart_loop.first),
+                format(&restart_loop.last)
+            );
+        }
+    }
+    0
+}
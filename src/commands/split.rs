@@ -0,0 +1,176 @@
+use chrono::Utc;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::{parse_cf_app_log, CfAppLogEntry, Component, ComponentInfoValid};
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args, parse_size};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("split")
+        .about("Write the lines which are CF application log entries into one file per component, instance or time bucket")
+        .arg(
+            Arg::with_name("by")
+                .value_name("KEY")
+                .long("by")
+                .help("What entries are split on, component writes e.g. RTR.log and APP.log, instance APP-0.log, hour and day 2021-09-28T08.log and 2021-09-28.log (UTC)")
+                .takes_value(true)
+                .possible_values(&["component", "instance", "hour", "day"])
+                .default_value("component"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .value_name("DIR")
+                .short("o")
+                .long("output")
+                .help("Directory to write the files to, created when missing")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("max_size")
+                .value_name("SIZE")
+                .long("max-size")
+                .help("Start a new file, e.g. APP.1.log after APP.log, rather than going over SIZE bytes (K, M and G suffixes are supported)")
+                .takes_value(true)
+                .validator(|v| parse_size(&v).map(|_| ())),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// File the entries of a key are written to, after `rotations` others got full
+struct Output {
+    file: io::BufWriter<File>,
+    path: PathBuf,
+    written: u64,
+    rotations: u32,
+}
+
+impl Output {
+    fn create(
+        directory: &Path,
+        name: &str,
+        rotations: u32,
+    ) -> Result<Output, (PathBuf, io::Error)> {
+        let path = if rotations == 0 {
+            directory.join(format!("{}.log", name))
+        } else {
+            directory.join(format!("{}.{}.log", name, rotations))
+        };
+        match File::create(&path) {
+            Ok(file) => Ok(Output {
+                file: io::BufWriter::new(file),
+                path,
+                written: 0,
+                rotations,
+            }),
+            Err(error) => Err((path, error)),
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let directory = Path::new(matches.value_of("output").unwrap());
+    if let Err(error) = fs::create_dir_all(directory) {
+        eprintln!("Could not create {}: {}", directory.display(), error);
+        return 2;
+    }
+    let by = matches.value_of("by").unwrap();
+    let max_size = matches
+        .value_of("max_size")
+        .map(|size| parse_size(size).unwrap());
+    let filter = entry_filter(matches);
+    let mut outputs: HashMap<String, Output> = HashMap::new();
+    let mut failure = None;
+
+    let code = for_each_line(matches, |_, line| {
+        let stripped = strip_ansi(line);
+        let entry = match parse_cf_app_log(&stripped) {
+            Ok((_, entry)) if filter.matches(&entry) => entry,
+            _ => return true,
+        };
+        let name = file_name(by, &entry);
+        let length = line.len() as u64 + 1;
+        let rotations = match outputs.get(&name) {
+            None => Some(0),
+            // a line larger than the limit still gets a file of its own
+            Some(output)
+                if output.written > 0
+                    && max_size.is_some_and(|max_size| output.written + length > max_size) =>
+            {
+                Some(output.rotations + 1)
+            }
+            Some(_) => None,
+        };
+        if let Some(rotations) = rotations {
+            if let Some(mut previous) = outputs.remove(&name) {
+                if let Err(error) = previous.file.flush() {
+                    failure = Some((previous.path, error));
+                    return false;
+                }
+            }
+            match Output::create(directory, &name, rotations) {
+                Ok(output) => {
+                    outputs.insert(name.clone(), output);
+                }
+                Err(error) => {
+                    failure = Some(error);
+                    return false;
+                }
+            }
+        }
+        let output = outputs.get_mut(&name).unwrap();
+        // lines are written as they are, envelope included
+        if let Err(error) = writeln!(output.file, "{}", line) {
+            failure = Some((output.path.clone(), error));
+            return false;
+        }
+        output.written += length;
+        true
+    });
+    let failure = failure.or_else(|| {
+        outputs.values_mut().find_map(|output| {
+            output
+                .file
+                .flush()
+                .err()
+                .map(|error| (output.path.clone(), error))
+        })
+    });
+    if let Some((path, error)) = failure {
+        eprintln!("Could not write to {}: {}", path.display(), error);
+        return 2;
+    }
+    code
+}
+
+/// Name of the file an entry is written to, without extension. Entries of components which are
+/// not known go to `other`. By instance, the component is kept in the name since instance
+/// indexes restart from 0 for each component, e.g. `APP-0` and `RTR-0`. By hour or day, the
+/// UTC date of the entry is used, e.g. `2021-09-28T08` or `2021-09-28`.
+fn file_name(by: &str, entry: &CfAppLogEntry) -> String {
+    let component = match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name != Component::INVALID => Some(info),
+        _ => None,
+    };
+    match (by, component) {
+        ("hour", _) => entry
+            .timestamp
+            .with_timezone(&Utc)
+            .format("%Y-%m-%dT%H")
+            .to_string(),
+        ("day", _) => entry
+            .timestamp
+            .with_timezone(&Utc)
+            .format("%Y-%m-%d")
+            .to_string(),
+        ("instance", Some(info)) => format!("{}-{}", info.name.as_str(), info.index),
+        (_, Some(info)) => info.name.as_str().to_string(),
+        (_, None) => "other".to_string(),
+    }
+}
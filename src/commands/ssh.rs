@@ -0,0 +1,106 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::ssh::{ssh_event, SshEvent};
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("ssh")
+        .about("Write the SSH sessions to app instances told by SSH entries, e.g. for a security review")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per session and line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Session of a client on an instance, `end` is `None` while it is still open
+struct Session {
+    index: u32,
+    address: String,
+    start: Option<DateTime<FixedOffset>>,
+    end: Option<DateTime<FixedOffset>>,
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let filter = entry_filter(matches);
+    let mut sessions: Vec<Session> = Vec::new();
+    let code = for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        let entry = match parse_cf_app_log(&line) {
+            Ok((_, entry)) if filter.matches(&entry) => entry,
+            _ => return true,
+        };
+        match ssh_event(&entry) {
+            Some((index, SshEvent::Start(address))) => sessions.push(Session {
+                index,
+                address: address.to_string(),
+                start: Some(entry.timestamp),
+                end: None,
+            }),
+            Some((index, SshEvent::End(address))) => {
+                // the latest session of the client still open on the instance
+                let open = sessions.iter_mut().rev().find(|session| {
+                    session.index == index && session.address == address && session.end.is_none()
+                });
+                match open {
+                    Some(session) => session.end = Some(entry.timestamp),
+                    // started before the capture
+                    None => sessions.push(Session {
+                        index,
+                        address: address.to_string(),
+                        start: None,
+                        end: Some(entry.timestamp),
+                    }),
+                }
+            }
+            None => {}
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let format = |timestamp: Option<DateTime<FixedOffset>>| {
+        timestamp.map(|timestamp| timestamp.to_rfc3339_opts(SecondsFormat::Millis, false))
+    };
+    for session in &sessions {
+        let duration = match (session.start, session.end) {
+            (Some(start), Some(end)) => Some((end - start).num_milliseconds() as f64 / 1000.0),
+            _ => None,
+        };
+        if matches.value_of("format") == Some("json") {
+            println!(
+                "{}",
+                json!({
+                    "index": session.index,
+                    "address": session.address,
+                    "start": format(session.start),
+                    "end": format(session.end),
+                    "duration": duration,
+                })
+            );
+            continue;
+        }
+        println!(
+            "instance {} {} from {} to {}{}",
+            session.index,
+            session.address,
+            format(session.start).unwrap_or_else(|| "?".to_string()),
+            format(session.end).unwrap_or_else(|| "?".to_string()),
+            duration.map_or(String::new(), |duration| format!(" ({}s)", duration))
+        );
+    }
+    0
+}
@@ -0,0 +1,86 @@
+use chrono::{Duration, SecondsFormat};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map, Value};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::staging::StagingTimeline;
+
+use super::{entry_filter, entry_filter_args, for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("staging")
+        .about("Write how long the stagings told by STG entries took, phase by phase, and the buildpacks they used")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, json writes one object per staging and line (NDJSON)")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Seconds, with milliseconds
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let filter = entry_filter(matches);
+    let mut timeline = StagingTimeline::new();
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if filter.matches(&entry) {
+                timeline.record(&entry);
+            }
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let json = matches.value_of("format") == Some("json");
+    for staging in &timeline.stagings {
+        let start = staging.start.to_rfc3339_opts(SecondsFormat::Millis, false);
+        if json {
+            let phases = staging
+                .phase_durations()
+                .iter()
+                .map(|(phase, duration)| (phase.as_str().to_string(), json!(seconds(*duration))))
+                .collect::<Map<String, Value>>();
+            println!(
+                "{}",
+                json!({
+                    "start": start,
+                    "duration": seconds(staging.duration()),
+                    "complete": staging.complete,
+                    "buildpacks": staging.buildpacks,
+                    "phases": phases,
+                })
+            );
+            continue;
+        }
+        println!(
+            "staging at {}: {}s{}",
+            start,
+            seconds(staging.duration()),
+            if staging.complete {
+                ""
+            } else {
+                " (incomplete)"
+            }
+        );
+        if !staging.buildpacks.is_empty() {
+            println!("  buildpacks: {}", staging.buildpacks.join(", "));
+        }
+        for (phase, duration) in staging.phase_durations() {
+            println!("  {}: {}s", phase.as_str(), seconds(duration));
+        }
+    }
+    0
+}
@@ -0,0 +1,669 @@
+use chrono::{DateTime, Duration, FixedOffset, SecondsFormat, TimeZone, Utc};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cf_app_log_detector::balance::{imbalance, Imbalance};
+use cf_app_log_detector::crash::{crash, out_of_memory};
+use cf_app_log_detector::filter::parse_duration;
+use cf_app_log_detector::framework::{framework, Framework};
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::level::{level, Level};
+use cf_app_log_detector::metrics::label_value;
+use cf_app_log_detector::rtr::rtr_access_log;
+use cf_app_log_detector::templates::message_template;
+use cf_app_log_detector::time::inner_timestamp;
+use cf_app_log_detector::timeline::Regressions;
+use cf_app_log_detector::{parse_cf_app_log, ChannelValid, Component, ComponentInfoValid};
+use serde_json::json;
+
+mod delay;
+mod http;
+pub(super) mod skew;
+use self::delay::DelayStats;
+use self::http::HttpStats;
+use self::skew::SkewStats;
+
+use super::{
+    count_lines, entry_filter, entry_filter_args, for_each_numbered_line, input_args, is_count,
+};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("stats")
+        .about("Count the lines which are CF application log entries")
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, prometheus is the text exposition format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "prometheus"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .short("c")
+                .long("count")
+                .help("Only write the number of selected entries, per file when several are given"),
+        )
+        .arg(
+            Arg::with_name("histogram")
+                .value_name("DURATION")
+                .long("histogram")
+                .help("Only write the number of selected entries per bucket of DURATION, e.g. 1m")
+                .takes_value(true)
+                .validator(|v| match parse_duration(&v) {
+                    Ok(duration) if duration <= Duration::zero() => {
+                        Err(format!("empty duration: {}", v))
+                    }
+                    duration => duration.map(|_| ()),
+                }),
+        )
+        .arg(
+            Arg::with_name("top")
+                .value_name("N")
+                .long("top")
+                .help("Only write the N instances with the most selected entries")
+                .takes_value(true)
+                .validator(is_count)
+                .conflicts_with("histogram"),
+        )
+        .arg(
+            Arg::with_name("message_prefix")
+                .value_name("CHARS")
+                .long("message-prefix")
+                .help("Rank instances and the first CHARS characters of their messages together with --top")
+                .takes_value(true)
+                .validator(is_count)
+                .requires("top"),
+        )
+        .arg(
+            Arg::with_name("templates")
+                .value_name("N")
+                .long("templates")
+                .help("Only write the N most frequent message templates, numbers and ids being masked")
+                .takes_value(true)
+                .validator(is_count)
+                .conflicts_with_all(&["histogram", "top"]),
+        )
+        .arg(
+            Arg::with_name("http")
+                .long("http")
+                .help("Only write the requests of the Gorouter access logs per status, and the paths failing the most")
+                .conflicts_with_all(&["histogram", "top", "templates"]),
+        )
+        .arg(
+            Arg::with_name("latency_by")
+                .value_name("GROUP")
+                .long("latency-by")
+                .help("Also write the latencies of --http per path or per app process")
+                .takes_value(true)
+                .possible_values(&["path", "process"])
+                .requires("http"),
+        )
+        .arg(
+            Arg::with_name("logging_delay")
+                .long("logging-delay")
+                .help("Only write how long after the timestamps written at the beginning of APP messages their entries got logged")
+                .conflicts_with_all(&["histogram", "top", "templates", "http"]),
+        )
+        .arg(
+            Arg::with_name("clock_skew")
+                .long("clock-skew")
+                .help("Only write how far the clocks of the cells are from the ones of the routers, from the app entries telling the ids of requests")
+                .conflicts_with_all(&["histogram", "top", "templates", "http", "logging_delay"]),
+        )
+        .arg(
+            Arg::with_name("bars")
+                .long("bars")
+                .help("Draw the histogram as a bar chart")
+                .requires("histogram"),
+        )
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Volume of entries of a component over time
+struct Throughput {
+    first: DateTime<FixedOffset>,
+    last: DateTime<FixedOffset>,
+    count: u64,
+    /// entries per second since the epoch, inputs not being necessarily ordered
+    per_second: HashMap<i64, u64>,
+}
+
+impl Throughput {
+    fn new(timestamp: DateTime<FixedOffset>) -> Throughput {
+        Throughput {
+            first: timestamp,
+            last: timestamp,
+            count: 0,
+            per_second: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, timestamp: DateTime<FixedOffset>) {
+        self.first = self.first.min(timestamp);
+        self.last = self.last.max(timestamp);
+        self.count += 1;
+        *self.per_second.entry(timestamp.timestamp()).or_insert(0) += 1;
+    }
+
+    /// Lines per second over the seconds from the first entry to the last one, both included
+    fn average(&self) -> f64 {
+        let seconds = self.last.timestamp() - self.first.timestamp() + 1;
+        let average = self.count as f64 / seconds as f64;
+        (average * 100.0).round() / 100.0
+    }
+
+    /// Lines of the busiest second
+    fn peak(&self) -> u64 {
+        self.per_second.values().copied().max().unwrap_or(0)
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let mut total_lines = 0;
+    let mut matching_lines = 0;
+    let mut components: BTreeMap<String, u64> = BTreeMap::new();
+    let mut channels: BTreeMap<String, u64> = BTreeMap::new();
+    let mut instances: BTreeMap<String, u64> = BTreeMap::new();
+    let mut throughputs: BTreeMap<String, Throughput> = BTreeMap::new();
+    let bucket = matches
+        .value_of("histogram")
+        .map(|duration| parse_duration(duration).unwrap().num_seconds());
+    // entries per bucket, by the start of the bucket in seconds since the epoch
+    let mut histogram: BTreeMap<i64, u64> = BTreeMap::new();
+    let count = |name| matches.value_of(name).map(|v| v.parse::<usize>().unwrap());
+    let top = count("top");
+    let message_prefix = count("message_prefix");
+    // entries per instance, or per instance and message prefix
+    let mut sources: HashMap<String, u64> = HashMap::new();
+    let templates = count("templates");
+    let mut http = if matches.is_present("http") {
+        Some(HttpStats::new(matches.value_of("latency_by")))
+    } else {
+        None
+    };
+    let mut delay = if matches.is_present("logging_delay") {
+        Some(DelayStats::default())
+    } else {
+        None
+    };
+    let mut skew = if matches.is_present("clock_skew") {
+        Some(SkewStats::default())
+    } else {
+        None
+    };
+    let mut template_counts: HashMap<String, u64> = HashMap::new();
+    // entries telling an instance crashed, and ran out of memory
+    let mut crashes = 0;
+    let mut out_of_memory_entries = 0;
+    // APP entries, and those of them written by each logging framework
+    let mut app_entries = 0;
+    let mut frameworks: HashMap<Framework, u64> = HashMap::new();
+    // APP entries per level written in their message
+    let mut levels: BTreeMap<Level, u64> = BTreeMap::new();
+    // entries per UTC offset in seconds, overall and per file since concatenating the logs of
+    // several foundations mixes them
+    let mut offsets: BTreeMap<i32, u64> = BTreeMap::new();
+    let mut file_offsets: BTreeMap<String, BTreeSet<i32>> = BTreeMap::new();
+    // APP entries of the web process and requests routed per instance index
+    let mut app_load: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut request_load: BTreeMap<u32, u64> = BTreeMap::new();
+    // entries logged before the previous one of their file
+    let mut regressions = Regressions::default();
+
+    let filter = entry_filter(matches);
+    if matches.is_present("count") {
+        return count_lines(matches, |line| match parse_cf_app_log(&strip_ansi(line)) {
+            Ok((_, entry)) => filter.matches(&entry),
+            Err(_) => false,
+        });
+    }
+    let code = for_each_numbered_line(matches, |filename, line_number, line| {
+        total_lines += 1;
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if !filter.matches(&entry) {
+                return true;
+            }
+            matching_lines += 1;
+            let timestamp = entry.timestamp;
+            let offset = timestamp.offset().local_minus_utc();
+            *offsets.entry(offset).or_insert(0) += 1;
+            file_offsets
+                .entry(filename.to_string())
+                .or_default()
+                .insert(offset);
+            regressions.record(filename, line_number, timestamp);
+            if let Some(log) = rtr_access_log(&entry) {
+                if let Some(index) = log.app_index() {
+                    *request_load.entry(index).or_insert(0) += 1;
+                }
+                if let Some(http) = &mut http {
+                    http.record(&log);
+                }
+                if let Some(skew) = &mut skew {
+                    skew.record_request(&log, timestamp);
+                }
+            }
+            if crash(&entry).is_some() {
+                crashes += 1;
+            }
+            if out_of_memory(&entry) {
+                out_of_memory_entries += 1;
+            }
+            if let ComponentInfoValid::Valid(info) = &entry.component {
+                if info.name == Component::APPLICATION {
+                    if let Some(delay) = &mut delay {
+                        let inner = entry
+                            .message
+                            .and_then(|message| inner_timestamp(message, &timestamp));
+                        delay.record(&info.instance(), &timestamp, inner);
+                    }
+                    if let Some(skew) = &mut skew {
+                        let message = entry.message.unwrap_or("");
+                        skew.record_app_entry(&info.instance(), timestamp, message);
+                    }
+                    app_entries += 1;
+                    // routes lead to the web process, the others run on their own
+                    if info
+                        .process_type
+                        .as_deref()
+                        .is_none_or(|process| process == "web")
+                    {
+                        *app_load.entry(info.index).or_insert(0) += 1;
+                    }
+                    if let Some(framework) = entry.message.and_then(framework) {
+                        *frameworks.entry(framework).or_insert(0) += 1;
+                    }
+                }
+            }
+            if let Some(level) = level(&entry) {
+                *levels.entry(level).or_insert(0) += 1;
+            }
+            if templates.is_some() {
+                let template = message_template(entry.message.unwrap_or(""));
+                *template_counts.entry(template).or_insert(0) += 1;
+            }
+            if let Some(bucket) = bucket {
+                let start = timestamp.timestamp().div_euclid(bucket) * bucket;
+                *histogram.entry(start).or_insert(0) += 1;
+            }
+            let (component, source) = match entry.component {
+                ComponentInfoValid::Valid(info) => {
+                    let instance = info.instance();
+                    *instances.entry(instance.clone()).or_insert(0) += 1;
+                    (info.name.as_str().to_string(), instance)
+                }
+                ComponentInfoValid::Invalid(raw) => (raw.clone(), raw),
+            };
+            if top.is_some() {
+                let source = match message_prefix {
+                    Some(length) => {
+                        let message = entry.message.unwrap_or("");
+                        let prefix: String = message.chars().take(length).collect();
+                        format!("{} {}", source, prefix)
+                    }
+                    None => source,
+                };
+                *sources.entry(source).or_insert(0) += 1;
+            }
+            throughputs
+                .entry(component.clone())
+                .or_insert_with(|| Throughput::new(timestamp))
+                .record(timestamp);
+            *components.entry(component).or_insert(0) += 1;
+            let channel = match entry.channel {
+                ChannelValid::Valid(channel) => channel.as_str().to_string(),
+                ChannelValid::Invalid(raw) => raw,
+            };
+            *channels.entry(channel).or_insert(0) += 1;
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    if let Some(bucket) = bucket {
+        write_histogram(&histogram, bucket, matches.is_present("bars"));
+        return 0;
+    }
+    if let Some(top) = top {
+        write_top(sources, top, matching_lines);
+        return 0;
+    }
+    if let Some(templates) = templates {
+        write_top(template_counts, templates, matching_lines);
+        return 0;
+    }
+    if let Some(http) = http {
+        http.write(matches.value_of("format").unwrap());
+        return 0;
+    }
+    if let Some(delay) = delay {
+        delay.write(matches.value_of("format").unwrap());
+        return 0;
+    }
+    if let Some(skew) = skew {
+        skew.write(matches.value_of("format").unwrap());
+        return 0;
+    }
+
+    let offsets: BTreeMap<String, u64> = offsets
+        .into_iter()
+        .map(|(offset, count)| (utc_offset(offset), count))
+        .collect();
+    // files whose entries carry more than one UTC offset, which confuses timelines
+    let mixed_offsets: BTreeMap<String, Vec<String>> = file_offsets
+        .into_iter()
+        .filter(|(_, offsets)| offsets.len() > 1)
+        .map(|(filename, offsets)| (filename, offsets.into_iter().map(utc_offset).collect()))
+        .collect();
+    // the framework which wrote the most APP entries, ties going to the first one
+    let framework = frameworks
+        .into_iter()
+        .max_by_key(|&(framework, entries)| (entries, std::cmp::Reverse(framework)));
+    // period the selected entries cover, the first and last timestamps of all the components
+    let period = throughputs
+        .values()
+        .map(|throughput| (throughput.first, throughput.last))
+        .reduce(|(first, last), (other_first, other_last)| {
+            (first.min(other_first), last.max(other_last))
+        });
+    let imbalances: Vec<(&str, Imbalance)> =
+        [("APP lines", &app_load), ("requests", &request_load)]
+            .iter()
+            .filter_map(|(load, counts)| Some((*load, imbalance(counts)?)))
+            .collect();
+    let percentage_matching = if total_lines > 0 {
+        (matching_lines as f64 / total_lines as f64 * 100.0).floor()
+    } else {
+        0.0
+    };
+    if matches.value_of("format") == Some("prometheus") {
+        println!("# HELP cf_app_log_lines_total Lines read.");
+        println!("# TYPE cf_app_log_lines_total counter");
+        println!("cf_app_log_lines_total {}", total_lines);
+        println!("# HELP cf_app_log_entries_total Lines which are CF application log entries.");
+        println!("# TYPE cf_app_log_entries_total counter");
+        for (component, count) in &components {
+            println!(
+                "cf_app_log_entries_total{{component=\"{}\"}} {}",
+                label_value(component),
+                count
+            );
+        }
+        println!("# HELP cf_app_log_crashes_total Entries telling an app instance crashed.");
+        println!("# TYPE cf_app_log_crashes_total counter");
+        println!("cf_app_log_crashes_total {}", crashes);
+        println!(
+            "# HELP cf_app_log_out_of_memory_total Entries telling an app instance ran out of memory."
+        );
+        println!("# TYPE cf_app_log_out_of_memory_total counter");
+        println!("cf_app_log_out_of_memory_total {}", out_of_memory_entries);
+        println!("# HELP cf_app_log_matching_ratio Share of the lines which are entries.");
+        println!("# TYPE cf_app_log_matching_ratio gauge");
+        println!(
+            "cf_app_log_matching_ratio {}",
+            if total_lines > 0 {
+                matching_lines as f64 / total_lines as f64
+            } else {
+                0.0
+            }
+        );
+        return 0;
+    }
+    if matches.value_of("format") == Some("json") {
+        println!(
+            "{}",
+            json!({
+                "total_lines": total_lines,
+                "matching_lines": matching_lines,
+                "percentage_matching": percentage_matching,
+                "components": components,
+                "channels": channels,
+                "instances": instances,
+                "crashes": crashes,
+                "out_of_memory": out_of_memory_entries,
+                "utc_offsets": offsets,
+                "mixed_utc_offsets": mixed_offsets,
+                "timestamp_regressions": {
+                    "count": regressions.count,
+                    "largest": regressions.largest.as_ref().map(|largest| json!({
+                        "file": largest.filename,
+                        "line": largest.line_number,
+                        "seconds": seconds(largest.duration),
+                    })),
+                },
+                "levels": levels
+                    .iter()
+                    .map(|(level, count)| (level.as_str().to_string(), json!(count)))
+                    .collect::<serde_json::Map<_, _>>(),
+                "framework": framework.map(|(framework, entries)| json!({
+                    "name": framework.as_str(),
+                    "entries": entries,
+                    "app_entries": app_entries,
+                })),
+                "period": period.map(|(first, last)| json!({
+                    "first": timestamp(&first),
+                    "last": timestamp(&last),
+                    "seconds": seconds(last - first),
+                })),
+                "component_periods": throughputs
+                    .iter()
+                    .map(|(component, throughput)| {
+                        let period = json!({
+                            "first": timestamp(&throughput.first),
+                            "last": timestamp(&throughput.last),
+                            "seconds": seconds(throughput.last - throughput.first),
+                            "entries": throughput.count,
+                        });
+                        (component.clone(), period)
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+                "load": {
+                    "app_lines": app_load,
+                    "requests": request_load,
+                    "imbalances": imbalances
+                        .iter()
+                        .map(|(load, imbalance)| json!({
+                            "of": load,
+                            "index": imbalance.index,
+                            "share": imbalance.share,
+                            "instances": imbalance.instances,
+                        }))
+                        .collect::<Vec<_>>(),
+                },
+                "throughput": throughputs
+                    .iter()
+                    .map(|(component, throughput)| {
+                        let rates = json!({
+                            "average": throughput.average(),
+                            "peak": throughput.peak(),
+                        });
+                        (component.clone(), rates)
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+            })
+        );
+        return 0;
+    }
+    println!("total number of lines: {}", total_lines);
+    println!("log lines matching: {}", matching_lines);
+    println!("percentage matching: {}", percentage_matching);
+    if let Some((first, last)) = period {
+        println!(
+            "period: {} to {} ({}s)",
+            timestamp(&first),
+            timestamp(&last),
+            seconds(last - first)
+        );
+    }
+    for (title, counts) in &[
+        ("component", &components),
+        ("channel", &channels),
+        ("instance", &instances),
+        ("utc offset", &offsets),
+    ] {
+        if counts.is_empty() {
+            continue;
+        }
+        println!("by {}:", title);
+        for (key, count) in counts.iter() {
+            println!("  {}: {}", key, count);
+        }
+    }
+    for (title, load) in &[
+        ("APP lines by web instance index", &app_load),
+        ("requests by app instance index", &request_load),
+    ] {
+        if load.len() < 2 {
+            continue;
+        }
+        let total: u64 = load.values().sum();
+        println!("{}:", title);
+        for (index, count) in load.iter() {
+            println!(
+                "  {}: {} ({:.1}%)",
+                index,
+                count,
+                *count as f64 / total as f64 * 100.0
+            );
+        }
+    }
+    for (load, imbalance) in &imbalances {
+        println!(
+            "load imbalance: instance {} got {:.1}% of the {} of {} instances",
+            imbalance.index,
+            imbalance.share * 100.0,
+            load,
+            imbalance.instances
+        );
+    }
+    if !mixed_offsets.is_empty() {
+        println!("files mixing utc offsets:");
+        for (filename, offsets) in &mixed_offsets {
+            println!("  {}: {}", filename, offsets.join(", "));
+        }
+    }
+    if let Some(largest) = &regressions.largest {
+        println!(
+            "timestamps going backwards: {} (up to {}s at {}:{})",
+            regressions.count,
+            seconds(largest.duration),
+            largest.filename,
+            largest.line_number
+        );
+    }
+    if !levels.is_empty() {
+        println!("by level:");
+        for (level, count) in &levels {
+            println!("  {}: {}", level, count);
+        }
+    }
+    if !throughputs.is_empty() {
+        println!("period by component:");
+        for (component, throughput) in &throughputs {
+            println!(
+                "  {}: {} entries from {} to {} ({}s)",
+                component,
+                throughput.count,
+                timestamp(&throughput.first),
+                timestamp(&throughput.last),
+                seconds(throughput.last - throughput.first)
+            );
+        }
+        println!("lines per second (average, peak):");
+        for (component, throughput) in &throughputs {
+            println!(
+                "  {}: {}, {}",
+                component,
+                throughput.average(),
+                throughput.peak()
+            );
+        }
+    }
+    if crashes > 0 || out_of_memory_entries > 0 {
+        println!("crashes: {}", crashes);
+        println!("out of memory: {}", out_of_memory_entries);
+    }
+    if let Some((framework, entries)) = framework {
+        println!(
+            "app log framework: {} ({} of {} APP entries)",
+            framework.as_str(),
+            entries,
+            app_entries
+        );
+    }
+    0
+}
+
+/// Seconds, with milliseconds
+fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+/// RFC 3339 timestamp with milliseconds, in the offset it was logged with
+fn timestamp(timestamp: &DateTime<FixedOffset>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+/// `+09:00` for an offset of 9 hours
+fn utc_offset(seconds: i32) -> String {
+    FixedOffset::east_opt(seconds).unwrap().to_string()
+}
+
+/// Write the number of entries per bucket, empty buckets included so that quiet periods show,
+/// either as `START COUNT` lines or as a bar chart
+fn write_histogram(histogram: &BTreeMap<i64, u64>, bucket: i64, bars: bool) {
+    const BAR_WIDTH: u64 = 50;
+
+    let (first, last) = match (histogram.keys().next(), histogram.keys().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return,
+    };
+    let highest = histogram.values().copied().max().unwrap_or(0);
+    let width = highest.to_string().len();
+    for start in (first..=last).step_by(bucket as usize) {
+        let count = histogram.get(&start).copied().unwrap_or(0);
+        let start = Utc
+            .timestamp_opt(start, 0)
+            .unwrap()
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+        if bars {
+            // a non empty bucket always gets a bar, however small
+            let length = (count * BAR_WIDTH).div_ceil(highest) as usize;
+            println!(
+                "{} {:>width$} {}",
+                start,
+                count,
+                "#".repeat(length),
+                width = width
+            );
+        } else {
+            println!("{} {}", start, count);
+        }
+    }
+}
+
+/// Write the `top` sources (or templates) with the most entries, the most verbose first, with
+/// their share of all the entries
+fn write_top(sources: HashMap<String, u64>, top: usize, entries: u64) {
+    let mut sources: Vec<_> = sources.into_iter().collect();
+    // ties ordered by name to keep the output stable
+    sources.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    sources.truncate(top);
+    let width = sources
+        .first()
+        .map_or(0, |(_, count)| count.to_string().len());
+    for (source, count) in sources {
+        println!(
+            "{:>width$} {:>5.1}% {}",
+            count,
+            count as f64 / entries as f64 * 100.0,
+            source,
+            width = width
+        );
+    }
+}
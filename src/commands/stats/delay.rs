@@ -0,0 +1,87 @@
+//! Delay between the timestamps apps write in their messages and the timestamps of the
+//! envelopes, `stats --logging-delay`
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use super::http::{latencies, PERCENTILES};
+
+#[derive(Default)]
+pub struct DelayStats {
+    app_entries: u64,
+    /// seconds per instance, `all` being every entry with a timestamp
+    delays: BTreeMap<String, Vec<f64>>,
+}
+
+impl DelayStats {
+    /// Account for an APP entry of `instance`, `inner` being the timestamp found in its message
+    pub fn record(
+        &mut self,
+        instance: &str,
+        envelope: &DateTime<FixedOffset>,
+        inner: Option<DateTime<FixedOffset>>,
+    ) {
+        self.app_entries += 1;
+        if let Some(inner) = inner {
+            let delay = envelope.signed_duration_since(inner).num_milliseconds() as f64 / 1000.0;
+            for group in &["all", instance] {
+                self.delays
+                    .entry(group.to_string())
+                    .or_default()
+                    .push(delay);
+            }
+        }
+    }
+
+    pub fn write(&self, format: &str) {
+        let entries = self.delays.get("all").map_or(0, Vec::len);
+        let delays = latencies(&self.delays);
+        match format {
+            "json" => println!(
+                "{}",
+                json!({
+                    "app_entries": self.app_entries,
+                    "entries": entries,
+                    "delay": delays
+                        .iter()
+                        .map(|(group, values)| {
+                            let values = values
+                                .iter()
+                                .map(|(name, value)| (name.to_string(), json!(value)))
+                                .collect::<Map<_, _>>();
+                            (group.to_string(), Value::Object(values))
+                        })
+                        .collect::<Map<_, _>>(),
+                })
+            ),
+            "prometheus" => {
+                println!("# HELP cf_app_log_logging_delay_seconds Delay of the envelopes after the timestamps of the app messages.");
+                println!("# TYPE cf_app_log_logging_delay_seconds summary");
+                if let Some((_, values)) = delays.iter().find(|(group, _)| *group == "all") {
+                    for ((_, value), (_, quantile)) in values.iter().zip(PERCENTILES) {
+                        println!(
+                            "cf_app_log_logging_delay_seconds{{quantile=\"{}\"}} {}",
+                            quantile, value
+                        );
+                    }
+                }
+            }
+            _ => {
+                println!(
+                    "entries with a timestamp: {} of {} APP entries",
+                    entries, self.app_entries
+                );
+                if delays.is_empty() {
+                    return;
+                }
+                println!("logging delay in seconds (p50, p90, p99, max):");
+                for (group, values) in &delays {
+                    let values: Vec<_> =
+                        values.iter().map(|(_, value)| value.to_string()).collect();
+                    println!("  {}: {}", group, values.join(", "));
+                }
+            }
+        }
+    }
+}
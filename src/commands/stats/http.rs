@@ -0,0 +1,207 @@
+//! Requests of the Gorouter access logs, `stats --http`
+
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use cf_app_log_detector::rtr::RtrAccessLog;
+
+/// Paths failing the most which are written
+const TOP_FAILING_PATHS: usize = 10;
+
+/// Percentiles of the latencies, besides the maximum
+pub(super) const PERCENTILES: &[(&str, f64)] = &[("p50", 0.5), ("p90", 0.9), ("p99", 0.99)];
+
+#[derive(Default)]
+pub struct HttpStats {
+    /// what latencies are grouped on besides all requests, `path` or `process`
+    latency_by: Option<String>,
+    requests: u64,
+    /// per status class, e.g. `5xx`
+    classes: BTreeMap<String, u64>,
+    statuses: BTreeMap<u16, u64>,
+    /// 5xx responses per path, query strings left out
+    failing_paths: HashMap<String, u64>,
+    /// seconds per group, `all` being every request
+    response_times: BTreeMap<String, Vec<f64>>,
+    gorouter_times: BTreeMap<String, Vec<f64>>,
+}
+
+impl HttpStats {
+    pub fn new(latency_by: Option<&str>) -> HttpStats {
+        HttpStats {
+            latency_by: latency_by.map(String::from),
+            ..HttpStats::default()
+        }
+    }
+
+    pub fn record(&mut self, log: &RtrAccessLog) {
+        self.requests += 1;
+        *self
+            .classes
+            .entry(format!("{}xx", log.status / 100))
+            .or_insert(0) += 1;
+        *self.statuses.entry(log.status).or_insert(0) += 1;
+        let path = log.path.split('?').next().unwrap_or(log.path);
+        if log.status >= 500 {
+            *self.failing_paths.entry(path.to_string()).or_insert(0) += 1;
+        }
+
+        // app processes are told apart by app and instance
+        let group = match self.latency_by.as_deref() {
+            Some("path") => Some(path.to_string()),
+            Some(_) => Some(match (log.app_id(), log.app_index()) {
+                (Some(app_id), Some(index)) => format!("{}/{}", app_id, index),
+                (Some(app_id), None) => app_id.to_string(),
+                _ => "-".to_string(),
+            }),
+            None => None,
+        };
+        for (times, time) in &mut [
+            (&mut self.response_times, log.response_time()),
+            (&mut self.gorouter_times, log.gorouter_time()),
+        ] {
+            if let Some(time) = time {
+                times.entry("all".to_string()).or_default().push(*time);
+                if let Some(group) = &group {
+                    times.entry(group.clone()).or_default().push(*time);
+                }
+            }
+        }
+    }
+
+    /// Paths with the most 5xx responses, the most failing first
+    fn top_failing_paths(&self) -> Vec<(&str, u64)> {
+        let mut paths: Vec<_> = self
+            .failing_paths
+            .iter()
+            .map(|(path, count)| (path.as_str(), *count))
+            .collect();
+        paths.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        paths.truncate(TOP_FAILING_PATHS);
+        paths
+    }
+
+    pub fn write(&self, format: &str) {
+        let latencies = [
+            ("response_time", latencies(&self.response_times)),
+            ("gorouter_time", latencies(&self.gorouter_times)),
+        ];
+        match format {
+            "json" => println!(
+                "{}",
+                json!({
+                    "requests": self.requests,
+                    "classes": self.classes,
+                    "statuses": self.statuses,
+                    "top_failing_paths": self
+                        .top_failing_paths()
+                        .iter()
+                        .map(|(path, count)| json!({ "path": path, "count": count }))
+                        .collect::<Vec<_>>(),
+                    "latency": latencies
+                        .iter()
+                        .map(|(name, groups)| {
+                            let groups = groups
+                                .iter()
+                                .map(|(group, values)| {
+                                    let values = values
+                                        .iter()
+                                        .map(|(name, value)| (name.to_string(), json!(value)))
+                                        .collect::<Map<_, _>>();
+                                    (group.to_string(), Value::Object(values))
+                                })
+                                .collect::<Map<_, _>>();
+                            (name.to_string(), Value::Object(groups))
+                        })
+                        .collect::<Map<_, _>>(),
+                })
+            ),
+            "prometheus" => {
+                println!(
+                    "# HELP cf_app_log_http_requests_total Requests of the router access logs."
+                );
+                println!("# TYPE cf_app_log_http_requests_total counter");
+                for (status, count) in &self.statuses {
+                    println!(
+                        "cf_app_log_http_requests_total{{status=\"{}\"}} {}",
+                        status, count
+                    );
+                }
+                for (name, groups) in &latencies {
+                    let all = match groups.iter().find(|(group, _)| *group == "all") {
+                        Some((_, values)) => values,
+                        None => continue,
+                    };
+                    println!(
+                        "# HELP cf_app_log_http_{}_seconds {} of the router access logs.",
+                        name, name
+                    );
+                    println!("# TYPE cf_app_log_http_{}_seconds summary", name);
+                    for ((_, value), (_, quantile)) in all.iter().zip(PERCENTILES) {
+                        println!(
+                            "cf_app_log_http_{}_seconds{{quantile=\"{}\"}} {}",
+                            name, quantile, value
+                        );
+                    }
+                }
+            }
+            _ => {
+                println!("requests: {}", self.requests);
+                println!("by status class:");
+                for (class, count) in &self.classes {
+                    println!("  {}: {}", class, count);
+                }
+                println!("by status:");
+                for (status, count) in &self.statuses {
+                    println!("  {}: {}", status, count);
+                }
+                let paths = self.top_failing_paths();
+                if !paths.is_empty() {
+                    println!("top failing paths (5xx):");
+                    for (path, count) in paths {
+                        println!("  {}: {}", path, count);
+                    }
+                }
+                for (name, groups) in &latencies {
+                    if groups.is_empty() {
+                        continue;
+                    }
+                    println!("{} in seconds (p50, p90, p99, max):", name);
+                    for (group, values) in groups {
+                        let values: Vec<_> =
+                            values.iter().map(|(_, value)| value.to_string()).collect();
+                        println!("  {}: {}", group, values.join(", "));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Percentiles and maximum of the latencies of each group, `all` first
+pub(super) fn latencies(
+    times: &BTreeMap<String, Vec<f64>>,
+) -> Vec<(&str, Vec<(&'static str, f64)>)> {
+    let mut groups: Vec<_> = times
+        .iter()
+        .map(|(group, times)| {
+            let mut sorted = times.clone();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            let mut values: Vec<_> = PERCENTILES
+                .iter()
+                .map(|(name, percentile)| (*name, nearest_rank(&sorted, *percentile)))
+                .collect();
+            values.push(("max", sorted[sorted.len() - 1]));
+            (group.as_str(), values)
+        })
+        .collect();
+    groups.sort_by_key(|(group, _)| *group != "all");
+    groups
+}
+
+/// Percentile of sorted values, as the smallest value which at least `percentile` of them do
+/// not exceed
+fn nearest_rank(sorted: &[f64], percentile: f64) -> f64 {
+    let rank = (percentile * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
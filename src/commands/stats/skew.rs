@@ -0,0 +1,173 @@
+//! Skew of the clocks of the cells against the ones of the routers, from the timestamps of the
+//! app entries logged while handling requests, `stats --clock-skew`
+
+use chrono::{DateTime, Duration, FixedOffset};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use cf_app_log_detector::rtr::RtrAccessLog;
+use cf_app_log_detector::timeline::clock_skew;
+use cf_app_log_detector::trace::trace_context;
+
+use super::http::{latencies, PERCENTILES};
+
+/// Request as seen by the router
+struct Request {
+    received: DateTime<FixedOffset>,
+    answered: DateTime<FixedOffset>,
+    /// `vcap_request_id` and B3 trace id, either of them being written by the app
+    ids: Vec<String>,
+}
+
+/// App entries telling the same request or trace id
+struct Span {
+    instance: String,
+    first: DateTime<FixedOffset>,
+    last: DateTime<FixedOffset>,
+}
+
+/// Request ids written in app messages, e.g. the `X-Vcap-Request-Id` header
+fn request_ids(message: &str) -> impl Iterator<Item = &str> {
+    static GUID: OnceLock<Regex> = OnceLock::new();
+    GUID.get_or_init(|| {
+        Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b").unwrap()
+    })
+    .find_iter(message)
+    .map(|id| id.as_str())
+}
+
+#[derive(Default)]
+pub struct SkewStats {
+    requests: Vec<Request>,
+    spans: HashMap<String, Span>,
+}
+
+impl SkewStats {
+    /// Account for the access log of a RTR entry logged at `timestamp`, when the request got
+    /// answered unless the log tells its response time
+    pub fn record_request(&mut self, log: &RtrAccessLog, timestamp: DateTime<FixedOffset>) {
+        let received = match log.received_at() {
+            Some(received) => received,
+            None => return,
+        };
+        let answered = log.response_time().map_or(timestamp, |time| {
+            received + Duration::microseconds((time * 1_000_000.0) as i64)
+        });
+        let ids = log
+            .vcap_request_id()
+            .into_iter()
+            .chain(log.b3_trace_id())
+            .map(str::to_ascii_lowercase)
+            .collect();
+        self.requests.push(Request {
+            received,
+            answered,
+            ids,
+        });
+    }
+
+    /// Account for an APP entry of `instance`
+    pub fn record_app_entry(
+        &mut self,
+        instance: &str,
+        timestamp: DateTime<FixedOffset>,
+        message: &str,
+    ) {
+        let trace_id = trace_context(message).map(|context| context.trace_id);
+        for id in request_ids(message).chain(trace_id) {
+            let span = self
+                .spans
+                .entry(id.to_ascii_lowercase())
+                .or_insert_with(|| Span {
+                    instance: instance.to_string(),
+                    first: timestamp,
+                    last: timestamp,
+                });
+            span.first = span.first.min(timestamp);
+            span.last = span.last.max(timestamp);
+        }
+    }
+
+    /// Seconds per instance, `all` being every request joined with app entries
+    fn skews(&self) -> BTreeMap<String, Vec<f64>> {
+        let mut skews: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for request in &self.requests {
+            let span = request.ids.iter().find_map(|id| self.spans.get(id));
+            if let Some(span) = span {
+                let skew = clock_skew(request.received, request.answered, span.first, span.last);
+                for group in &["all", &span.instance] {
+                    skews
+                        .entry(group.to_string())
+                        .or_default()
+                        .push(skew.num_milliseconds() as f64 / 1000.0);
+                }
+            }
+        }
+        skews
+    }
+
+    /// Median skew in seconds over the requests joined with app entries, and their number
+    pub fn median(&self) -> Option<(f64, usize)> {
+        let skews = self.skews();
+        let joined = skews.get("all").map_or(0, Vec::len);
+        let (_, values) = latencies(&skews)
+            .into_iter()
+            .find(|(group, _)| *group == "all")?;
+        Some((values.first()?.1, joined))
+    }
+
+    pub fn write(&self, format: &str) {
+        let skews = self.skews();
+        let joined = skews.get("all").map_or(0, Vec::len);
+        let skews = latencies(&skews);
+        match format {
+            "json" => println!(
+                "{}",
+                json!({
+                    "requests": self.requests.len(),
+                    "joined": joined,
+                    "skew": skews
+                        .iter()
+                        .map(|(group, values)| {
+                            let values = values
+                                .iter()
+                                .map(|(name, value)| (name.to_string(), json!(value)))
+                                .collect::<Map<_, _>>();
+                            (group.to_string(), Value::Object(values))
+                        })
+                        .collect::<Map<_, _>>(),
+                })
+            ),
+            "prometheus" => {
+                println!("# HELP cf_app_log_clock_skew_seconds Skew of the clocks of the cells against the ones of the routers.");
+                println!("# TYPE cf_app_log_clock_skew_seconds summary");
+                if let Some((_, values)) = skews.iter().find(|(group, _)| *group == "all") {
+                    for ((_, value), (_, quantile)) in values.iter().zip(PERCENTILES) {
+                        println!(
+                            "cf_app_log_clock_skew_seconds{{quantile=\"{}\"}} {}",
+                            quantile, value
+                        );
+                    }
+                }
+            }
+            _ => {
+                println!(
+                    "requests joined with app entries: {} of {} requests",
+                    joined,
+                    self.requests.len()
+                );
+                if skews.is_empty() {
+                    return;
+                }
+                println!("clock skew of cells against routers in seconds (p50, p90, p99, max):");
+                for (group, values) in &skews {
+                    let values: Vec<_> =
+                        values.iter().map(|(_, value)| value.to_string()).collect();
+                    println!("  {}: {}", group, values.join(", "));
+                }
+            }
+        }
+    }
+}
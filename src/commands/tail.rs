@@ -0,0 +1,200 @@
+use clap::{value_t, App, Arg, ArgMatches, SubCommand};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, IsTerminal, Seek, Write};
+use std::thread;
+use std::time::Duration;
+
+use cf_app_log_detector::color::{channel_color, component_color, paint, DIM};
+use cf_app_log_detector::filter::EntryFilter;
+use cf_app_log_detector::input::{line_to_str, read_line_capped, strip_ansi};
+use cf_app_log_detector::{parse_cf_app_log, CfAppLogEntry, ComponentInfoValid};
+
+use super::{
+    color, color_arg, entry_filter, entry_filter_args, for_each_line, input_args, is_count,
+    parse_size,
+};
+
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("tail")
+        .about("Write the entries with aligned columns, colored by component and channel on a terminal")
+        .arg(
+            Arg::with_name("follow")
+                .short("f")
+                .long("follow")
+                .help("Keep reading the file as it grows and write the entries appended to it"),
+        )
+        .arg(
+            Arg::with_name("last")
+                .value_name("NUM")
+                .long("last")
+                .help("Only write the last NUM entries, those already in the file with --follow")
+                .takes_value(true)
+                .validator(is_count),
+        )
+        .arg(color_arg())
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Widths of the columns, growing with the widest value written so far
+struct Layout {
+    color: bool,
+    timestamp: usize,
+    envelope: usize,
+}
+
+impl Layout {
+    /// The line of `entry` with its timestamp and envelope as written in it, padded to the
+    /// columns
+    fn format(&mut self, line: &str, entry: &CfAppLogEntry) -> String {
+        let line = line.trim_start_matches(' ');
+        let (timestamp, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let envelope = rest.find(']').map_or(rest, |end| &rest[..=end]);
+        let channel = &rest[envelope.len()..].trim_start()[..3];
+        self.timestamp = self.timestamp.max(timestamp.len());
+        self.envelope = self.envelope.max(envelope.len());
+
+        let timestamp = format!("{:<width$}", timestamp, width = self.timestamp);
+        let envelope = format!("{:<width$}", envelope, width = self.envelope);
+        let mut formatted = if self.color {
+            let component = match &entry.component {
+                ComponentInfoValid::Valid(info) => component_color(&info.name),
+                ComponentInfoValid::Invalid(_) => None,
+            };
+            format!(
+                "{} {}",
+                paint(&timestamp, DIM),
+                component.map_or(envelope.clone(), |color| paint(&envelope, color))
+            )
+        } else {
+            format!("{} {}", timestamp, envelope)
+        };
+        let output = match entry.message {
+            Some(message) => format!("{} {}", channel, message),
+            None => channel.to_string(),
+        };
+        formatted.push(' ');
+        match channel_color(&entry.channel) {
+            Some(color) if self.color => formatted.push_str(&paint(&output, color)),
+            _ => formatted.push_str(&output),
+        }
+        formatted
+    }
+}
+
+/// Writes the selected entries, the last ones kept until the end of the inputs with `--last`
+struct Tail<W: Write> {
+    out: W,
+    layout: Layout,
+    filter: EntryFilter,
+    last: Option<usize>,
+    kept: VecDeque<String>,
+}
+
+impl<W: Write> Tail<W> {
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        let line = strip_ansi(line);
+        let entry = match parse_cf_app_log(&line) {
+            Ok((_, entry)) if self.filter.matches(&entry) => entry,
+            _ => return Ok(()),
+        };
+        let formatted = self.layout.format(&line, &entry);
+        match self.last {
+            Some(last) => {
+                self.kept.push_back(formatted);
+                if self.kept.len() > last {
+                    self.kept.pop_front();
+                }
+                Ok(())
+            }
+            None => writeln!(self.out, "{}", formatted),
+        }
+    }
+
+    /// Write the entries kept for `--last`, the following ones being written as they come
+    fn flush(&mut self) -> io::Result<()> {
+        self.last = None;
+        for line in self.kept.drain(..) {
+            writeln!(self.out, "{}", line)?;
+        }
+        self.out.flush()
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let stdout = io::stdout();
+    let mut tail = Tail {
+        out: io::BufWriter::new(stdout.lock()),
+        layout: Layout {
+            color: color(matches, io::stdout().is_terminal()),
+            timestamp: 0,
+            envelope: 0,
+        },
+        filter: entry_filter(matches),
+        last: if matches.is_present("last") {
+            Some(value_t!(matches, "last", usize).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        },
+        kept: VecDeque::new(),
+    };
+
+    if matches.is_present("follow") {
+        let inputs: Vec<&str> = matches.values_of("log").unwrap().collect();
+        if inputs.len() > 1 || inputs[0] == "-" {
+            eprintln!("--follow is only supported with a single file");
+            return 2;
+        }
+        let max_line_length =
+            parse_size(matches.value_of("max_line_length").unwrap()).unwrap() as usize;
+        return match follow(&mut tail, inputs[0], max_line_length) {
+            Ok(()) => 0,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("File {} not found", inputs[0]);
+                1
+            }
+            // stdout got closed
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+            Err(e) => {
+                eprintln!("Failed reading file: {}, message: {}", inputs[0], e);
+                2
+            }
+        };
+    }
+
+    let code = for_each_line(matches, |_, line| tail.line(line).is_ok());
+    // nothing to report when stdout got closed
+    let _ = tail.flush();
+    code
+}
+
+/// Like `tail -f`: write the entries of the file, then those appended to it, until interrupted
+fn follow<W: Write>(tail: &mut Tail<W>, path: &str, max_line_length: usize) -> io::Result<()> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+    let mut line = Vec::new();
+    let mut position = 0;
+
+    loop {
+        let (read, complete) = read_line_capped(&mut reader, &mut line, max_line_length)?;
+        position += read as u64;
+        if complete {
+            tail.line(line_to_str(&line).trim_end_matches(&['\r', '\n'][..]))?;
+            line.clear();
+            continue;
+        }
+
+        // reached the end of what has been written so far
+        tail.flush()?;
+        thread::sleep(FOLLOW_POLL_INTERVAL);
+
+        // file got truncated (e.g. log rotation with copytruncate), start over
+        if fs::metadata(path)?.len() < position {
+            reader.seek(io::SeekFrom::Start(0))?;
+            position = 0;
+            line.clear();
+        }
+    }
+}
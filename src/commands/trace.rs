@@ -0,0 +1,76 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::{self, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::rtr::rtr_access_log;
+
+use super::{for_each_line, input_args};
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("trace")
+        .about("Only write the CF application log entries of a single HTTP request, from the router to the app")
+        .arg(
+            Arg::with_name("id")
+                .value_name("REQUEST_ID")
+                .help("vcap_request_id of the Gorouter access log, or B3 trace id, e.g. the one of a Sleuth [app,trace,span] log")
+                .required(true)
+                .index(1),
+        )
+        .args(&input_args())
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    let id = matches.value_of("id").unwrap().to_ascii_lowercase();
+    // apps usually log the request before the router logs its response, so entries are kept
+    // until the access logs tell every id of the request
+    let mut entries = Vec::new();
+    let mut ids = vec![id.clone()];
+    let code = for_each_line(matches, |_, line| {
+        let line = strip_ansi(line);
+        if let Ok((_, entry)) = parse_cf_app_log(&line) {
+            if let Some(log) = rtr_access_log(&entry) {
+                let request_ids = [log.vcap_request_id(), log.b3_trace_id()];
+                if request_ids
+                    .iter()
+                    .flatten()
+                    .any(|request_id| request_id.eq_ignore_ascii_case(&id))
+                {
+                    for request_id in request_ids.iter().flatten() {
+                        let request_id = request_id.to_ascii_lowercase();
+                        if !ids.contains(&request_id) {
+                            ids.push(request_id);
+                        }
+                    }
+                }
+            }
+            entries.push(line.into_owned());
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut found = false;
+    for line in &entries {
+        let message = match parse_cf_app_log(line) {
+            Ok((_, entry)) => entry.message.unwrap_or("").to_ascii_lowercase(),
+            Err(_) => continue,
+        };
+        if ids.iter().any(|id| message.contains(id.as_str())) {
+            found = true;
+            // a failed write means stdout got closed, e.g. piped into head
+            if writeln!(out, "{}", line).is_err() {
+                break;
+            }
+        }
+    }
+    if found {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,372 @@
+use chrono::{DateTime, FixedOffset, NaiveTime};
+use clap::{App, ArgMatches, SubCommand};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, IsTerminal, Write};
+
+use cf_app_log_detector::input::strip_ansi;
+use cf_app_log_detector::{parse_cf_app_log, ChannelValid, ComponentInfoValid};
+
+use super::{color, color_arg, entry_filter, entry_filter_args, for_each_line, input_args};
+
+const HELP: &str =
+    "q quit  ↑↓ PgUp PgDn g G move  / search  n N next  c component  o channel  t jump to time";
+
+pub fn app() -> App<'static, 'static> {
+    SubCommand::with_name("view")
+        .about("Browse the entries in a terminal UI, with scrolling, search, component and channel filters and jumps to a time")
+        .arg(color_arg())
+        .args(&entry_filter_args())
+        .args(&input_args())
+}
+
+/// Entry as displayed
+struct Row {
+    timestamp: DateTime<FixedOffset>,
+    component: String,
+    instance: String,
+    channel: String,
+    message: String,
+}
+
+/// Text being typed at the bottom of the screen
+enum Prompt {
+    Search(String),
+    Jump(String),
+}
+
+/// State of the UI, independent of the terminal
+struct Viewer {
+    rows: Vec<Row>,
+    /// whether `ERR` entries are red
+    color: bool,
+    /// components of the rows, cycled through by the component filter
+    components: Vec<String>,
+    component: Option<String>,
+    channel: Option<&'static str>,
+    /// indexes of the rows going through the filters
+    visible: Vec<usize>,
+    /// position in `visible` of the selected row, and of the first one on screen
+    cursor: usize,
+    top: usize,
+    search: Option<String>,
+    prompt: Option<Prompt>,
+    /// outcome of the last action, e.g. a search without match
+    status: String,
+}
+
+impl Viewer {
+    fn new(rows: Vec<Row>, color: bool) -> Viewer {
+        let mut components: Vec<String> = rows.iter().map(|row| row.component.clone()).collect();
+        components.sort();
+        components.dedup();
+        let mut viewer = Viewer {
+            rows,
+            color,
+            components,
+            component: None,
+            channel: None,
+            visible: Vec::new(),
+            cursor: 0,
+            top: 0,
+            search: None,
+            prompt: None,
+            status: String::new(),
+        };
+        viewer.refilter();
+        viewer
+    }
+
+    /// Apply the filters again, keeping the selected row when it still goes through them
+    fn refilter(&mut self) {
+        let selected = self.visible.get(self.cursor).copied();
+        self.visible = (0..self.rows.len())
+            .filter(|index| {
+                let row = &self.rows[*index];
+                self.component.as_ref().is_none_or(|c| *c == row.component)
+                    && self.channel.is_none_or(|c| c == row.channel)
+            })
+            .collect();
+        self.cursor = selected
+            .map(|selected| self.visible.partition_point(|index| *index < selected))
+            .unwrap_or(0)
+            .min(self.visible.len().saturating_sub(1));
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        let last = self.visible.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + offset).clamp(0, last.max(0)) as usize;
+    }
+
+    /// Select the next row, or the previous one, whose line contains the search
+    fn search_next(&mut self, forward: bool) {
+        let search = match &self.search {
+            Some(search) => search.to_lowercase(),
+            None => return,
+        };
+        let matching = |position: &usize| {
+            let row = &self.rows[self.visible[*position]];
+            row.message.to_lowercase().contains(&search)
+                || row.instance.to_lowercase().contains(&search)
+        };
+        let found = if forward {
+            (self.cursor + 1..self.visible.len()).find(matching)
+        } else {
+            (0..self.cursor).rev().find(matching)
+        };
+        match found {
+            Some(position) => {
+                self.cursor = position;
+                self.status.clear();
+            }
+            None => self.status = format!("no more match of {}", search),
+        }
+    }
+
+    /// Select the first row logged at or after `time`, a RFC 3339 date or a time of the day of
+    /// the selected row, e.g. `17:05` or `17:05:30`
+    fn jump(&mut self, time: &str) {
+        let selected = match self.visible.get(self.cursor) {
+            Some(index) => self.rows[*index].timestamp,
+            None => return,
+        };
+        let target = DateTime::parse_from_rfc3339(time).ok().or_else(|| {
+            let time = NaiveTime::parse_from_str(time, "%H:%M:%S%.f")
+                .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+                .ok()?;
+            selected
+                .date_naive()
+                .and_time(time)
+                .and_local_timezone(*selected.offset())
+                .single()
+        });
+        match target {
+            Some(target) => {
+                let rows = &self.rows;
+                // inputs are not necessarily in order, take the first one from the top
+                match self
+                    .visible
+                    .iter()
+                    .position(|index| rows[*index].timestamp >= target)
+                {
+                    Some(position) => {
+                        self.cursor = position;
+                        self.status.clear();
+                    }
+                    None => self.status = format!("no entry from {}", time),
+                }
+            }
+            None => self.status = format!("invalid time: {}", time),
+        }
+    }
+
+    /// Apply a key press, false to quit
+    fn handle(&mut self, key: KeyEvent, page: usize) -> bool {
+        if let Some(prompt) = &mut self.prompt {
+            let text = match prompt {
+                Prompt::Search(text) | Prompt::Jump(text) => text,
+            };
+            match key.code {
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Enter => match self.prompt.take() {
+                    Some(Prompt::Search(text)) => {
+                        self.search = Some(text).filter(|text| !text.is_empty());
+                        self.search_next(true);
+                    }
+                    Some(Prompt::Jump(text)) => self.jump(text.trim()),
+                    None => {}
+                },
+                _ => {}
+            }
+            return true;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.move_by(page as isize),
+            KeyCode::PageUp => self.move_by(-(page as isize)),
+            KeyCode::Home | KeyCode::Char('g') => self.cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => self.cursor = self.visible.len().saturating_sub(1),
+            KeyCode::Char('/') => self.prompt = Some(Prompt::Search(String::new())),
+            KeyCode::Char('t') => self.prompt = Some(Prompt::Jump(String::new())),
+            KeyCode::Char('n') => self.search_next(true),
+            KeyCode::Char('N') => self.search_next(false),
+            KeyCode::Char('c') => {
+                // all of them, then each one in turn
+                let next = match &self.component {
+                    None => 0,
+                    Some(component) => {
+                        self.components
+                            .iter()
+                            .position(|c| c == component)
+                            .unwrap_or(0)
+                            + 1
+                    }
+                };
+                self.component = self.components.get(next).cloned();
+                self.refilter();
+            }
+            KeyCode::Char('o') => {
+                self.channel = match self.channel {
+                    None => Some("OUT"),
+                    Some("OUT") => Some("ERR"),
+                    Some(_) => None,
+                };
+                self.refilter();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, out: &mut impl Write, width: usize, height: usize) -> io::Result<()> {
+        // the header and the status line take a line each
+        let lines = height.saturating_sub(2).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + lines {
+            self.top = self.cursor + 1 - lines;
+        }
+        let fit = |text: String| text.chars().take(width).collect::<String>();
+
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        let header = format!(
+            "{} of {} entries | component: {} | channel: {}{}",
+            self.visible.len(),
+            self.rows.len(),
+            self.component.as_deref().unwrap_or("all"),
+            self.channel.unwrap_or("all"),
+            self.search
+                .as_ref()
+                .map_or(String::new(), |search| format!(" | search: {}", search))
+        );
+        queue!(
+            out,
+            SetAttribute(Attribute::Bold),
+            Print(fit(header)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        for (line, position) in (self.top..self.visible.len()).take(lines).enumerate() {
+            let row = &self.rows[self.visible[position]];
+            let text = format!(
+                "{} {:<14} {} {}",
+                row.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"),
+                row.instance,
+                row.channel,
+                row.message
+            );
+            queue!(out, MoveTo(0, line as u16 + 1))?;
+            if self.color && row.channel == "ERR" {
+                queue!(out, SetForegroundColor(Color::Red))?;
+            }
+            if position == self.cursor {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(fit(text)),
+                SetAttribute(Attribute::Reset),
+                ResetColor
+            )?;
+        }
+        let bottom = match &self.prompt {
+            Some(Prompt::Search(text)) => format!("/{}", text),
+            Some(Prompt::Jump(text)) => format!("time (HH:MM[:SS] or RFC 3339): {}", text),
+            None if !self.status.is_empty() => self.status.clone(),
+            None => HELP.to_string(),
+        };
+        queue!(
+            out,
+            MoveTo(0, height.saturating_sub(1) as u16),
+            Print(fit(bottom))
+        )?;
+        out.flush()
+    }
+}
+
+/// Puts the terminal back as it was, however the UI ends
+struct Screen;
+
+impl Screen {
+    fn enter(out: &mut impl Write) -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn browse(viewer: &mut Viewer) -> io::Result<()> {
+    let mut out = io::stdout();
+    let _screen = Screen::enter(&mut out)?;
+    loop {
+        let (width, height) = terminal::size()?;
+        viewer.draw(&mut out, width as usize, height as usize)?;
+        if let Event::Key(key) = event::read()? {
+            // key releases are reported on Windows
+            if key.kind != KeyEventKind::Release
+                && !viewer.handle(key, (height as usize).saturating_sub(2))
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> i32 {
+    if !io::stdout().is_terminal() {
+        eprintln!("view needs a terminal, use filter or parse to write entries elsewhere");
+        return 2;
+    }
+    let filter = entry_filter(matches);
+    let mut rows = Vec::new();
+    let code = for_each_line(matches, |_, line| {
+        if let Ok((_, entry)) = parse_cf_app_log(&strip_ansi(line)) {
+            if filter.matches(&entry) {
+                let (component, instance) = match &entry.component {
+                    ComponentInfoValid::Valid(info) => {
+                        (info.name.as_str().to_string(), info.instance())
+                    }
+                    ComponentInfoValid::Invalid(raw) => (raw.clone(), raw.clone()),
+                };
+                rows.push(Row {
+                    timestamp: entry.timestamp,
+                    component,
+                    instance,
+                    channel: match &entry.channel {
+                        ChannelValid::Valid(channel) => channel.as_str().to_string(),
+                        ChannelValid::Invalid(raw) => raw.clone(),
+                    },
+                    message: entry.message.unwrap_or("").to_string(),
+                });
+            }
+        }
+        true
+    });
+    if code != 0 {
+        return code;
+    }
+
+    if let Err(error) = browse(&mut Viewer::new(rows, color(matches, true))) {
+        eprintln!("Could not drive the terminal: {}", error);
+        return 2;
+    }
+    0
+}
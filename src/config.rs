@@ -0,0 +1,385 @@
+//! Defaults of the command line options read from a TOML file, e.g.
+//!
+//! ```toml
+//! color = "never"
+//!
+//! [detect]
+//! percentage-matching = 80
+//!
+//! [parse]
+//! format = "csv"
+//! ```
+//!
+//! Top level keys apply to every subcommand having the option, tables to a single subcommand.
+//! Keys are the long option names, `true` giving a flag and arrays an option several times.
+//! `CF_LOG_DETECTOR_*` environment variables set options the same way as top level keys.
+//!
+//! `[[envelope]]` tables define the envelopes of site-specific log wrappers, see
+//! `crate::envelope`:
+//!
+//! ```toml
+//! [[envelope]]
+//! name = "forwarder"
+//! regex = '^(?P<timestamp>\S+ \S+) app=(?P<component>\S+)/(?P<index>\d+) (?P<channel>OUT|ERR) ?(?P<message>.*)$'
+//! timestamp-format = "%Y-%m-%d %H:%M:%S"
+//! ```
+
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use toml::{Table, Value};
+
+use crate::envelope::Envelope;
+
+/// Prefix of the environment variables setting options, e.g. `CF_LOG_DETECTOR_FORMAT`
+pub const ENV_PREFIX: &str = "CF_LOG_DETECTOR_";
+/// Environment variable giving the configuration file, like `--config`
+pub const ENV_CONFIG: &str = "CF_LOG_DETECTOR_CONFIG";
+/// Key of the envelope tables, not an option
+const ENVELOPE_KEY: &str = "envelope";
+/// Keys of an envelope table
+const ENVELOPE_KEYS: &[&str] = &["name", "description", "regex", "timestamp-format"];
+
+#[derive(Debug, Default)]
+pub struct Config {
+    table: Table,
+    envelopes: Vec<Envelope>,
+}
+
+/// Option of the command line set by the configuration
+#[derive(Debug, PartialEq)]
+pub struct ConfigOption {
+    /// long name, e.g. `percentage-matching`
+    pub key: String,
+    /// e.g. `--percentage-matching=80`, nothing for a `false` flag
+    pub args: Vec<String>,
+    /// set at the top level, only applying to the subcommands having the option
+    pub global: bool,
+}
+
+/// `$XDG_CONFIG_HOME/cf-app-log-detector/config.toml`, `~/.config` by default
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("cf-app-log-detector").join("config.toml"))
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        let table: Table = content
+            .parse()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        let mut envelopes = Vec::new();
+        for (key, value) in &table {
+            if key == ENVELOPE_KEY {
+                envelopes = parse_envelopes(value)?;
+                continue;
+            }
+            match value {
+                Value::Table(options) => {
+                    for (option, value) in options {
+                        option_args(option, value)
+                            .map_err(|e| format!("{}.{}: {}", key, option, e))?;
+                    }
+                }
+                value => {
+                    option_args(key, value).map_err(|e| format!("{}: {}", key, e))?;
+                }
+            }
+        }
+        Ok(Config { table, envelopes })
+    }
+}
+
+impl Config {
+    /// Options of `subcommand`, those of its table replacing the top level ones
+    pub fn options(&self, subcommand: &str) -> Vec<ConfigOption> {
+        let mut options: Vec<ConfigOption> = Vec::new();
+        for (key, value) in &self.table {
+            if !value.is_table() && key != ENVELOPE_KEY {
+                options.push(ConfigOption {
+                    key: key.clone(),
+                    args: option_args(key, value).unwrap(),
+                    global: true,
+                });
+            }
+        }
+        if let Some(Value::Table(table)) = self.table.get(subcommand) {
+            for (key, value) in table {
+                options.retain(|option| option.key != *key);
+                options.push(ConfigOption {
+                    key: key.clone(),
+                    args: option_args(key, value).unwrap(),
+                    global: false,
+                });
+            }
+        }
+        options
+    }
+
+    /// Envelopes of the `[[envelope]]` tables, in order
+    pub fn envelopes(&self) -> &[Envelope] {
+        &self.envelopes
+    }
+}
+
+/// Envelopes of the `[[envelope]]` tables, having a `name`, a `regex` and an optional
+/// `description` and `timestamp-format`
+fn parse_envelopes(value: &Value) -> Result<Vec<Envelope>, String> {
+    let tables = match value {
+        Value::Array(tables) => tables,
+        _ => {
+            return Err(format!(
+                "{}: expected [[{}]] tables",
+                ENVELOPE_KEY, ENVELOPE_KEY
+            ))
+        }
+    };
+    tables
+        .iter()
+        .enumerate()
+        .map(|(number, table)| {
+            let invalid = |e: String| format!("{} {}: {}", ENVELOPE_KEY, number + 1, e);
+            let table = table
+                .as_table()
+                .ok_or_else(|| invalid("expected a table".to_string()))?;
+            let string = |key: &str| match table.get(key) {
+                Some(Value::String(value)) => Ok(Some(value.as_str())),
+                Some(value) => Err(invalid(format!("{}: unsupported value: {}", key, value))),
+                None => Ok(None),
+            };
+            if let Some(key) = table
+                .keys()
+                .find(|key| !ENVELOPE_KEYS.contains(&key.as_str()))
+            {
+                return Err(invalid(format!("unknown key: {}", key)));
+            }
+            let name = string("name")?.ok_or_else(|| invalid("missing name".to_string()))?;
+            let regex = string("regex")?.ok_or_else(|| invalid("missing regex".to_string()))?;
+            let envelope =
+                Envelope::new(name, regex, string("timestamp-format")?).map_err(invalid)?;
+            Ok(match string("description")? {
+                Some(description) => envelope.with_description(description),
+                None => envelope,
+            })
+        })
+        .collect()
+}
+
+/// Options set by the `CF_LOG_DETECTOR_*` variables among `vars`, e.g.
+/// `CF_LOG_DETECTOR_PERCENTAGE_MATCHING=80`, applying to every subcommand having the option.
+/// `true` gives a flag and `false` leaves it out.
+pub fn env_options<I>(vars: I) -> Vec<ConfigOption>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut options: Vec<ConfigOption> = vars
+        .into_iter()
+        .filter(|(name, _)| name != ENV_CONFIG)
+        .filter_map(|(name, value)| {
+            let key = name
+                .strip_prefix(ENV_PREFIX)
+                .filter(|key| !key.is_empty())?
+                .to_lowercase()
+                .replace('_', "-");
+            let args = match value.as_str() {
+                "true" => vec![format!("--{}", key)],
+                "false" => Vec::new(),
+                value => vec![format!("--{}={}", key, value)],
+            };
+            Some(ConfigOption {
+                key,
+                args,
+                global: true,
+            })
+        })
+        .collect();
+    // the environment has no order
+    options.sort_by(|a, b| a.key.cmp(&b.key));
+    options
+}
+
+/// Command line arguments giving `value` to the option `key`
+fn option_args(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    let scalar = |value: &Value| match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        _ => Err(format!("unsupported value: {}", value)),
+    };
+    match value {
+        Value::Boolean(true) => Ok(vec![format!("--{}", key)]),
+        Value::Boolean(false) => Ok(Vec::new()),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| scalar(value).map(|value| format!("--{}={}", key, value)))
+            .collect(),
+        value => scalar(value).map(|value| vec![format!("--{}={}", key, value)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::LogFormatDetector;
+
+    #[test]
+    fn test_options() {
+        let config: Config = r#"
+            color = "never"
+            format = "json"
+            reassemble = true
+
+            [parse]
+            format = "csv"
+            component = ["APP", "RTR"]
+            utc = false
+
+            [detect]
+            percentage-matching = 80
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config.options("parse"),
+            vec![
+                ConfigOption {
+                    key: "color".to_string(),
+                    args: vec!["--color=never".to_string()],
+                    global: true
+                },
+                ConfigOption {
+                    key: "reassemble".to_string(),
+                    args: vec!["--reassemble".to_string()],
+                    global: true
+                },
+                ConfigOption {
+                    key: "component".to_string(),
+                    args: vec!["--component=APP".to_string(), "--component=RTR".to_string()],
+                    global: false
+                },
+                ConfigOption {
+                    key: "format".to_string(),
+                    args: vec!["--format=csv".to_string()],
+                    global: false
+                },
+                ConfigOption {
+                    key: "utc".to_string(),
+                    args: vec![],
+                    global: false
+                },
+            ]
+        );
+        assert_eq!(
+            config.options("detect")[3].args,
+            vec!["--percentage-matching=80".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_env_options() {
+        let vars = vec![
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            (
+                "CF_LOG_DETECTOR_PERCENTAGE_MATCHING".to_string(),
+                "80".to_string(),
+            ),
+            ("CF_LOG_DETECTOR_CONFIG".to_string(), "ci.toml".to_string()),
+            (
+                "CF_LOG_DETECTOR_ONE_LINE_MATCH".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "CF_LOG_DETECTOR_REASSEMBLE".to_string(),
+                "false".to_string(),
+            ),
+        ];
+        let options = env_options(vars);
+        let args: Vec<(&str, &[String])> = options
+            .iter()
+            .map(|option| (option.key.as_str(), option.args.as_slice()))
+            .collect();
+        assert_eq!(
+            args,
+            vec![
+                ("one-line-match", &["--one-line-match".to_string()][..]),
+                (
+                    "percentage-matching",
+                    &["--percentage-matching=80".to_string()][..]
+                ),
+                ("reassemble", &[][..]),
+            ]
+        );
+        assert!(options.iter().all(|option| option.global));
+    }
+
+    #[test]
+    fn test_envelopes() {
+        let config: Config = r#"
+            color = "never"
+
+            [[envelope]]
+            name = "shipper"
+            regex = '^(?P<timestamp>\S+) (?P<component>\S+) (?P<channel>OUT|ERR) ?(?P<message>.*)$'
+
+            [[envelope]]
+            name = "forwarder"
+            description = "forwarded CF log"
+            regex = '^(?P<timestamp>\S+ \S+) app=(?P<component>\S+) (?P<channel>\w+)$'
+            timestamp-format = "%Y-%m-%d %H:%M:%S"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.envelopes().len(), 2);
+        let forwarder = &config.envelopes()[1];
+        assert_eq!(forwarder.name(), "forwarder");
+        assert_eq!(forwarder.description(), "forwarded CF log");
+        assert!(forwarder
+            .parse("2021-09-28 08:00:09 app=RTR/0 OUT")
+            .is_some());
+        assert_eq!(config.options("parse").len(), 1);
+
+        assert_eq!(
+            "[[envelope]]\nname = \"x\"\nregex = '(?P<timestamp>.+)'\n"
+                .parse::<Config>()
+                .unwrap_err(),
+            "envelope 1: missing capture: (?P<component>...)"
+        );
+        assert_eq!(
+            "[[envelope]]\nname = \"x\"\ntimestamp-format = \"%s\"\n"
+                .parse::<Config>()
+                .unwrap_err(),
+            "envelope 1: missing regex"
+        );
+        assert_eq!(
+            "[[envelope]]\nregex = '(?P<timestamp>.+)'\n"
+                .parse::<Config>()
+                .unwrap_err(),
+            "envelope 1: missing name"
+        );
+        assert_eq!(
+            "envelope = \"regex\"\n".parse::<Config>().unwrap_err(),
+            "envelope: expected [[envelope]] tables"
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!("color = ".parse::<Config>().is_err());
+        assert_eq!(
+            "[parse]\nsince = { days = 1 }\n"
+                .parse::<Config>()
+                .unwrap_err(),
+            "parse.since: unsupported value: { days = 1 }"
+        );
+        assert_eq!(
+            "columns = [[\"message\"]]\n".parse::<Config>().unwrap_err(),
+            "columns: unsupported value: [\"message\"]"
+        );
+    }
+}
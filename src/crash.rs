@@ -0,0 +1,290 @@
+//! Detection of app instances exiting or crashing, from the entries the platform writes about
+//! them
+
+use chrono::{DateTime, Duration, FixedOffset};
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{CfAppLogEntry, Component, ComponentInfoValid};
+
+/// Instance exit told by an entry
+#[derive(Debug, PartialEq)]
+pub struct Crash {
+    /// instance index, from the payload of API entries or the envelope of the others
+    pub index: Option<u32>,
+    pub exit_status: Option<i32>,
+    /// e.g. `CRASHED`, given by API entries
+    pub reason: Option<String>,
+    /// e.g. `APP/PROC/WEB: Exited with status 1`
+    pub description: Option<String>,
+}
+
+impl Crash {
+    /// Whether the instance got killed for going over its memory quota: exit status 137
+    /// (SIGKILL, sent by the OOM killer) or a description telling so
+    pub fn is_out_of_memory(&self) -> bool {
+        self.exit_status == Some(137)
+            || self
+                .description
+                .as_deref()
+                .is_some_and(|description| patterns().out_of_memory.is_match(description))
+    }
+}
+
+struct Patterns {
+    exit_status: Regex,
+    index: Regex,
+    reason: Regex,
+    description: Regex,
+    process_crashed: Regex,
+    out_of_memory: Regex,
+    container_start: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        exit_status: Regex::new(r"(?i)(?:exit status|exited with status) (-?\d+)").unwrap(),
+        // payloads are Ruby hashes, `"index"=>0`, or JSON objects, `"index":0`
+        index: Regex::new(r#""index"\s*(?:=>|:)\s*(\d+)"#).unwrap(),
+        reason: Regex::new(r#""reason"\s*(?:=>|:)\s*"([^"]*)""#).unwrap(),
+        description: Regex::new(r#""exit_description"\s*(?:=>|:)\s*"([^"]*)""#).unwrap(),
+        process_crashed: Regex::new(r"^Process has crashed with type").unwrap(),
+        out_of_memory: Regex::new(r"(?i)out of memory|memory quota|oom.?kill").unwrap(),
+        container_start: Regex::new(r"(?i)\bcreating container\b").unwrap(),
+    })
+}
+
+/// Crash or exit told by an entry:
+///
+/// - `App instance exited with guid ... payload: {..., "index"=>0, "reason"=>"CRASHED",
+///   "exit_description"=>"APP/PROC/WEB: Exited with status 1", ...}` written by the API
+/// - `Process has crashed with type: "web"` written by the API
+/// - `Exit status 1` written by the CELL or APP instance itself, instances stopping with
+///   status 0 are not crashes and are left out
+pub fn crash(entry: &CfAppLogEntry) -> Option<Crash> {
+    let info = match &entry.component {
+        ComponentInfoValid::Valid(info) => info,
+        ComponentInfoValid::Invalid(_) => return None,
+    };
+    let message = entry.message?;
+    let patterns = patterns();
+    let capture = |regex: &Regex, text: &str| {
+        regex
+            .captures(text)
+            .and_then(|captures| captures.get(1))
+            .map(|value| value.as_str().to_string())
+    };
+    match info.name {
+        Component::API if message.starts_with("App instance exited") => {
+            let description = capture(&patterns.description, message);
+            Some(Crash {
+                index: capture(&patterns.index, message).and_then(|index| index.parse().ok()),
+                exit_status: description
+                    .as_deref()
+                    .and_then(|description| capture(&patterns.exit_status, description))
+                    .and_then(|status| status.parse().ok()),
+                reason: capture(&patterns.reason, message),
+                description,
+            })
+        }
+        Component::API if patterns.process_crashed.is_match(message) => Some(Crash {
+            index: None,
+            exit_status: None,
+            reason: Some("CRASHED".to_string()),
+            description: Some(message.to_string()),
+        }),
+        Component::CELL | Component::APPLICATION if message.starts_with("Exit status") => {
+            let exit_status = capture(&patterns.exit_status, message)?.parse().ok()?;
+            if exit_status == 0 {
+                return None;
+            }
+            Some(Crash {
+                index: Some(info.index),
+                exit_status: Some(exit_status),
+                reason: None,
+                description: Some(message.to_string()),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Whether the entry tells an instance ran out of memory: a crash with `Crash::is_out_of_memory`,
+/// or an API or CELL entry about the memory quota, e.g. `Instance exceeded its memory quota`
+pub fn out_of_memory(entry: &CfAppLogEntry) -> bool {
+    if let Some(crash) = crash(entry) {
+        return crash.is_out_of_memory();
+    }
+    match &entry.component {
+        ComponentInfoValid::Valid(info)
+            if [Component::API, Component::CELL].contains(&info.name) =>
+        {
+            entry
+                .message
+                .is_some_and(|message| patterns().out_of_memory.is_match(message))
+        }
+        _ => false,
+    }
+}
+
+/// Index of the instance whose container the entry tells is being created, the CELL writing
+/// `Cell ... creating container for instance ...` each time an instance starts or restarts
+pub fn container_start(entry: &CfAppLogEntry) -> Option<u32> {
+    match &entry.component {
+        ComponentInfoValid::Valid(info)
+            if info.name == Component::CELL
+                && patterns()
+                    .container_start
+                    .is_match(entry.message.unwrap_or("")) =>
+        {
+            Some(info.index)
+        }
+        _ => None,
+    }
+}
+
+/// Period an instance kept restarting in
+#[derive(Debug, PartialEq)]
+pub struct RestartLoop {
+    pub first: DateTime<FixedOffset>,
+    pub last: DateTime<FixedOffset>,
+    /// starts in the period, the first one included
+    pub starts: usize,
+}
+
+/// Periods where an instance started more than `max` times within `window`, given the sorted
+/// times it started at. Overlapping windows are merged into a single period.
+pub fn restart_loops(
+    starts: &[DateTime<FixedOffset>],
+    max: usize,
+    window: Duration,
+) -> Vec<RestartLoop> {
+    // starts belonging to a window going over `max`
+    let mut looping = vec![false; starts.len()];
+    let mut end = 0;
+    for first in 0..starts.len() {
+        end = end.max(first);
+        while end + 1 < starts.len() && starts[end + 1] - starts[first] <= window {
+            end += 1;
+        }
+        if end - first + 1 > max {
+            looping[first..=end]
+                .iter_mut()
+                .for_each(|start| *start = true);
+        }
+    }
+
+    let mut loops: Vec<RestartLoop> = Vec::new();
+    for (start, _) in starts.iter().zip(&looping).filter(|(_, looping)| **looping) {
+        match loops.last_mut() {
+            Some(restart_loop) if *start - restart_loop.last <= window => {
+                restart_loop.last = *start;
+                restart_loop.starts += 1;
+            }
+            _ => loops.push(RestartLoop {
+                first: *start,
+                last: *start,
+                starts: 1,
+            }),
+        }
+    }
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_crash() {
+        let (_, entry) = parse_cf_app_log(r#"2021-09-28T17:00:11.00+0900 [API/1] OUT App instance exited with guid 79bb58ab-3737-43be-ac70-39a2843b5177 payload: {"instance"=>"5e3f", "index"=>2, "cell_id"=>"c1", "reason"=>"CRASHED", "exit_description"=>"APP/PROC/WEB: Exited with status 1", "crash_count"=>3}"#).unwrap();
+        assert_eq!(
+            crash(&entry),
+            Some(Crash {
+                index: Some(2),
+                exit_status: Some(1),
+                reason: Some("CRASHED".to_string()),
+                description: Some("APP/PROC/WEB: Exited with status 1".to_string()),
+            })
+        );
+
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/2] OUT Exit status 137")
+                .unwrap();
+        let crash = super::crash(&entry).unwrap();
+        assert_eq!((crash.index, crash.exit_status), (Some(2), Some(137)));
+
+        assert!(crash.is_out_of_memory());
+
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:10.00+0900 [CELL/0] OUT Exit status 0").unwrap();
+        assert_eq!(super::crash(&entry), None);
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/0] OUT Exit status is fine",
+        )
+        .unwrap();
+        assert_eq!(super::crash(&entry), None);
+    }
+
+    #[test]
+    fn test_out_of_memory() {
+        let (_, entry) = parse_cf_app_log(r#"2021-09-28T17:00:11.00+0900 [API/1] OUT App instance exited with guid 79bb58ab payload: {"index"=>0, "reason"=>"CRASHED", "exit_description"=>"APP/PROC/WEB: Exited with status 137 (out of memory)"}"#).unwrap();
+        assert!(out_of_memory(&entry));
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:11.00+0900 [CELL/0] OUT Instance 5e3f exceeded its memory quota",
+        )
+        .unwrap();
+        assert!(out_of_memory(&entry));
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:11.00+0900 [APP/PROC/WEB/0] OUT Exit status 1")
+                .unwrap();
+        assert!(!out_of_memory(&entry));
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:11.00+0900 [APP/PROC/WEB/0] OUT cache out of memory, evicting",
+        )
+        .unwrap();
+        assert!(!out_of_memory(&entry));
+    }
+
+    #[test]
+    fn test_container_start() {
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:11.00+0900 [CELL/2] OUT Cell 8f4a creating container for instance 5e3f",
+        )
+        .unwrap();
+        assert_eq!(container_start(&entry), Some(2));
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:12.00+0900 [CELL/2] OUT Cell 8f4a successfully created container for instance 5e3f",
+        )
+        .unwrap();
+        assert_eq!(container_start(&entry), None);
+    }
+
+    #[test]
+    fn test_restart_loops() {
+        let start = DateTime::parse_from_rfc3339("2021-09-28T17:00:00+09:00").unwrap();
+        let starts: Vec<_> = [0, 60, 120, 180, 3600, 7200, 7230, 7260, 7290, 7320]
+            .iter()
+            .map(|seconds| start + Duration::seconds(*seconds))
+            .collect();
+        assert_eq!(
+            restart_loops(&starts, 3, Duration::minutes(5)),
+            vec![
+                RestartLoop {
+                    first: starts[0],
+                    last: starts[3],
+                    starts: 4,
+                },
+                RestartLoop {
+                    first: starts[5],
+                    last: starts[9],
+                    starts: 5,
+                },
+            ]
+        );
+        assert_eq!(restart_loops(&starts, 5, Duration::minutes(5)), vec![]);
+        assert_eq!(restart_loops(&[], 0, Duration::minutes(5)), vec![]);
+    }
+}
@@ -0,0 +1,649 @@
+//! Decide whether a file is a CF application log, or in another format of
+//! `crate::formats`, from the share of its lines matching the expected format
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::Rng;
+
+use crate::formats::{CfAppLog, LogFormatDetector};
+use crate::input::{line_to_str, read_line_capped, strip_ansi};
+use crate::is_cf_cli_banner;
+use crate::loggregator::loss_warning;
+use crate::metrics::Metrics;
+
+const PROGRESS_TEMPLATE: &str =
+    "[{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta})";
+
+const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const EARLY_EXIT_MIN_LINES: usize = 100;
+
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleStrategy {
+    /// the first N lines
+    Head,
+    /// N lines picked at random byte offsets, without reading the whole file
+    Random,
+    /// N lines picked uniformly over the whole file (reads everything)
+    Reservoir,
+}
+
+impl FromStr for SampleStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(SampleStrategy::Head),
+            "random" => Ok(SampleStrategy::Random),
+            "reservoir" => Ok(SampleStrategy::Reservoir),
+            _ => Err(format!("unknown sample strategy: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for SampleStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SampleStrategy::Head => write!(f, "head"),
+            SampleStrategy::Random => write!(f, "random"),
+            SampleStrategy::Reservoir => write!(f, "reservoir"),
+        }
+    }
+}
+
+pub struct CfAppLogDetector {
+    format: Arc<dyn LogFormatDetector>,
+    one_line_match: bool,
+    sample: Option<(usize, SampleStrategy)>,
+    early_exit: bool,
+    exited_early: bool,
+    max_lines: Option<usize>,
+    max_bytes: Option<u64>,
+    limit_reached: bool,
+    lines_read: usize,
+    skip_lines: usize,
+    max_line_length: usize,
+    mmap: bool,
+    progress: bool,
+    progress_bar: Option<ProgressBar>,
+    start_offset: u64,
+    offset: u64,
+    skipped_lines: usize,
+    in_header: bool,
+    total_log_lines: usize,
+    log_lines_matching: usize,
+    trigger_percentage: usize,
+    metrics: Option<Arc<Metrics>>,
+    fail_on_loss: bool,
+    loss_warnings: usize,
+    dropped_messages: u64,
+}
+
+impl CfAppLogDetector {
+    pub fn new(trigger_percentage: usize, one_line_match: bool) -> CfAppLogDetector {
+        CfAppLogDetector {
+            format: Arc::new(CfAppLog::default()),
+            trigger_percentage,
+            one_line_match,
+            sample: None,
+            early_exit: false,
+            exited_early: false,
+            max_lines: None,
+            max_bytes: None,
+            limit_reached: false,
+            lines_read: 0,
+            skip_lines: 0,
+            max_line_length: usize::MAX,
+            mmap: false,
+            progress: false,
+            progress_bar: None,
+            start_offset: 0,
+            offset: 0,
+            skipped_lines: 0,
+            in_header: true,
+            total_log_lines: 0,
+            log_lines_matching: 0,
+            metrics: None,
+            fail_on_loss: false,
+            loss_warnings: 0,
+            dropped_messages: 0,
+        }
+    }
+
+    /// Detect whether the file is in `format` rather than a CF application log
+    pub fn with_format(mut self, format: Arc<dyn LogFormatDetector>) -> CfAppLogDetector {
+        self.format = format;
+        self
+    }
+
+    /// Only parse `size` lines of the file, picked according to `strategy`
+    pub fn with_sample(mut self, size: usize, strategy: SampleStrategy) -> CfAppLogDetector {
+        self.sample = Some((size, strategy));
+        self
+    }
+
+    /// Stop reading once the verdict can no longer change whatever the remaining lines are
+    pub fn with_early_exit(mut self, early_exit: bool) -> CfAppLogDetector {
+        self.early_exit = early_exit;
+        self
+    }
+
+    /// Give a distinct verdict (exit code 3) to CF application logs in which Loggregator
+    /// warns about lost messages. The whole file is read, not to miss any warning.
+    pub fn with_fail_on_loss(mut self, fail_on_loss: bool) -> CfAppLogDetector {
+        self.fail_on_loss = fail_on_loss;
+        self
+    }
+
+    /// Account for the lines read and the verdicts given in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> CfAppLogDetector {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Do not read more than `max_lines` lines of the file
+    pub fn with_max_lines(mut self, max_lines: usize) -> CfAppLogDetector {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Do not read more than `max_bytes` bytes of the file
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> CfAppLogDetector {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Ignore the first `skip_lines` lines of the file
+    pub fn with_skip_lines(mut self, skip_lines: usize) -> CfAppLogDetector {
+        self.skip_lines = skip_lines;
+        self
+    }
+
+    /// Only keep the first `max_line_length` bytes of each line, lines are classified on
+    /// that prefix
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> CfAppLogDetector {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Always read the file through a memory mapping, by default only files bigger than
+    /// `MMAP_THRESHOLD` are
+    pub fn with_mmap(mut self, mmap: bool) -> CfAppLogDetector {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Start reading the file at byte `offset` instead of its beginning
+    pub fn with_start_offset(mut self, offset: u64) -> CfAppLogDetector {
+        self.start_offset = offset;
+        self
+    }
+
+    /// Byte offset right after the last line read, where a later run can resume from
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Display a progress bar on stderr while reading the file
+    pub fn with_progress(mut self, progress: bool) -> CfAppLogDetector {
+        self.progress = progress;
+        self
+    }
+
+    pub fn process_file(&mut self, path: &str) -> io::Result<()> {
+        let result = self.read_file(path);
+        if let Some(progress_bar) = self.progress_bar.take() {
+            progress_bar.finish_and_clear();
+        }
+        result
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<()> {
+        let mut file = fs::File::open(path)?;
+        let length = file.metadata()?.len();
+        let max_lines = self.max_lines.unwrap_or(usize::MAX);
+        let max_bytes = self.max_bytes.unwrap_or(u64::MAX);
+
+        // a file smaller than the offset has been rotated or truncated, start over
+        let start = if self.start_offset <= length {
+            self.start_offset
+        } else {
+            0
+        };
+        file.seek(io::SeekFrom::Start(start))?;
+        self.offset = start;
+        let remaining = length - start;
+
+        if self.progress {
+            let progress_bar = ProgressBar::new(remaining.min(max_bytes));
+            progress_bar.set_style(
+                ProgressStyle::with_template(PROGRESS_TEMPLATE)
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            self.progress_bar = Some(progress_bar);
+        }
+
+        if let Some((size, SampleStrategy::Random)) = self.sample {
+            let mut reader = io::BufReader::new(file);
+            self.limit_reached = remaining > max_bytes;
+            let end = start + remaining.min(max_bytes);
+            self.offset = end;
+            return self.process_random_sample(&mut reader, size, start, end);
+        }
+
+        let max_entries = match self.sample {
+            Some((size, SampleStrategy::Head)) => size,
+            _ => usize::MAX,
+        };
+        // early exit relies on the number of lines left, meaningless when sampling
+        let early_exit_length = match self.sample {
+            None => Some(remaining.min(max_bytes)),
+            Some(_) => None,
+        };
+
+        let use_mmap = self.mmap || remaining >= MMAP_THRESHOLD;
+        if use_mmap && remaining > 0 && !matches!(self.sample, Some((_, SampleStrategy::Reservoir)))
+        {
+            // Safety: the mapping is only read, if another process truncates the file while
+            // it is mapped reading it can fail (SIGBUS), the same risk every mmap user takes
+            let map = unsafe { memmap2::MmapOptions::new().populate().map(&file)? };
+            let end = start.saturating_add(max_bytes).min(length) as usize;
+            let data = &map[start as usize..end];
+            let consumed =
+                self.process_mapped_lines(data, max_lines, max_entries, early_exit_length);
+            self.limit_reached = (remaining > max_bytes && consumed == data.len())
+                || (self.lines_read >= max_lines && consumed < data.len());
+            return Ok(());
+        }
+
+        let mut reader = io::BufReader::new(file).take(max_bytes);
+        match self.sample {
+            Some((size, SampleStrategy::Reservoir)) => {
+                self.process_reservoir_sample(&mut reader, size, max_lines)?
+            }
+            _ => self.process_lines(&mut reader, max_lines, max_entries, early_exit_length)?,
+        }
+        self.limit_reached = (reader.limit() == 0 && remaining > max_bytes)
+            || (self.lines_read >= max_lines && !reader.fill_buf()?.is_empty());
+        Ok(())
+    }
+
+    /// Read lines until `max_lines` lines have been read or `max_entries` have been accounted
+    /// for. `length` is the total size of the input when known, it enables early exit.
+    fn process_lines<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        max_lines: usize,
+        max_entries: usize,
+        length: Option<u64>,
+    ) -> io::Result<()> {
+        let mut line = Vec::new();
+        let mut bytes_read = 0;
+
+        while self.lines_read < max_lines && self.total_log_lines < max_entries {
+            let (read, complete) = read_line_capped(reader, &mut line, self.max_line_length)?;
+            if read == 0 {
+                break;
+            }
+            bytes_read += read as u64;
+            let done = self.consume_line(&line_to_str(&line), read, complete, bytes_read, length);
+            line.clear();
+            if done {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `process_lines` but iterating over the lines of a memory mapped file, avoiding
+    /// read calls and copies. Returns the number of bytes consumed.
+    fn process_mapped_lines(
+        &mut self,
+        data: &[u8],
+        max_lines: usize,
+        max_entries: usize,
+        length: Option<u64>,
+    ) -> usize {
+        let mut bytes_read = 0;
+
+        while bytes_read < data.len() {
+            if self.lines_read >= max_lines || self.total_log_lines >= max_entries {
+                break;
+            }
+            let rest = &data[bytes_read..];
+            let (line, complete) = match memchr::memchr(b'\n', rest) {
+                Some(newline) => (&rest[..=newline], true),
+                None => (rest, false),
+            };
+            bytes_read += line.len();
+            let read = line.len();
+            let line = line_to_str(&line[..read.min(self.max_line_length)]);
+            if self.consume_line(&line, read, complete, bytes_read as u64, length) {
+                break;
+            }
+        }
+        bytes_read
+    }
+
+    /// Account for a line read sequentially from the input, `read` is the number of bytes
+    /// the line occupied in the input (it may have been truncated) and `bytes_read` includes
+    /// them. A line that is not `complete`, the last one missing its newline, is left out of
+    /// the offset: it may still be being written and a run resuming from the offset reads it
+    /// again in full. Returns true when there is no need to read further.
+    fn consume_line(
+        &mut self,
+        line: &str,
+        read: usize,
+        complete: bool,
+        bytes_read: u64,
+        length: Option<u64>,
+    ) -> bool {
+        if complete {
+            self.offset += read as u64;
+        }
+        self.lines_read += 1;
+        if let Some(progress_bar) = &self.progress_bar {
+            progress_bar.inc(read as u64);
+        }
+        if self.skip_header_line(line) {
+            return false;
+        }
+        if self.process_line(line) {
+            return true;
+        }
+        if let Some(length) = length {
+            if self.early_exit && !self.fail_on_loss && self.verdict_decided(bytes_read, length) {
+                self.exited_early = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the remaining bytes can still move the percentage across the threshold.
+    /// The remaining lines in the format are bounded by its shortest line, the remaining lines
+    /// not in it are estimated from the average line length seen so far, assuming they could
+    /// be twice as short to stay on the safe side.
+    fn verdict_decided(&self, bytes_read: u64, length: u64) -> bool {
+        if self.total_log_lines < EARLY_EXIT_MIN_LINES {
+            return false;
+        }
+        let remaining = length.saturating_sub(bytes_read);
+        let average_line_length = bytes_read as f64 / self.lines_read as f64;
+        let remaining_lines = (remaining as f64 / average_line_length * 2.0).ceil();
+        // each line takes its newline too, but the last one
+        let remaining_matching =
+            ((remaining + 1) / (self.format.shortest_line() as u64 + 1)) as f64;
+        let matching = self.log_lines_matching as f64;
+        let total_lines = self.total_log_lines as f64;
+        let trigger = self.trigger_percentage as f64;
+
+        let lowest_percentage = (matching / (total_lines + remaining_lines) * 100.0).floor();
+        let highest_percentage =
+            ((matching + remaining_matching) / (total_lines + remaining_matching) * 100.0).floor();
+
+        lowest_percentage >= trigger || highest_percentage < trigger
+    }
+
+    /// Seek to random offsets between `start` and `end` and parse the first complete line
+    /// following each of them
+    fn process_random_sample<R: BufRead + Seek>(
+        &mut self,
+        reader: &mut R,
+        size: usize,
+        start: u64,
+        end: u64,
+    ) -> io::Result<()> {
+        if end <= start {
+            return Ok(());
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut offsets: Vec<u64> = (0..size).map(|_| rng.gen_range(start..end)).collect();
+        offsets.sort_unstable();
+
+        let mut line = Vec::new();
+        let mut position = start;
+        let mut first_line_sampled = false;
+        for offset in offsets {
+            // offsets landing in a line already sampled would give a duplicate
+            if offset < position {
+                continue;
+            }
+            reader.seek(io::SeekFrom::Start(offset))?;
+            position = offset;
+            if offset > start {
+                // skip the remaining of the line the offset landed in
+                position += read_line_capped(reader, &mut line, 0)?.0 as u64;
+            }
+            if position >= end {
+                // landed in the last line, wrap around to the first one
+                if first_line_sampled {
+                    break;
+                }
+                reader.seek(io::SeekFrom::Start(start))?;
+            }
+            if position == start || position >= end {
+                first_line_sampled = true;
+            }
+            position += read_line_capped(reader, &mut line, self.max_line_length)?.0 as u64;
+            let done = self.process_line(&line_to_str(&line));
+            line.clear();
+            if done {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Classic reservoir sampling (algorithm R), every line has the same chance to be kept
+    fn process_reservoir_sample<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        size: usize,
+        max_lines: usize,
+    ) -> io::Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut reservoir: Vec<String> = Vec::with_capacity(size);
+        let mut line = Vec::new();
+        let mut seen = 0;
+
+        while self.lines_read < max_lines {
+            let (read, complete) = read_line_capped(reader, &mut line, self.max_line_length)?;
+            if read == 0 {
+                break;
+            }
+            // see `consume_line`
+            if complete {
+                self.offset += read as u64;
+            }
+            self.lines_read += 1;
+            if let Some(progress_bar) = &self.progress_bar {
+                progress_bar.inc(read as u64);
+            }
+            let candidate = line_to_str(&line).into_owned();
+            line.clear();
+            if self.skip_header_line(&candidate) {
+                continue;
+            }
+            if reservoir.len() < size {
+                reservoir.push(candidate);
+            } else {
+                let index = rng.gen_range(0..=seen);
+                if index < size {
+                    reservoir[index] = candidate;
+                }
+            }
+            seen += 1;
+        }
+
+        for line in reservoir {
+            if self.process_line(&line) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `tail -f`: keep reading lines appended to the file and print a
+    /// verdict update each time new lines have been processed.
+    pub fn follow_file(&mut self, path: &str, debug: bool) -> io::Result<()> {
+        let mut reader = io::BufReader::new(fs::File::open(path)?);
+        let mut line = Vec::new();
+        let mut position = 0;
+        let mut reported_lines = None;
+
+        loop {
+            let (read, complete) = read_line_capped(&mut reader, &mut line, self.max_line_length)?;
+            position += read as u64;
+
+            if complete {
+                self.lines_read += 1;
+                let line_str = line_to_str(&line).into_owned();
+                line.clear();
+                if self.skip_header_line(&line_str) {
+                    continue;
+                }
+                let done = self.process_line(&line_str);
+                if done {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            // reached the end of what has been written so far
+            if reported_lines != Some(self.total_log_lines) {
+                self.show_results(path, debug);
+                reported_lines = Some(self.total_log_lines);
+            }
+            thread::sleep(FOLLOW_POLL_INTERVAL);
+
+            // file got truncated (e.g. log rotation with copytruncate), start over
+            if fs::metadata(path)?.len() < position {
+                reader.seek(io::SeekFrom::Start(0))?;
+                position = 0;
+                line.clear();
+            }
+        }
+    }
+
+    /// Whether a line is part of the header of the file (the lines explicitly skipped and
+    /// the cf CLI banner) and should not be accounted for
+    fn skip_header_line(&mut self, line: &str) -> bool {
+        if self.skipped_lines < self.skip_lines {
+            self.skipped_lines += 1;
+            return true;
+        }
+        if self.in_header {
+            if is_cf_cli_banner(line) {
+                self.skipped_lines += 1;
+                return true;
+            }
+            self.in_header = false;
+        }
+        false
+    }
+
+    /// Account for a single line, returns true when there is no need to read further
+    fn process_line(&mut self, line: &str) -> bool {
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        self.total_log_lines += 1;
+        let line = strip_ansi(line);
+        let matching = match self.format.parse(&line) {
+            Some(entry) => {
+                if let Some(warning) = loss_warning(&entry) {
+                    self.loss_warnings += 1;
+                    self.dropped_messages += warning.dropped.unwrap_or(0);
+                }
+                true
+            }
+            None => self.format.recognizes(&line),
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record_line(matching);
+        }
+        if matching {
+            self.log_lines_matching += 1;
+            self.one_line_match && !self.fail_on_loss
+        } else {
+            false
+        }
+    }
+
+    pub fn show_results(&mut self, path: &str, debug: bool) -> i32 {
+        if debug {
+            println!("[DEBUG] skipped header lines: {}", self.skipped_lines);
+            println!("[DEBUG] total number of lines: {}", self.total_log_lines);
+            println!("[DEBUG] log lines matching: {}", self.log_lines_matching);
+        }
+        let percentage_matching = if self.total_log_lines > 0 {
+            (self.log_lines_matching as f64 / self.total_log_lines as f64 * 100.0).floor()
+        } else {
+            0.0
+        };
+
+        if debug {
+            println!("[DEBUG] percentage matching: {}", percentage_matching)
+        }
+
+        let mut notes = match self.sample {
+            Some((_, strategy)) => format!(
+                " [sampled {} lines, {} strategy]",
+                self.total_log_lines, strategy
+            ),
+            None if self.exited_early => format!(
+                " [early exit after {} lines, verdict could not change]",
+                self.total_log_lines
+            ),
+            None => String::new(),
+        };
+        if self.limit_reached {
+            notes.push_str(" [read limit reached, best-effort verdict]");
+        }
+        if self.loss_warnings > 0 {
+            notes.push_str(&format!(
+                " [message loss: {} LGR warnings, {} messages dropped]",
+                self.loss_warnings, self.dropped_messages
+            ));
+        }
+
+        let matching = percentage_matching >= self.trigger_percentage as f64
+            || (self.log_lines_matching > 0 && self.one_line_match);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_verdict(path, matching, percentage_matching);
+        }
+        if matching {
+            eprintln!(
+                "{} is a {} [{}% line matching]{}",
+                path,
+                self.format.description(),
+                percentage_matching,
+                notes
+            );
+            if self.fail_on_loss && self.loss_warnings > 0 {
+                3
+            } else {
+                0
+            }
+        } else {
+            eprintln!(
+                "{} is NOT {} [{}% line matching]{}",
+                path,
+                self.format.description(),
+                percentage_matching,
+                notes
+            );
+            1
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Envelopes of the log wrappers of a site, e.g. a forwarder writing
+//! `2021-09-28 08:00:09 host=cell-3 app=APP/PROC/WEB index=0 OUT started`, defined in the
+//! configuration file by a regex with named captures:
+//!
+//! - `timestamp`, RFC 3339 unless a `timestamp-format` (`strftime` syntax) is given
+//! - `component`, e.g. `RTR` or `APP/PROC/WEB`, followed by the instance index unless an `index`
+//!   capture gives it
+//! - `channel`, `OUT` or `ERR`, `stdout` and `stderr` being accepted too
+//! - `message`, optional
+//!
+//! Lines matching an envelope are entries like those written by the cf CLI, see
+//! `parse_cf_app_log`. Each envelope is a log format of its own, under the name given to it, see
+//! `formats::Registry`.
+
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+
+use crate::formats::LogFormatDetector;
+use crate::{parse_component, parse_timestamp, CfAppLogEntry, Channel, ChannelValid};
+
+/// Captures an envelope regex must have
+pub const REQUIRED_CAPTURES: &[&str] = &["timestamp", "component", "channel"];
+
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    name: String,
+    /// what a file in the envelope is, the name by default
+    description: Option<String>,
+    regex: Regex,
+    /// `strftime` format of the `timestamp` capture, RFC 3339 by default
+    timestamp_format: Option<String>,
+}
+
+impl Envelope {
+    pub fn new(
+        name: &str,
+        pattern: &str,
+        timestamp_format: Option<&str>,
+    ) -> Result<Envelope, String> {
+        let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+        let names: Vec<&str> = regex.capture_names().flatten().collect();
+        if let Some(missing) = REQUIRED_CAPTURES
+            .iter()
+            .find(|capture| !names.contains(capture))
+        {
+            return Err(format!("missing capture: (?P<{}>...)", missing));
+        }
+        if let Some(format) = timestamp_format {
+            crate::is_timestamp_format(format)?;
+        }
+        Ok(Envelope {
+            name: name.to_string(),
+            description: None,
+            regex,
+            timestamp_format: timestamp_format.map(str::to_string),
+        })
+    }
+
+    /// Tell what a file in the envelope is, e.g. `forwarded CF log`, instead of its name
+    pub fn with_description(mut self, description: &str) -> Envelope {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    fn timestamp(&self, timestamp: &str) -> Option<DateTime<FixedOffset>> {
+        match &self.timestamp_format {
+            Some(format) => parse_timestamp(timestamp, format),
+            None => DateTime::parse_from_rfc3339(timestamp)
+                .or_else(|_| DateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f%z"))
+                .ok(),
+        }
+    }
+}
+
+impl LogFormatDetector for Envelope {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Entry of `line` when it matches the envelope
+    fn parse<'a>(&self, line: &'a str) -> Option<CfAppLogEntry<'a>> {
+        let captures = self.regex.captures(line)?;
+        let timestamp = self.timestamp(&captures["timestamp"])?;
+        let component = match captures.name("index") {
+            Some(index) => format!("[{}/{}]", &captures["component"], index.as_str()),
+            None => format!("[{}]", &captures["component"]),
+        };
+        let (_, component) = parse_component(&component).ok()?;
+        let channel = match captures["channel"].to_uppercase().as_str() {
+            "OUT" | "STDOUT" => Channel::STDOUT,
+            "ERR" | "STDERR" => Channel::STDERR,
+            _ => return None,
+        };
+        Some(CfAppLogEntry {
+            timestamp,
+            component,
+            channel: ChannelValid::Valid(channel),
+            message: captures
+                .name("message")
+                .map(|message| message.as_str())
+                .filter(|message| !message.is_empty()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Component, ComponentInfoValid};
+
+    #[test]
+    fn test_envelope() {
+        let envelope = Envelope::new(
+            "forwarder",
+            r"^(?P<timestamp>\S+ \S+) host=\S+ app=(?P<component>\S+) index=(?P<index>\d+) (?P<channel>\w+) ?(?P<message>.*)$",
+            Some("%Y-%m-%d %H:%M:%S"),
+        )
+        .unwrap();
+        assert_eq!(envelope.name(), "forwarder");
+        assert_eq!(envelope.description(), "forwarder");
+        let entry = envelope
+            .parse("2021-09-28 08:00:09 host=cell-3 app=APP/PROC/WEB index=2 stderr boom")
+            .unwrap();
+        assert_eq!(
+            entry.timestamp,
+            DateTime::parse_from_rfc3339("2021-09-28T08:00:09Z").unwrap()
+        );
+        match entry.component {
+            ComponentInfoValid::Valid(info) => {
+                assert_eq!(info.name, Component::APPLICATION);
+                assert_eq!(info.index, 2);
+                assert_eq!(info.process_type, Some("web".to_string()));
+            }
+            component => panic!("unexpected component: {:?}", component),
+        }
+        assert!(matches!(
+            entry.channel,
+            ChannelValid::Valid(Channel::STDERR)
+        ));
+        assert_eq!(entry.message, Some("boom"));
+
+        // unknown components are kept like in the cf CLI format
+        let entry = envelope
+            .parse("2021-09-28 08:00:09 host=cell-3 app=worker index=0 OUT")
+            .unwrap();
+        assert_eq!(
+            entry.component,
+            ComponentInfoValid::Invalid("worker/0".to_string())
+        );
+        assert_eq!(entry.message, None);
+
+        assert!(envelope
+            .parse("2021-09-28 08:00:09 host=cell-3 app=RTR index=0 DEBUG")
+            .is_none());
+        assert!(envelope
+            .parse("2021-09-28T08:00:09Z host=cell-3 app=RTR index=0 OUT")
+            .is_none());
+
+        let envelope = envelope.with_description("forwarded CF log");
+        assert_eq!(envelope.description(), "forwarded CF log");
+    }
+
+    #[test]
+    fn test_invalid_envelope() {
+        assert_eq!(
+            Envelope::new(
+                "forwarder",
+                r"^(?P<timestamp>\S+) (?P<component>\S+) (?P<message>.*)$",
+                None
+            )
+            .unwrap_err(),
+            "missing capture: (?P<channel>...)"
+        );
+        assert!(Envelope::new("forwarder", r"^(?P<timestamp>\S+", None).is_err());
+        assert!(Envelope::new(
+            "forwarder",
+            r"^(?P<timestamp>\S+) (?P<component>\S+) (?P<channel>\S+)$",
+            Some("%Y %Q")
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Selection of parsed entries on their fields
+
+use chrono::{DateTime, Duration, FixedOffset};
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+
+use crate::level::{level, Level};
+use crate::rtr::rtr_access_log;
+use crate::{CfAppLogEntry, ChannelValid, Component, ComponentInfoValid};
+
+/// Values accepted to select components, `other` being any component which is not known
+pub const COMPONENTS: &[&str] = &["API", "STG", "RTR", "LGR", "APP", "SSH", "CELL", "other"];
+
+/// Values accepted to select channels
+pub const CHANNELS: &[&str] = &["out", "err"];
+
+/// Criteria entries have to meet to be selected, every entry is selected by default
+#[derive(Debug, Default)]
+pub struct EntryFilter {
+    /// upper case component names, `OTHER` for the unknown ones
+    components: Option<Vec<String>>,
+    /// upper case channel names
+    channels: Option<Vec<String>>,
+    instances: Option<Vec<RangeInclusive<u32>>>,
+    message_match: Option<Regex>,
+    message_exclude: Option<Regex>,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+    drop_health_checks: bool,
+    min_level: Option<Level>,
+}
+
+impl EntryFilter {
+    pub fn new() -> EntryFilter {
+        EntryFilter::default()
+    }
+
+    /// Only select entries of these components (case insensitive, see `COMPONENTS`)
+    pub fn with_components<S: AsRef<str>>(mut self, components: &[S]) -> EntryFilter {
+        self.components = Some(
+            components
+                .iter()
+                .map(|component| component.as_ref().to_uppercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only select entries written to these channels (case insensitive, see `CHANNELS`)
+    pub fn with_channels<S: AsRef<str>>(mut self, channels: &[S]) -> EntryFilter {
+        self.channels = Some(
+            channels
+                .iter()
+                .map(|channel| channel.as_ref().to_uppercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only select entries of these instance indexes, see `parse_instances`
+    pub fn with_instances(mut self, instances: Vec<RangeInclusive<u32>>) -> EntryFilter {
+        self.instances = Some(instances);
+        self
+    }
+
+    /// Only select entries whose message matches `regex`, a missing message being empty
+    pub fn with_match(mut self, regex: Regex) -> EntryFilter {
+        self.message_match = Some(regex);
+        self
+    }
+
+    /// Leave out entries whose message matches `regex`, a missing message being empty
+    pub fn with_exclude(mut self, regex: Regex) -> EntryFilter {
+        self.message_exclude = Some(regex);
+        self
+    }
+
+    /// Only select entries logged at or after `since`
+    pub fn with_since(mut self, since: DateTime<FixedOffset>) -> EntryFilter {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only select entries logged at or before `until`
+    pub fn with_until(mut self, until: DateTime<FixedOffset>) -> EntryFilter {
+        self.until = Some(until);
+        self
+    }
+
+    /// Leave out health checks, see `is_health_check`
+    pub fn with_drop_health_checks(mut self) -> EntryFilter {
+        self.drop_health_checks = true;
+        self
+    }
+
+    /// Only select APP entries whose message tells a level at least as severe as `min_level`,
+    /// see `level::level`
+    pub fn with_min_level(mut self, min_level: Level) -> EntryFilter {
+        self.min_level = Some(min_level);
+        self
+    }
+
+    pub fn matches(&self, entry: &CfAppLogEntry) -> bool {
+        if self.since.is_some_and(|since| entry.timestamp < since)
+            || self.until.is_some_and(|until| entry.timestamp > until)
+        {
+            return false;
+        }
+        if let Some(components) = &self.components {
+            let component = match &entry.component {
+                ComponentInfoValid::Valid(info) if info.name != crate::Component::INVALID => {
+                    info.name.as_str()
+                }
+                _ => "OTHER",
+            };
+            if !components.iter().any(|selected| selected == component) {
+                return false;
+            }
+        }
+        if let Some(channels) = &self.channels {
+            let channel = match &entry.channel {
+                ChannelValid::Valid(channel) => channel.as_str(),
+                ChannelValid::Invalid(raw) => raw.as_str(),
+            };
+            if !channels.iter().any(|selected| selected == channel) {
+                return false;
+            }
+        }
+        if let Some(instances) = &self.instances {
+            let index = match &entry.component {
+                ComponentInfoValid::Valid(info) => info.index,
+                ComponentInfoValid::Invalid(_) => return false,
+            };
+            if !instances.iter().any(|range| range.contains(&index)) {
+                return false;
+            }
+        }
+        let message = entry.message.unwrap_or("");
+        if let Some(regex) = &self.message_match {
+            if !regex.is_match(message) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.message_exclude {
+            if regex.is_match(message) {
+                return false;
+            }
+        }
+        if let Some(min_level) = self.min_level {
+            if level(entry).is_none_or(|level| level < min_level) {
+                return false;
+            }
+        }
+        !(self.drop_health_checks && is_health_check(entry))
+    }
+}
+
+/// Paths of the usual health endpoints, e.g. `/health` or `/actuator/health/liveness`
+fn health_path() -> &'static Regex {
+    static HEALTH_PATH: OnceLock<Regex> = OnceLock::new();
+    HEALTH_PATH.get_or_init(|| {
+        Regex::new(r"(?i)^/(health|healthz|healthcheck|actuator/health|readyz?|livez?|ping)(/[^?\s]*)?(\?\S*)?$")
+            .unwrap()
+    })
+}
+
+/// User agents of the platform and load balancer health checkers
+fn health_checker() -> &'static Regex {
+    static HEALTH_CHECKER: OnceLock<Regex> = OnceLock::new();
+    HEALTH_CHECKER.get_or_init(|| {
+        Regex::new(
+            r"(?i)health.?check|kube-probe|GoogleHC|Consul Health|Pingdom|UptimeRobot|StatusCake",
+        )
+        .unwrap()
+    })
+}
+
+/// Whether the entry is a health check rather than real traffic: a Gorouter access log of a
+/// health checker user agent or health endpoint, or an app entry telling a request to a health
+/// endpoint, e.g. `GET /actuator/health 200`
+pub fn is_health_check(entry: &CfAppLogEntry) -> bool {
+    if let Some(log) = rtr_access_log(entry) {
+        return health_path().is_match(log.path)
+            || log
+                .user_agent
+                .is_some_and(|user_agent| health_checker().is_match(user_agent));
+    }
+    match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::APPLICATION => entry
+            .message
+            .unwrap_or("")
+            .split(|c: char| c.is_whitespace() || c == '"')
+            .any(|word| word.starts_with('/') && health_path().is_match(word)),
+        _ => false,
+    }
+}
+
+/// Comma separated instance indexes or ranges of them, e.g. `3` or `0-2,5`
+pub fn parse_instances(instances: &str) -> Result<Vec<RangeInclusive<u32>>, String> {
+    instances
+        .split(',')
+        .map(|instance| {
+            let invalid = || format!("invalid instance: {}", instance);
+            let (start, end) = match instance.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => (instance, instance),
+            };
+            let start = start.trim().parse::<u32>().map_err(|_| invalid())?;
+            let end = end.trim().parse::<u32>().map_err(|_| invalid())?;
+            if start > end {
+                return Err(invalid());
+            }
+            Ok(start..=end)
+        })
+        .collect()
+}
+
+/// Point in time given either as a RFC 3339 date (the offset of the logs, e.g. `+0900`, is
+/// accepted too) or as a duration before `now`, see `parse_duration`, e.g. `15m`
+pub fn parse_time(time: &str, now: DateTime<FixedOffset>) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(time) {
+        return Ok(date);
+    }
+    if let Ok(date) = DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Ok(date);
+    }
+    parse_duration(time)
+        .ok()
+        .and_then(|duration| now.checked_sub_signed(duration))
+        .ok_or_else(|| format!("invalid time: {}", time))
+}
+
+/// Duration given as a number with a `s`, `m`, `h` or `d` suffix, e.g. `15m`
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {}", duration);
+    let seconds = match duration.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count = duration[..duration.len() - 1]
+        .parse::<u32>()
+        .map_err(|_| invalid())?;
+    Ok(Duration::seconds(i64::from(count) * seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_components() {
+        let (_, router) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT").unwrap();
+        let (_, unknown) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [FOO/1] OUT").unwrap();
+
+        assert!(EntryFilter::new().matches(&router));
+        let filter = EntryFilter::new().with_components(&["rtr", "APP"]);
+        assert!(filter.matches(&router));
+        assert!(!filter.matches(&unknown));
+        let filter = EntryFilter::new().with_components(&["other"]);
+        assert!(!filter.matches(&router));
+        assert!(filter.matches(&unknown));
+    }
+
+    #[test]
+    fn test_channels() {
+        let (_, out) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT").unwrap();
+        let (_, err) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/1] ERR").unwrap();
+
+        let filter = EntryFilter::new().with_channels(&["err"]);
+        assert!(!filter.matches(&out));
+        assert!(filter.matches(&err));
+        let filter = filter.with_components(&["RTR"]);
+        assert!(!filter.matches(&err));
+    }
+
+    #[test]
+    fn test_instances() {
+        assert_eq!(parse_instances("3"), Ok(vec![3..=3]));
+        assert_eq!(parse_instances("0-2,5"), Ok(vec![0..=2, 5..=5]));
+        assert!(parse_instances("2-0").is_err());
+        assert!(parse_instances("a").is_err());
+
+        let (_, first) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/1] OUT").unwrap();
+        let (_, third) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/3] OUT").unwrap();
+        let filter = EntryFilter::new().with_instances(parse_instances("0-2").unwrap());
+        assert!(filter.matches(&first));
+        assert!(!filter.matches(&third));
+    }
+
+    #[test]
+    fn test_message_regexes() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT GET /health 200").unwrap();
+
+        let filter = EntryFilter::new().with_match(Regex::new(r"\s200$").unwrap());
+        assert!(filter.matches(&entry));
+        // only the message is looked at, not the envelope
+        let filter = EntryFilter::new().with_match(Regex::new("RTR").unwrap());
+        assert!(!filter.matches(&entry));
+        let filter = EntryFilter::new().with_exclude(Regex::new("/health").unwrap());
+        assert!(!filter.matches(&entry));
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let parse = |line| parse_cf_app_log(line).unwrap().1;
+        let filter = EntryFilter::new().with_drop_health_checks();
+        let probe = parse(
+            r#"2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET /health HTTP/1.1" 200 0 15 "-" "curl/7.64.1" "10.0.0.1:51234" "10.0.1.5:61001""#,
+        );
+        assert!(!filter.matches(&probe));
+        let checker = parse(
+            r#"2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET / HTTP/1.1" 200 0 15 "-" "ELB-HealthChecker/2.0" "10.0.0.1:51234" "10.0.1.5:61001""#,
+        );
+        assert!(!filter.matches(&checker));
+        let request = parse(
+            r#"2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET /healthy-recipes HTTP/1.1" 200 0 15 "-" "curl/7.64.1" "10.0.0.1:51234" "10.0.1.5:61001""#,
+        );
+        assert!(filter.matches(&request));
+        let app = parse("2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] OUT GET /actuator/health/liveness 200 2ms");
+        assert!(!filter.matches(&app));
+        let app = parse("2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] OUT ping from scheduler");
+        assert!(filter.matches(&app));
+        assert!(EntryFilter::new().matches(&probe));
+    }
+
+    #[test]
+    fn test_min_level() {
+        let parse = |line| parse_cf_app_log(line).unwrap().1;
+        let filter = EntryFilter::new().with_min_level(Level::Warn);
+        assert!(filter.matches(&parse(
+            "2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] ERR WARN: slow"
+        )));
+        assert!(filter.matches(&parse(
+            r#"2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] OUT {"level":"fatal"}"#
+        )));
+        assert!(!filter.matches(&parse(
+            "2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] OUT INFO started"
+        )));
+        assert!(!filter.matches(&parse(
+            "2021-09-28T17:00:10.12+0900 [APP/PROC/WEB/0] OUT started"
+        )));
+        assert!(!filter.matches(&parse("2021-09-28T17:00:10.12+0900 [API/0] OUT ERROR boom")));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let now = DateTime::parse_from_rfc3339("2021-09-28T17:30:00+09:00").unwrap();
+        assert_eq!(
+            parse_time("15m", now),
+            DateTime::parse_from_rfc3339("2021-09-28T17:15:00+09:00").map_err(|_| String::new())
+        );
+        assert_eq!(
+            parse_time("2021-09-28T08:00:09.36+0000", now),
+            DateTime::parse_from_rfc3339("2021-09-28T17:00:09.36+09:00").map_err(|_| String::new())
+        );
+        assert!(parse_time("15y", now).is_err());
+        assert_eq!(parse_duration("2h"), Ok(Duration::hours(2)));
+        assert!(parse_time("m", now).is_err());
+        assert_eq!(
+            parse_time("99999999d", now),
+            Err("invalid time: 99999999d".to_string())
+        );
+
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT").unwrap();
+        let filter = EntryFilter::new()
+            .with_since(parse_time("2021-09-28T17:00:00+09:00", now).unwrap())
+            .with_until(parse_time("2021-09-28T17:00:09.36+09:00", now).unwrap());
+        assert!(filter.matches(&entry));
+        let filter = EntryFilter::new().with_since(parse_time("15m", now).unwrap());
+        assert!(!filter.matches(&entry));
+    }
+}
@@ -0,0 +1,638 @@
+//! Log formats a file can be detected to be in, the CF application log written by the cf CLI
+//! being one of them. A `Registry` holds the formats known to the detector; crates using this
+//! one can register their own by implementing `LogFormatDetector`:
+//!
+//! ```
+//! use cf_app_log_detector::formats::{LogFormatDetector, Registry};
+//!
+//! struct Nginx;
+//!
+//! impl LogFormatDetector for Nginx {
+//!     fn name(&self) -> &'static str {
+//!         "nginx"
+//!     }
+//!
+//!     fn description(&self) -> &'static str {
+//!         "nginx access log"
+//!     }
+//!
+//!     fn recognizes(&self, line: &str) -> bool {
+//!         line.contains("\" 200 ")
+//!     }
+//! }
+//!
+//! let mut registry = Registry::default();
+//! registry.register(Nginx);
+//! assert!(registry.get("nginx").is_some());
+//! ```
+
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock};
+
+use crate::{
+    parse_cf_app_log, parse_with_timestamp_format, CfAppLogEntry, Channel, ChannelValid, Component,
+    ComponentInfo, ComponentInfoValid,
+};
+
+/// Tells whether lines are in a log format
+pub trait LogFormatDetector: Send + Sync {
+    /// Short name, e.g. `cf`, given on the command line
+    fn name(&self) -> &str;
+
+    /// What a file in the format is, e.g. `CF application log`
+    fn description(&self) -> &str;
+
+    /// Entry of `line` when it is in the format, for the formats mapping to CF application log
+    /// entries
+    fn parse<'a>(&self, _line: &'a str) -> Option<CfAppLogEntry<'a>> {
+        None
+    }
+
+    /// Entry of `line` when it is in the format, for the formats mapping to CF application log
+    /// entries whose message is not as is in the line, e.g. JSON escaped
+    fn parse_owned(&self, _line: &str) -> Option<OwnedEntry> {
+        None
+    }
+
+    /// Whether `line` is in the format, only asked for the lines `parse` gives no entry of
+    fn recognizes(&self, _line: &str) -> bool {
+        false
+    }
+
+    /// Whether `line` is in the format
+    fn matches(&self, line: &str) -> bool {
+        self.parse(line).is_some() || self.recognizes(line)
+    }
+
+    /// Length of the shortest line in the format, bounding how many lines in the format the
+    /// rest of a file can hold when deciding the verdict early
+    fn shortest_line(&self) -> usize {
+        1
+    }
+}
+
+/// Entry owning its message, see `LogFormatDetector::parse_owned`
+#[derive(Debug)]
+pub struct OwnedEntry {
+    pub timestamp: DateTime<FixedOffset>,
+    pub component: ComponentInfoValid,
+    pub channel: ChannelValid,
+    pub message: Option<String>,
+}
+
+impl OwnedEntry {
+    /// The entry borrowing the message, as parsed from CF application logs
+    pub fn entry(&self) -> CfAppLogEntry<'_> {
+        CfAppLogEntry {
+            timestamp: self.timestamp,
+            component: self.component.clone(),
+            channel: self.channel.clone(),
+            message: self.message.as_deref(),
+        }
+    }
+}
+
+/// Shortest entry the cf CLI writes, e.g. `2021-09-28T17:00:09.36+0900 [RTR/0] OUT`
+const SHORTEST_CF_ENTRY: usize = 39;
+
+/// Entries written by the cf CLI, see `parse_cf_app_log`
+#[derive(Default)]
+pub struct CfAppLog {
+    timestamp_format: Option<String>,
+}
+
+impl CfAppLog {
+    /// Also take the entries whose timestamp is written in `format`, e.g. by a log forwarder
+    /// reshaping them, see `parse_with_timestamp_format`
+    pub fn with_timestamp_format(format: &str) -> CfAppLog {
+        CfAppLog {
+            timestamp_format: Some(format.to_string()),
+        }
+    }
+}
+
+impl LogFormatDetector for CfAppLog {
+    fn name(&self) -> &'static str {
+        "cf"
+    }
+
+    fn description(&self) -> &'static str {
+        "CF application log"
+    }
+
+    fn parse<'a>(&self, line: &'a str) -> Option<CfAppLogEntry<'a>> {
+        let parsed = parse_cf_app_log(line).ok().or_else(|| {
+            self.timestamp_format
+                .as_ref()
+                .and_then(|format| parse_with_timestamp_format(line, format).ok())
+        });
+        parsed.map(|(_, entry)| entry)
+    }
+
+    /// Timestamps written in another format can be shorter
+    fn shortest_line(&self) -> usize {
+        match self.timestamp_format {
+            Some(_) => 1,
+            None => SHORTEST_CF_ENTRY,
+        }
+    }
+}
+
+/// CF application log of the router only, e.g. the access log of a route gathered from
+/// `cf logs` filtered on `RTR`
+#[derive(Default)]
+pub struct RouterLog {
+    cf: CfAppLog,
+}
+
+impl RouterLog {
+    /// Router entries of `cf`
+    pub fn new(cf: CfAppLog) -> RouterLog {
+        RouterLog { cf }
+    }
+}
+
+impl LogFormatDetector for RouterLog {
+    fn name(&self) -> &'static str {
+        "rtr"
+    }
+
+    fn description(&self) -> &'static str {
+        "CF router log"
+    }
+
+    fn parse<'a>(&self, line: &'a str) -> Option<CfAppLogEntry<'a>> {
+        self.cf.parse(line).filter(|entry| match &entry.component {
+            ComponentInfoValid::Valid(info) => info.name == Component::ROUTER,
+            ComponentInfoValid::Invalid(_) => false,
+        })
+    }
+
+    fn shortest_line(&self) -> usize {
+        self.cf.shortest_line()
+    }
+}
+
+/// Syslog messages, RFC 5424 ones like those of syslog drains, e.g.
+/// `<14>1 2021-09-28T08:00:09.36Z host app [APP/PROC/WEB/0] - - started`, and RFC 3164 ones, e.g.
+/// `<14>Sep 28 08:00:09 host app: started`
+pub struct Syslog;
+
+impl LogFormatDetector for Syslog {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn description(&self) -> &'static str {
+        "syslog output"
+    }
+
+    fn recognizes(&self, line: &str) -> bool {
+        static HEADER: OnceLock<Regex> = OnceLock::new();
+        HEADER
+            .get_or_init(|| {
+                Regex::new(
+                    r"^<\d{1,3}>(1 (\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d\S*|-) |[A-Z][a-z]{2} [ \d]\d \d\d:\d\d:\d\d )",
+                )
+                .unwrap()
+            })
+            .is_match(line)
+    }
+}
+
+/// One JSON object per line, e.g. structured logs written by an app
+pub struct JsonLines;
+
+impl LogFormatDetector for JsonLines {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn description(&self) -> &'static str {
+        "JSON lines"
+    }
+
+    fn recognizes(&self, line: &str) -> bool {
+        let line = line.trim();
+        line.starts_with('{')
+            && serde_json::from_str::<serde_json::Value>(line).is_ok_and(|value| value.is_object())
+    }
+}
+
+/// Container logs written by the kubelet through the CRI, e.g.
+/// `2021-09-28T11:58:42.73Z stdout F started`, `P` instead of `F` telling a partial line
+pub struct KubernetesLog;
+
+impl LogFormatDetector for KubernetesLog {
+    fn name(&self) -> &'static str {
+        "cri"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kubernetes container log"
+    }
+
+    fn recognizes(&self, line: &str) -> bool {
+        static PREFIX: OnceLock<Regex> = OnceLock::new();
+        PREFIX
+            .get_or_init(|| {
+                Regex::new(
+                    r"^\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d(\.\d+)?(Z|[+-]\d\d:\d\d) (stdout|stderr) [FP]( |$)",
+                )
+                .unwrap()
+            })
+            .is_match(line)
+    }
+}
+
+/// Container logs written by the json-file logging driver of Docker, e.g.
+/// `{"log":"started\n","stream":"stdout","time":"2021-09-28T11:58:42.730158Z"}`. Their entries
+/// are those of a `docker` component.
+pub struct DockerLog;
+
+impl LogFormatDetector for DockerLog {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn description(&self) -> &'static str {
+        "Docker container log"
+    }
+
+    fn parse_owned(&self, line: &str) -> Option<OwnedEntry> {
+        let line = line.trim();
+        if !line.starts_with('{') {
+            return None;
+        }
+        let object: Value = serde_json::from_str(line).ok()?;
+        let channel = match object.get("stream")?.as_str()? {
+            "stdout" => Channel::STDOUT,
+            "stderr" => Channel::STDERR,
+            _ => return None,
+        };
+        let timestamp = DateTime::parse_from_rfc3339(object.get("time")?.as_str()?).ok()?;
+        // lines end with the newline the container wrote
+        let message = object
+            .get("log")?
+            .as_str()?
+            .trim_end_matches(&['\r', '\n'][..]);
+        Some(OwnedEntry {
+            timestamp,
+            component: ComponentInfoValid::Invalid("docker".to_string()),
+            channel: ChannelValid::Valid(channel),
+            message: Some(message.to_string()).filter(|message| !message.is_empty()),
+        })
+    }
+
+    fn recognizes(&self, line: &str) -> bool {
+        self.parse_owned(line).is_some()
+    }
+}
+
+/// Logs of Heroku apps as drained from Logplex or written by `heroku logs`, e.g.
+/// `2021-09-28T11:58:42.730158+00:00 app[web.1]: started`. Their sources map to the CF
+/// components doing the same job: `heroku[router]` to RTR, the dyno manager (`heroku[web.1]`)
+/// to CELL, `app[api]` to API, `heroku[logplex]` to LGR, and dynos to APP with their process
+/// type. Heroku tells no channel, entries are written to OUT.
+pub struct HerokuLog;
+
+impl LogFormatDetector for HerokuLog {
+    fn name(&self) -> &'static str {
+        "heroku"
+    }
+
+    fn description(&self) -> &'static str {
+        "Heroku log"
+    }
+
+    fn parse<'a>(&self, line: &'a str) -> Option<CfAppLogEntry<'a>> {
+        static LINE: OnceLock<Regex> = OnceLock::new();
+        let captures = LINE
+            .get_or_init(|| {
+                Regex::new(r"^(\d{4}-\d\d-\d\dT\S+) (app|heroku)\[([\w.-]+)\]: ?(.*)$").unwrap()
+            })
+            .captures(line)?;
+        let timestamp = DateTime::parse_from_rfc3339(&captures[1]).ok()?;
+        let component = heroku_component(&captures[2], &captures[3]);
+        let message = captures.get(4).map_or("", |message| message.as_str());
+        Some(CfAppLogEntry {
+            timestamp,
+            component,
+            channel: ChannelValid::Valid(Channel::STDOUT),
+            message: Some(message).filter(|message| !message.is_empty()),
+        })
+    }
+}
+
+/// Component of the Heroku `source[dyno]` of a line, see `HerokuLog`
+fn heroku_component(source: &str, dyno: &str) -> ComponentInfoValid {
+    let component = |name, process_type: Option<&str>, index| {
+        ComponentInfoValid::Valid(ComponentInfo {
+            name,
+            index,
+            process_type: process_type.map(str::to_string),
+        })
+    };
+    match (source, dyno) {
+        ("heroku", "router") => component(Component::ROUTER, None, 0),
+        ("heroku", "logplex") => component(Component::LOGGREGATOR, None, 0),
+        ("app", "api") => component(Component::API, None, 0),
+        _ => match dyno
+            .rsplit_once('.')
+            .and_then(|(process_type, index)| Some((process_type, index.parse().ok()?)))
+        {
+            Some((process_type, index)) if source == "heroku" => {
+                component(Component::CELL, Some(process_type), index)
+            }
+            Some((process_type, index)) => {
+                component(Component::APPLICATION, Some(process_type), index)
+            }
+            // e.g. app[heroku-postgres]
+            None => ComponentInfoValid::Invalid(format!("{}[{}]", source, dyno)),
+        },
+    }
+}
+
+/// Formats the detector knows about, in order of registration
+#[derive(Clone)]
+pub struct Registry {
+    formats: Vec<Arc<dyn LogFormatDetector>>,
+}
+
+impl Default for Registry {
+    /// The formats of this crate, those a line of another one can also be in first, e.g. Docker
+    /// logs before JSON lines, as the first formats registered win ties
+    fn default() -> Registry {
+        let mut registry = Registry::empty();
+        registry.register(RouterLog::default());
+        registry.register(CfAppLog::default());
+        registry.register(KubernetesLog);
+        registry.register(DockerLog);
+        registry.register(HerokuLog);
+        registry.register(Syslog);
+        registry.register(JsonLines);
+        registry
+    }
+}
+
+impl Registry {
+    pub fn empty() -> Registry {
+        Registry {
+            formats: Vec::new(),
+        }
+    }
+
+    /// The CF formats also taking the entries whose timestamp is written in `format`, see
+    /// `CfAppLog::with_timestamp_format`
+    pub fn with_timestamp_format(mut self, format: &str) -> Registry {
+        self.register(RouterLog::new(CfAppLog::with_timestamp_format(format)));
+        self.register(CfAppLog::with_timestamp_format(format));
+        self
+    }
+
+    /// Add `format`, replacing the one registered under the same name
+    pub fn register<F: LogFormatDetector + 'static>(&mut self, format: F) {
+        match self
+            .formats
+            .iter()
+            .position(|registered| registered.name() == format.name())
+        {
+            Some(index) => self.formats[index] = Arc::new(format),
+            None => self.formats.push(Arc::new(format)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn LogFormatDetector>> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.formats.iter().map(|format| format.name()).collect()
+    }
+
+    pub fn formats(&self) -> &[Arc<dyn LogFormatDetector>] {
+        &self.formats
+    }
+
+    /// Counts of the lines of each format, the formats being run side by side
+    pub fn tally(&self) -> Tally {
+        Tally {
+            formats: self.formats.clone(),
+            matching: vec![0; self.formats.len()],
+            lines: 0,
+        }
+    }
+}
+
+/// Lines in each format of a registry among the lines seen
+pub struct Tally {
+    formats: Vec<Arc<dyn LogFormatDetector>>,
+    matching: Vec<usize>,
+    lines: usize,
+}
+
+impl Tally {
+    pub fn line(&mut self, line: &str) {
+        self.lines += 1;
+        for (format, matching) in self.formats.iter().zip(self.matching.iter_mut()) {
+            if format.matches(line) {
+                *matching += 1;
+            }
+        }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Share of the lines seen in each format, from 0 to 100, in order of registration
+    pub fn percentages(&self) -> Vec<(&dyn LogFormatDetector, f64)> {
+        self.formats
+            .iter()
+            .zip(&self.matching)
+            .map(|(format, matching)| {
+                let percentage = if self.lines > 0 {
+                    *matching as f64 / self.lines as f64 * 100.0
+                } else {
+                    0.0
+                };
+                (format.as_ref(), percentage)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        let app = "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT started";
+        let router = "2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /";
+        let (cf, rtr) = (CfAppLog::default(), RouterLog::default());
+        assert!(cf.matches(app) && cf.matches(router));
+        assert!(!rtr.matches(app) && rtr.matches(router));
+        assert!(Syslog.matches("<14>1 2021-09-28T08:00:09.36Z host app [APP/PROC/WEB/0] - - x"));
+        assert!(Syslog.matches("<14>Sep 28 08:00:09 host app: started"));
+        assert!(Syslog.matches("<14>Sep  8 08:00:09 host app: started"));
+        assert!(!Syslog.matches(app));
+        assert!(JsonLines.matches(r#" {"level":"info","msg":"started"}"#));
+        assert!(!JsonLines.matches(r#"{"level":"info""#));
+        assert!(!JsonLines.matches("[1, 2]"));
+        assert!(KubernetesLog.matches("2021-09-28T11:58:42.73Z stdout F started"));
+        assert!(KubernetesLog.matches("2021-09-28T11:58:42.730158+09:00 stderr P"));
+        assert!(!KubernetesLog.matches("2021-09-28T11:58:42.73Z stdout started"));
+        assert!(!KubernetesLog.matches(app));
+
+        let docker = r#"{"log":"GET /\tdone \"ok\"\n","stream":"stderr","time":"2021-09-28T11:58:42.730158Z"}"#;
+        assert!(DockerLog.matches(docker) && JsonLines.matches(docker));
+        let owned = DockerLog.parse_owned(docker).unwrap();
+        let entry = owned.entry();
+        assert_eq!(
+            entry.timestamp,
+            DateTime::parse_from_rfc3339("2021-09-28T11:58:42.730158Z").unwrap()
+        );
+        assert_eq!(
+            entry.component,
+            ComponentInfoValid::Invalid("docker".to_string())
+        );
+        assert!(matches!(
+            entry.channel,
+            ChannelValid::Valid(Channel::STDERR)
+        ));
+        assert_eq!(entry.message, Some("GET /\tdone \"ok\""));
+        assert!(DockerLog
+            .parse_owned(r#"{"log":"\n","stream":"stdout","time":"2021-09-28T11:58:42Z"}"#)
+            .is_some_and(|owned| owned.message.is_none()));
+        assert!(!DockerLog.matches(r#"{"log":"x","stream":"stdin","time":"2021-09-28T11:58:42Z"}"#));
+        assert!(!DockerLog.matches(r#"{"log":"x","stream":"stdout"}"#));
+    }
+
+    #[test]
+    fn test_heroku_log() {
+        let instance = |line| match HerokuLog.parse(line).map(|entry| entry.component) {
+            Some(ComponentInfoValid::Valid(info)) => info.instance(),
+            component => panic!("unexpected component: {:?}", component),
+        };
+        let router = "2021-09-28T11:58:42.730158+00:00 heroku[router]: at=info method=GET path=\"/\" host=app.herokuapp.com status=200";
+        assert_eq!(instance(router), "RTR/0");
+        assert_eq!(
+            instance("2021-09-28T11:58:42+00:00 app[web.1]: started"),
+            "APP/web/1"
+        );
+        assert_eq!(
+            instance(
+                "2021-09-28T11:58:42+00:00 heroku[worker.2]: State changed from starting to up"
+            ),
+            "CELL/worker/2"
+        );
+        assert_eq!(
+            instance("2021-09-28T11:58:42+00:00 app[api]: Release v2 created by user@example.com"),
+            "API/0"
+        );
+
+        let entry = HerokuLog
+            .parse("2021-09-28T11:58:42+00:00 heroku[logplex]: Error L10 (output buffer overflow): 500 messages dropped since 2021-09-28T11:50:00+00:00.")
+            .unwrap();
+        assert_eq!(
+            crate::loggregator::loss_warning(&entry).unwrap().dropped,
+            Some(500)
+        );
+        let entry = HerokuLog
+            .parse("2021-09-28T11:58:42+00:00 app[heroku-postgres]:")
+            .unwrap();
+        assert_eq!(
+            entry.component,
+            ComponentInfoValid::Invalid("app[heroku-postgres]".to_string())
+        );
+        assert!(matches!(
+            entry.channel,
+            ChannelValid::Valid(Channel::STDOUT)
+        ));
+        assert_eq!(entry.message, None);
+
+        assert!(!HerokuLog.matches("2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /"));
+        assert!(!HerokuLog.matches("Sep 28 11:58:42 app[web.1]: started"));
+    }
+
+    #[test]
+    fn test_registry() {
+        struct Override;
+
+        impl LogFormatDetector for Override {
+            fn name(&self) -> &'static str {
+                "rtr"
+            }
+
+            fn description(&self) -> &'static str {
+                "anything"
+            }
+
+            fn recognizes(&self, _line: &str) -> bool {
+                true
+            }
+        }
+
+        let mut registry = Registry::default();
+        assert_eq!(
+            registry.names(),
+            vec!["rtr", "cf", "cri", "docker", "heroku", "syslog", "json"]
+        );
+        registry.register(Override);
+        assert_eq!(
+            registry.names(),
+            vec!["rtr", "cf", "cri", "docker", "heroku", "syslog", "json"]
+        );
+        assert_eq!(registry.get("rtr").unwrap().description(), "anything");
+        assert!(registry.get("yaml").is_none());
+
+        let mut tally = Registry::default().tally();
+        tally.line("2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /");
+        tally.line("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT started");
+        tally.line("<14>Sep 28 08:00:09 host app: started");
+        tally.line("not a log line");
+        let percentages: Vec<(&str, f64)> = tally
+            .percentages()
+            .into_iter()
+            .map(|(format, percentage)| (format.name(), percentage))
+            .collect();
+        assert_eq!(
+            percentages,
+            vec![
+                ("rtr", 25.0),
+                ("cf", 50.0),
+                ("cri", 0.0),
+                ("docker", 0.0),
+                ("heroku", 0.0),
+                ("syslog", 25.0),
+                ("json", 0.0)
+            ]
+        );
+        assert_eq!(tally.lines(), 4);
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        let app = "2021-09-28 17:00:09.360 +0900 [APP/PROC/WEB/0] OUT started";
+        let router = "2021-09-28 17:00:09.360 +0900 [RTR/0] OUT GET /";
+        let registry = Registry::default();
+        assert!(!registry.get("cf").unwrap().matches(app));
+
+        let registry = registry.with_timestamp_format("%Y-%m-%d %H:%M:%S%.f %z");
+        assert_eq!(
+            registry.names(),
+            vec!["rtr", "cf", "cri", "docker", "heroku", "syslog", "json"]
+        );
+        let (cf, rtr) = (registry.get("cf").unwrap(), registry.get("rtr").unwrap());
+        assert!(cf.matches(app) && cf.matches(router));
+        assert!(!rtr.matches(app) && rtr.matches(router));
+        // the cf CLI format is still taken
+        assert!(cf.matches("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT started"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Guess of the logging framework of an app from the messages it writes, telling which parser
+//! to apply next to the messages of APP entries
+
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Framework {
+    /// Java loggers writing the default patterns of Logback, Log4j or Spring Boot
+    Logback,
+    /// Node.js, JSON lines with a `"v":0` version and an ISO 8601 time
+    Bunyan,
+    /// Node.js, JSON lines with a numeric level and time
+    Pino,
+    /// Ruby `Logger` and Rails request logs
+    Rails,
+    /// Python `logging`, gunicorn, and tracebacks
+    Python,
+}
+
+impl Framework {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Framework::Logback => "logback/log4j",
+            Framework::Bunyan => "bunyan",
+            Framework::Pino => "pino",
+            Framework::Rails => "ruby/rails",
+            Framework::Python => "python",
+        }
+    }
+}
+
+struct Patterns {
+    logback: Regex,
+    rails: Regex,
+    python: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        logback: Regex::new(concat!(
+            // Spring Boot: `2021-09-28 08:00:09.360  INFO 1 --- [main] c.e.App : ...`, Spring
+            // Cloud Sleuth adding `[app,trace,span]` before the pid
+            r"^\d{4}-\d{2}-\d{2}[ T][\d:.]+\S*\s+(?:TRACE|DEBUG|INFO|WARN|ERROR)\s+(?:\[[^\]]*\]\s+)?\d+ --- \[",
+            // Logback: `08:00:09.360 [main] INFO c.e.App - ...`
+            r"|^(?:\d{4}-\d{2}-\d{2}[ T])?\d{2}:\d{2}:\d{2}[.,]\d{3} \[[^\]]+\] (?:TRACE|DEBUG|INFO|WARN|ERROR)\s+\S+ - ",
+            // Log4j: `2021-09-28 08:00:09,360 INFO [main] c.e.App: ...`
+            r"|^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3} (?:TRACE|DEBUG|INFO|WARN|ERROR|FATAL)\s+\[",
+        ))
+        .unwrap(),
+        rails: Regex::new(concat!(
+            // Logger: `I, [2021-09-28T08:00:09.360123 #1]  INFO -- : ...`
+            r"^[DIWEFA], \[\d{4}-\d{2}-\d{2}T[\d:.]+ #\d+\]\s+[A-Z]+ -- ",
+            r#"|^Started (?:GET|POST|PUT|PATCH|DELETE|HEAD|OPTIONS) "[^"]*" for "#,
+            r"|^Processing by \w+(?:::\w+)*#\w+ as ",
+            r"|^Completed \d{3} [A-Za-z ]+ in \d+",
+        ))
+        .unwrap(),
+        python: Regex::new(concat!(
+            // logging.basicConfig: `INFO:app.views:...`
+            r"^(?:DEBUG|INFO|WARNING|ERROR|CRITICAL):[\w.]+:",
+            // `2021-09-28 08:00:09,360 - app.views - INFO - ...`
+            r"|^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3} - [\w.]+ - (?:DEBUG|INFO|WARNING|ERROR|CRITICAL) - ",
+            // gunicorn: `[2021-09-28 08:00:09 +0000] [1] [INFO] ...`
+            r"|^\[\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2} [+-]\d{4}\] \[\d+\] \[(?:DEBUG|INFO|WARNING|ERROR|CRITICAL)\] ",
+            r"|^Traceback \(most recent call last\):",
+        ))
+        .unwrap(),
+    })
+}
+
+/// Framework which wrote `message`, `None` when nothing tells
+pub fn framework(message: &str) -> Option<Framework> {
+    if message.starts_with('{') {
+        return json_framework(message);
+    }
+    let patterns = patterns();
+    if patterns.logback.is_match(message) {
+        Some(Framework::Logback)
+    } else if patterns.rails.is_match(message) {
+        Some(Framework::Rails)
+    } else if patterns.python.is_match(message) {
+        Some(Framework::Python)
+    } else {
+        None
+    }
+}
+
+/// Bunyan and pino both write `level` as a number along `hostname` and `pid`, bunyan adding a
+/// `v` version and writing `time` as a string
+fn json_framework(message: &str) -> Option<Framework> {
+    let object = match serde_json::from_str::<Value>(message) {
+        Ok(Value::Object(object)) => object,
+        _ => return None,
+    };
+    if !object.get("level").is_some_and(Value::is_u64) || !object.contains_key("hostname") {
+        return None;
+    }
+    match (object.get("v"), object.get("time")) {
+        (Some(_), Some(Value::String(_))) => Some(Framework::Bunyan),
+        (None, Some(Value::Number(_))) => Some(Framework::Pino),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framework() {
+        for (message, expected) in &[
+            (
+                "2021-09-28 08:00:09.360  INFO 1 --- [           main] c.e.Application : Started",
+                Some(Framework::Logback),
+            ),
+            (
+                "2021-09-28 08:00:09.361 DEBUG [,6152cb8077136e53,942078a29eb7d0d8] 15 --- [   scheduling-1] i.s.Scheduler : done",
+                Some(Framework::Logback),
+            ),
+            (
+                "08:00:09.360 [http-nio-8080-exec-1] WARN  c.e.Controller - slow request",
+                Some(Framework::Logback),
+            ),
+            (
+                r#"{"name":"app","hostname":"abc","pid":7,"level":30,"msg":"hi","time":"2021-09-28T08:00:09.360Z","v":0}"#,
+                Some(Framework::Bunyan),
+            ),
+            (
+                r#"{"level":30,"time":1632816009360,"pid":7,"hostname":"abc","msg":"hi"}"#,
+                Some(Framework::Pino),
+            ),
+            (
+                "I, [2021-09-28T08:00:09.360123 #1]  INFO -- : Started",
+                Some(Framework::Rails),
+            ),
+            (
+                r#"Started GET "/health" for 10.0.0.1 at 2021-09-28 08:00:09 +0000"#,
+                Some(Framework::Rails),
+            ),
+            ("INFO:app.views:request handled", Some(Framework::Python)),
+            (
+                "[2021-09-28 08:00:09 +0000] [7] [INFO] Booting worker with pid: 7",
+                Some(Framework::Python),
+            ),
+            (r#"{"msg":"hi"}"#, None),
+            ("Listening on port 8080", None),
+        ] {
+            assert_eq!(framework(message), *expected, "{}", message);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Reading inputs line by line, whatever their size or encoding
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+const BINARY_DETECTION_CHUNK_SIZE: usize = 8192;
+
+/// Open a file for reading, `-` standing for stdin
+pub fn open(input: &str) -> io::Result<Box<dyn BufRead>> {
+    if input == "-" {
+        Ok(Box::new(io::BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(io::BufReader::new(fs::File::open(input)?)))
+    }
+}
+
+/// Iterator over the lines of a reader, without their terminator and truncated to
+/// `max_line_length` bytes
+pub struct Lines<R> {
+    reader: R,
+    buf: Vec<u8>,
+    max_line_length: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    pub fn new(reader: R, max_line_length: usize) -> Lines<R> {
+        Lines {
+            reader,
+            buf: Vec::new(),
+            max_line_length,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buf.clear();
+        match read_line_capped(&mut self.reader, &mut self.buf, self.max_line_length) {
+            Ok((0, _)) => None,
+            Ok(_) => {
+                let line = line_to_str(&self.buf);
+                Some(Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Remove ANSI escape sequences (colors) the cf CLI adds when writing to a terminal
+pub fn strip_ansi(line: &str) -> Cow<'_, str> {
+    // no escape sequence to strip, spare the copy
+    if !line.contains('\x1b') {
+        return Cow::Borrowed(line);
+    }
+    match strip_ansi_escapes::strip(line) {
+        Ok(stripped) => Cow::Owned(line_to_str(&stripped).into_owned()),
+        Err(_) => Cow::Borrowed(line),
+    }
+}
+
+/// Expand the directories among `inputs` into the files they contain, recursively. Files
+/// found in directories are flagged, unlike the ones given explicitly.
+pub fn expand_inputs(inputs: &[&str]) -> io::Result<Vec<(PathBuf, bool)>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut found = Vec::new();
+            collect_files(path, &mut found)?;
+            files.extend(found.into_iter().map(|path| (path, true)));
+        } else {
+            files.push((path.to_path_buf(), false));
+        }
+    }
+    Ok(files)
+}
+
+/// Recursively list the files of a directory, in a stable order. Symlinks to directories are
+/// not followed as they can loop back to an ancestor, those to files are kept.
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if fs::symlink_metadata(&path)?.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Like `BufRead::read_line` but only the first `max_length` bytes of the line are appended
+/// to `buf`, the rest of the line is read and dropped so a huge line never sits in memory.
+/// Returns the number of bytes read and whether the end of the line was reached.
+pub fn read_line_capped<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_length: usize,
+) -> io::Result<(usize, bool)> {
+    let mut read = 0;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            return Ok((read, false));
+        }
+        let (chunk, complete) = match memchr::memchr(b'\n', available) {
+            Some(newline) => (&available[..=newline], true),
+            None => (available, false),
+        };
+        let kept = chunk.len().min(max_length.saturating_sub(buf.len()));
+        buf.extend_from_slice(&chunk[..kept]);
+
+        let length = chunk.len();
+        reader.consume(length);
+        read += length;
+        if complete {
+            return Ok((read, true));
+        }
+    }
+}
+
+/// Lines are not required to be valid UTF-8 (or can have been truncated in the middle of a
+/// character), invalid sequences are replaced rather than failing the whole file
+pub fn line_to_str(line: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(line) {
+        Ok(line) => Cow::Borrowed(line),
+        Err(_) => String::from_utf8_lossy(line),
+    }
+}
+
+/// A file is considered binary when its first chunk contains a NUL byte
+pub fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut chunk = Vec::with_capacity(BINARY_DETECTION_CHUNK_SIZE);
+    fs::File::open(path)?
+        .take(BINARY_DETECTION_CHUNK_SIZE as u64)
+        .read_to_end(&mut chunk)?;
+    Ok(chunk.contains(&0))
+}
@@ -0,0 +1,268 @@
+//! Severity apps write in their messages, e.g. `INFO` of a Logback pattern or the `level` field
+//! of JSON messages
+
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::{CfAppLogEntry, Channel, ChannelValid, Component, ComponentInfoValid};
+
+/// Levels from the least to the most severe
+pub const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error", "fatal"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        LEVELS[*self as usize]
+    }
+
+    /// RFC 5424 severity, also used by GELF: 3 is error and 6 informational
+    pub fn syslog_severity(&self) -> u8 {
+        match self {
+            Level::Trace | Level::Debug => 7,
+            Level::Info => 6,
+            Level::Warn => 4,
+            Level::Error => 3,
+            Level::Fatal => 2,
+        }
+    }
+
+    /// Level of a severity name, e.g. `WARNING` of Python or `SEVERE` of `java.util.logging`
+    fn from_name(name: &str) -> Option<Level> {
+        match name.to_ascii_lowercase().as_str() {
+            "trace" | "finest" | "finer" => Some(Level::Trace),
+            "debug" | "fine" => Some(Level::Debug),
+            "info" | "notice" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" | "err" | "severe" => Some(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" => Some(Level::Fatal),
+            _ => None,
+        }
+    }
+
+    /// Level of the numbers of bunyan and pino, 30 being info
+    fn from_number(number: u64) -> Option<Level> {
+        match number {
+            0..=19 => Some(Level::Trace),
+            20..=29 => Some(Level::Debug),
+            30..=39 => Some(Level::Info),
+            40..=49 => Some(Level::Warn),
+            50..=59 => Some(Level::Error),
+            _ => Some(Level::Fatal),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::from_name(s).ok_or_else(|| format!("unknown level: {}", s))
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+struct Patterns {
+    /// first severity token: upper case ones, bracketed or `level=` ones of any case
+    token: Regex,
+    /// severity letter of the Ruby `Logger`, e.g. `E, [2021-09-28T08:00:09.360123 #1]`
+    ruby: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        token: Regex::new(concat!(
+            r"\b(TRACE|DEBUG|INFO|NOTICE|WARN(?:ING)?|ERROR|SEVERE|FATAL|CRITICAL)\b",
+            r"|(?i:\[(trace|debug|info|notice|warn(?:ing)?|error|fatal|critical)\]",
+            r"|\blevel=(trace|debug|info|notice|warn(?:ing)?|error|fatal|critical)\b)",
+        ))
+        .unwrap(),
+        ruby: Regex::new(r"^([DIWEF]), \[\d{4}-\d{2}-\d{2}T").unwrap(),
+    })
+}
+
+/// Level written in `message`: the `level` (or `severity`, `log.level`, `levelname`) field of
+/// JSON objects, the first severity token of the others
+pub fn message_level(message: &str) -> Option<Level> {
+    if message.starts_with('{') {
+        if let Ok(Value::Object(object)) = serde_json::from_str::<Value>(message) {
+            let value = ["level", "severity", "log.level", "levelname"]
+                .iter()
+                .find_map(|key| object.get(*key))?;
+            return match value {
+                Value::String(name) => Level::from_name(name),
+                Value::Number(number) => number.as_u64().and_then(Level::from_number),
+                _ => None,
+            };
+        }
+    }
+    let patterns = patterns();
+    if let Some(captures) = patterns.ruby.captures(message) {
+        return match &captures[1] {
+            "D" => Some(Level::Debug),
+            "I" => Some(Level::Info),
+            "W" => Some(Level::Warn),
+            "E" => Some(Level::Error),
+            _ => Some(Level::Fatal),
+        };
+    }
+    let captures = patterns.token.captures(message)?;
+    let name = captures.iter().skip(1).flatten().next()?;
+    Level::from_name(name.as_str())
+}
+
+/// Level of an APP entry, `None` for the entries of the platform components and the messages
+/// without one
+pub fn level(entry: &CfAppLogEntry) -> Option<Level> {
+    match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::APPLICATION => {
+            entry.message.and_then(message_level)
+        }
+        _ => None,
+    }
+}
+
+/// Severity output formats give entries: the level of APP entries, `error` for the other
+/// entries written to stderr and `info` for the rest. Apps not following these conventions,
+/// e.g. writing every line to stderr, get their own mapping, see `from_str`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeverityMapping {
+    out: Level,
+    err: Level,
+    /// level given to each level told by messages, from the least severe
+    levels: [Level; 6],
+}
+
+impl Default for SeverityMapping {
+    fn default() -> SeverityMapping {
+        SeverityMapping {
+            out: Level::Info,
+            err: Level::Error,
+            levels: [
+                Level::Trace,
+                Level::Debug,
+                Level::Info,
+                Level::Warn,
+                Level::Error,
+                Level::Fatal,
+            ],
+        }
+    }
+}
+
+impl SeverityMapping {
+    pub fn severity(&self, entry: &CfAppLogEntry) -> Level {
+        match (level(entry), &entry.channel) {
+            (Some(level), _) => self.levels[level as usize],
+            (None, ChannelValid::Valid(Channel::STDERR)) => self.err,
+            (None, _) => self.out,
+        }
+    }
+}
+
+impl FromStr for SeverityMapping {
+    type Err = String;
+
+    /// Comma separated overrides of the default mapping, `out` and `err` giving the level of
+    /// the entries without one and level names mapping the levels told by messages, e.g.
+    /// `err=info,debug=info`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mapping = SeverityMapping::default();
+        for pair in s.split(',') {
+            let (from, to) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected FROM=LEVEL: {}", pair))?;
+            let to = to.trim().parse()?;
+            match from.trim().to_ascii_lowercase().as_str() {
+                "out" => mapping.out = to,
+                "err" => mapping.err = to,
+                from => mapping.levels[from.parse::<Level>()? as usize] = to,
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_message_level() {
+        for (message, expected) in &[
+            (
+                "2021-09-28 08:00:09.360  WARN 1 --- [main] c.e.App : slow",
+                Some(Level::Warn),
+            ),
+            (
+                "08:00:09.360 [main] ERROR c.e.App - boom",
+                Some(Level::Error),
+            ),
+            (r#"{"level":"debug","msg":"hi"}"#, Some(Level::Debug)),
+            (r#"{"level":50,"msg":"hi"}"#, Some(Level::Error)),
+            (r#"{"log.level":"warning"}"#, Some(Level::Warn)),
+            (
+                "E, [2021-09-28T08:00:09.360123 #1] ERROR -- : boom",
+                Some(Level::Error),
+            ),
+            (
+                "[2021-09-28 08:00:09 +0000] [7] [info] Booting",
+                Some(Level::Info),
+            ),
+            ("time=now level=warn msg=slow", Some(Level::Warn)),
+            ("CRITICAL:app.views:down", Some(Level::Fatal)),
+            ("no error here", None),
+            (r#"{"msg":"hi"}"#, None),
+        ] {
+            assert_eq!(message_level(message), *expected, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_severity_mapping() {
+        let parse = |line| parse_cf_app_log(line).unwrap().1;
+        let warn = parse("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR WARN: slow");
+        let err = parse("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom");
+        let out = parse("2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /");
+        let mapping = SeverityMapping::default();
+        assert_eq!(mapping.severity(&warn), Level::Warn);
+        assert_eq!(mapping.severity(&err), Level::Error);
+        assert_eq!(mapping.severity(&out), Level::Info);
+        let mapping: SeverityMapping = "err=info, warn=error".parse().unwrap();
+        assert_eq!(mapping.severity(&warn), Level::Error);
+        assert_eq!(mapping.severity(&err), Level::Info);
+        assert!("err".parse::<SeverityMapping>().is_err());
+        assert!("loud=error".parse::<SeverityMapping>().is_err());
+    }
+
+    #[test]
+    fn test_level() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT ERROR boom")
+                .unwrap();
+        assert_eq!(level(&entry), Some(Level::Error));
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [API/0] OUT ERROR boom").unwrap();
+        assert_eq!(level(&entry), None);
+        assert_eq!("WARNING".parse(), Ok(Level::Warn));
+        assert!(LEVELS.iter().all(|name| name.parse::<Level>().is_ok()));
+    }
+}
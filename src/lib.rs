@@ -1,8 +1,33 @@
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
 use nom::*;
 
+pub mod audit;
+pub mod balance;
+pub mod color;
+pub mod config;
+pub mod crash;
+pub mod detector;
+pub mod envelope;
+pub mod filter;
+pub mod formats;
+pub mod framework;
+pub mod input;
+pub mod level;
+pub mod loggregator;
+pub mod metrics;
+pub mod output;
+pub mod redact;
+pub mod rtr;
+pub mod ssh;
+pub mod staging;
+pub mod templates;
+pub mod time;
+pub mod timeline;
+pub mod trace;
+
 // https://docs.cloudfoundry.org/devguide/deploy-apps/streaming-logs.html#format
-#[derive(Debug, PartialEq, PartialOrd, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
 pub enum Component {
     API,
     STAGING,
@@ -14,26 +39,65 @@ pub enum Component {
     INVALID,
 }
 
-#[derive(Debug)]
+impl Component {
+    /// Name of the component as it appears in the log envelope
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::API => "API",
+            Component::STAGING => "STG",
+            Component::ROUTER => "RTR",
+            Component::LOGGREGATOR => "LGR",
+            Component::APPLICATION => "APP",
+            Component::SSH => "SSH",
+            Component::CELL => "CELL",
+            Component::INVALID => "INVALID",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ComponentInfoValid {
     Valid(ComponentInfo),
     Invalid(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ComponentInfo {
     pub name: Component,
     pub index: u32,
+    /// e.g. `web` for `[APP/PROC/WEB/0]`, the task name for `[APP/TASK/migrate/0]`
+    pub process_type: Option<String>,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq)]
+impl ComponentInfo {
+    /// Name of the instance, process types being told apart, e.g. `APP/web/0` and `RTR/0`
+    pub fn instance(&self) -> String {
+        match &self.process_type {
+            Some(process_type) => format!("{}/{}/{}", self.name.as_str(), process_type, self.index),
+            None => format!("{}/{}", self.name.as_str(), self.index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq)]
 pub enum Channel {
     STDOUT,
     STDERR,
     INVALID,
 }
 
-#[derive(Debug)]
+impl Channel {
+    /// Name of the channel as it appears in the log envelope
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::STDOUT => "OUT",
+            Channel::STDERR => "ERR",
+            Channel::INVALID => "INVALID",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ChannelValid {
     Valid(Channel),
     Invalid(String),
@@ -68,6 +132,24 @@ named!(parse_component_name <&str, Component>,
     )
 );
 
+/// `path` is what follows the component name in the envelope, e.g. `PROC/WEB/0` for
+/// `[APP/PROC/WEB/0]`: optional process type segments, then the instance index
+fn parse_component_path(name: Component, path: &str) -> Option<ComponentInfo> {
+    let mut segments: Vec<&str> = path.split('/').collect();
+    let index = segments.pop()?.parse().ok()?;
+    let process_type = match segments.as_slice() {
+        [] => None,
+        // cf CLI upper cases process types, they are lower case everywhere else
+        ["PROC", process_type] => Some(process_type.to_lowercase()),
+        segments => segments.last().map(|segment| segment.to_string()),
+    };
+    Some(ComponentInfo {
+        name,
+        index,
+        process_type,
+    })
+}
+
 named!(parse_component <&str, ComponentInfoValid>,
     alt!(
         delimited!(
@@ -75,20 +157,8 @@ named!(parse_component <&str, ComponentInfoValid>,
             do_parse!(
                 name: parse_component_name >>
                 tag!("/") >>
-                index: flat_map!(take_until!("]"), parse_to!(u32)) >>
-                (ComponentInfoValid::Valid(ComponentInfo {name, index}))
-            ),
-            tag!("]")
-        ) |
-        delimited!(
-            tag!("["),
-            do_parse!(
-                name: parse_component_name >>
-                tag!("/") >>
-                opt!(take_until_and_consume!("/")) >>
-                opt!(take_until_and_consume!("/")) >>
-                index: flat_map!(take_until!("]"), parse_to!(u32)) >>
-                (ComponentInfoValid::Valid(ComponentInfo {name, index}))
+                info: map_opt!(take_until!("]"), |path| parse_component_path(name, path)) >>
+                (ComponentInfoValid::Valid(info))
             ),
             tag!("]")
         ) |
@@ -120,17 +190,26 @@ fn parse_message(input: &str) -> IResult<&str, Option<&str>> {
     }
 }
 
-named!(pub parse_cf_app_log <&str, CfAppLogEntry>,
+// what follows the timestamp: component, channel and message
+named!(parse_envelope <&str, (ComponentInfoValid, ChannelValid, Option<&str>)>,
     do_parse!(
-        many0!(tag!(" ")) >>
-        timestamp: parse_date >>
-        tag!(" ") >>
         component: parse_component >>
-        tag!(" ") >>
+        many1!(tag!(" ")) >>
         channel: parse_channel >>
         alt!(not!(complete!(non_empty)) => {|_tag| ""} | tag!(" ")) >>
         message: parse_message >>
+        ((component, channel, message))
+    )
+);
+
+named!(pub parse_cf_app_log <&str, CfAppLogEntry<'_>>,
+    do_parse!(
+        many0!(tag!(" ")) >>
+        timestamp: parse_date >>
+        tag!(" ") >>
+        envelope: parse_envelope >>
         ({
+            let (component, channel, message) = envelope;
             CfAppLogEntry {
                 timestamp,
                 component,
@@ -141,7 +220,64 @@ named!(pub parse_cf_app_log <&str, CfAppLogEntry>,
     )
 );
 
+/// Whether `format` is a valid chrono `strftime` format
+pub fn is_timestamp_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        Err(format!("invalid timestamp format: {}", format))
+    } else {
+        Ok(())
+    }
+}
+
+/// `timestamp` written in `format`, in UTC when it has no offset
+fn parse_timestamp(timestamp: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(timestamp, format)
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(timestamp, format)
+                .ok()
+                .map(|timestamp| timestamp.and_utc().fixed_offset())
+        })
+}
+
+/// Entry whose timestamp is written in `format` (chrono `strftime` syntax), timestamps without
+/// an offset being taken in UTC, the envelope starting at the first ` [` after which the
+/// timestamp parses
+pub fn parse_with_timestamp_format<'a>(
+    input: &'a str,
+    format: &str,
+) -> IResult<&'a str, CfAppLogEntry<'a>> {
+    let line = input.trim_start_matches(' ');
+    for (end, _) in line.match_indices(" [") {
+        let timestamp = match parse_timestamp(&line[..end], format) {
+            Some(timestamp) => timestamp,
+            None => continue,
+        };
+        let (rest, (component, channel, message)) = parse_envelope(&line[end + 1..])?;
+        return Ok((
+            rest,
+            CfAppLogEntry {
+                timestamp,
+                component,
+                channel,
+                message,
+            },
+        ));
+    }
+    Err(Err::Error(Context::Code(input, ErrorKind::MapRes)))
+}
+
+/// Lines printed by the cf CLI before the logs themselves, e.g.
+/// "Retrieving logs for app my-app in org my-org / space dev as admin..."
+pub fn is_cf_cli_banner(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty()
+        || line.starts_with("Retrieving logs for app ")
+        || line.starts_with("Connected, tailing logs for app ")
+}
+
 #[cfg(test)]
+#[allow(deprecated, clippy::zero_prefixed_literal)]
 mod tests {
     use super::*;
     use nom::Context::Code;
@@ -194,6 +330,7 @@ mod tests {
             ComponentInfoValid::Valid(component_info) => {
                 assert_eq!(component_info.name, Component::API);
                 assert_eq!(component_info.index, 0);
+                assert_eq!(component_info.process_type, None);
             }
             ComponentInfoValid::Invalid(str) => panic!("Invalid component [{}]", str),
         }
@@ -210,11 +347,38 @@ mod tests {
             ComponentInfoValid::Valid(component_info) => {
                 assert_eq!(component_info.name, Component::APPLICATION);
                 assert_eq!(component_info.index, 0);
+                assert_eq!(component_info.process_type, Some("web".to_string()));
+            }
+            ComponentInfoValid::Invalid(str) => panic!("Invalid component [{}]", str),
+        }
+    }
+
+    #[test]
+    fn test_component_app_task() {
+        let res = parse_component("[APP/TASK/db-migrate/2]");
+        assert!(res.is_ok(), "res: {:#?}", res);
+
+        match res.unwrap().1 {
+            ComponentInfoValid::Valid(component_info) => {
+                assert_eq!(component_info.name, Component::APPLICATION);
+                assert_eq!(component_info.index, 2);
+                assert_eq!(component_info.process_type, Some("db-migrate".to_string()));
             }
             ComponentInfoValid::Invalid(str) => panic!("Invalid component [{}]", str),
         }
     }
 
+    #[test]
+    fn test_component_invalid_index() {
+        let res = parse_component("[APP/PROC/WEB/first]");
+        assert!(res.is_ok(), "res: {:#?}", res);
+
+        match res.unwrap().1 {
+            ComponentInfoValid::Valid(_) => panic!("should be Invalid"),
+            ComponentInfoValid::Invalid(left) => assert_eq!(left, "APP/PROC/WEB/first"),
+        }
+    }
+
     #[test]
     fn test_component_unknown_component() {
         let res = parse_component("[FOO/0]");
@@ -353,4 +517,67 @@ mod tests {
 			Some("2021-09-28 08:00:09.361 DEBUG [,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8] 15 --- [   scheduling-1] i.s.l.r.s.ReminderEmailSchedulerImpl     : result ===> false")
 		);
     }
+
+    #[test]
+    fn test_parse_cf_app_log_padded_channel() {
+        let entry =
+            parse_cf_app_log(r#"2016-06-14T15:16:12.70-0700 [RTR/4]      OUT www.example.com"#);
+        assert!(entry.is_ok(), "res: {:#?}", entry);
+
+        let entry = entry.unwrap().1;
+        match entry.component {
+            ComponentInfoValid::Valid(comp) => {
+                assert_eq!(comp.name, Component::ROUTER);
+                assert_eq!(comp.index, 4);
+            }
+            ComponentInfoValid::Invalid(_) => panic!("should be valid"),
+        }
+        assert_eq!(entry.message, Some("www.example.com"));
+    }
+
+    #[test]
+    fn test_parse_with_timestamp_format() {
+        let (_, entry) = parse_with_timestamp_format(
+            "2021-09-28 17:00:09.360 +0900 [APP/PROC/WEB/0] OUT started",
+            "%Y-%m-%d %H:%M:%S%.f %z",
+        )
+        .unwrap();
+        assert_eq!(
+            entry.timestamp,
+            DateTime::parse_from_rfc3339("2021-09-28T17:00:09.360+09:00").unwrap()
+        );
+        assert_eq!(entry.message, Some("started"));
+
+        // without an offset, in brackets
+        let (_, entry) = parse_with_timestamp_format(
+            "[28/Sep/2021 08:00:09] [RTR/1]  ERR",
+            "[%d/%b/%Y %H:%M:%S]",
+        )
+        .unwrap();
+        assert_eq!(
+            entry.timestamp,
+            DateTime::parse_from_rfc3339("2021-09-28T08:00:09Z").unwrap()
+        );
+        assert_eq!(entry.message, None);
+
+        assert!(parse_with_timestamp_format(
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT",
+            "%Y-%m-%d %H:%M:%S"
+        )
+        .is_err());
+        assert!(is_timestamp_format("%Y-%m-%d %H:%M:%S%.f").is_ok());
+        assert!(is_timestamp_format("%Y-%m-%d %Q").is_err());
+    }
+
+    #[test]
+    fn test_is_cf_cli_banner() {
+        assert!(is_cf_cli_banner(
+            "Retrieving logs for app my-app in org my-org / space dev as admin..."
+        ));
+        assert!(is_cf_cli_banner(
+            "Connected, tailing logs for app my-app in org my-org / space dev as admin..."
+        ));
+        assert!(is_cf_cli_banner("   "));
+        assert!(!is_cf_cli_banner("2021-09-28T17:00:09.36+0900 [RTR/0] OUT"));
+    }
 }
@@ -0,0 +1,177 @@
+//! Traces Loggregator leaves in the logs: warnings of the LGR component telling log messages
+//! got lost, and messages split over several entries
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::input::strip_ansi;
+use crate::{parse_cf_app_log, CfAppLogEntry, Component, ComponentInfoValid};
+
+/// Length at which Loggregator splits messages, the rest being sent as entries of their own
+/// with the same timestamp and source
+pub const MAX_MESSAGE_LENGTH: usize = 61440;
+
+/// Messages lost according to a LGR entry, e.g. `Log message output too high. We've dropped 100
+/// messages` or `app instance exceeded log rate limit (1024 bytes/sec)`
+#[derive(Debug, PartialEq)]
+pub struct LossWarning {
+    /// number of messages dropped, when told
+    pub dropped: Option<u64>,
+}
+
+fn patterns() -> &'static (Regex, [Regex; 2]) {
+    static PATTERNS: OnceLock<(Regex, [Regex; 2])> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        (
+            Regex::new(r"(?i)\bdropp(ed|ing)\b|rate limit|output too high").unwrap(),
+            [
+                Regex::new(r"(?i)\bdropped (\d+)").unwrap(),
+                Regex::new(r"(?i)\b(\d+) (?:log )?(?:messages|envelopes) (?:were )?dropped")
+                    .unwrap(),
+            ],
+        )
+    })
+}
+
+/// Loss told by an entry, `None` for entries which are not LGR ones telling a loss
+pub fn loss_warning(entry: &CfAppLogEntry) -> Option<LossWarning> {
+    match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::LOGGREGATOR => {}
+        _ => return None,
+    }
+    let message = entry.message?;
+    let (loss, counts) = patterns();
+    if !loss.is_match(message) {
+        return None;
+    }
+    let dropped = counts
+        .iter()
+        .find_map(|count| count.captures(message))
+        .and_then(|captures| captures.get(1)?.as_str().parse().ok());
+    Some(LossWarning { dropped })
+}
+
+/// Whether the message of `entry` got truncated by Loggregator, to continue in the next entry
+pub fn is_truncated(entry: &CfAppLogEntry) -> bool {
+    entry
+        .message
+        .is_some_and(|message| message.len() >= MAX_MESSAGE_LENGTH)
+}
+
+/// Whether `next` carries the rest of the message truncated in `previous`
+pub fn is_continuation(previous: &CfAppLogEntry, next: &CfAppLogEntry) -> bool {
+    is_truncated(previous)
+        && next.timestamp == previous.timestamp
+        && next.component == previous.component
+}
+
+/// Joins back the messages Loggregator split, line after line: a line whose message got
+/// truncated is held until the lines carrying the rest of it are read
+#[derive(Default)]
+pub struct Reassembler {
+    /// number of the line held, and the line with the pieces of message read so far
+    pending: Option<(u64, String)>,
+}
+
+impl Reassembler {
+    /// Account for the line numbered `line_number`, `emit` gets called with the lines complete
+    /// so far and their number, that of the first piece for reassembled ones. Returns false as
+    /// soon as `emit` does.
+    pub fn push<F>(&mut self, line_number: u64, line: &str, emit: &mut F) -> bool
+    where
+        F: FnMut(u64, &str) -> bool,
+    {
+        let stripped = strip_ansi(line);
+        let entry = parse_cf_app_log(&stripped).ok().map(|(_, entry)| entry);
+        if let Some((_, pending)) = &mut self.pending {
+            let continued = match (&entry, parse_cf_app_log(&strip_ansi(pending))) {
+                (Some(next), Ok((_, previous))) => is_continuation(&previous, next),
+                _ => false,
+            };
+            if let (true, Some(next)) = (continued, &entry) {
+                pending.push_str(next.message.unwrap_or_default());
+                // the rest may have been truncated again
+                return is_truncated(next) || self.finish(emit);
+            }
+            if !self.finish(emit) {
+                return false;
+            }
+        }
+        match entry {
+            Some(entry) if is_truncated(&entry) => {
+                self.pending = Some((line_number, line.to_string()));
+                true
+            }
+            _ => emit(line_number, line),
+        }
+    }
+
+    /// Emit the line held, at the end of the input. Returns what `emit` does.
+    pub fn finish<F>(&mut self, emit: &mut F) -> bool
+    where
+        F: FnMut(u64, &str) -> bool,
+    {
+        match self.pending.take() {
+            Some((line_number, line)) => emit(line_number, &line),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_loss_warning() {
+        let warning = |line| loss_warning(&parse_cf_app_log(line).unwrap().1);
+        assert_eq!(
+            warning("2021-09-28T17:00:00.00+0900 [LGR/0] ERR Log message output too high. We've dropped 100 messages"),
+            Some(LossWarning { dropped: Some(100) })
+        );
+        assert_eq!(
+            warning("2021-09-28T17:00:00.00+0900 [LGR/0] ERR 42 messages dropped by doppler"),
+            Some(LossWarning { dropped: Some(42) })
+        );
+        assert_eq!(
+            warning("2021-09-28T17:00:00.00+0900 [LGR/0] ERR app instance exceeded log rate limit (1024 bytes/sec)"),
+            Some(LossWarning { dropped: None })
+        );
+        assert_eq!(
+            warning("2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT dropped 3 rows"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_reassembler() {
+        let head = "x".repeat(MAX_MESSAGE_LENGTH);
+        let lines = [
+            format!("2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT {}", head),
+            "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT tail".to_string(),
+            "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT next".to_string(),
+            format!("2021-09-28T17:00:01.00+0900 [APP/PROC/WEB/0] OUT {}", head),
+            "2021-09-28T17:00:01.00+0900 [APP/PROC/WEB/1] OUT other".to_string(),
+        ];
+        let mut reassembler = Reassembler::default();
+        let mut emitted = Vec::new();
+        let mut emit = |line_number, line: &str| {
+            emitted.push((line_number, line.to_string()));
+            true
+        };
+        for (number, line) in lines.iter().enumerate() {
+            assert!(reassembler.push(number as u64 + 1, line, &mut emit));
+        }
+        assert!(reassembler.finish(&mut emit));
+        assert_eq!(
+            emitted,
+            vec![
+                (1, format!("{}tail", lines[0])),
+                (3, lines[2].clone()),
+                (4, lines[3].clone()),
+                (5, lines[4].clone()),
+            ]
+        );
+    }
+}
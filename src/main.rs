@@ -1,152 +1,281 @@
-use clap::{crate_version, value_t, App, Arg};
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, ErrorKind};
+use std::env;
+use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::io::prelude::*;
+use std::path::PathBuf;
 
-use cf_app_log_detector::parse_cf_app_log;
+use cf_app_log_detector::config::{default_path, env_options, Config, ENV_CONFIG, ENV_PREFIX};
 
-fn main() {
-    let matches = App::new("cf-app-log-detector")
-       .version(crate_version!())
-       .author("Olivier Lechevalier <olivier.lechevalier@gmail.com>")
-       .about("Try to detect log outputted by CF cli")
-       .arg(Arg::with_name("percentage_matching")
-          .short("p")
-          .long("percentage-matching")
-          .value_name("PERCENTAGE_MATCHING")
-          .help("Percentage of line matching expected format for the file to be considered an application log")
-          .takes_value(true)
-          .default_value("90"))
-        .arg(Arg::with_name("one_line_match")
-          .value_name("ONE_LINE_MATCH")
-          .long("one-line-match")
-          .help("Consider the file to be CF app log if a single line matches expected format")
-          .takes_value(false))
-        .arg(Arg::with_name("debug")
-          .value_name("DEBUG")
-          .long("debug")
-          .short("d")
-          .help("Enable debugging")
-          .takes_value(false))
-        .arg(Arg::with_name("log")
-          .value_name("LOG")
-          .help("Log file")
-          .index(1)
-          .takes_value(true))
-       .get_matches();
-
-    let mut detector = CfAppLogDetector::new(
-        value_t!(matches, "percentage_matching", usize).unwrap(),
-        matches.is_present("one_line_match"),
-    );
-
-    let filename = matches.value_of("log").unwrap();
-    match detector.process_file(filename) {
-        Ok(()) => (),
-        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
-            eprintln!("File {} not found", filename);
-            std::process::exit(1);
-        }
-        Err(e) => {
-            eprintln!("Failed parsing file: {}, message: {}", filename, e);
-            std::process::exit(2);
-        }
-    }
+mod commands;
+
+/// Options of the top level taking a value, they can come before the subcommand
+const GLOBAL_OPTIONS: &[&str] = &["--config"];
+
+/// Names that are not taken as a log file when given as first argument
+const SUBCOMMANDS: &[&str] = &[
+    "detect",
+    "parse",
+    "stats",
+    "filter",
+    "merge",
+    "split",
+    "export",
+    "query",
+    "forward",
+    "check",
+    "trace",
+    "requests",
+    "crashes",
+    "restarts",
+    "staging",
+    "ssh",
+    "audit",
+    "redact",
+    "gaps",
+    "doctor",
+    "report",
+    "view",
+    "tail",
+    "identify",
+    "completions",
+    "man",
+    "help",
+    "-h",
+    "--help",
+    "-V",
+    "--version",
+];
 
-    std::process::exit(detector.show_results(filename, matches.is_present("debug")));
+fn app() -> App<'static, 'static> {
+    App::new("cf-app-log-detector")
+        .version(crate_version!())
+        .author("Olivier Lechevalier <olivier.lechevalier@gmail.com>")
+        .about("Try to detect log outputted by CF cli")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("config")
+                .value_name("FILE")
+                .long("config")
+                .help("Read the defaults of the options from FILE, or CF_LOG_DETECTOR_CONFIG, instead of ~/.config/cf-app-log-detector/config.toml")
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(commands::detect::app())
+        .subcommand(commands::parse::app())
+        .subcommand(commands::stats::app())
+        .subcommand(commands::filter::app())
+        .subcommand(commands::merge::app())
+        .subcommand(commands::split::app())
+        .subcommand(commands::export::app())
+        .subcommand(commands::query::app())
+        .subcommand(commands::forward::app())
+        .subcommand(commands::check::app())
+        .subcommand(commands::trace::app())
+        .subcommand(commands::requests::app())
+        .subcommand(commands::crashes::app())
+        .subcommand(commands::restarts::app())
+        .subcommand(commands::staging::app())
+        .subcommand(commands::ssh::app())
+        .subcommand(commands::audit::app())
+        .subcommand(commands::redact::app())
+        .subcommand(commands::gaps::app())
+        .subcommand(commands::doctor::app())
+        .subcommand(commands::report::app())
+        .subcommand(commands::view::app())
+        .subcommand(commands::tail::app())
+        .subcommand(commands::identify::app())
+        .subcommand(commands::completions::app())
+        .subcommand(commands::man::app())
 }
 
-pub struct CfAppLogDetector {
-    one_line_match: bool,
-    total_log_lines: usize,
-    log_lines_matching: usize,
-    trigger_percentage: usize,
+fn main() {
+    let matches = with_config(with_default_subcommand(std::env::args_os().collect()));
+    let code = match matches.subcommand() {
+        ("detect", Some(matches)) => commands::detect::run(matches),
+        ("parse", Some(matches)) => commands::parse::run(matches),
+        ("stats", Some(matches)) => commands::stats::run(matches),
+        ("filter", Some(matches)) => commands::filter::run(matches),
+        ("merge", Some(matches)) => commands::merge::run(matches),
+        ("split", Some(matches)) => commands::split::run(matches),
+        ("export", Some(matches)) => commands::export::run(matches),
+        ("query", Some(matches)) => commands::query::run(matches),
+        ("forward", Some(matches)) => commands::forward::run(matches),
+        ("check", Some(matches)) => commands::check::run(matches),
+        ("trace", Some(matches)) => commands::trace::run(matches),
+        ("requests", Some(matches)) => commands::requests::run(matches),
+        ("crashes", Some(matches)) => commands::crashes::run(matches),
+        ("restarts", Some(matches)) => commands::restarts::run(matches),
+        ("staging", Some(matches)) => commands::staging::run(matches),
+        ("ssh", Some(matches)) => commands::ssh::run(matches),
+        ("audit", Some(matches)) => commands::audit::run(matches),
+        ("redact", Some(matches)) => commands::redact::run(matches),
+        ("gaps", Some(matches)) => commands::gaps::run(matches),
+        ("doctor", Some(matches)) => commands::doctor::run(matches),
+        ("report", Some(matches)) => commands::report::run(matches),
+        ("view", Some(matches)) => commands::view::run(matches),
+        ("tail", Some(matches)) => commands::tail::run(matches),
+        ("identify", Some(matches)) => commands::identify::run(matches),
+        ("completions", Some(matches)) => commands::completions::run(matches),
+        ("man", Some(matches)) => commands::man::run(matches),
+        _ => unreachable!("a subcommand is required"),
+    };
+    std::process::exit(code);
 }
 
-impl CfAppLogDetector {
-    pub fn new(trigger_percentage: usize, one_line_match: bool) -> CfAppLogDetector {
-        CfAppLogDetector {
-            trigger_percentage,
-            one_line_match,
-            total_log_lines: 0,
-            log_lines_matching: 0,
-        }
+/// Parse the command line, the options it does not give being taken from the `CF_LOG_DETECTOR_*`
+/// environment variables or the configuration file when they set them, see
+/// `cf_app_log_detector::config`
+fn with_config(args: Vec<OsString>) -> ArgMatches<'static> {
+    let matches = app()
+        .get_matches_from_safe(args.clone())
+        .unwrap_or_else(|e| e.exit());
+    let (subcommand, subcommand_matches) = match matches.subcommand() {
+        (name, Some(subcommand_matches)) => (name.to_string(), subcommand_matches),
+        _ => return matches,
+    };
+    let config = read_config(subcommand_matches);
+    let mut options = config
+        .as_ref()
+        .map_or_else(Vec::new, |(_, config)| config.options(&subcommand));
+    for option in env_options(env::vars()) {
+        options.retain(|set| set.key != option.key);
+        options.push(option);
+    }
+    options.retain(|option| subcommand_matches.occurrences_of(option.key.replace('-', "_")) == 0);
+    if options.is_empty() {
+        return matches;
     }
 
-    pub fn process_file(&mut self, path: &str) -> io::Result<()> {
-        let reader = io::BufReader::new(fs::File::open(path)?);
-
-        for line in reader.lines() {
-            match CfAppLogDetector::parse_line(&line?) {
-                Ok(_log) => {
-                    self.total_log_lines += 1;
-                    self.log_lines_matching += 1;
-                    if self.one_line_match {
-                        break;
-                    }
-                }
-                Err(_err) => {
-                    // eprintln!("parsing error: {}", _err);
-                    self.total_log_lines += 1;
+    // the options go right after the subcommand, before those given
+    let position = args
+        .iter()
+        .skip(1)
+        .position(|arg| *arg == *subcommand)
+        .unwrap()
+        + 2;
+    loop {
+        let mut with_options = args.clone();
+        with_options.splice(
+            position..position,
+            options
+                .iter()
+                .flat_map(|option| option.args.iter().map(OsString::from)),
+        );
+        let error = match app().get_matches_from_safe(with_options) {
+            Ok(matches) => return matches,
+            Err(error) => error,
+        };
+        // top level options only apply to the subcommands having them and not conflicting
+        // with the options given
+        let rejected = match error.kind {
+            ErrorKind::UnknownArgument | ErrorKind::ArgumentConflict => error.info.as_ref(),
+            _ => None,
+        };
+        let rejected = rejected.and_then(|info| {
+            options.iter().position(|option| {
+                let flag = format!("--{}", option.key);
+                option.global
+                    && info.iter().any(|arg| {
+                        *arg == option.key.replace('-', "_")
+                            || arg == &flag
+                            || arg.starts_with(&format!("{}=", flag))
+                            || arg.starts_with(&format!("{} ", flag))
+                    })
+            })
+        });
+        match rejected {
+            Some(index) => {
+                options.remove(index);
+            }
+            None => {
+                match &config {
+                    Some((path, _)) => eprintln!(
+                        "In the options set by {} or {}* variables:",
+                        path.display(),
+                        ENV_PREFIX
+                    ),
+                    None => eprintln!("In the options set by {}* variables:", ENV_PREFIX),
                 }
-            };
+                error.exit();
+            }
         }
-        Ok(())
     }
+}
 
-    pub fn show_results(&mut self, path: &str, debug: bool) -> i32 {
-        if debug {
-            println!("[DEBUG] total number of lines: {}", self.total_log_lines);
-            println!("[DEBUG] log lines matching: {}", self.log_lines_matching);
-        }
-        let percentage_matching = if self.total_log_lines > 0 {
-            (self.log_lines_matching as f64 / self.total_log_lines as f64 * 100.0).floor()
-        } else {
-            0.0
-        };
-
-        if debug {
-            println!("[DEBUG] percentage matching: {}", percentage_matching)
+/// The configuration file given by `--config` or `CF_LOG_DETECTOR_CONFIG`, the default one when
+/// it exists otherwise
+fn read_config(matches: &ArgMatches) -> Option<(PathBuf, Config)> {
+    let (path, explicit) = match matches
+        .value_of_os("config")
+        .map(OsString::from)
+        .or_else(|| env::var_os(ENV_CONFIG))
+    {
+        Some(path) => (PathBuf::from(path), true),
+        None => (default_path()?, false),
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => match content.parse() {
+            Ok(config) => Some((path, config)),
+            Err(e) => {
+                eprintln!("Invalid configuration {}: {}", path.display(), e);
+                std::process::exit(2);
+            }
+        },
+        Err(ref e) if !explicit && e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Could not read the configuration {}: {}", path.display(), e);
+            std::process::exit(2);
         }
+    }
+}
 
-        let matching = percentage_matching >= self.trigger_percentage as f64
-            || (self.log_lines_matching > 0 && self.one_line_match);
-        if matching {
-            eprintln!(
-                "{} is a CF application log [{}% line matching]",
-                path, percentage_matching
-            );
-            0
+/// `cf-app-log-detector [OPTIONS] LOG` predates subcommands, keep it working as an alias of
+/// `cf-app-log-detector detect [OPTIONS] LOG`
+fn with_default_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    // the subcommand comes after the global options given before it
+    let mut position = 1;
+    while let Some(arg) = args
+        .get(position)
+        .map(|arg| arg.to_string_lossy().into_owned())
+    {
+        if GLOBAL_OPTIONS.contains(&arg.as_str()) {
+            position += 2;
+        } else if GLOBAL_OPTIONS
+            .iter()
+            .any(|option| arg.starts_with(&format!("{}=", option)))
+        {
+            position += 1;
         } else {
-            eprintln!(
-                "{} is NOT CF application log [{}% line matching]",
-                path, percentage_matching
-            );
-            1
+            break;
         }
     }
-
-    fn parse_line(line: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        // 136 |                     Err(err) => Err(Box::new(err)),
-        //                  ^^^^^^^^^^^^^^^^^^ returns a value referencing data owned by the current function
-        let stripped_line: String;
-        match strip_ansi_escapes::strip(line) {
-            Ok(stripped_vector) => {
-                stripped_line = String::from_utf8(stripped_vector)?;
-                match parse_cf_app_log(&stripped_line) {
-                    Ok(_) => Ok(true),
-                    Err(_) => Ok(false), // TODO: can't do better now
-                }
-            }
-            Err(err) => Err(Box::new(err)),
+    let first = args
+        .get(position)
+        .map(|arg| arg.to_string_lossy().into_owned());
+    match first {
+        Some(ref first) if !SUBCOMMANDS.contains(&first.as_str()) => {
+            args.insert(position, OsString::from("detect"));
         }
+        _ => (),
     }
+    args
 }
 
 #[cfg(test)]
 mod tests {
     use assert_cmd::Command;
     use predicates::prelude::*;
+    use std::fs;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Directory of its own for the files a test writes, removed with them when dropped
+    fn temp_dir() -> TempDir {
+        tempfile::tempdir().unwrap()
+    }
 
     #[test]
     fn file_doesnt_exist() {
@@ -185,4 +314,2323 @@ mod tests {
             .success()
             .stdout(predicate::str::contains("total number of lines: 1"));
     }
+
+    #[test]
+    fn partially_matching_file() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/partially_matching.txt");
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "test/file/partially_matching.txt is NOT CF application log [50% line matching]",
+        ));
+    }
+    #[test]
+    fn follow() {
+        let dir = temp_dir();
+        let path = dir.path().join("follow.log");
+        fs::write(&path, "not a log line\n").unwrap();
+
+        let appender = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(500));
+                let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+                writeln!(file, "2021-09-28T17:00:09.36+0900 [RTR/0] OUT").unwrap();
+            })
+        };
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path)
+            .arg("--follow")
+            .timeout(Duration::from_secs(2));
+        cmd.assert()
+            .interrupted()
+            .stderr(predicate::str::contains(
+                "is NOT CF application log [0% line matching]",
+            ))
+            .stderr(predicate::str::contains(
+                "is NOT CF application log [50% line matching]",
+            ));
+
+        appender.join().unwrap();
+    }
+
+    #[test]
+    fn follow_metrics() {
+        let address = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let scraper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(700));
+            let get = |path: &str| {
+                let mut stream = std::net::TcpStream::connect(address).unwrap();
+                write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+                let mut response = String::new();
+                std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+                response
+            };
+            (get("/metrics"), get("/healthz"))
+        });
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt")
+            .arg("--follow")
+            .arg("--metrics-addr")
+            .arg(address.to_string())
+            .timeout(Duration::from_secs(2));
+        cmd.assert().interrupted();
+
+        let (metrics, healthz) = scraper.join().unwrap();
+        assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(metrics.contains("\ncf_app_log_lines_parsed_total 3\n"));
+        assert!(metrics.contains("\ncf_app_log_verdict{file=\"test/file/with_banner.txt\"} 1\n"));
+        assert!(healthz.ends_with("\r\n\r\nok\n"));
+    }
+
+    #[test]
+    fn watch() {
+        let dir = temp_dir();
+
+        let writer = {
+            let dir = dir.path().to_path_buf();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(500));
+                fs::copy("test/file/matching.txt", dir.join("dropped.log")).unwrap();
+            })
+        };
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("--watch")
+            .arg(dir.path())
+            .timeout(Duration::from_secs(2));
+        cmd.assert().interrupted().stderr(predicate::str::contains(
+            "dropped.log is a CF application log [100% line matching]",
+        ));
+
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn sample() {
+        for strategy in &["head", "random", "reservoir"] {
+            let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+            cmd.arg("test/file/matching.txt")
+                .arg("--sample")
+                .arg("2")
+                .arg("--sample-strategy")
+                .arg(strategy);
+            cmd.assert()
+                .success()
+                .stderr(predicate::str::contains("[100% line matching]"))
+                .stderr(predicate::str::contains(format!("{} strategy]", strategy)));
+        }
+
+        // the offset stops where the byte limit did
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .args(["--sample", "2", "--sample-strategy", "random"])
+            .args(["--max-bytes", "100", "--emit-offset"]);
+        cmd.assert().stdout("100\n");
+    }
+
+    #[test]
+    fn early_exit() {
+        let dir = temp_dir();
+        let path = dir.path().join("early-exit.log");
+        let mut content = String::new();
+        for _ in 0..200 {
+            content.push_str("definitely not a CF application log line\n");
+        }
+        for _ in 0..200 {
+            content.push_str("2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+        }
+        fs::write(&path, content).unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path).arg("--debug");
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains("total number of lines: 100\n"))
+            .stderr(predicate::str::contains("[early exit after 100 lines"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path).arg("--debug").arg("--no-early-exit");
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains("total number of lines: 400\n"))
+            .stderr(predicate::str::contains("[50% line matching]\n"));
+    }
+
+    #[test]
+    fn early_exit_short_entries_after_long_lines() {
+        let dir = temp_dir();
+        let path = dir.path().join("long-lines-first.log");
+        let mut content = String::new();
+        for _ in 0..100 {
+            content.push_str(&"x".repeat(2000));
+            content.push('\n');
+        }
+        for _ in 0..2000 {
+            content.push_str("2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+        }
+        fs::write(&path, content).unwrap();
+
+        // the short entries left can still make 90% after the long lines
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path);
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("is a CF application log ["));
+    }
+
+    #[test]
+    fn fail_on_loss() {
+        let dir = temp_dir();
+        let path = dir.path().join("fail-on-loss.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n\
+             2021-09-28T17:00:10.00+0900 [LGR/0] ERR Log message output too high. We've dropped 100 messages\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "[message loss: 1 LGR warnings, 100 messages dropped]",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path).arg("--fail-on-loss");
+        cmd.assert().code(3);
+    }
+
+    #[test]
+    fn max_lines_and_bytes() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--max-lines")
+            .arg("1")
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 1\n"))
+            .stderr(predicate::str::contains("[read limit reached"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--max-bytes")
+            .arg("1K")
+            .arg("--debug");
+        cmd.assert()
+            .stdout(predicate::str::contains("total number of lines: 3\n"))
+            .stderr(predicate::str::contains("[read limit reached"));
+    }
+
+    #[test]
+    fn max_lines_not_reached() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--max-lines")
+            .arg("10");
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("[read limit reached").not());
+    }
+
+    #[test]
+    fn skip_lines() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt").arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("skipped header lines: 2\n"))
+            .stderr(predicate::str::contains("[100% line matching]"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt")
+            .arg("--skip-lines")
+            .arg("3")
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("skipped header lines: 3\n"))
+            .stdout(predicate::str::contains("total number of lines: 2\n"));
+    }
+
+    #[test]
+    fn start_and_emit_offset() {
+        let length = fs::metadata("test/file/with_banner.txt").unwrap().len();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt").arg("--emit-offset");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff(format!("{}\n", length)));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt")
+            .arg("--start-offset")
+            .arg(length.to_string())
+            .arg("--debug");
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::contains("total number of lines: 0\n"));
+    }
+
+    #[test]
+    fn checkpoint() {
+        let dir = temp_dir();
+        let checkpoint = dir.path().join("checkpoint");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 3\n"));
+
+        // the last line has no newline, it may not be complete yet and gets read again
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 1\n"));
+    }
+
+    #[test]
+    fn checkpoint_partial_line() {
+        let dir = temp_dir();
+        let path = dir.path().join("partial-line.log");
+        let checkpoint = dir.path().join("checkpoint");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT a\n2021-09-28T17:00:09.37+0900 [RTR/0] OU",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path).arg("--checkpoint").arg(&checkpoint);
+        cmd.assert().failure();
+        assert_eq!(fs::read_to_string(&checkpoint).unwrap().trim(), "42");
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"T b\n").unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path)
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--emit-offset")
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 1\n"))
+            .stderr(predicate::str::contains("[100% line matching]"));
+        assert_eq!(fs::read_to_string(&checkpoint).unwrap().trim(), "84");
+    }
+
+    #[test]
+    fn mmap() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/with_banner.txt")
+            .arg("--mmap")
+            .arg("--max-lines")
+            .arg("4")
+            .arg("--emit-offset")
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("skipped header lines: 2\n"))
+            .stdout(predicate::str::contains("total number of lines: 2\n"))
+            .stderr(predicate::str::contains(
+                "[100% line matching] [read limit reached",
+            ));
+
+        let content = fs::read_to_string("test/file/matching.txt").unwrap();
+        let first_line = content.find('\n').unwrap() + 1;
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("test/file/matching.txt")
+            .arg("--mmap")
+            .arg("--start-offset")
+            .arg(first_line.to_string())
+            .arg("--debug");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 2\n"));
+    }
+
+    #[test]
+    fn directory() {
+        let dir = temp_dir();
+        fs::create_dir_all(dir.path().join("nested")).unwrap();
+        fs::copy("test/file/matching.txt", dir.path().join("nested/app.log")).unwrap();
+        fs::write(dir.path().join("notes.txt"), "nothing to see here\n").unwrap();
+        fs::write(dir.path().join("core.dump"), b"\x7fELF\x00\x00\x01").unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(dir.path());
+        cmd.assert()
+            .success()
+            .stderr(predicate::str::contains("core.dump skipped: binary"))
+            .stderr(predicate::str::contains(
+                "app.log is a CF application log [100% line matching]",
+            ))
+            .stderr(predicate::str::contains(
+                "notes.txt is NOT CF application log [0% line matching]",
+            ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directory_symlink_loop() {
+        let dir = temp_dir();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::copy("test/file/matching.txt", dir.path().join("app.log")).unwrap();
+        std::os::unix::fs::symlink("..", dir.path().join("sub/loop")).unwrap();
+        std::os::unix::fs::symlink("../app.log", dir.path().join("sub/linked.log")).unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(dir.path());
+        let output = cmd.assert().success().get_output().stderr.clone();
+        let verdicts = String::from_utf8(output).unwrap();
+        assert_eq!(verdicts.matches("is a CF application log").count(), 2);
+        assert!(verdicts.contains("linked.log is a CF application log"));
+    }
+
+    #[test]
+    fn max_line_length() {
+        let dir = temp_dir();
+        let path = dir.path().join("long-line.log");
+        let mut content = String::from("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT {");
+        content.push_str(&"\"key\": \"value\", ".repeat(100_000));
+        content.push_str("}\n2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+        fs::write(&path, content).unwrap();
+
+        for mmap in &[false, true] {
+            let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+            cmd.arg(&path)
+                .arg("--max-line-length")
+                .arg("1K")
+                .arg("--debug");
+            if *mmap {
+                cmd.arg("--mmap");
+            }
+            cmd.assert()
+                .success()
+                .stdout(predicate::str::contains("total number of lines: 2\n"))
+                .stderr(predicate::str::contains("[100% line matching]"));
+        }
+    }
+
+    #[test]
+    fn detect_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("detect").arg("test/file/matching.txt");
+        cmd.assert().success().stderr(predicate::str::contains(
+            "test/file/matching.txt is a CF application log [100% line matching]",
+        ));
+    }
+
+    #[test]
+    fn parse_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse").arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains(
+                r#"{"timestamp":"2021-09-28T17:00:10.130+09:00","component":"APP","index":0,"process_type":"web","channel":"ERR","message":"WARN: slow request"}"#,
+            ))
+            .stdout(predicate::str::contains("Retrieving logs").not());
+    }
+
+    #[test]
+    fn filter_invert() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--show-nonmatching")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("Retrieving logs for app my-app in org my-org / space dev as admin...\n\n");
+    }
+
+    #[test]
+    fn filter_component() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--component")
+            .arg("rtr,other")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("log lines matching: 1\n"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--component")
+            .arg("APP")
+            .arg("--columns")
+            .arg("component")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("{\"component\":\"APP\"}\n{\"component\":\"APP\"}\n");
+    }
+
+    #[test]
+    fn filter_channel() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--channel")
+            .arg("ERR")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+    }
+
+    #[test]
+    fn filter_instance() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--instance")
+            .arg("3-5")
+            .arg("test/file/matching.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("log lines matching: 3\n"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--instance")
+            .arg("0")
+            .arg("test/file/matching.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("log lines matching: 0\n"));
+    }
+
+    #[test]
+    fn filter_message_regexes() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--match")
+            .arg("^WARN|DEBUG")
+            .arg("--exclude")
+            .arg("scheduling")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+    }
+
+    #[test]
+    fn filter_time_range() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--since")
+            .arg("2021-09-28T08:00:10.125Z")
+            .arg("--until")
+            .arg("2021-09-28T17:00:10.13+09:00")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+    }
+
+    #[test]
+    fn filter_context() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("-B")
+            .arg("1")
+            .arg("--channel")
+            .arg("err")
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/with_banner.txt");
+        let entries = "   2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com";
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with(entries));
+        assert!(lines[1].ends_with("ERR WARN: slow request"));
+        assert_eq!(lines[2], "--");
+        assert!(lines[3].starts_with(entries));
+        assert!(lines[4].ends_with("ERR WARN: slow request"));
+    }
+
+    #[test]
+    fn filter_no_pager() {
+        let line = "2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("--no-pager").write_stdin(line);
+        cmd.assert().success().stdout(line);
+
+        // pipes are never paged
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--columns")
+            .arg("message")
+            .env("PAGER", "false")
+            .write_stdin(line);
+        cmd.assert().success().stdout("{\"message\":null}\n");
+    }
+
+    #[test]
+    fn filter_count() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("-c")
+            .arg("--invert")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout("2\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--count")
+            .arg("--component")
+            .arg("app")
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/matching.txt");
+        cmd.assert()
+            .success()
+            .stdout("test/file/with_banner.txt:2\ntest/file/matching.txt:0\n");
+    }
+
+    #[test]
+    fn filter_origin() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("-Hn")
+            .arg("--match")
+            .arg("WARN")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(
+            "test/file/with_banner.txt:5:   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--line-numbers")
+            .arg("--with-filename")
+            .arg("--columns")
+            .arg("channel")
+            .arg("--format")
+            .arg("csv")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(
+            "file,line,channel\ntest/file/with_banner.txt,3,OUT\ntest/file/with_banner.txt,4,OUT\ntest/file/with_banner.txt,5,ERR\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("-n")
+            .arg("--format")
+            .arg("gelf")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().code(2);
+    }
+
+    #[test]
+    fn timezone() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--utc")
+            .arg("--match")
+            .arg("WARN")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   2021-09-28T08:00:10.13+0000 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--tz")
+            .arg("Europe/Paris")
+            .arg("--format")
+            .arg("csv")
+            .arg("--columns")
+            .arg("timestamp")
+            .arg("--channel")
+            .arg("err")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("timestamp\n2021-09-28T10:00:10.130+02:00\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("merge").arg("--tz").arg("Mars/Olympus").arg("-");
+        cmd.assert().code(1);
+    }
+
+    #[test]
+    fn relative_timestamps() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--relative")
+            .arg("--channel")
+            .arg("err")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   +00:00:00.770 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+    }
+
+    #[test]
+    fn filter_dedup_consecutive() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("--dedup-consecutive").write_stdin(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT ping\n\
+             2021-09-28T17:00:10.36+0900 [APP/PROC/WEB/0] OUT ping\n\
+             2021-09-28T17:00:11.36+0900 [APP/PROC/WEB/0] OUT ping\n\
+             2021-09-28T17:00:12.36+0900 [APP/PROC/WEB/1] OUT ping\n\
+             2021-09-28T17:00:13.36+0900 [APP/PROC/WEB/1] OUT pong\n",
+        );
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT ping (repeated 3 times)\n\
+             2021-09-28T17:00:12.36+0900 [APP/PROC/WEB/1] OUT ping\n\
+             2021-09-28T17:00:13.36+0900 [APP/PROC/WEB/1] OUT pong\n",
+        );
+    }
+
+    #[test]
+    fn parse_rtr_fields() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--rtr-fields")
+            .arg("--columns")
+            .arg("component")
+            .arg("--format")
+            .arg("logfmt")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(
+            "component=APP\n\
+             component=RTR host=my-app.example.com method=GET path=/health protocol=HTTP/1.1 status=200 bytes_received=0 bytes_sent=15 user_agent=curl/7.64.1 remote_addr=10.0.0.1:51234 backend_addr=10.0.1.5:61001 x_forwarded_for=203.0.113.10 x_forwarded_proto=https vcap_request_id=3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d response_time=0.012345 gorouter_time=0.000321 app_id=79bb58ab-3737-43be-ac70-39a2843b5177 app_index=0\n\
+             component=APP\n",
+        );
+    }
+
+    #[test]
+    fn parse_apache_combined() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--format")
+            .arg("apache-combined")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(
+            "203.0.113.10 - - [28/Sep/2021:08:00:10 +0000] \"GET /health HTTP/1.1\" 200 15 \"-\" \"curl/7.64.1\"\n",
+        );
+    }
+
+    #[test]
+    fn parse_trace_fields() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--trace-fields")
+            .arg("--columns")
+            .arg("component")
+            .arg("--format")
+            .arg("logfmt")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(
+            "component=APP trace_id=6152cb8077136e53942078a29eb7d0d8 span_id=942078a29eb7d0d8\n\
+             component=RTR\n\
+             component=APP\n",
+        );
+    }
+
+    #[test]
+    fn filter_drop_health_checks() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--count")
+            .arg("--drop-health-checks")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout("2\n");
+    }
+
+    #[test]
+    fn filter_strip_ansi() {
+        let line = "\x1b[32m2021-09-28T17:00:09.36+0900 [RTR/0] OUT\x1b[0m\nnot a log line\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").write_stdin(line);
+        cmd.assert()
+            .success()
+            .stdout("\x1b[32m2021-09-28T17:00:09.36+0900 [RTR/0] OUT\x1b[0m\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("--strip-ansi").write_stdin(line);
+        cmd.assert()
+            .success()
+            .stdout("2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+    }
+
+    #[test]
+    fn parse_stdin() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .write_stdin("2021-09-28T17:00:09.36+0900 [RTR/1] OUT\nnot a log line\n");
+        cmd.assert().success().stdout(predicate::str::diff(
+            r#"{"timestamp":"2021-09-28T17:00:09.360+09:00","component":"RTR","index":1,"process_type":null,"channel":"OUT","message":null}"#.to_string() + "\n",
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_write_error() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["parse", "-o", "/dev/full", "test/file/matching.txt"]);
+        cmd.assert().code(2).stderr(predicate::str::starts_with(
+            "Could not write to /dev/full: ",
+        ));
+    }
+
+    /// Run with the standard output on /dev/full, so that every write to it fails
+    #[cfg(target_os = "linux")]
+    fn assert_full_stdout(args: &[&str]) -> assert_cmd::assert::Assert {
+        use assert_cmd::assert::OutputAssertExt;
+
+        std::process::Command::new(assert_cmd::cargo::cargo_bin("cf-app-log-detector"))
+            .args(args)
+            .stdout(fs::File::create("/dev/full").unwrap())
+            .output()
+            .unwrap()
+            .assert()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn filter_write_error() {
+        assert_full_stdout(&["filter", "test/file/matching.txt"])
+            .code(2)
+            .stderr(predicate::str::starts_with(
+                "Could not write to stdout: No space left on device",
+            ));
+    }
+
+    #[test]
+    fn parse_csv() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--format")
+            .arg("csv")
+            .arg("--columns")
+            .arg("timestamp,component,index,channel,message")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                "timestamp,component,index,channel,message\n",
+            ))
+            .stdout(predicate::str::contains(
+                "2021-09-28T17:00:10.130+09:00,APP,0,ERR,WARN: slow request\n",
+            ))
+            .stdout(predicate::str::contains(
+                r#",RTR,0,OUT,"my-app.example.com - "#,
+            ));
+    }
+
+    #[test]
+    fn filter_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                "   2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT",
+            ))
+            .stdout(predicate::str::contains("Retrieving logs").not());
+    }
+
+    #[test]
+    fn stats_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("total number of lines: 5\n"))
+            .stdout(predicate::str::contains("log lines matching: 3\n"));
+    }
+
+    #[test]
+    fn stats_breakdown() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(predicate::str::contains(
+            "percentage matching: 60\n\
+             period: 2021-09-28T17:00:09.360+09:00 to 2021-09-28T17:00:10.130+09:00 (0.77s)\n",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout(predicate::str::ends_with(
+            "by component:\n  APP: 2\n  RTR: 1\n\
+             by channel:\n  ERR: 1\n  OUT: 2\n\
+             by instance:\n  APP/web/0: 2\n  RTR/0: 1\n\
+             by utc offset:\n  +09:00: 3\n\
+             by level:\n  debug: 1\n  warn: 1\n\
+             period by component:\n  \
+             APP: 2 entries from 2021-09-28T17:00:09.360+09:00 to 2021-09-28T17:00:10.130+09:00 (0.77s)\n  \
+             RTR: 1 entries from 2021-09-28T17:00:10.120+09:00 to 2021-09-28T17:00:10.120+09:00 (0s)\n\
+             lines per second (average, peak):\n  APP: 1, 1\n  RTR: 1, 1\n\
+             app log framework: logback/log4j (1 of 2 APP entries)\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--format")
+            .arg("json")
+            .arg("test/file/with_banner.txt");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(stats["total_lines"], 5);
+        assert_eq!(stats["percentage_matching"], 60.0);
+        assert_eq!(stats["channels"]["ERR"], 1);
+        assert_eq!(stats["instances"]["APP/web/0"], 2);
+        assert_eq!(stats["throughput"]["APP"]["peak"], 1);
+        assert_eq!(stats["levels"]["warn"], 1);
+        assert_eq!(stats["period"]["last"], "2021-09-28T17:00:10.130+09:00");
+        assert_eq!(stats["period"]["seconds"], 0.77);
+        assert_eq!(stats["component_periods"]["RTR"]["entries"], 1);
+    }
+
+    #[test]
+    fn stats_mixed_utc_offsets() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").write_stdin(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT a\n\
+             2021-09-28T08:00:10.00+0000 [APP/PROC/WEB/0] OUT b\n\
+             2021-09-28T17:00:11.36+0900 [APP/PROC/WEB/0] OUT c\n",
+        );
+        cmd.assert().success().stdout(predicate::str::contains(
+            "by utc offset:\n  +00:00: 1\n  +09:00: 2\n\
+             files mixing utc offsets:\n  -: +00:00, +09:00\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--format")
+            .arg("json")
+            .arg("test/file/with_banner.txt");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(stats["utc_offsets"]["+09:00"], 3);
+        assert_eq!(stats["mixed_utc_offsets"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn stats_timestamp_regressions() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").write_stdin(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT a\n\
+             2021-09-28T17:00:08.86+0900 [APP/PROC/WEB/1] OUT b\n\
+             2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/0] OUT c\n\
+             2021-09-28T17:00:07.00+0900 [APP/PROC/WEB/1] OUT d\n",
+        );
+        cmd.assert().success().stdout(predicate::str::contains(
+            "timestamps going backwards: 2 (up to 3s at -:4)\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--format")
+            .arg("json")
+            .arg("test/file/with_banner.txt");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            stats["timestamp_regressions"],
+            serde_json::json!({"count": 0, "largest": null})
+        );
+    }
+
+    #[test]
+    fn stats_clock_skew() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("--clock-skew").write_stdin(
+            "2021-09-28T17:00:09.90+0900 [APP/PROC/WEB/0] OUT handling 3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\n\
+             2021-09-28T17:00:10.60+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:10.000+0000] \"GET / HTTP/1.1\" 200 0 15 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" vcap_request_id:\"3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\" response_time:0.5\n\
+             2021-09-28T17:00:12.00+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:11.000+0000] \"GET / HTTP/1.1\" 200 0 15 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" vcap_request_id:\"-\" response_time:0.5\n",
+        );
+        cmd.assert().success().stdout(
+            "requests joined with app entries: 1 of 2 requests\n\
+             clock skew of cells against routers in seconds (p50, p90, p99, max):\n  \
+             all: -0.1, -0.1, -0.1, -0.1\n  \
+             APP/web/0: -0.1, -0.1, -0.1, -0.1\n",
+        );
+    }
+
+    #[test]
+    fn stats_load_imbalance() {
+        let log: String = (0..20)
+            .map(|i| {
+                format!(
+                    "2021-09-28T17:00:{:02}.00+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:{:02}.000+0000] \"GET / HTTP/1.1\" 200 0 15 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" app_index:\"{}\"\n",
+                    i,
+                    i,
+                    if i < 18 { 0 } else { 1 }
+                )
+            })
+            .collect();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").write_stdin(log.clone());
+        cmd.assert().success().stdout(predicate::str::contains(
+            "requests by app instance index:\n  0: 18 (90.0%)\n  1: 2 (10.0%)\n\
+             load imbalance: instance 0 got 90.0% of the requests of 2 instances\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--format")
+            .arg("json")
+            .write_stdin(log);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(stats["load"]["requests"]["1"], 2);
+        assert_eq!(stats["load"]["imbalances"][0]["of"], "requests");
+        assert_eq!(stats["load"]["imbalances"][0]["share"], 0.9);
+    }
+
+    #[test]
+    fn stats_histogram() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("--histogram").arg("1m").write_stdin(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT a\n\
+             2021-09-28T17:00:49.36+0900 [APP/PROC/WEB/0] OUT b\n\
+             2021-09-28T08:02:00.00+0000 [APP/PROC/WEB/0] OUT c\n",
+        );
+        cmd.assert()
+            .success()
+            .stdout("2021-09-28T08:00:00Z 2\n2021-09-28T08:01:00Z 0\n2021-09-28T08:02:00Z 1\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--histogram")
+            .arg("1h")
+            .arg("--bars")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(format!("2021-09-28T08:00:00Z 3 {}\n", "#".repeat(50)));
+    }
+
+    #[test]
+    fn stats_top() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--top")
+            .arg("1")
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success().stdout("2  66.7% APP/web/0\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--top")
+            .arg("5")
+            .arg("--message-prefix")
+            .arg("4")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("1  33.3% APP/web/0 2021\n1  33.3% APP/web/0 WARN\n1  33.3% RTR/0 my-a\n");
+    }
+
+    #[test]
+    fn stats_templates() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("--templates").arg("2").write_stdin(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR timeout after 30s on 10.0.1.5:61001\n\
+             2021-09-28T17:00:10.36+0900 [APP/PROC/WEB/1] ERR timeout after 12s on 10.0.1.6:61002\n\
+             2021-09-28T17:00:11.36+0900 [APP/PROC/WEB/0] OUT started\n",
+        );
+        cmd.assert()
+            .success()
+            .stdout("2  66.7% timeout after <num>s on <ip>\n1  33.3% started\n");
+    }
+
+    #[test]
+    fn stats_http() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("--http").arg("test/file/matching.txt");
+        cmd.assert().success().stdout(
+            "requests: 3\nby status class:\n  2xx: 3\nby status:\n  200: 3\n\
+                 response_time in seconds (p50, p90, p99, max):\n  \
+                 all: 0.033311281, 0.12050583, 0.12050583, 0.12050583\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--http")
+            .arg("--format")
+            .arg("json")
+            .write_stdin(
+                "2021-09-28T17:00:09.36+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:09.102+0000] \"POST /orders?id=1 HTTP/1.1\" 503 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\"\n\
+                 2021-09-28T17:00:10.36+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:10.102+0000] \"POST /orders?id=2 HTTP/1.1\" 502 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\"\n",
+            );
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(stats["classes"]["5xx"], 2);
+        assert_eq!(stats["statuses"]["503"], 1);
+        assert_eq!(
+            stats["top_failing_paths"],
+            serde_json::json!([{ "path": "/orders", "count": 2 }])
+        );
+    }
+
+    #[test]
+    fn stats_http_latency_by() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--http")
+            .arg("--latency-by")
+            .arg("path")
+            .arg("test/file/matching.txt");
+        cmd.assert().success().stdout(predicate::str::contains(
+            "\n  /test/: 0.033311281, 0.033311281, 0.033311281, 0.033311281\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--latency-by")
+            .arg("process")
+            .arg("test/file/matching.txt");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn stats_prometheus() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats")
+            .arg("--format")
+            .arg("prometheus")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("\ncf_app_log_lines_total 5\n"))
+            .stdout(predicate::str::contains(
+                "\ncf_app_log_entries_total{component=\"APP\"} 2\n",
+            ))
+            .stdout(predicate::str::contains(
+                "\ncf_app_log_matching_ratio 0.6\n",
+            ));
+    }
+
+    #[test]
+    fn merge_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("merge")
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/matching.txt");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("2016-06-14T15:16:12.70-0700"));
+        assert!(lines[5].starts_with("   2021-09-28T17:00:10.13+0900"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn merge_write_error() {
+        assert_full_stdout(&[
+            "merge",
+            "test/file/matching.txt",
+            "test/file/with_banner.txt",
+        ])
+        .code(2)
+        .stderr(predicate::str::starts_with(
+            "Could not write to stdout: No space left on device",
+        ));
+    }
+
+    #[test]
+    fn merge_ties_keep_input_order() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("merge")
+            .arg("-")
+            .arg("test/file/with_banner.txt")
+            .write_stdin("2021-09-28T08:00:10.13+0000 [APP/PROC/WEB/1] OUT same time\n");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].ends_with("same time"));
+        assert!(lines[3].ends_with("WARN: slow request"));
+    }
+
+    #[test]
+    fn split_by_component() {
+        let dir = temp_dir();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("split")
+            .arg("--by")
+            .arg("component")
+            .arg("-o")
+            .arg(dir.path())
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/matching.txt");
+        cmd.assert().success();
+        let mut files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["APP.log", "RTR.log"]);
+        let app = fs::read_to_string(dir.path().join("APP.log")).unwrap();
+        assert_eq!(app.lines().count(), 2);
+        assert!(app
+            .ends_with("   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n"));
+        let rtr = fs::read_to_string(dir.path().join("RTR.log")).unwrap();
+        assert_eq!(rtr.lines().count(), 4);
+    }
+
+    #[test]
+    fn split_by_instance() {
+        let dir = temp_dir();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("split")
+            .arg("--by")
+            .arg("instance")
+            .arg("-o")
+            .arg(dir.path())
+            .arg("-")
+            .write_stdin(
+                "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT zero\n\
+                 2021-09-28T17:00:09.37+0900 [APP/PROC/WEB/1] OUT one\n\
+                 2021-09-28T17:00:09.38+0900 [APP/PROC/WEB/0] ERR zero again\n",
+            );
+        cmd.assert().success();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("APP-0.log")).unwrap(),
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT zero\n\
+             2021-09-28T17:00:09.38+0900 [APP/PROC/WEB/0] ERR zero again\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("APP-1.log")).unwrap(),
+            "2021-09-28T17:00:09.37+0900 [APP/PROC/WEB/1] OUT one\n"
+        );
+    }
+
+    #[test]
+    fn split_by_hour() {
+        let dir = temp_dir();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("split")
+            .arg("--by")
+            .arg("hour")
+            .arg("--max-size")
+            .arg("60")
+            .arg("-o")
+            .arg(dir.path())
+            .arg("-")
+            .write_stdin(
+                "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT first\n\
+                 2021-09-28T08:59:09.37+0000 [APP/PROC/WEB/1] OUT second\n\
+                 2021-09-28T18:00:09.38+0900 [APP/PROC/WEB/0] OUT third\n",
+            );
+        cmd.assert().success();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("2021-09-28T08.log")).unwrap(),
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT first\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("2021-09-28T08.1.log")).unwrap(),
+            "2021-09-28T08:59:09.37+0000 [APP/PROC/WEB/1] OUT second\n"
+        );
+        assert!(dir.path().join("2021-09-28T09.log").exists());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn export_subcommand() {
+        let dir = temp_dir();
+        let db = dir.path().join("export.db");
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("export")
+            .arg("--sqlite")
+            .arg(&db)
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/matching.txt");
+        cmd.assert().success();
+        let connection = rusqlite::Connection::open(&db).unwrap();
+        let count: i64 = connection
+            .query_row(
+                "SELECT count(*) FROM entries WHERE file = 'test/file/matching.txt'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn check_max_5xx_rate() {
+        let log = "2021-09-28T17:00:09.36+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:09.102+0000] \"POST /orders HTTP/1.1\" 503 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\"\n\
+                   2021-09-28T17:00:10.36+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:10.102+0000] \"GET / HTTP/1.1\" 200 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\"\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("check")
+            .arg("--max-5xx-rate")
+            .arg("10%")
+            .write_stdin(log);
+        cmd.assert()
+            .code(1)
+            .stdout("FAIL: 5xx rate 50.00% (1 of 2 requests), maximum 10%\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("check")
+            .arg("--max-5xx-rate")
+            .arg("50")
+            .write_stdin(log);
+        cmd.assert().success();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("check")
+            .arg("--max-5xx-rate")
+            .arg("1%")
+            .arg("test/file/matching.txt");
+        cmd.assert()
+            .success()
+            .stdout("OK: 5xx rate 0.00% (0 of 3 requests), maximum 1%\n");
+    }
+
+    #[test]
+    fn trace_request() {
+        let log = "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT 2021-09-28 08:00:09.361 INFO [orders,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8] creating order\n\
+                   2021-09-28T17:00:09.37+0900 [APP/PROC/WEB/0] OUT 2021-09-28 08:00:09.371 INFO [orders,0000cb8077136e53942078a29eb7d0d8,042078a29eb7d0d8] listing orders\n\
+                   2021-09-28T17:00:09.40+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:09.102+0000] \"POST /orders HTTP/1.1\" 201 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" vcap_request_id:\"3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\" x_b3_traceid:\"6152cb8077136e53942078a29eb7d0d8\"\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("trace")
+            .arg("3E5C7B4A-1D2F-4C3B-6A5D-7E8F9A0B1C2D")
+            .write_stdin(log);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("creating order"))
+            .stdout(predicate::str::contains("[RTR/0]"))
+            .stdout(predicate::str::contains("listing orders").not());
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("trace").arg("unknown").write_stdin(log);
+        cmd.assert().code(1).stdout("");
+    }
+
+    #[test]
+    fn requests_report() {
+        let log = "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT 2021-09-28 08:00:09.361 INFO [orders,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8] creating order\n\
+                   2021-09-28T17:00:09.38+0900 [APP/PROC/WEB/0] ERR 2021-09-28 08:00:09.381 ERROR [orders,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8] payment failed\n\
+                   2021-09-28T17:00:09.40+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:09.102+0000] \"POST /orders HTTP/1.1\" 502 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" vcap_request_id:\"3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\" response_time:0.05 x_b3_traceid:\"6152cb8077136e53942078a29eb7d0d8\"\n\
+                   2021-09-28T17:00:10.40+0900 [RTR/0] OUT a.example.com - [2021-09-28T08:00:10.102+0000] \"GET /health HTTP/1.1\" 200 10 0 \"-\" \"curl\" \"10.0.0.1:1\" \"10.0.1.5:2\" vcap_request_id:\"aaaa7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\" response_time:0.001\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("requests").write_stdin(log);
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:09.400+09:00 POST /orders 502 0.05s, 2 app entries over 0.02s [5xx, app errors] 3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\n\
+             2021-09-28T17:00:10.400+09:00 GET /health 200 0.001s, no app entries aaaa7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("requests")
+            .arg("--errors")
+            .arg("--format")
+            .arg("json")
+            .write_stdin(log);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let request: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(request["app_entries"], 2);
+        assert_eq!(request["app_errors"], 1);
+        assert_eq!(request["flags"], serde_json::json!(["5xx", "app errors"]));
+    }
+
+    #[test]
+    fn crashes_report() {
+        let log = "2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/2] OUT Exit status 1\n\
+                   2021-09-28T17:00:10.50+0900 [CELL/0] OUT Exit status 0\n\
+                   2021-09-28T17:00:11.00+0900 [API/1] OUT App instance exited with guid 79bb58ab-3737-43be-ac70-39a2843b5177 payload: {\"index\"=>2, \"reason\"=>\"CRASHED\", \"exit_description\"=>\"APP/PROC/WEB: Exited with status 1\"}\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("crashes").write_stdin(log);
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:10.000+09:00 APP/web/2 instance 2 exit status 1\n\
+             2021-09-28T17:00:11.000+09:00 API/1 instance 2 exit status 1 CRASHED (APP/PROC/WEB: Exited with status 1)\n\
+             2 crashes, 0 out of memory\n",
+        );
+    }
+
+    #[test]
+    fn stats_out_of_memory() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").write_stdin(
+            "2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/2] OUT Exit status 137\n\
+             2021-09-28T17:00:12.00+0900 [APP/PROC/WEB/0] OUT Exit status 1\n",
+        );
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::ends_with("crashes: 2\nout of memory: 1\n"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("crashes")
+            .write_stdin("2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/2] OUT Exit status 137\n");
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:10.000+09:00 APP/web/2 instance 2 exit status 137 [out of memory]\n\
+             1 crashes, 1 out of memory\n",
+        );
+    }
+
+    #[test]
+    fn restarts_loop() {
+        let log = "2021-09-28T17:00:00.00+0900 [CELL/2] OUT Cell 8f4a creating container for instance 5e3f\n\
+                   2021-09-28T17:01:00.00+0900 [CELL/2] OUT Cell 8f4a creating container for instance 5e3f\n\
+                   2021-09-28T17:02:00.00+0900 [CELL/2] OUT Cell 8f4a creating container for instance 5e3f\n\
+                   2021-09-28T17:30:00.00+0900 [CELL/2] OUT Cell 8f4a creating container for instance 5e3f\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("restarts").arg("--max").arg("2").write_stdin(log);
+        cmd.assert().success().stdout(
+            "instance 2: 3 restarts\n  \
+             restart loop: 3 starts from 2021-09-28T17:00:00.000+09:00 to 2021-09-28T17:02:00.000+09:00\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("restarts").write_stdin(log);
+        cmd.assert().success().stdout("instance 2: 3 restarts\n");
+    }
+
+    #[test]
+    fn staging_phases() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("staging").write_stdin(
+            "2021-09-28T17:00:00.00+0900 [STG/0] OUT Cell 8f4a creating container for instance 5e3f\n\
+             2021-09-28T17:00:02.00+0900 [STG/0] OUT Downloading app package...\n\
+             2021-09-28T17:00:05.00+0900 [STG/0] OUT -----> Java Buildpack v4.45\n\
+             2021-09-28T17:00:45.50+0900 [STG/0] OUT Uploading droplet...\n",
+        );
+        cmd.assert().success().stdout(
+            "staging at 2021-09-28T17:00:00.000+09:00: 45.5s (incomplete)\n  \
+             buildpacks: Java Buildpack v4.45\n  \
+             setup: 2s\n  download: 3s\n  compile: 40.5s\n  upload: 0s\n",
+        );
+    }
+
+    #[test]
+    fn ssh_sessions() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("ssh").write_stdin(
+            "2021-09-28T17:00:00.00+0900 [SSH/1] OUT Successful remote access by 192.0.2.33:53422\n\
+             2021-09-28T17:01:00.00+0900 [SSH/0] OUT Remote access ended for 192.0.2.40:1111\n\
+             2021-09-28T17:05:30.50+0900 [SSH/1] OUT Remote access ended for 192.0.2.33:53422\n",
+        );
+        cmd.assert().success().stdout(
+            "instance 1 192.0.2.33:53422 from 2021-09-28T17:00:00.000+09:00 to 2021-09-28T17:05:30.500+09:00 (330.5s)\n\
+             instance 0 192.0.2.40:1111 from ? to 2021-09-28T17:01:00.000+09:00\n",
+        );
+    }
+
+    #[test]
+    fn audit_events() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("audit").write_stdin(
+            "2021-09-28T17:00:00.00+0900 [API/0] OUT Updated app with guid 79bb58ab ({\"state\"=>\"STOPPED\"})\n\
+             2021-09-28T17:00:01.00+0900 [APP/PROC/WEB/0] OUT Updated app with guid 79bb58ab\n\
+             2021-09-28T17:02:00.00+0900 [API/1] OUT Updated app with guid 79bb58ab (instances: 3) by user admin\n",
+        );
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:00.000+09:00 updated app 79bb58ab state=STOPPED\n\
+             2021-09-28T17:02:00.000+09:00 updated app 79bb58ab instances=3 by admin\n",
+        );
+    }
+
+    #[test]
+    fn reassemble_truncated_messages() {
+        let dir = temp_dir();
+        let path = dir.path().join("reassemble.log");
+        let head = "x".repeat(61440);
+        fs::write(
+            &path,
+            format!(
+                "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT {}\n\
+                 2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT tail\n",
+                head
+            ),
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("--count").arg(&path);
+        cmd.assert().success().stdout("2\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--reassemble")
+            .arg("--line-numbers")
+            .arg("--columns")
+            .arg("message")
+            .arg(&path);
+        cmd.assert()
+            .success()
+            .stdout(format!("{{\"line\":1,\"message\":\"{}tail\"}}\n", head));
+    }
+
+    #[test]
+    fn stats_framework() {
+        let dir = temp_dir();
+        let path = dir.path().join("framework.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT 08:00:09.360 [main] INFO c.e.App - Started\n\
+             2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT Listening on port 8080\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg(&path);
+        cmd.assert().success().stdout(predicate::str::contains(
+            "app log framework: logback/log4j (1 of 2 APP entries)\n",
+        ));
+    }
+
+    #[test]
+    fn stats_logging_delay() {
+        let dir = temp_dir();
+        let path = dir.path().join("logging-delay.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:10.00+0900 [APP/PROC/WEB/1] OUT 08:00:08.000 [main] INFO c.e.App - Started\n\
+             2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT hello\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("stats").arg("--logging-delay").arg(&path);
+        cmd.assert().success().stdout(
+            "entries with a timestamp: 1 of 2 APP entries\n\
+             logging delay in seconds (p50, p90, p99, max):\n  all: 2, 2, 2, 2\n  APP/web/1: 2, 2, 2, 2\n",
+        );
+    }
+
+    #[test]
+    fn json_messages() {
+        let dir = temp_dir();
+        let path = dir.path().join("json-messages.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT {\"level\":\"info\",\"msg\":\"hi\"}\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter").arg("--pretty-json").arg(&path);
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT {\n  \"level\": \"info\",\n  \"msg\": \"hi\"\n}\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--format")
+            .arg("csv")
+            .arg("--columns")
+            .arg("channel")
+            .arg("--json-keys")
+            .arg("level")
+            .arg(&path);
+        cmd.assert()
+            .success()
+            .stdout("channel,json.level\nOUT,info\n");
+    }
+
+    #[test]
+    fn filter_min_level() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("filter")
+            .arg("--min-level")
+            .arg("warn")
+            .arg("test/file/with_banner.txt");
+        cmd.assert()
+            .success()
+            .stdout("   2021-09-28T17:00:10.13+0900 [APP/PROC/WEB/0] ERR WARN: slow request\n");
+    }
+
+    #[test]
+    fn severity_mapping() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--format")
+            .arg("syslog")
+            .arg("--severity-mapping")
+            .arg("err=info")
+            .write_stdin("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR Booting\n");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with("<14>1 "));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("parse")
+            .arg("--format")
+            .arg("syslog")
+            .arg("--severity-mapping")
+            .arg("loud=error");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn redact_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("redact").write_stdin(
+            "login of jane@example.com\n\
+             2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT login of jane@example.com from 10.0.0.1\n",
+        );
+        cmd.assert().success().stdout(
+            "login of <email>\n\
+             2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT login of <email> from <ip>\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("redact").arg("--keep").arg("ip").write_stdin(
+            "2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - \"GET /health HTTP/1.1\" 200 \"10.0.0.1:51234\"\n",
+        );
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - \"GET /health HTTP/1.1\" 200 \"10.0.0.1:51234\"\n",
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn redact_write_error() {
+        assert_full_stdout(&["redact", "test/file/matching.txt"])
+            .code(2)
+            .stderr(predicate::str::starts_with(
+                "Could not write to stdout: No space left on device",
+            ));
+    }
+
+    #[test]
+    fn redact_custom_patterns() {
+        let dir = temp_dir();
+        let config = dir.path().join("redact.conf");
+        fs::write(&config, "# tickets\nticket = (INC)-\\d+ => ${1}-<id>\n").unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("redact")
+            .arg("--redact-config")
+            .arg(&config)
+            .arg("--redact-pattern")
+            .arg("employee=EMP-\\d{6}=><employee>")
+            .write_stdin(
+                "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT EMP-123456 closed INC-42\n",
+            );
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] OUT <employee> closed INC-<id>\n",
+        );
+
+        fs::write(&config, "ticket INC-\\d+\n").unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("redact").arg("--redact-config").arg(&config);
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("line 1"));
+    }
+
+    #[test]
+    fn gaps_subcommand() {
+        let log = "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT a\n\
+                   2021-09-28T17:00:30.00+0900 [APP/PROC/WEB/1] OUT b\n\
+                   2021-09-28T17:02:30.00+0900 [APP/PROC/WEB/0] OUT c\n\
+                   2021-09-28T17:01:00.00+0900 [APP/PROC/WEB/1] OUT d\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("gaps").write_stdin(log);
+        cmd.assert()
+            .success()
+            .stdout("90s from 2021-09-28T17:01:00.000+09:00 to 2021-09-28T17:02:30.000+09:00\n");
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("gaps")
+            .arg("--by")
+            .arg("instance")
+            .arg("--longer-than")
+            .arg("20s")
+            .write_stdin(log);
+        cmd.assert().success().stdout(
+            "APP/web/0:\n  \
+             150s from 2021-09-28T17:00:00.000+09:00 to 2021-09-28T17:02:30.000+09:00\n\
+             APP/web/1:\n  \
+             30s from 2021-09-28T17:00:30.000+09:00 to 2021-09-28T17:01:00.000+09:00\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("gaps")
+            .arg("--by")
+            .arg("component")
+            .arg("--format")
+            .arg("json")
+            .write_stdin(log);
+        cmd.assert().success().stdout(
+            "{\"component\":\"APP\",\"start\":\"2021-09-28T17:01:00.000+09:00\",\"end\":\"2021-09-28T17:02:30.000+09:00\",\"seconds\":90.0}\n",
+        );
+    }
+
+    #[test]
+    fn doctor_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("doctor").write_stdin(
+            "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/2] OUT Exit status 137\n\
+             2021-09-28T17:10:00.00+0900 [APP/PROC/WEB/2] OUT ERROR boom\n",
+        );
+        cmd.assert().success().stdout(
+            "critical: 1 app instance crashes\n\
+             critical: 1 entries tell an app instance ran out of memory\n\
+             warning: 50.0% of the 2 APP entries tell an error\n\
+             warning: APP/web/2 logged nothing for 600s from 2021-09-28T17:00:00.000+09:00 to 2021-09-28T17:10:00.000+09:00, 1 gaps over 300s\n\
+             info: 100% of the 2 lines are CF application log entries\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("doctor")
+            .arg("--format")
+            .arg("json")
+            .arg("test/file/with_banner.txt");
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let diagnosis: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(diagnosis["is_log"], true);
+        assert_eq!(diagnosis["findings"][0]["check"], "detection");
+    }
+
+    #[test]
+    fn report_html() {
+        let dir = temp_dir();
+        let path = dir.path().join("report.html");
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("report").arg("--html").arg(&path).write_stdin(
+            "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/2] OUT Exit status 137 <oom>\n\
+             2021-09-28T17:00:01.00+0900 [APP/PROC/WEB/2] OUT started\n",
+        );
+        cmd.assert().success().stdout("");
+        let html = fs::read_to_string(&path).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(
+            "<tr class=\"critical\"><td>critical</td><td>crashes</td><td>1 app instance crashes</td></tr>"
+        ));
+        assert!(html.contains("<tr><td>APP/web/2</td><td class=\"count\">2</td></tr>"));
+        assert!(html
+            .contains("<td>out of memory</td><td><code>Exit status 137 &lt;oom&gt;</code></td>"));
+    }
+
+    #[test]
+    fn report_markdown() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("report").arg("--markdown").write_stdin(
+            "2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/2] OUT Exit status 1\n\
+             2021-09-28T17:00:01.00+0900 [APP/PROC/WEB/2] OUT started\n",
+        );
+        cmd.assert().success().stdout(
+            "## CF application log report\n\n\
+             - **Inputs:** -\n\
+             - **Verdict:** CF application log, 2 of 2 lines are entries\n\
+             - **Period:** 2021-09-28T17:00:00.000+09:00 to 2021-09-28T17:00:01.000+09:00\n\
+             - **Entries:** APP 2\n\n\
+             | severity | check | finding |\n\
+             | --- | --- | --- |\n\
+             | critical | crashes | 1 app instance crashes |\n\
+             | info | detection | 100% of the 2 lines are CF application log entries |\n\n\
+             ### Timeline\n\n\
+             - `2021-09-28T17:00:00.000+09:00` APP/web/2 crash: `Exit status 1`\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("report").write_stdin("");
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn view_needs_terminal() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("view")
+            .write_stdin("2021-09-28T17:00:00.00+0900 [APP/PROC/WEB/0] OUT started\n");
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("view needs a terminal"));
+    }
+
+    #[test]
+    fn tail_aligns_columns() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail").write_stdin(
+            "   2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /\n\
+             not a log line\n\
+             2021-09-28T17:00:10.123+0900 [APP/PROC/WEB/0] ERR boom\n\
+             2021-09-28T17:00:11.1+0900 [RTR/0] OUT\n",
+        );
+        cmd.assert().success().stdout(
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /\n\
+             2021-09-28T17:00:10.123+0900 [APP/PROC/WEB/0] ERR boom\n\
+             2021-09-28T17:00:11.1+0900   [RTR/0]          OUT\n",
+        );
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--last", "1", "--component", "RTR"])
+            .write_stdin(
+                "2021-09-28T17:00:09.36+0900 [RTR/0] OUT first\n\
+                 2021-09-28T17:00:10.36+0900 [RTR/1] OUT second\n\
+                 2021-09-28T17:00:11.36+0900 [APP/PROC/WEB/0] OUT third\n",
+            );
+        cmd.assert()
+            .success()
+            .stdout("2021-09-28T17:00:10.36+0900 [RTR/1] OUT second\n");
+    }
+
+    #[test]
+    fn tail_color() {
+        let line = "2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /\n\
+                    2021-09-28T17:00:10.36+0900 [APP/PROC/WEB/0] ERR boom\n";
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--color", "always"]).write_stdin(line);
+        cmd.assert().success().stdout(
+            "\x1b[2m2021-09-28T17:00:09.36+0900\x1b[0m \x1b[34m[RTR/0]\x1b[0m OUT GET /\n\
+             \x1b[2m2021-09-28T17:00:10.36+0900\x1b[0m [APP/PROC/WEB/0] \x1b[31mERR boom\x1b[0m\n",
+        );
+
+        // auto never colors pipes, NO_COLOR does not override always
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--color", "auto"]).write_stdin(line);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("\x1b").not());
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--color", "always"])
+            .env("NO_COLOR", "1")
+            .write_stdin(line);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("\x1b[34m[RTR/0]"));
+    }
+
+    #[test]
+    fn tail_follow() {
+        let dir = temp_dir();
+        let path = dir.path().join("tail-follow.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT old\n\
+             2021-09-28T17:00:09.37+0900 [RTR/0] OUT last\n",
+        )
+        .unwrap();
+
+        let appender = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(500));
+                let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+                writeln!(file, "2021-09-28T17:00:10.36+0900 [APP/PROC/WEB/0] OUT new").unwrap();
+            })
+        };
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--follow", "--last", "1"])
+            .arg(&path)
+            .timeout(Duration::from_secs(2));
+        cmd.assert().interrupted().stdout(
+            "2021-09-28T17:00:09.37+0900 [RTR/0] OUT last\n\
+             2021-09-28T17:00:10.36+0900 [APP/PROC/WEB/0] OUT new\n",
+        );
+
+        appender.join().unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--follow"]).write_stdin("");
+        cmd.assert()
+            .code(2)
+            .stderr("--follow is only supported with a single file\n");
+    }
+
+    #[test]
+    fn config_file() {
+        let dir = temp_dir();
+        let config = dir.path().join("config.toml");
+        fs::write(
+            &config,
+            "color = \"always\"\nreassemble = true\n\n[detect]\npercentage-matching = 50\n",
+        )
+        .unwrap();
+        let log = dir.path().join("config.log");
+        fs::write(
+            &log,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT\nnot a log line\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("--config").arg(&config).arg(&log);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a CF application log [50% line matching]",
+        ));
+
+        // reassemble does not apply to tail, the command line wins over the configuration
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail").arg("--config").arg(&config).arg(&log);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("\x1b[34m[RTR/0]"));
+        // global options can come before the subcommand
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("--config").arg(&config).arg("tail").arg(&log);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("\x1b[34m[RTR/0]"));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["tail", "--color", "never", "--config"])
+            .arg(&config)
+            .arg(&log);
+        cmd.assert()
+            .success()
+            .stdout("2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+
+        // the environment wins over the configuration, options missing from a subcommand are
+        // left out
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail")
+            .env("CF_LOG_DETECTOR_CONFIG", &config)
+            .env("CF_LOG_DETECTOR_COLOR", "never")
+            .env("CF_LOG_DETECTOR_PERCENTAGE_MATCHING", "50")
+            .arg(&log);
+        cmd.assert()
+            .success()
+            .stdout("2021-09-28T17:00:09.36+0900 [RTR/0] OUT\n");
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail")
+            .env("CF_LOG_DETECTOR_COLOR", "sometimes")
+            .arg(&log);
+        cmd.assert().code(1).stderr(predicate::str::contains(
+            "In the options set by CF_LOG_DETECTOR_* variables:",
+        ));
+
+        fs::write(&config, "[tail]\nformat = \"json\"\n").unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail").arg("--config").arg(&config).arg(&log);
+        cmd.assert()
+            .code(1)
+            .stderr(predicate::str::contains("In the options set by"))
+            .stderr(predicate::str::contains("Found argument '--format'"));
+
+        fs::remove_file(&config).unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("tail").arg("--config").arg(&config).arg(&log);
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("Could not read the configuration"));
+    }
+
+    #[test]
+    fn completions() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["completions", "bash"]);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with("_cf-app-log-detector() {"))
+            .stdout(predicate::str::contains("--percentage-matching"));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["completions", "fish"]);
+        cmd.assert().success().stdout(predicate::str::contains(
+            "complete -c cf-app-log-detector -n \"__fish_use_subcommand\" -f -a \"tail\"",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["completions", "tcsh"]);
+        cmd.assert().failure();
+    }
+
+    #[test]
+    fn man_page() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("man");
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                ".TH CF\\-APP\\-LOG\\-DETECTOR 1 ",
+            ))
+            .stdout(predicate::str::contains(
+                ".SS tail\nWrite the entries with aligned columns, colored by component and channel on a terminal\n",
+            ))
+            .stdout(predicate::str::contains("\\-\\-percentage\\-matching"));
+    }
+
+    #[test]
+    fn detect_log_format() {
+        let dir = temp_dir();
+        let path = dir.path().join("log-format.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /\n\
+             2021-09-28T17:00:09.37+0900 [RTR/1] OUT GET /\n",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--log-format", "rtr"]).arg(&path);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a CF router log [100% line matching]",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--log-format", "syslog"]).arg(&path);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "is NOT syslog output [0% line matching]",
+        ));
+    }
+
+    #[test]
+    fn identify() {
+        let dir = temp_dir();
+        let path = dir.path().join("identify.log");
+        fs::write(
+            &path,
+            "2021-09-28T17:00:09.36+0900 [RTR/0] OUT GET /\n\
+             2021-09-28T17:00:09.37+0900 [APP/PROC/WEB/0] OUT {\"msg\":\"started\"}\n\
+             {\"level\":\"info\"}\n\
+             <14>Sep 28 08:00:09 host app: started\n",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("identify").arg(&path);
+        cmd.assert().success().stdout(predicate::str::ends_with(
+            ": CF application log (50% CF application log, 25% CF router log, 25% syslog output, 25% JSON lines, 0% Kubernetes container log, 0% Docker container log, 0% Heroku log)\n",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["identify", "--format", "json"]).arg(&path);
+        cmd.assert().success().stdout(predicate::str::ends_with(
+            "\"format\":\"cf\",\"lines\":4,\"formats\":{\"cf\":50.0,\"rtr\":25.0,\"syslog\":25.0,\"json\":25.0,\"cri\":0.0,\"docker\":0.0,\"heroku\":0.0}}\n",
+        ));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("identify").write_stdin("not a log line\n");
+        cmd.assert().code(1).stdout(predicate::str::starts_with(
+            "-: unknown format (0% CF router log, 0% CF application log",
+        ));
+
+        // an empty file and a file given twice each get their own lines
+        let empty = dir.path().join("identify-empty.log");
+        fs::write(&empty, "").unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["identify", "--format", "json", "test/file/matching.txt"])
+            .arg(&empty)
+            .args(["test/file/matching.txt", "test/file/matching.txt"]);
+        let output = cmd.assert().code(1).get_output().stdout.clone();
+        let lines: Vec<u64> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["lines"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(lines, vec![3, 0, 3, 3]);
+    }
+
+    #[test]
+    fn detect_kubernetes_log() {
+        let dir = temp_dir();
+        let path = dir.path().join("cri.log");
+        fs::write(
+            &path,
+            "2021-09-28T11:58:42.73Z stdout F started\n\
+             2021-09-28T11:58:43.01Z stderr P partial \n",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--log-format", "cri"]).arg(&path);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a Kubernetes container log [100% line matching]",
+        ));
+    }
+
+    #[test]
+    fn docker_log() {
+        let dir = temp_dir();
+        let path = dir.path().join("docker.log");
+        fs::write(
+            &path,
+            "{\"log\":\"started\\n\",\"stream\":\"stdout\",\"time\":\"2021-09-28T11:58:42.730158Z\"}\n\
+             {\"log\":\"failed: \\\"timeout\\\"\\n\",\"stream\":\"stderr\",\"time\":\"2021-09-28T11:58:43Z\"}\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--log-format", "docker"]).arg(&path);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a Docker container log [100% line matching]",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("identify").arg(&path);
+        cmd.assert().success().stdout(predicate::str::contains(
+            ": Docker container log (100% Docker container log, 100% JSON lines,",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["parse", "--log-format", "docker", "--channel", "err"])
+            .arg(&path);
+        cmd.assert().success().stdout(
+            "{\"timestamp\":\"2021-09-28T11:58:43.000+00:00\",\"component\":\"docker\",\"index\":null,\"process_type\":null,\"channel\":\"ERR\",\"message\":\"failed: \\\"timeout\\\"\"}\n",
+        );
+    }
+
+    #[test]
+    fn heroku_log() {
+        let dir = temp_dir();
+        let path = dir.path().join("heroku.log");
+        fs::write(
+            &path,
+            "2021-09-28T11:58:42.730158+00:00 heroku[router]: at=info method=GET path=\"/\" status=200\n\
+             2021-09-28T11:58:43+00:00 app[web.1]: started\n\
+             2021-09-28T11:58:44+00:00 heroku[logplex]: Error L10 (output buffer overflow): 500 messages dropped since 2021-09-28T11:50:00+00:00.\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--log-format", "heroku", "--fail-on-loss"])
+            .arg(&path);
+        cmd.assert().code(3).stderr(predicate::str::contains(
+            "is a Heroku log [100% line matching] [message loss: 1 LGR warnings, 500 messages dropped]",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("identify").arg(&path);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains(": Heroku log (100% Heroku log,"));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["parse", "--log-format", "heroku", "--component", "app"])
+            .arg(&path);
+        cmd.assert().success().stdout(
+            "{\"timestamp\":\"2021-09-28T11:58:43.000+00:00\",\"component\":\"APP\",\"index\":1,\"process_type\":\"web\",\"channel\":\"OUT\",\"message\":\"started\"}\n",
+        );
+    }
+
+    #[test]
+    fn config_envelope() {
+        let dir = temp_dir();
+        let config = dir.path().join("envelope.toml");
+        fs::write(
+            &config,
+            "[[envelope]]\n\
+             name = \"forwarder\"\n\
+             description = \"forwarded CF log\"\n\
+             regex = '^(?P<timestamp>\\S+ \\S+) host=\\S+ app=(?P<component>\\S+) index=(?P<index>\\d+) (?P<channel>\\w+) ?(?P<message>.*)$'\n\
+             timestamp-format = \"%Y-%m-%d %H:%M:%S\"\n",
+        )
+        .unwrap();
+        let log = dir.path().join("envelope.log");
+        fs::write(
+            &log,
+            "2021-09-28 08:00:09 host=cell-3 app=APP/PROC/WEB index=0 stdout started\n\
+             2021-09-28 08:00:10 host=cell-3 app=RTR index=0 OUT GET /\n",
+        )
+        .unwrap();
+
+        // the envelope is a log format of its own, not the cf CLI one
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("--config").arg(&config).arg(&log);
+        cmd.assert().failure();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--config"])
+            .arg(&config)
+            .args(["detect", "--log-format", "forwarder"])
+            .arg(&log);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a forwarded CF log [100% line matching]",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["detect", "--log-format", "forwarder"]).arg(&log);
+        cmd.env("XDG_CONFIG_HOME", dir.path());
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("Unknown log format forwarder"));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args([
+            "parse",
+            "--format",
+            "json",
+            "--log-format",
+            "forwarder",
+            "--config",
+        ])
+        .arg(&config)
+        .arg(&log);
+        cmd.assert().success().stdout(predicate::str::starts_with(
+            "{\"timestamp\":\"2021-09-28T08:00:09.000+00:00\",\"component\":\"APP\",\"index\":0,\"process_type\":\"web\",\"channel\":\"OUT\",\"message\":\"started\"}\n",
+        ));
+
+        fs::write(
+            &config,
+            "[[envelope]]\nname = \"forwarder\"\nregex = '(?P<timestamp>.+)'\n",
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("--config").arg(&config).arg(&log);
+        cmd.assert().code(2).stderr(predicate::str::contains(
+            "envelope 1: missing capture: (?P<component>...)",
+        ));
+    }
+
+    #[test]
+    fn timestamp_format() {
+        let dir = temp_dir();
+        let path = dir.path().join("timestamp-format.log");
+        fs::write(
+            &path,
+            "2021-09-28 17:00:09.360 +0900 [APP/PROC/WEB/0] OUT started\n\
+             2021-09-28T17:00:10.36+0900 [RTR/0] OUT GET /\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg(&path);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "is NOT CF application log [50% line matching]",
+        ));
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["--timestamp-format", "%Y-%m-%d %H:%M:%S%.f %z"])
+            .arg(&path);
+        cmd.assert().success().stderr(predicate::str::contains(
+            "is a CF application log [100% line matching]",
+        ));
+
+        // also set through the environment, the cf CLI timestamps keep parsing
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["parse", "--format", "json"])
+            .env(
+                "CF_LOG_DETECTOR_TIMESTAMP_FORMAT",
+                "%Y-%m-%d %H:%M:%S%.f %z",
+            )
+            .arg(&path);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "\"timestamp\":\"2021-09-28T17:00:09.360+09:00\"",
+            ))
+            .stdout(predicate::str::contains("\"message\":\"GET /\""));
+
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.args(["detect", "--timestamp-format", "%Y-%m-%d %Q"])
+            .arg(&path);
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("invalid timestamp format"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn query_subcommand() {
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("query")
+            .arg("SELECT component, count(*) AS n FROM log GROUP BY 1 ORDER BY 1")
+            .arg("test/file/with_banner.txt")
+            .arg("test/file/matching.txt");
+        cmd.assert()
+            .success()
+            .stdout(predicate::eq("component\tn\nAPP\t2\nRTR\t4\n"));
+    }
+
+    #[test]
+    fn forward_gelf_tcp() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut received = Vec::new();
+            let (mut stream, _) = listener.accept().unwrap();
+            std::io::Read::read_to_end(&mut stream, &mut received).unwrap();
+            received
+        });
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("forward")
+            .arg("--gelf")
+            .arg(&url)
+            .arg("test/file/matching.txt");
+        cmd.assert().success();
+        let received = server.join().unwrap();
+        let messages: Vec<&[u8]> = received.split(|b| *b == 0).collect();
+        assert_eq!(messages.len(), 4);
+        assert!(messages[3].is_empty());
+        let message: serde_json::Value = serde_json::from_slice(messages[0]).unwrap();
+        assert_eq!(message["host"], "RTR/4");
+        assert_eq!(message["level"], 6);
+    }
+
+    #[test]
+    fn forward_syslog_tcp() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut received = String::new();
+            let (mut stream, _) = listener.accept().unwrap();
+            std::io::Read::read_to_string(&mut stream, &mut received).unwrap();
+            received
+        });
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("forward")
+            .arg("--syslog")
+            .arg(&url)
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success();
+        let received = server.join().unwrap();
+        let (length, rest) = received.split_once(' ').unwrap();
+        let length: usize = length.parse().unwrap();
+        // the DEBUG level of the message wins over the OUT channel
+        assert!(rest[..length].starts_with("<15>1 2021-09-28T17:00:09.360+09:00 - APP 0 OUT "));
+        assert!(rest.ends_with("channel=\"ERR\"] WARN: slow request"));
+    }
+
+    #[test]
+    fn forward_otlp() {
+        use std::io::{BufRead, BufReader, Read};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header == "\r\n" {
+                    break;
+                }
+                if let Some(length) = header.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            (request_line, body)
+        });
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("forward")
+            .arg("--otlp")
+            .arg(&url)
+            .arg("test/file/with_banner.txt");
+        cmd.assert().success();
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /v1/logs "));
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let records = &body["resourceLogs"][0]["scopeLogs"][0]["logRecords"];
+        assert_eq!(records.as_array().unwrap().len(), 3);
+        assert_eq!(records[1]["severityText"], "INFO");
+        assert_eq!(records[2]["severityText"], "WARN");
+    }
+
+    /// Produces to the broker named by `CF_LOG_DETECTOR_TEST_KAFKA`, run it with
+    /// `CF_LOG_DETECTOR_TEST_KAFKA=localhost:9092 cargo test kafka_broker -- --ignored`.
+    #[test]
+    #[ignore]
+    fn forward_kafka_broker() {
+        let broker = std::env::var("CF_LOG_DETECTOR_TEST_KAFKA")
+            .expect("CF_LOG_DETECTOR_TEST_KAFKA names the broker to test against");
+        let mut cmd = Command::cargo_bin("cf-app-log-detector").unwrap();
+        cmd.arg("forward")
+            .arg("--kafka")
+            .arg(&broker)
+            .arg("--topic")
+            .arg("cf-app-log-detector-test")
+            .arg("test/file/matching.txt");
+        cmd.assert().success();
+    }
 }
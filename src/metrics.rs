@@ -0,0 +1,112 @@
+//! Counters of long-running detections (follow and watch modes), rendered in the Prometheus
+//! text exposition format
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Shared between the detectors updating it and whatever exposes it
+#[derive(Debug, Default)]
+pub struct Metrics {
+    lines_parsed: AtomicU64,
+    parse_errors: AtomicU64,
+    /// whether each file is a CF application log and its percentage of matching lines
+    verdicts: Mutex<BTreeMap<String, (bool, f64)>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Account for a line, depending on whether it is a CF application log entry
+    pub fn record_line(&self, matching: bool) {
+        if matching {
+            self.lines_parsed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.parse_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Keep the last verdict given for `path`
+    pub fn record_verdict(&self, path: &str, matching: bool, percentage_matching: f64) {
+        self.verdicts
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), (matching, percentage_matching));
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP cf_app_log_lines_parsed_total Lines which are CF application log entries.\n\
+             # TYPE cf_app_log_lines_parsed_total counter\n\
+             cf_app_log_lines_parsed_total {}",
+            self.lines_parsed.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP cf_app_log_parse_errors_total Lines which are not CF application log entries.\n\
+             # TYPE cf_app_log_parse_errors_total counter\n\
+             cf_app_log_parse_errors_total {}",
+            self.parse_errors.load(Ordering::Relaxed)
+        );
+        let verdicts = self.verdicts.lock().unwrap();
+        out.push_str(
+            "# HELP cf_app_log_verdict Last verdict per file, 1 when it is a CF application log.\n\
+             # TYPE cf_app_log_verdict gauge\n",
+        );
+        for (path, (matching, _)) in verdicts.iter() {
+            let _ = writeln!(
+                out,
+                "cf_app_log_verdict{{file=\"{}\"}} {}",
+                label_value(path),
+                *matching as u8
+            );
+        }
+        out.push_str(
+            "# HELP cf_app_log_matching_percentage Percentage of matching lines at the last verdict.\n\
+             # TYPE cf_app_log_matching_percentage gauge\n",
+        );
+        for (path, (_, percentage)) in verdicts.iter() {
+            let _ = writeln!(
+                out,
+                "cf_app_log_matching_percentage{{file=\"{}\"}} {}",
+                label_value(path),
+                percentage
+            );
+        }
+        out
+    }
+}
+
+/// Label values escape backslashes, quotes and new lines
+pub fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_line(true);
+        metrics.record_line(true);
+        metrics.record_line(false);
+        metrics.record_verdict("a \"b\".log", true, 66.0);
+        let rendered = metrics.render();
+        assert!(rendered.contains("\ncf_app_log_lines_parsed_total 2\n"));
+        assert!(rendered.contains("\ncf_app_log_parse_errors_total 1\n"));
+        assert!(rendered.contains("\ncf_app_log_verdict{file=\"a \\\"b\\\".log\"} 1\n"));
+        assert!(
+            rendered.contains("\ncf_app_log_matching_percentage{file=\"a \\\"b\\\".log\"} 66\n")
+        );
+    }
+}
@@ -0,0 +1,588 @@
+//! Rendering of parsed log entries
+
+use chrono::SecondsFormat;
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::level::SeverityMapping;
+use crate::rtr::RtrAccessLog;
+use crate::trace::TraceContext;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+mod apache;
+pub use self::apache::{to_apache_combined, ApacheCombinedWriter};
+mod batch;
+mod cef;
+pub use self::cef::{to_cef, CefWriter};
+mod fluentd;
+pub use self::fluentd::{to_fluentd, FluentdWriter};
+mod gelf;
+pub use self::gelf::{to_gelf, GelfUdpWriter, GelfWriter};
+mod kafka;
+pub use self::kafka::KafkaWriter;
+mod otlp;
+pub use self::otlp::{to_otlp_log_record, OtlpWriter};
+#[cfg(feature = "parquet")]
+mod parquet_writer;
+#[cfg(feature = "parquet")]
+pub use self::parquet_writer::ParquetWriter;
+mod syslog;
+pub use self::syslog::{to_syslog, SyslogUdpWriter, SyslogWriter};
+#[cfg(feature = "sqlite")]
+mod sqlite_writer;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite_writer::SqliteWriter;
+
+/// Fields of an entry, in the order they are output by default
+pub const COLUMNS: &[&str] = &[
+    "timestamp",
+    "component",
+    "index",
+    "process_type",
+    "channel",
+    "message",
+];
+
+/// Somewhere parsed entries are written to, in a given format
+pub trait EntryWriter {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()>;
+
+    /// File and line number the entry written next comes from, the formats with columns write
+    /// them as the `file` and `line` fields when these columns are selected
+    fn set_origin(&mut self, _file: &str, _line: u64) {}
+
+    /// Fields added to the entry written next, replacing the ones given for the previous entry,
+    /// written by the formats with columns when these columns are selected
+    fn set_fields(&mut self, _fields: Map<String, Value>) {}
+
+    /// How the formats with a severity tell the one of entries, see `SeverityMapping`
+    fn set_severity_mapping(&mut self, _mapping: SeverityMapping) {}
+
+    /// Called once every entry has been written
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// One JSON object per line (NDJSON)
+pub struct JsonWriter<W: Write> {
+    out: W,
+    columns: Vec<String>,
+    origin: Option<(String, u64)>,
+    fields: Map<String, Value>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(out: W, columns: Vec<String>) -> JsonWriter<W> {
+        JsonWriter {
+            out,
+            columns,
+            origin: None,
+            fields: Map::new(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for JsonWriter<W> {
+    fn set_origin(&mut self, file: &str, line: u64) {
+        self.origin = Some((file.to_string(), line));
+    }
+
+    fn set_fields(&mut self, fields: Map<String, Value>) {
+        self.fields = fields;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}",
+            select_columns(
+                with_extra(to_json(entry), &self.origin, &self.fields),
+                &self.columns
+            )
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Elasticsearch `_bulk` API body: an index action followed by the entry as JSON, per entry
+pub struct EsBulkWriter<W: Write> {
+    out: W,
+    action: String,
+    columns: Vec<String>,
+    origin: Option<(String, u64)>,
+    fields: Map<String, Value>,
+}
+
+impl<W: Write> EsBulkWriter<W> {
+    pub fn new(out: W, index: &str, columns: Vec<String>) -> EsBulkWriter<W> {
+        EsBulkWriter {
+            out,
+            action: json!({ "index": { "_index": index } }).to_string(),
+            columns,
+            origin: None,
+            fields: Map::new(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for EsBulkWriter<W> {
+    fn set_origin(&mut self, file: &str, line: u64) {
+        self.origin = Some((file.to_string(), line));
+    }
+
+    fn set_fields(&mut self, fields: Map<String, Value>) {
+        self.fields = fields;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}\n{}",
+            self.action,
+            select_columns(
+                with_extra(to_json(entry), &self.origin, &self.fields),
+                &self.columns
+            )
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// CSV with a header line, messages containing separators or new lines are quoted
+pub struct CsvWriter<W: Write> {
+    out: csv::Writer<W>,
+    columns: Vec<String>,
+    origin: Option<(String, u64)>,
+    fields: Map<String, Value>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(out: W, columns: Vec<String>) -> io::Result<CsvWriter<W>> {
+        let mut out = csv::Writer::from_writer(out);
+        out.write_record(&columns)?;
+        Ok(CsvWriter {
+            out,
+            columns,
+            origin: None,
+            fields: Map::new(),
+        })
+    }
+}
+
+impl<W: Write> EntryWriter for CsvWriter<W> {
+    fn set_origin(&mut self, file: &str, line: u64) {
+        self.origin = Some((file.to_string(), line));
+    }
+
+    fn set_fields(&mut self, fields: Map<String, Value>) {
+        self.fields = fields;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let json = with_extra(to_json(entry), &self.origin, &self.fields);
+        let record = self
+            .columns
+            .iter()
+            .map(|column| match &json[column.as_str()] {
+                Value::Null => String::new(),
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            });
+        self.out.write_record(record).map_err(io::Error::from)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// `key=value` pairs (logfmt), `timestamp` and `message` are written as `ts` and `msg`, absent
+/// fields are left out
+pub struct LogfmtWriter<W: Write> {
+    out: W,
+    columns: Vec<String>,
+    origin: Option<(String, u64)>,
+    fields: Map<String, Value>,
+}
+
+impl<W: Write> LogfmtWriter<W> {
+    pub fn new(out: W, columns: Vec<String>) -> LogfmtWriter<W> {
+        LogfmtWriter {
+            out,
+            columns,
+            origin: None,
+            fields: Map::new(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for LogfmtWriter<W> {
+    fn set_origin(&mut self, file: &str, line: u64) {
+        self.origin = Some((file.to_string(), line));
+    }
+
+    fn set_fields(&mut self, fields: Map<String, Value>) {
+        self.fields = fields;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let json = with_extra(to_json(entry), &self.origin, &self.fields);
+        let mut pairs = Vec::with_capacity(self.columns.len());
+        for column in &self.columns {
+            let key = match column.as_str() {
+                "timestamp" => "ts",
+                "message" => "msg",
+                column => column,
+            };
+            match &json[column.as_str()] {
+                Value::Null => (),
+                Value::String(value) => pairs.push(format!("{}={}", key, logfmt_value(value))),
+                value => pairs.push(format!("{}={}", key, value)),
+            }
+        }
+        writeln!(self.out, "{}", pairs.join(" "))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Value quoted when it is empty or contains spaces, `=`, quotes or backslashes
+fn logfmt_value(value: &str) -> Cow<'_, str> {
+    if !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\')
+    {
+        return Cow::Borrowed(value);
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Add the `file` and `line` fields, and the extra ones, to an entry rendered by `to_json`
+fn with_extra(
+    mut json: Value,
+    origin: &Option<(String, u64)>,
+    extra: &Map<String, Value>,
+) -> Value {
+    if let Value::Object(fields) = &mut json {
+        if let Some((file, line)) = origin {
+            fields.insert("file".to_string(), json!(file));
+            fields.insert("line".to_string(), json!(line));
+        }
+        for (name, value) in extra {
+            fields.insert(name.clone(), value.clone());
+        }
+    }
+    json
+}
+
+/// Only keep the given fields of an entry rendered by `to_json`, in the given order
+fn select_columns(json: Value, columns: &[String]) -> Value {
+    match json {
+        Value::Object(mut fields) => {
+            let mut selected = Map::new();
+            for column in columns {
+                if let Some(value) = fields.remove(column) {
+                    selected.insert(column.clone(), value);
+                }
+            }
+            Value::Object(selected)
+        }
+        json => json,
+    }
+}
+
+/// Fields of a Gorouter access log, in the order they are output
+pub const RTR_COLUMNS: &[&str] = &[
+    "host",
+    "method",
+    "path",
+    "protocol",
+    "status",
+    "bytes_received",
+    "bytes_sent",
+    "referer",
+    "user_agent",
+    "remote_addr",
+    "backend_addr",
+    "x_forwarded_for",
+    "x_forwarded_proto",
+    "vcap_request_id",
+    "response_time",
+    "gorouter_time",
+    "app_id",
+    "app_index",
+];
+
+/// Fields of a Gorouter access log as JSON values, absent ones being left out
+pub fn rtr_fields(log: &RtrAccessLog) -> Map<String, Value> {
+    let values = [
+        json!(log.host),
+        json!(log.method),
+        json!(log.path),
+        json!(log.protocol),
+        json!(log.status),
+        json!(log.bytes_received),
+        json!(log.bytes_sent),
+        json!(log.referer),
+        json!(log.user_agent),
+        json!(log.remote_addr),
+        json!(log.backend_addr),
+        json!(log.x_forwarded_for()),
+        json!(log.x_forwarded_proto()),
+        json!(log.vcap_request_id()),
+        json!(log.response_time()),
+        json!(log.gorouter_time()),
+        json!(log.app_id()),
+        json!(log.app_index()),
+    ];
+    RTR_COLUMNS
+        .iter()
+        .zip(values)
+        .filter(|(_, value)| !value.is_null())
+        .map(|(column, value)| (column.to_string(), value))
+        .collect()
+}
+
+/// Fields added by `parse --trace-fields`, see `trace_fields`
+pub const TRACE_COLUMNS: &[&str] = &["trace_id", "span_id"];
+
+/// Ids of a trace context as JSON values
+pub fn trace_fields(context: &TraceContext) -> Map<String, Value> {
+    let values = [json!(context.trace_id), json!(context.span_id)];
+    TRACE_COLUMNS
+        .iter()
+        .zip(values)
+        .map(|(column, value)| (column.to_string(), value))
+        .collect()
+}
+
+/// Column of the `key` of JSON messages added by `parse --json-keys`, prefixed not to collide
+/// with the columns of entries, e.g. `json.level`
+pub fn json_column(key: &str) -> String {
+    format!("json.{}", key)
+}
+
+/// Top level `keys` of a message which is a JSON object, named after `json_column`. Keys the
+/// message lacks are left out.
+pub fn json_fields(message: &str, keys: &[&str]) -> Map<String, Value> {
+    match serde_json::from_str(message) {
+        Ok(Value::Object(mut object)) => keys
+            .iter()
+            .filter_map(|key| Some((json_column(key), object.remove(*key)?)))
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+/// Message re-indented over several lines when it is a JSON object, as apps logging in JSON
+/// write them
+pub fn pretty_json(message: &str) -> Option<String> {
+    match serde_json::from_str(message) {
+        Ok(object @ Value::Object(_)) => serde_json::to_string_pretty(&object).ok(),
+        _ => None,
+    }
+}
+
+/// Entry as a JSON object, components and channels which are not known are kept verbatim
+pub fn to_json(entry: &CfAppLogEntry) -> Value {
+    let (component, index, process_type) = match &entry.component {
+        ComponentInfoValid::Valid(info) => (
+            json!(info.name.as_str()),
+            json!(info.index),
+            json!(info.process_type),
+        ),
+        ComponentInfoValid::Invalid(raw) => (json!(raw), Value::Null, Value::Null),
+    };
+    let channel = match &entry.channel {
+        ChannelValid::Valid(channel) => json!(channel.as_str()),
+        ChannelValid::Invalid(raw) => json!(raw),
+    };
+    json!({
+        "timestamp": entry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, false),
+        "component": component,
+        "index": index,
+        "process_type": process_type,
+        "channel": channel,
+        "message": entry.message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_json() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        assert_eq!(
+            to_json(&entry),
+            json!({
+                "timestamp": "2021-09-28T17:00:09.360+09:00",
+                "component": "APP",
+                "index": 0,
+                "process_type": "web",
+                "channel": "ERR",
+                "message": "boom",
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_json_invalid_component() {
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [FOO/1] OUT").unwrap();
+        let json = to_json(&entry);
+        assert_eq!(json["component"], "FOO/1");
+        assert_eq!(json["index"], Value::Null);
+        assert_eq!(json["message"], Value::Null);
+    }
+
+    #[test]
+    fn test_json_writer_columns() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        let mut out = Vec::new();
+        JsonWriter::new(&mut out, vec!["message".to_string(), "index".to_string()])
+            .write_entry(&entry)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"message\":\"boom\",\"index\":0}\n"
+        );
+    }
+
+    #[test]
+    fn test_writer_origin() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        let columns = vec![
+            "file".to_string(),
+            "line".to_string(),
+            "message".to_string(),
+        ];
+        let mut out = Vec::new();
+        let mut writer = LogfmtWriter::new(&mut out, columns);
+        // nothing to write before the origin is known
+        writer.write_entry(&entry).unwrap();
+        writer.set_origin("app.log", 3);
+        writer.write_entry(&entry).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "msg=boom\nfile=app.log line=3 msg=boom\n"
+        );
+    }
+
+    #[test]
+    fn test_rtr_fields() {
+        let log = crate::rtr::parse_rtr_access_log(
+            r#"www.example.com - [14/06/2016:22:16:12.582 +0000] "GET / HTTP/1.1" 503 0 10 "-" "curl" 192.0.2.206:27743 response_time:0.5"#,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let mut writer = JsonWriter::new(
+            &mut out,
+            vec![
+                "channel".to_string(),
+                "status".to_string(),
+                "response_time".to_string(),
+            ],
+        );
+        writer.set_fields(rtr_fields(&log));
+        let (_, entry) = parse_cf_app_log("2016-06-14T15:16:12.70-0700 [RTR/4] OUT").unwrap();
+        writer.write_entry(&entry).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"channel\":\"OUT\",\"status\":503,\"response_time\":0.5}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_message() {
+        let message = r#"{"level":"info","msg":"hi","user":{"id":7}}"#;
+        assert_eq!(
+            Value::Object(json_fields(message, &["user", "level", "missing"])),
+            json!({ "json.user": { "id": 7 }, "json.level": "info" })
+        );
+        assert!(json_fields("not json", &["level"]).is_empty());
+        assert_eq!(
+            pretty_json(message).unwrap(),
+            "{\n  \"level\": \"info\",\n  \"msg\": \"hi\",\n  \"user\": {\n    \"id\": 7\n  }\n}"
+        );
+        assert_eq!(pretty_json("[1, 2]"), None);
+    }
+
+    #[test]
+    fn test_csv_writer_quoting() {
+        let (_, entry) =
+            parse_cf_app_log(r#"2021-09-28T17:00:09.36+0900 [RTR/1] OUT GET /a,b "quoted""#)
+                .unwrap();
+        let mut out = Vec::new();
+        {
+            let columns = vec!["component".to_string(), "message".to_string()];
+            let mut writer = CsvWriter::new(&mut out, columns).unwrap();
+            writer.write_entry(&entry).unwrap();
+            writer.finish().unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "component,message\nRTR,\"GET /a,b \"\"quoted\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_logfmt_writer() {
+        let (_, entry) = parse_cf_app_log(
+            r#"2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR key=value "quoted" \ end"#,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let columns = COLUMNS.iter().map(|column| column.to_string()).collect();
+        LogfmtWriter::new(&mut out, columns)
+            .write_entry(&entry)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ts=2021-09-28T17:00:09.360+09:00 component=APP index=0 process_type=web \
+             channel=ERR msg=\"key=value \\\"quoted\\\" \\\\ end\"\n"
+        );
+    }
+
+    #[test]
+    fn test_es_bulk_writer() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        let mut out = Vec::new();
+        EsBulkWriter::new(&mut out, "cf-logs", vec!["message".to_string()])
+            .write_entry(&entry)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"index\":{\"_index\":\"cf-logs\"}}\n{\"message\":\"boom\"}\n"
+        );
+    }
+}
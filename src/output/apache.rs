@@ -0,0 +1,102 @@
+//! Apache combined log format rendering of Gorouter access logs
+
+use chrono::DateTime;
+use std::io::{self, Write};
+
+use super::EntryWriter;
+use crate::rtr::rtr_access_log;
+use crate::CfAppLogEntry;
+
+/// Formats of the time written by the router, current and older ones
+const RTR_TIME_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f%z", "%d/%m/%Y:%H:%M:%S%.f %z"];
+
+/// One combined log line per Gorouter access log, as read by goaccess or awstats, other
+/// entries are left out
+pub struct ApacheCombinedWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> ApacheCombinedWriter<W> {
+    pub fn new(out: W) -> ApacheCombinedWriter<W> {
+        ApacheCombinedWriter { out }
+    }
+}
+
+impl<W: Write> EntryWriter for ApacheCombinedWriter<W> {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        match to_apache_combined(entry) {
+            Some(line) => writeln!(self.out, "{}", line),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Gorouter access log of an RTR entry as an Apache combined log line, `None` for other
+/// entries. The client is the first `X-Forwarded-For` address when there is one, the router
+/// usually being behind a load balancer, and the time is the one of the request.
+pub fn to_apache_combined(entry: &CfAppLogEntry) -> Option<String> {
+    let log = rtr_access_log(entry)?;
+    let client = log
+        .x_forwarded_for()
+        .and_then(|addresses| addresses.split(',').next())
+        .or_else(|| log.remote_addr.map(strip_port))
+        .map_or("-", str::trim);
+    let time = RTR_TIME_FORMATS
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(log.time, format).ok())
+        .unwrap_or(entry.timestamp);
+    let bytes_sent = match log.bytes_sent {
+        0 => "-".to_string(),
+        bytes => bytes.to_string(),
+    };
+    Some(format!(
+        "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\"",
+        client,
+        time.format("%d/%b/%Y:%H:%M:%S %z"),
+        log.method,
+        log.path,
+        log.protocol,
+        log.status,
+        bytes_sent,
+        log.referer.unwrap_or("-"),
+        log.user_agent.unwrap_or("-")
+    ))
+}
+
+/// Address without its port, e.g. `10.0.0.1` for `10.0.0.1:51234` or `::1` for `[::1]:51234`
+fn strip_port(address: &str) -> &str {
+    match address.rfind(':') {
+        Some(colon) if !address[..colon].contains(':') => &address[..colon],
+        Some(colon) if address.starts_with('[') => address[1..colon].trim_end_matches(']'),
+        _ => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_apache_combined() {
+        let (_, entry) = parse_cf_app_log(r#"2021-09-28T17:00:10.12+0900 [RTR/0] OUT my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET /health?full=1 HTTP/1.1" 200 0 15 "-" "curl/7.64.1" "10.0.0.1:51234" "10.0.1.5:61001" x_forwarded_for:"203.0.113.10, 10.0.0.1""#).unwrap();
+        assert_eq!(
+            to_apache_combined(&entry).unwrap(),
+            r#"203.0.113.10 - - [28/Sep/2021:08:00:10 +0000] "GET /health?full=1 HTTP/1.1" 200 15 "-" "curl/7.64.1""#
+        );
+
+        let (_, entry) = parse_cf_app_log(r#"2016-06-14T15:16:12.70-0700 [RTR/4] OUT www.example.com - [14/06/2016:22:16:12.582 +0000] "GET / HTTP/1.1" 304 0 0 "http://www.example.com/" "Mozilla/5.0" 192.0.2.206:27743"#).unwrap();
+        assert_eq!(
+            to_apache_combined(&entry).unwrap(),
+            r#"192.0.2.206 - - [14/Jun/2016:22:16:12 +0000] "GET / HTTP/1.1" 304 - "http://www.example.com/" "Mozilla/5.0""#
+        );
+
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:10.12+0900 [APP/0] OUT GET /").unwrap();
+        assert_eq!(to_apache_combined(&entry), None);
+        assert_eq!(strip_port("[::1]:51234"), "::1");
+    }
+}
@@ -0,0 +1,140 @@
+//! Records exported in batches from a thread of their own
+
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest time a record waits for its batch to fill up before being exported
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Buffers records and exports them in batches
+pub trait Exporter: Send + 'static {
+    type Record: Send + 'static;
+
+    /// Buffer `record`, exporting the batch it fills up
+    fn push(&mut self, record: Self::Record) -> io::Result<()>;
+
+    /// Export every record buffered
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// An `Exporter` running in a thread of its own, which also flushes the records buffered for
+/// `FLUSH_INTERVAL`, so that the entries of a quiet app piped in from a live `cf logs` are not
+/// held back
+pub struct BatchExport<R> {
+    records: Option<mpsc::Sender<R>>,
+    exporter: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl<R: Send + 'static> BatchExport<R> {
+    pub fn spawn<E: Exporter<Record = R>>(exporter: E) -> BatchExport<R> {
+        let (records, received) = mpsc::channel();
+        BatchExport {
+            records: Some(records),
+            exporter: Some(thread::spawn(move || run(exporter, received))),
+        }
+    }
+
+    /// Hand `record` over to the exporter, fails with its error when it stopped on one
+    pub fn send(&mut self, record: R) -> io::Result<()> {
+        let sent = match &self.records {
+            Some(records) => records.send(record).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.finish()?;
+            return Err(io::Error::other("the exporter stopped"));
+        }
+        Ok(())
+    }
+
+    /// Wait for the records left to be exported
+    pub fn finish(&mut self) -> io::Result<()> {
+        // closing the channel tells the exporter to flush and stop
+        self.records = None;
+        match self.exporter.take() {
+            Some(exporter) => exporter
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("the exporter panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn run<E: Exporter>(mut exporter: E, received: mpsc::Receiver<E::Record>) -> io::Result<()> {
+    // when the records buffered since the last flush are due
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let record = match deadline {
+            None => received.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => {
+                received.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+        };
+        match record {
+            Ok(record) => {
+                exporter.push(record)?;
+                deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                exporter.flush()?;
+                deadline = None;
+            }
+            Err(RecvTimeoutError::Disconnected) => return exporter.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Batches {
+        buffered: Vec<u32>,
+        exported: Arc<Mutex<Vec<Vec<u32>>>>,
+    }
+
+    impl Exporter for Batches {
+        type Record = u32;
+
+        fn push(&mut self, record: u32) -> io::Result<()> {
+            self.buffered.push(record);
+            if self.buffered.len() == 2 {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if !self.buffered.is_empty() {
+                let batch = std::mem::take(&mut self.buffered);
+                self.exported.lock().unwrap().push(batch);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_batch_export() {
+        let batches = Batches::default();
+        let exported = batches.exported.clone();
+        let mut export = BatchExport::spawn(batches);
+        for record in 1..=3 {
+            export.send(record).unwrap();
+        }
+        // the last record is exported once idle, without waiting for the end of the input
+        thread::sleep(FLUSH_INTERVAL * 3);
+        assert_eq!(*exported.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+
+        export.send(4).unwrap();
+        export.finish().unwrap();
+        assert_eq!(
+            *exported.lock().unwrap(),
+            vec![vec![1, 2], vec![3], vec![4]]
+        );
+        assert!(export.send(5).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! ArcSight Common Event Format (CEF) rendering of parsed entries
+
+use std::io::{self, Write};
+
+use super::EntryWriter;
+use crate::level::SeverityMapping;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+const VENDOR: &str = "Cloud Foundry";
+const PRODUCT: &str = "cf-app-log-detector";
+const NAME: &str = "CF application log entry";
+
+/// CEF severity (0 to 10) of each level, from the least severe
+const SEVERITIES: [u8; 6] = [1, 2, 3, 5, 6, 9];
+
+/// One CEF event per line
+///
+/// The component is the signature id and the `deviceProcessName` extension, the index is in
+/// `cn1`, the process type in `cs1`, the channel in `cs2` and the message in `msg`.
+pub struct CefWriter<W: Write> {
+    out: W,
+    severity_mapping: SeverityMapping,
+}
+
+impl<W: Write> CefWriter<W> {
+    pub fn new(out: W) -> CefWriter<W> {
+        CefWriter {
+            out,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for CefWriter<W> {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        writeln!(self.out, "{}", to_cef(entry, &self.severity_mapping))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Entry as a CEF event
+pub fn to_cef(entry: &CfAppLogEntry, severity_mapping: &SeverityMapping) -> String {
+    let (component, index, process_type) = match &entry.component {
+        ComponentInfoValid::Valid(info) => (
+            info.name.as_str(),
+            Some(info.index),
+            info.process_type.as_deref(),
+        ),
+        ComponentInfoValid::Invalid(raw) => (raw.as_str(), None, None),
+    };
+    let channel = match &entry.channel {
+        ChannelValid::Valid(channel) => channel.as_str(),
+        ChannelValid::Invalid(raw) => raw.as_str(),
+    };
+    let severity = SEVERITIES[severity_mapping.severity(entry) as usize];
+
+    let mut extensions = vec![
+        format!("rt={}", entry.timestamp.timestamp_millis()),
+        format!("deviceProcessName={}", extension_value(component)),
+    ];
+    if let Some(index) = index {
+        extensions.push(format!("cn1={} cn1Label=index", index));
+    }
+    if let Some(process_type) = process_type {
+        extensions.push(format!(
+            "cs1={} cs1Label=processType",
+            extension_value(process_type)
+        ));
+    }
+    extensions.push(format!("cs2={} cs2Label=channel", extension_value(channel)));
+    if let Some(message) = entry.message {
+        extensions.push(format!("msg={}", extension_value(message)));
+    }
+
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        env!("CARGO_PKG_VERSION"),
+        header_value(component),
+        NAME,
+        severity,
+        extensions.join(" ")
+    )
+}
+
+/// Header fields escape pipes and backslashes
+fn header_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Extension values escape equal signs and backslashes, new lines are written as `\n`
+fn extension_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '=' => escaped.push_str("\\="),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_cef() {
+        let (_, entry) =
+            parse_cf_app_log(r"2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR a=b c\d | e")
+                .unwrap();
+        assert_eq!(
+            to_cef(&entry, &SeverityMapping::default()),
+            format!(
+                "CEF:0|Cloud Foundry|cf-app-log-detector|{}|APP|CF application log entry|6|\
+                 rt=1632816009360 deviceProcessName=APP cn1=0 cn1Label=index \
+                 cs1=web cs1Label=processType cs2=ERR cs2Label=channel msg=a\\=b c\\\\d | e",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [FOO|1] OUT").unwrap();
+        assert!(to_cef(&entry, &SeverityMapping::default())
+            .contains("|FOO\\|1|CF application log entry|3|"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Fluentd forward protocol (msgpack) encoding of parsed entries
+//!
+//! <https://github.com/fluent/fluentd/wiki/Forward-Protocol-Specification-v1>
+
+use rmp::encode;
+use serde_json::Value;
+use std::io::{self, Write};
+
+use super::{to_json, EntryWriter};
+use crate::CfAppLogEntry;
+
+/// Extension type of EventTime, timestamps with a nanosecond precision
+const EVENT_TIME_EXT_TYPE: i8 = 0;
+
+/// One `[tag, time, record]` message (Message Mode) per entry
+///
+/// The record holds the fields of the entry but the timestamp, which is the event time.
+pub struct FluentdWriter<W: Write> {
+    out: W,
+    tag: String,
+}
+
+impl<W: Write> FluentdWriter<W> {
+    pub fn new(out: W, tag: &str) -> FluentdWriter<W> {
+        FluentdWriter {
+            out,
+            tag: tag.to_string(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for FluentdWriter<W> {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        // a single write so that unbuffered sockets get whole messages
+        let message = to_fluentd(&self.tag, entry)?;
+        self.out.write_all(&message)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Entry as a msgpack encoded forward protocol message
+pub fn to_fluentd(tag: &str, entry: &CfAppLogEntry) -> io::Result<Vec<u8>> {
+    let mut message = Vec::new();
+    encode::write_array_len(&mut message, 3)?;
+    encode::write_str(&mut message, tag)?;
+
+    encode::write_ext_meta(&mut message, 8, EVENT_TIME_EXT_TYPE)?;
+    message.extend_from_slice(&(entry.timestamp.timestamp() as u32).to_be_bytes());
+    message.extend_from_slice(&entry.timestamp.timestamp_subsec_nanos().to_be_bytes());
+
+    let mut record = match to_json(entry) {
+        Value::Object(fields) => fields,
+        _ => unreachable!("entries are rendered as objects"),
+    };
+    record.shift_remove("timestamp");
+    encode::write_map_len(&mut message, record.len() as u32)?;
+    for (name, value) in &record {
+        encode::write_str(&mut message, name)?;
+        match value {
+            Value::String(value) => encode::write_str(&mut message, value)?,
+            Value::Number(value) => {
+                encode::write_uint(&mut message, value.as_u64().unwrap_or_default())?;
+            }
+            _ => encode::write_nil(&mut message)?,
+        }
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_fluentd() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        let message = to_fluentd("cf.app", &entry).unwrap();
+
+        let mut expected = vec![0x93, 0xa6];
+        expected.extend_from_slice(b"cf.app");
+        // fixext 8, EventTime
+        expected.extend_from_slice(&[0xd7, 0x00]);
+        expected.extend_from_slice(&1632816009u32.to_be_bytes());
+        expected.extend_from_slice(&360_000_000u32.to_be_bytes());
+        expected.push(0x85);
+        for (name, value) in &[
+            ("component", Some("APP")),
+            ("index", None),
+            ("process_type", Some("web")),
+            ("channel", Some("ERR")),
+            ("message", Some("boom")),
+        ] {
+            expected.push(0xa0 | name.len() as u8);
+            expected.extend_from_slice(name.as_bytes());
+            match value {
+                Some(value) => {
+                    expected.push(0xa0 | value.len() as u8);
+                    expected.extend_from_slice(value.as_bytes());
+                }
+                // positive fixint 0
+                None => expected.push(0x00),
+            }
+        }
+        assert_eq!(message, expected);
+    }
+}
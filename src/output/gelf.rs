@@ -0,0 +1,201 @@
+//! GELF 1.1 rendering of parsed entries, and its UDP transport
+//!
+//! <https://go2docs.graylog.org/current/getting_in_log_data/gelf.html>
+
+use rand::Rng;
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+use std::net::UdpSocket;
+
+use super::{to_json, EntryWriter};
+use crate::level::SeverityMapping;
+use crate::{CfAppLogEntry, ComponentInfoValid};
+
+/// Largest datagram sent, messages longer than that are split in chunks
+const UDP_CHUNK_SIZE: usize = 8192;
+/// Chunk header: magic bytes, message id, sequence number and count
+const UDP_CHUNK_HEADER_SIZE: usize = 12;
+const UDP_MAX_CHUNKS: usize = 128;
+
+/// Entry as a GELF message
+///
+/// The host is the component instance (e.g. `APP/0`), the level is the syslog severity of the
+/// entry (see `SeverityMapping`) and the other fields are added as additional `_` prefixed
+/// fields.
+pub fn to_gelf(entry: &CfAppLogEntry, severity_mapping: &SeverityMapping) -> Value {
+    let host = match &entry.component {
+        ComponentInfoValid::Valid(info) => format!("{}/{}", info.name.as_str(), info.index),
+        ComponentInfoValid::Invalid(raw) => raw.clone(),
+    };
+    let level = severity_mapping.severity(entry).syslog_severity();
+    let mut gelf = Map::new();
+    gelf.insert("version".to_string(), json!("1.1"));
+    gelf.insert("host".to_string(), json!(host));
+    // short_message is mandatory and must not be empty
+    gelf.insert(
+        "short_message".to_string(),
+        json!(entry.message.filter(|m| !m.is_empty()).unwrap_or("-")),
+    );
+    gelf.insert(
+        "timestamp".to_string(),
+        json!(entry.timestamp.timestamp_millis() as f64 / 1000.0),
+    );
+    gelf.insert("level".to_string(), json!(level));
+    if let Value::Object(fields) = to_json(entry) {
+        for (name, value) in fields {
+            if name != "timestamp" && name != "message" && !value.is_null() {
+                gelf.insert(format!("_{}", name), value);
+            }
+        }
+    }
+    Value::Object(gelf)
+}
+
+/// GELF messages separated by new lines, or by null bytes as expected by GELF TCP inputs
+pub struct GelfWriter<W: Write> {
+    out: W,
+    delimiter: u8,
+    severity_mapping: SeverityMapping,
+}
+
+impl<W: Write> GelfWriter<W> {
+    pub fn new(out: W) -> GelfWriter<W> {
+        GelfWriter {
+            out,
+            delimiter: b'\n',
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+
+    pub fn null_delimited(out: W) -> GelfWriter<W> {
+        GelfWriter {
+            out,
+            delimiter: 0,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for GelfWriter<W> {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        // a single write so that unbuffered sockets get whole messages
+        let mut message = serde_json::to_vec(&to_gelf(entry, &self.severity_mapping))?;
+        message.push(self.delimiter);
+        self.out.write_all(&message)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// One datagram per GELF message, chunked when it does not fit
+pub struct GelfUdpWriter {
+    socket: UdpSocket,
+    severity_mapping: SeverityMapping,
+}
+
+impl GelfUdpWriter {
+    /// `socket` has to be connected to the GELF input
+    pub fn new(socket: UdpSocket) -> GelfUdpWriter {
+        GelfUdpWriter {
+            socket,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+impl EntryWriter for GelfUdpWriter {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let message = serde_json::to_vec(&to_gelf(entry, &self.severity_mapping))?;
+        for datagram in udp_datagrams(message, rand::thread_rng().gen())? {
+            self.socket.send(&datagram)?;
+        }
+        Ok(())
+    }
+}
+
+/// The message as is when small enough, chunks sharing `id` otherwise
+fn udp_datagrams(message: Vec<u8>, id: [u8; 8]) -> io::Result<Vec<Vec<u8>>> {
+    if message.len() <= UDP_CHUNK_SIZE {
+        return Ok(vec![message]);
+    }
+    let chunks: Vec<&[u8]> = message
+        .chunks(UDP_CHUNK_SIZE - UDP_CHUNK_HEADER_SIZE)
+        .collect();
+    if chunks.len() > UDP_MAX_CHUNKS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "GELF message too large for UDP",
+        ));
+    }
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut datagram = Vec::with_capacity(UDP_CHUNK_HEADER_SIZE + chunk.len());
+            datagram.extend_from_slice(&[0x1e, 0x0f]);
+            datagram.extend_from_slice(&id);
+            datagram.push(sequence as u8);
+            datagram.push(chunks.len() as u8);
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_gelf() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        assert_eq!(
+            to_gelf(&entry, &SeverityMapping::default()),
+            json!({
+                "version": "1.1",
+                "host": "APP/0",
+                "short_message": "boom",
+                "timestamp": 1632816009.36,
+                "level": 3,
+                "_component": "APP",
+                "_index": 0,
+                "_process_type": "web",
+                "_channel": "ERR",
+            })
+        );
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT").unwrap();
+        let gelf = to_gelf(&entry, &SeverityMapping::default());
+        assert_eq!(gelf["short_message"], "-");
+        assert_eq!(gelf["level"], 6);
+        assert_eq!(gelf.get("_process_type"), None);
+    }
+
+    #[test]
+    fn test_udp_datagrams_chunking() {
+        let id = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(udp_datagrams(vec![b'a'; 10], id).unwrap().len(), 1);
+
+        let datagrams = udp_datagrams(vec![b'a'; 20000], id).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        assert_eq!(
+            &datagrams[1][..12],
+            &[0x1e, 0x0f, 1, 2, 3, 4, 5, 6, 7, 8, 1, 3]
+        );
+        let payload: usize = datagrams.iter().map(|d| d.len() - 12).sum();
+        assert_eq!(payload, 20000);
+
+        assert!(udp_datagrams(vec![b'a'; 129 * 8180], id).is_err());
+    }
+}
@@ -0,0 +1,537 @@
+//! Minimal Kafka producer, one record per parsed entry, produced in batches per partition
+//!
+//! Only the requests needed to produce are implemented: Metadata v4 to find the partition
+//! leaders and Produce v3 with record batches (message format v2), supported by brokers from
+//! Kafka 1.0 onwards. <https://kafka.apache.org/protocol>
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use super::batch::{BatchExport, Exporter};
+use super::{to_json, EntryWriter};
+use crate::{CfAppLogEntry, ComponentInfoValid};
+
+const CLIENT_ID: &str = "cf-app-log-detector";
+
+const PRODUCE: i16 = 0;
+const PRODUCE_VERSION: i16 = 3;
+const METADATA: i16 = 3;
+const METADATA_VERSION: i16 = 4;
+
+/// Wait for the leader only, the topic gets created when it does not exist yet
+const ACKS: i16 = 1;
+const PRODUCE_TIMEOUT_MS: i32 = 10_000;
+
+/// Records produced per request to a partition
+const BATCH_SIZE: usize = 100;
+
+const LEADER_NOT_AVAILABLE: i16 = 5;
+/// A new topic has no leader for a short while, metadata is asked again meanwhile
+const METADATA_RETRIES: usize = 10;
+const METADATA_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Records keyed by component instance (e.g. `APP/0`) with the entry as JSON as value, sent
+/// to the partition Java clients would pick for the same key, see `BatchExport`
+pub struct KafkaWriter {
+    export: BatchExport<Record>,
+}
+
+/// Record of an entry, waiting to be produced with the others of its partition
+struct Record {
+    timestamp: i64,
+    key: String,
+    value: String,
+}
+
+struct KafkaProducer {
+    topic: String,
+    /// address of each broker, by node id
+    brokers: HashMap<i32, String>,
+    /// node id of the leader of each partition, by partition index
+    leaders: Vec<i32>,
+    connections: HashMap<i32, TcpStream>,
+    correlation_id: i32,
+    /// records not produced yet, by partition index
+    pending: Vec<Vec<Record>>,
+}
+
+impl KafkaWriter {
+    /// Find the leaders of the partitions of `topic` from the `bootstrap` broker (`host:port`)
+    pub fn connect(bootstrap: &str, topic: &str) -> io::Result<KafkaWriter> {
+        let producer = KafkaProducer::connect(bootstrap, topic)?;
+        Ok(KafkaWriter {
+            export: BatchExport::spawn(producer),
+        })
+    }
+}
+
+impl KafkaProducer {
+    fn connect(bootstrap: &str, topic: &str) -> io::Result<KafkaProducer> {
+        let mut writer = KafkaProducer {
+            topic: topic.to_string(),
+            brokers: HashMap::new(),
+            leaders: Vec::new(),
+            connections: HashMap::new(),
+            correlation_id: 0,
+            pending: Vec::new(),
+        };
+        let mut stream = TcpStream::connect(bootstrap)?;
+        let mut retries = 0;
+        loop {
+            let mut body = Encoder::default();
+            body.i32(1);
+            body.string(topic);
+            // allow_auto_topic_creation
+            body.i8(1);
+            let response = writer.request(&mut stream, METADATA, METADATA_VERSION, &body.0)?;
+            match writer.read_metadata(&response)? {
+                LEADER_NOT_AVAILABLE if retries < METADATA_RETRIES => {
+                    retries += 1;
+                    thread::sleep(METADATA_RETRY_DELAY);
+                }
+                0 => {
+                    writer.pending = writer.leaders.iter().map(|_| Vec::new()).collect();
+                    return Ok(writer);
+                }
+                code => return Err(kafka_error("metadata", code)),
+            }
+        }
+    }
+
+    /// Keep the brokers and partition leaders, returns the error code of the topic
+    fn read_metadata(&mut self, response: &[u8]) -> io::Result<i16> {
+        let mut response = Decoder(response);
+        let _throttle_time_ms = response.i32()?;
+        for _ in 0..response.i32()? {
+            let node_id = response.i32()?;
+            let host = response.string()?;
+            let port = response.i32()?;
+            let _rack = response.nullable_string()?;
+            self.brokers.insert(node_id, format!("{}:{}", host, port));
+        }
+        let _cluster_id = response.nullable_string()?;
+        let _controller_id = response.i32()?;
+
+        let mut error_code = LEADER_NOT_AVAILABLE;
+        let mut leaders = Vec::new();
+        for _ in 0..response.i32()? {
+            let topic_error_code = response.i16()?;
+            let name = response.string()?;
+            let _is_internal = response.i8()?;
+            let mut partitions = Vec::new();
+            for _ in 0..response.i32()? {
+                let partition_error_code = response.i16()?;
+                let index = response.i32()?;
+                let leader = response.i32()?;
+                let _replicas = response.i32_array()?;
+                let _isr = response.i32_array()?;
+                partitions.push((index, leader, partition_error_code));
+            }
+            if name != self.topic {
+                continue;
+            }
+            partitions.sort();
+            error_code = topic_error_code;
+            if error_code == 0 && partitions.is_empty() {
+                error_code = LEADER_NOT_AVAILABLE;
+            }
+            for (_, leader, partition_error_code) in partitions {
+                if error_code == 0 && (leader < 0 || partition_error_code == LEADER_NOT_AVAILABLE) {
+                    error_code = LEADER_NOT_AVAILABLE;
+                }
+                leaders.push(leader);
+            }
+        }
+        self.leaders = leaders;
+        Ok(error_code)
+    }
+
+    /// Send the pending records of `partition` in a single batch
+    fn produce(&mut self, partition: usize) -> io::Result<()> {
+        let batch = record_batch(&self.pending[partition]);
+        self.pending[partition].clear();
+        let mut body = Encoder::default();
+        // transactional_id
+        body.i16(-1);
+        body.i16(ACKS);
+        body.i32(PRODUCE_TIMEOUT_MS);
+        body.i32(1);
+        body.string(&self.topic);
+        body.i32(1);
+        body.i32(partition as i32);
+        body.bytes(&batch);
+
+        let leader = self.leaders[partition];
+        let mut stream = match self.connections.remove(&leader) {
+            Some(stream) => stream,
+            None => match self.brokers.get(&leader) {
+                Some(address) => TcpStream::connect(address)?,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("unknown Kafka broker {}", leader),
+                    ))
+                }
+            },
+        };
+        let response = self.request(&mut stream, PRODUCE, PRODUCE_VERSION, &body.0)?;
+        self.connections.insert(leader, stream);
+
+        let mut response = Decoder(&response);
+        for _ in 0..response.i32()? {
+            let _name = response.string()?;
+            for _ in 0..response.i32()? {
+                let _index = response.i32()?;
+                let error_code = response.i16()?;
+                let _base_offset = response.i64()?;
+                let _log_append_time_ms = response.i64()?;
+                if error_code != 0 {
+                    return Err(kafka_error("produce", error_code));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a request and wait for its response, returned without its header
+    fn request(
+        &mut self,
+        stream: &mut TcpStream,
+        api_key: i16,
+        api_version: i16,
+        body: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        self.correlation_id += 1;
+        let mut request = Encoder::default();
+        request.i16(api_key);
+        request.i16(api_version);
+        request.i32(self.correlation_id);
+        request.string(CLIENT_ID);
+        request.raw(body);
+        let mut frame = Encoder::default();
+        frame.bytes(&request.0);
+        stream.write_all(&frame.0)?;
+
+        let mut size = [0; 4];
+        stream.read_exact(&mut size)?;
+        let mut response = vec![0; i32::from_be_bytes(size).max(0) as usize];
+        stream.read_exact(&mut response)?;
+        if Decoder(&response).i32()? != self.correlation_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Kafka response to another request",
+            ));
+        }
+        Ok(response.split_off(4))
+    }
+}
+
+impl Exporter for KafkaProducer {
+    type Record = Record;
+
+    fn push(&mut self, record: Record) -> io::Result<()> {
+        let partition =
+            (murmur2(record.key.as_bytes()) & 0x7fff_ffff) as usize % self.leaders.len();
+        self.pending[partition].push(record);
+        if self.pending[partition].len() >= BATCH_SIZE {
+            self.produce(partition)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for partition in 0..self.pending.len() {
+            if !self.pending[partition].is_empty() {
+                self.produce(partition)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EntryWriter for KafkaWriter {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let key = match &entry.component {
+            ComponentInfoValid::Valid(info) => format!("{}/{}", info.name.as_str(), info.index),
+            ComponentInfoValid::Invalid(raw) => raw.clone(),
+        };
+        self.export.send(Record {
+            timestamp: entry.timestamp.timestamp_millis(),
+            key,
+            value: to_json(entry).to_string(),
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.export.finish()
+    }
+}
+
+fn kafka_error(request: &str, code: i16) -> io::Error {
+    io::Error::other(format!("Kafka {} failed with error code {}", request, code))
+}
+
+/// Record batch (message format v2) holding `records`, at least one
+fn record_batch(records: &[Record]) -> Vec<u8> {
+    let first_timestamp = records[0].timestamp;
+    let max_timestamp = records.iter().map(|record| record.timestamp).max().unwrap();
+
+    let mut checked = Encoder::default();
+    // attributes: no compression, create time
+    checked.i16(0);
+    // last offset delta
+    checked.i32(records.len() as i32 - 1);
+    checked.i64(first_timestamp);
+    checked.i64(max_timestamp);
+    // producer id, producer epoch and base sequence, unused without idempotence
+    checked.i64(-1);
+    checked.i16(-1);
+    checked.i32(-1);
+    checked.i32(records.len() as i32);
+    for (offset_delta, record) in records.iter().enumerate() {
+        let mut encoded = Encoder::default();
+        // attributes, timestamp delta and offset delta
+        encoded.i8(0);
+        encoded.varint(record.timestamp - first_timestamp);
+        encoded.varint(offset_delta as i64);
+        encoded.varint(record.key.len() as i64);
+        encoded.raw(record.key.as_bytes());
+        encoded.varint(record.value.len() as i64);
+        encoded.raw(record.value.as_bytes());
+        // headers
+        encoded.varint(0);
+        checked.varint(encoded.0.len() as i64);
+        checked.raw(&encoded.0);
+    }
+
+    let mut batch = Encoder::default();
+    // base offset, assigned by the broker
+    batch.i64(0);
+    // length of what follows: partition leader epoch, magic, crc and the checked part
+    batch.i32(4 + 1 + 4 + checked.0.len() as i32);
+    batch.i32(-1);
+    batch.i8(2);
+    batch.raw(&crc32c(&checked.0).to_be_bytes());
+    batch.raw(&checked.0);
+    batch.0
+}
+
+/// CRC-32C (Castagnoli), the checksum of record batches
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Hash used by the default partitioner of the Java client
+fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let tail = chunks.remainder();
+    if tail.len() == 3 {
+        h ^= u32::from(tail[2]) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= u32::from(tail[1]) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= u32::from(tail[0]);
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// Big-endian encoding of the protocol primitive types
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn i8(&mut self, value: i8) {
+        self.0.push(value as u8);
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.i16(value.len() as i16);
+        self.raw(value.as_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.i32(value.len() as i32);
+        self.raw(value);
+    }
+
+    /// Zigzag encoded variable length integer, as used in records
+    fn varint(&mut self, value: i64) {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn raw(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated Kafka response",
+            ));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn i8(&mut self) -> io::Result<i8> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(bytes))
+    }
+
+    fn nullable_string(&mut self) -> io::Result<Option<String>> {
+        let length = self.i16()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        let bytes = self.take(length as usize)?;
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        Ok(self.nullable_string()?.unwrap_or_default())
+    }
+
+    fn i32_array(&mut self) -> io::Result<Vec<i32>> {
+        let length = self.i32()?;
+        (0..length.max(0)).map(|_| self.i32()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_murmur2() {
+        // same values as the Java client
+        assert_eq!(murmur2(b"21"), -973932308);
+        assert_eq!(murmur2(b"foobar"), -790332482);
+        assert_eq!(murmur2(b"a-little-bit-long-string"), -985981536);
+        assert_eq!(murmur2(b"a-little-bit-longer-string"), -1486304829);
+        assert_eq!(
+            murmur2(b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8"),
+            -58897971
+        );
+        assert_eq!(murmur2(b"abc"), 479470107);
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut encoder = Encoder::default();
+        encoder.varint(0);
+        encoder.varint(-1);
+        encoder.varint(1);
+        encoder.varint(300);
+        assert_eq!(encoder.0, vec![0x00, 0x01, 0x02, 0xd8, 0x04]);
+    }
+
+    #[test]
+    fn test_record_batch() {
+        let record = |timestamp, value: &str| Record {
+            timestamp,
+            key: "APP/0".to_string(),
+            value: value.to_string(),
+        };
+        let batch = record_batch(&[record(1632816009360, "{}"), record(1632816009361, "[]")]);
+        let mut decoder = Decoder(&batch);
+        assert_eq!(decoder.i64().unwrap(), 0);
+        assert_eq!(decoder.i32().unwrap() as usize, batch.len() - 12);
+        assert_eq!(decoder.i32().unwrap(), -1);
+        assert_eq!(decoder.i8().unwrap(), 2);
+        let crc = decoder.i32().unwrap() as u32;
+        assert_eq!(crc, crc32c(decoder.0));
+        assert_eq!(decoder.i16().unwrap(), 0);
+        // last offset delta, first and max timestamps
+        assert_eq!(decoder.i32().unwrap(), 1);
+        assert_eq!(decoder.i64().unwrap(), 1632816009360);
+        assert_eq!(decoder.i64().unwrap(), 1632816009361);
+        assert_eq!(decoder.i64().unwrap(), -1);
+        assert_eq!(decoder.i16().unwrap(), -1);
+        assert_eq!(decoder.i32().unwrap(), -1);
+        assert_eq!(decoder.i32().unwrap(), 2);
+        // records: length, attributes, deltas, key, value and no header
+        assert_eq!(
+            decoder.0,
+            &[
+                0x1a, 0x00, 0x00, 0x00, 0x0a, b'A', b'P', b'P', b'/', b'0', 0x04, b'{', b'}', 0x00,
+                0x1a, 0x00, 0x02, 0x02, 0x0a, b'A', b'P', b'P', b'/', b'0', 0x04, b'[', b']', 0x00,
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! OpenTelemetry log records, exported with OTLP/HTTP in its JSON encoding
+//!
+//! <https://opentelemetry.io/docs/specs/otlp/>
+
+use serde_json::{json, Value};
+use std::io;
+
+use super::batch::{BatchExport, Exporter};
+use super::EntryWriter;
+use crate::level::SeverityMapping;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+/// Log records sent per export request
+const BATCH_SIZE: usize = 100;
+
+/// Severity number and text of each level, from the least severe
+const SEVERITIES: [(u8, &str); 6] = [
+    (1, "TRACE"),
+    (5, "DEBUG"),
+    (9, "INFO"),
+    (13, "WARN"),
+    (17, "ERROR"),
+    (21, "FATAL"),
+];
+
+/// Entry as an OTLP log record
+///
+/// The severity is the one of the entry (see `SeverityMapping`), the message is the body and
+/// the other fields are `cf.` prefixed attributes.
+pub fn to_otlp_log_record(entry: &CfAppLogEntry, severity_mapping: &SeverityMapping) -> Value {
+    let mut attributes = Vec::new();
+    match &entry.component {
+        ComponentInfoValid::Valid(info) => {
+            attributes.push(attribute(
+                "cf.component",
+                json!({ "stringValue": info.name.as_str() }),
+            ));
+            attributes.push(attribute(
+                "cf.index",
+                json!({ "intValue": info.index.to_string() }),
+            ));
+            if let Some(process_type) = &info.process_type {
+                attributes.push(attribute(
+                    "cf.process_type",
+                    json!({ "stringValue": process_type }),
+                ));
+            }
+        }
+        ComponentInfoValid::Invalid(raw) => {
+            attributes.push(attribute("cf.component", json!({ "stringValue": raw })))
+        }
+    }
+    let channel = match &entry.channel {
+        ChannelValid::Valid(channel) => channel.as_str(),
+        ChannelValid::Invalid(raw) => raw.as_str(),
+    };
+    let (severity_number, severity_text) = SEVERITIES[severity_mapping.severity(entry) as usize];
+    attributes.push(attribute("cf.channel", json!({ "stringValue": channel })));
+
+    let time_unix_nano = entry.timestamp.timestamp_nanos_opt().unwrap_or_default();
+    let mut record = json!({
+        // 64 bits integers are strings in the JSON encoding
+        "timeUnixNano": time_unix_nano.to_string(),
+        "severityNumber": severity_number,
+        "severityText": severity_text,
+        "attributes": attributes,
+    });
+    if let Some(message) = entry.message {
+        record["body"] = json!({ "stringValue": message });
+    }
+    record
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+/// Log records POSTed in batches to the `/v1/logs` path of an OTLP/HTTP endpoint, see
+/// `BatchExport`
+pub struct OtlpWriter {
+    export: BatchExport<Value>,
+    severity_mapping: SeverityMapping,
+}
+
+impl OtlpWriter {
+    /// `endpoint` is the base URL of the collector, e.g. `http://localhost:4318`
+    pub fn new(endpoint: &str) -> OtlpWriter {
+        OtlpWriter {
+            export: BatchExport::spawn(OtlpExporter {
+                url: format!("{}/v1/logs", endpoint.trim_end_matches('/')),
+                agent: ureq::Agent::new(),
+                records: Vec::new(),
+            }),
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+struct OtlpExporter {
+    url: String,
+    agent: ureq::Agent,
+    records: Vec<Value>,
+}
+
+impl OtlpExporter {
+    fn export(&mut self) -> io::Result<()> {
+        let request = json!({
+            "resourceLogs": [{
+                "resource": {},
+                "scopeLogs": [{
+                    "scope": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "logRecords": self.records,
+                }],
+            }],
+        });
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&request.to_string())
+            .map_err(io::Error::other)?;
+        self.records.clear();
+        Ok(())
+    }
+}
+
+impl Exporter for OtlpExporter {
+    type Record = Value;
+
+    fn push(&mut self, record: Value) -> io::Result<()> {
+        self.records.push(record);
+        if self.records.len() >= BATCH_SIZE {
+            self.export()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        self.export()
+    }
+}
+
+impl EntryWriter for OtlpWriter {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        self.export
+            .send(to_otlp_log_record(entry, &self.severity_mapping))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.export.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_otlp_log_record() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        assert_eq!(
+            to_otlp_log_record(&entry, &SeverityMapping::default()),
+            json!({
+                "timeUnixNano": "1632816009360000000",
+                "severityNumber": 17,
+                "severityText": "ERROR",
+                "body": { "stringValue": "boom" },
+                "attributes": [
+                    { "key": "cf.component", "value": { "stringValue": "APP" } },
+                    { "key": "cf.index", "value": { "intValue": "0" } },
+                    { "key": "cf.process_type", "value": { "stringValue": "web" } },
+                    { "key": "cf.channel", "value": { "stringValue": "ERR" } },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_export_when_idle() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut writer = OtlpWriter::new(&format!("http://{}", listener.local_addr().unwrap()));
+        let (bodies, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header == "\r\n" {
+                    break;
+                }
+                if let Some(length) = header.to_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            bodies.send(body).unwrap();
+        });
+
+        // a single entry is exported without waiting for more or for the end of the input
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/0] OUT").unwrap();
+        writer.write_entry(&entry).unwrap();
+        let body = received
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the batch should be exported once idle");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let records = &body["resourceLogs"][0]["scopeLogs"][0]["logRecords"];
+        assert_eq!(records.as_array().unwrap().len(), 1);
+        writer.finish().unwrap();
+    }
+}
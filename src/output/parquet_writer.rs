@@ -0,0 +1,221 @@
+//! Columnar export of parsed entries, only built with the `parquet` feature
+
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+
+use super::EntryWriter;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+/// Number of entries buffered before they are written as a row group
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Parquet file with one typed column per selected field
+///
+/// Timestamps are stored in UTC milliseconds, the index as an unsigned integer and
+/// everything else as UTF-8 strings. Unknown components are kept verbatim with no index.
+pub struct ParquetWriter {
+    writer: Option<SerializedFileWriter<File>>,
+    columns: Vec<String>,
+    rows: Vec<Row>,
+}
+
+struct Row {
+    timestamp: i64,
+    component: String,
+    index: Option<i32>,
+    process_type: Option<String>,
+    channel: String,
+    message: Option<String>,
+}
+
+impl ParquetWriter {
+    pub fn new(out: File, columns: Vec<String>) -> io::Result<ParquetWriter> {
+        let fields: Vec<&str> = columns
+            .iter()
+            .map(|column| match column.as_str() {
+                "timestamp" => "REQUIRED INT64 timestamp (TIMESTAMP(MILLIS,true));",
+                "component" => "REQUIRED BYTE_ARRAY component (STRING);",
+                "index" => "OPTIONAL INT32 index (INTEGER(32,false));",
+                "process_type" => "OPTIONAL BYTE_ARRAY process_type (STRING);",
+                "channel" => "REQUIRED BYTE_ARRAY channel (STRING);",
+                _ => "OPTIONAL BYTE_ARRAY message (STRING);",
+            })
+            .collect();
+        let schema = parse_message_type(&format!("message entry {{ {} }}", fields.join(" ")))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::UNCOMPRESSED)
+            .build();
+        let writer = SerializedFileWriter::new(out, Arc::new(schema), Arc::new(properties))?;
+        Ok(ParquetWriter {
+            writer: Some(writer),
+            columns,
+            rows: Vec::new(),
+        })
+    }
+
+    fn write_row_group(&mut self) -> Result<(), ParquetError> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let mut row_group = writer.next_row_group()?;
+        for column in &self.columns {
+            let mut column_writer = match row_group.next_column()? {
+                Some(column_writer) => column_writer,
+                None => break,
+            };
+            let rows = &self.rows;
+            match (column.as_str(), column_writer.untyped()) {
+                ("timestamp", ColumnWriter::Int64ColumnWriter(writer)) => {
+                    let values: Vec<i64> = rows.iter().map(|row| row.timestamp).collect();
+                    writer.write_batch(&values, None, None)?;
+                }
+                ("index", ColumnWriter::Int32ColumnWriter(writer)) => {
+                    let (values, levels) = optional(rows.iter().map(|row| row.index));
+                    writer.write_batch(&values, Some(&levels), None)?;
+                }
+                ("component", ColumnWriter::ByteArrayColumnWriter(writer)) => {
+                    let values: Vec<ByteArray> = rows
+                        .iter()
+                        .map(|row| ByteArray::from(row.component.as_str()))
+                        .collect();
+                    writer.write_batch(&values, None, None)?;
+                }
+                ("channel", ColumnWriter::ByteArrayColumnWriter(writer)) => {
+                    let values: Vec<ByteArray> = rows
+                        .iter()
+                        .map(|row| ByteArray::from(row.channel.as_str()))
+                        .collect();
+                    writer.write_batch(&values, None, None)?;
+                }
+                ("process_type", ColumnWriter::ByteArrayColumnWriter(writer)) => {
+                    let (values, levels) = optional(
+                        rows.iter()
+                            .map(|row| row.process_type.as_deref().map(ByteArray::from)),
+                    );
+                    writer.write_batch(&values, Some(&levels), None)?;
+                }
+                (_, ColumnWriter::ByteArrayColumnWriter(writer)) => {
+                    let (values, levels) = optional(
+                        rows.iter()
+                            .map(|row| row.message.as_deref().map(ByteArray::from)),
+                    );
+                    writer.write_batch(&values, Some(&levels), None)?;
+                }
+                _ => unreachable!("column types are set by the schema"),
+            }
+            column_writer.close()?;
+        }
+        row_group.close()?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+/// Values which are present and the matching definition levels (0 for null, 1 otherwise)
+fn optional<T>(values: impl Iterator<Item = Option<T>>) -> (Vec<T>, Vec<i16>) {
+    let mut present = Vec::new();
+    let mut levels = Vec::new();
+    for value in values {
+        match value {
+            Some(value) => {
+                present.push(value);
+                levels.push(1);
+            }
+            None => levels.push(0),
+        }
+    }
+    (present, levels)
+}
+
+impl EntryWriter for ParquetWriter {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let (component, index, process_type) = match &entry.component {
+            ComponentInfoValid::Valid(info) => (
+                info.name.as_str().to_string(),
+                Some(info.index as i32),
+                info.process_type.clone(),
+            ),
+            ComponentInfoValid::Invalid(raw) => (raw.to_string(), None, None),
+        };
+        let channel = match &entry.channel {
+            ChannelValid::Valid(channel) => channel.as_str().to_string(),
+            ChannelValid::Invalid(raw) => raw.to_string(),
+        };
+        self.rows.push(Row {
+            timestamp: entry.timestamp.timestamp_millis(),
+            component,
+            index,
+            process_type,
+            channel,
+            message: entry.message.map(String::from),
+        });
+        if self.rows.len() >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.rows.is_empty() {
+            self.write_row_group()?;
+        }
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    #[test]
+    fn test_parquet_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("parquet-writer.parquet");
+        let columns = super::super::COLUMNS
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let mut writer = ParquetWriter::new(File::create(&path).unwrap(), columns).unwrap();
+        for line in &[
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom",
+            "2021-09-28T17:00:10.00+0900 [FOO/1] OUT",
+        ] {
+            let (_, entry) = parse_cf_app_log(line).unwrap();
+            writer.write_entry(&entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        let first: Vec<_> = rows[0].get_column_iter().map(|(_, f)| f.clone()).collect();
+        assert_eq!(first[0], Field::TimestampMillis(1632816009360));
+        assert_eq!(first[1], Field::Str("APP".to_string()));
+        assert_eq!(first[2], Field::UInt(0));
+        assert_eq!(first[3], Field::Str("web".to_string()));
+        assert_eq!(first[5], Field::Str("boom".to_string()));
+        let second: Vec<_> = rows[1].get_column_iter().map(|(_, f)| f.clone()).collect();
+        assert_eq!(second[1], Field::Str("FOO/1".to_string()));
+        assert_eq!(second[2], Field::Null);
+        assert_eq!(second[5], Field::Null);
+    }
+}
@@ -0,0 +1,164 @@
+//! Export of parsed entries to a SQLite table, only built with the `sqlite` feature
+
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use std::io;
+
+use super::EntryWriter;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+/// Rows inserted in a table with one column per field plus the file the entry comes from
+///
+/// Timestamps are stored as RFC 3339 UTC strings so they sort and work with the SQLite date
+/// functions. `index` is a SQL keyword and has to be quoted in queries. Every insert happens
+/// in a single transaction committed by `finish`.
+pub struct SqliteWriter {
+    connection: Connection,
+    table: String,
+    source: String,
+}
+
+impl SqliteWriter {
+    /// Create `table` unless it already exists, rows are appended to it
+    pub fn new(connection: Connection, table: &str) -> io::Result<SqliteWriter> {
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    file TEXT NOT NULL,
+                    timestamp TEXT NOT NULL,
+                    component TEXT NOT NULL,
+                    \"index\" INTEGER,
+                    process_type TEXT,
+                    channel TEXT NOT NULL,
+                    message TEXT
+                );
+                BEGIN;",
+                table
+            ))
+            .map_err(to_io_error)?;
+        Ok(SqliteWriter {
+            connection,
+            table: table.to_string(),
+            source: String::new(),
+        })
+    }
+
+    /// File the entries written next come from
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_string();
+    }
+
+    /// Connection the entries were written to, once finished
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+}
+
+impl EntryWriter for SqliteWriter {
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let (component, index, process_type) = match &entry.component {
+            ComponentInfoValid::Valid(info) => (
+                info.name.as_str(),
+                Some(info.index),
+                info.process_type.as_deref(),
+            ),
+            ComponentInfoValid::Invalid(raw) => (raw.as_str(), None, None),
+        };
+        let channel = match &entry.channel {
+            ChannelValid::Valid(channel) => channel.as_str(),
+            ChannelValid::Invalid(raw) => raw.as_str(),
+        };
+        let timestamp = entry
+            .timestamp
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+        self.connection
+            .prepare_cached(&format!(
+                "INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                self.table
+            ))
+            .and_then(|mut insert| {
+                insert.execute(params![
+                    self.source,
+                    timestamp,
+                    component,
+                    index,
+                    process_type,
+                    channel,
+                    entry.message,
+                ])
+            })
+            .map(|_| ())
+            .map_err(to_io_error)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.connection.is_autocommit() {
+            return Ok(());
+        }
+        self.connection.execute_batch("COMMIT").map_err(to_io_error)
+    }
+}
+
+fn to_io_error(error: rusqlite::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_sqlite_writer() {
+        let mut writer =
+            SqliteWriter::new(Connection::open_in_memory().unwrap(), "entries").unwrap();
+        writer.set_source("app.log");
+        for line in &[
+            "2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom",
+            "2021-09-28T17:00:10.00+0900 [FOO/1] OUT",
+        ] {
+            let (_, entry) = parse_cf_app_log(line).unwrap();
+            writer.write_entry(&entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let connection = writer.into_connection();
+        let row: (String, String, String, Option<u32>, Option<String>, String) = connection
+            .query_row(
+                "SELECT file, timestamp, component, \"index\", process_type, message
+                 FROM entries WHERE channel = 'ERR'",
+                [],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "app.log".to_string(),
+                "2021-09-28T08:00:09.360Z".to_string(),
+                "APP".to_string(),
+                Some(0),
+                Some("web".to_string()),
+                "boom".to_string()
+            )
+        );
+        let index: Option<u32> = connection
+            .query_row(
+                "SELECT \"index\" FROM entries WHERE component = 'FOO/1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(index, None);
+    }
+}
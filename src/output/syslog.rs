@@ -0,0 +1,197 @@
+//! RFC 5424 syslog rendering of parsed entries
+
+use chrono::SecondsFormat;
+use std::io::{self, Write};
+use std::net::UdpSocket;
+
+use super::EntryWriter;
+use crate::level::SeverityMapping;
+use crate::{CfAppLogEntry, ChannelValid, ComponentInfoValid};
+
+/// user-level messages
+const FACILITY: u8 = 1;
+
+/// Structured data id, under the Cloud Foundry private enterprise number
+const SD_ID: &str = "cf@47450";
+
+/// One syslog message per line, or prefixed by its length as expected by syslog servers over
+/// TCP and TLS (octet counting, RFC 6587)
+///
+/// APP-NAME is the component, PROCID the instance index and MSGID the channel, all of them
+/// are also in a `cf@47450` structured data element along with the process type.
+pub struct SyslogWriter<W: Write> {
+    out: W,
+    octet_counting: bool,
+    severity_mapping: SeverityMapping,
+}
+
+impl<W: Write> SyslogWriter<W> {
+    pub fn new(out: W) -> SyslogWriter<W> {
+        SyslogWriter {
+            out,
+            octet_counting: false,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+
+    pub fn octet_counted(out: W) -> SyslogWriter<W> {
+        SyslogWriter {
+            out,
+            octet_counting: true,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+impl<W: Write> EntryWriter for SyslogWriter<W> {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let message = to_syslog(entry, &self.severity_mapping);
+        // a single write so that unbuffered sockets get whole messages
+        let framed = if self.octet_counting {
+            format!("{} {}", message.len(), message)
+        } else {
+            format!("{}\n", message)
+        };
+        self.out.write_all(framed.as_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// One datagram per syslog message
+pub struct SyslogUdpWriter {
+    socket: UdpSocket,
+    severity_mapping: SeverityMapping,
+}
+
+impl SyslogUdpWriter {
+    /// `socket` has to be connected to the syslog server
+    pub fn new(socket: UdpSocket) -> SyslogUdpWriter {
+        SyslogUdpWriter {
+            socket,
+            severity_mapping: SeverityMapping::default(),
+        }
+    }
+}
+
+impl EntryWriter for SyslogUdpWriter {
+    fn set_severity_mapping(&mut self, mapping: SeverityMapping) {
+        self.severity_mapping = mapping;
+    }
+
+    fn write_entry(&mut self, entry: &CfAppLogEntry) -> io::Result<()> {
+        let message = to_syslog(entry, &self.severity_mapping);
+        self.socket.send(message.as_bytes()).map(|_| ())
+    }
+}
+
+/// Entry as a RFC 5424 message, without any transport framing
+pub fn to_syslog(entry: &CfAppLogEntry, severity_mapping: &SeverityMapping) -> String {
+    let (component, index, process_type) = match &entry.component {
+        ComponentInfoValid::Valid(info) => (
+            info.name.as_str(),
+            Some(info.index),
+            info.process_type.as_deref(),
+        ),
+        ComponentInfoValid::Invalid(raw) => (raw.as_str(), None, None),
+    };
+    let channel = match &entry.channel {
+        ChannelValid::Valid(channel) => channel.as_str(),
+        ChannelValid::Invalid(raw) => raw.as_str(),
+    };
+    let severity = severity_mapping.severity(entry).syslog_severity();
+
+    let mut structured_data = format!("[{} component=\"{}\"", SD_ID, param_value(component));
+    if let Some(index) = index {
+        structured_data.push_str(&format!(" index=\"{}\"", index));
+    }
+    if let Some(process_type) = process_type {
+        structured_data.push_str(&format!(" process_type=\"{}\"", param_value(process_type)));
+    }
+    structured_data.push_str(&format!(" channel=\"{}\"]", param_value(channel)));
+
+    let mut message = format!(
+        "<{}>1 {} - {} {} {} {}",
+        FACILITY * 8 + severity,
+        entry
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Millis, false),
+        header_value(component, 48),
+        index.map_or_else(|| "-".to_string(), |index| index.to_string()),
+        header_value(channel, 32),
+        structured_data
+    );
+    if let Some(text) = entry.message {
+        message.push(' ');
+        message.push_str(text);
+    }
+    message
+}
+
+/// Header fields only allow up to `max_length` printable ASCII characters, others are replaced
+fn header_value(value: &str, max_length: usize) -> String {
+    if value.is_empty() {
+        return "-".to_string();
+    }
+    value
+        .chars()
+        .take(max_length)
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .collect()
+}
+
+/// Structured data parameter values escape quotes, backslashes and closing brackets
+fn param_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '"' || c == '\\' || c == ']' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_to_syslog() {
+        let (_, entry) =
+            parse_cf_app_log("2021-09-28T17:00:09.36+0900 [APP/PROC/WEB/0] ERR boom").unwrap();
+        assert_eq!(
+            to_syslog(&entry, &SeverityMapping::default()),
+            "<11>1 2021-09-28T17:00:09.360+09:00 - APP 0 ERR [cf@47450 component=\"APP\" \
+             index=\"0\" process_type=\"web\" channel=\"ERR\"] boom"
+        );
+
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [FOO \"1\"] OUT").unwrap();
+        assert_eq!(
+            to_syslog(&entry, &SeverityMapping::default()),
+            "<14>1 2021-09-28T17:00:09.360+09:00 - FOO_\"1\" - OUT [cf@47450 \
+             component=\"FOO \\\"1\\\"\" channel=\"OUT\"]"
+        );
+    }
+
+    #[test]
+    fn test_syslog_writer_octet_counting() {
+        let (_, entry) = parse_cf_app_log("2021-09-28T17:00:09.36+0900 [RTR/1] OUT").unwrap();
+        let mut out = Vec::new();
+        SyslogWriter::octet_counted(&mut out)
+            .write_entry(&entry)
+            .unwrap();
+        let message = to_syslog(&entry, &SeverityMapping::default());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{} {}", message.len(), message)
+        );
+    }
+}
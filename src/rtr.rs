@@ -0,0 +1,321 @@
+//! Parsing of the messages of Gorouter (RTR) entries, which are HTTP access logs
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{CfAppLogEntry, Component, ComponentInfoValid};
+
+/// Fields of a Gorouter access log, e.g.
+/// `my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET /health HTTP/1.1" 200 0 15 "-"
+/// "curl/7.64.1" "10.0.0.1:51234" "10.0.1.5:61001" x_forwarded_for:"203.0.113.10" ...`.
+/// `-` values are `None`.
+#[derive(Debug, PartialEq)]
+pub struct RtrAccessLog<'a> {
+    pub host: &'a str,
+    /// as written by the router, e.g. `2021-09-28T08:00:10.102+0000` or
+    /// `14/06/2016:22:16:12.582 +0000` for older ones
+    pub time: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub protocol: &'a str,
+    pub status: u16,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub referer: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub remote_addr: Option<&'a str>,
+    /// address of the app instance, absent from older logs
+    pub backend_addr: Option<&'a str>,
+    /// every `key:value` field following the addresses, in order, e.g. `vcap_request_id`
+    pub fields: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> RtrAccessLog<'a> {
+    /// Value of a `key:value` field, `None` when absent or `-`
+    pub fn field(&self, name: &str) -> Option<&'a str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+            .filter(|value| *value != "-")
+    }
+
+    /// Time the router received the request at, by its own clock
+    pub fn received_at(&self) -> Option<DateTime<FixedOffset>> {
+        ["%Y-%m-%dT%H:%M:%S%.f%z", "%d/%m/%Y:%H:%M:%S%.f %z"]
+            .iter()
+            .find_map(|format| DateTime::parse_from_str(self.time, format).ok())
+    }
+
+    pub fn x_forwarded_for(&self) -> Option<&'a str> {
+        self.field("x_forwarded_for")
+    }
+
+    pub fn x_forwarded_proto(&self) -> Option<&'a str> {
+        self.field("x_forwarded_proto")
+    }
+
+    pub fn vcap_request_id(&self) -> Option<&'a str> {
+        self.field("vcap_request_id")
+    }
+
+    /// B3 trace id propagated by the router, from `x_b3_traceid` or the single `b3` header
+    /// (`TRACEID-SPANID-...`)
+    pub fn b3_trace_id(&self) -> Option<&'a str> {
+        self.field("x_b3_traceid")
+            .or_else(|| self.field("b3")?.split('-').next())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Seconds between the router receiving the request and sending the response
+    pub fn response_time(&self) -> Option<f64> {
+        self.field("response_time")?.parse().ok()
+    }
+
+    /// Seconds spent in the router itself
+    pub fn gorouter_time(&self) -> Option<f64> {
+        self.field("gorouter_time")?.parse().ok()
+    }
+
+    pub fn app_id(&self) -> Option<&'a str> {
+        self.field("app_id")
+    }
+
+    pub fn app_index(&self) -> Option<u32> {
+        self.field("app_index")?.parse().ok()
+    }
+}
+
+/// Cursor over a message, every token being followed by a space or the end of the message
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn skip_spaces(&mut self) {
+        self.rest = self.rest.trim_start_matches(' ');
+    }
+
+    /// Text up to the next space
+    fn word(&mut self) -> Option<&'a str> {
+        self.skip_spaces();
+        if self.rest.is_empty() {
+            return None;
+        }
+        let end = self.rest.find(' ').unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(word)
+    }
+
+    /// Text between `open` and `close`, without them
+    fn delimited(&mut self, open: char, close: char) -> Option<&'a str> {
+        self.skip_spaces();
+        let inner = self.rest.strip_prefix(open)?;
+        let end = closing(inner, close)?;
+        self.rest = &inner[end + close.len_utf8()..];
+        Some(&inner[..end])
+    }
+
+    /// Quoted text, or a word when not quoted
+    fn value(&mut self) -> Option<&'a str> {
+        self.skip_spaces();
+        if self.rest.starts_with('"') {
+            self.delimited('"', '"')
+        } else {
+            self.word()
+        }
+    }
+
+    /// `key:value` field, the value being quoted or not
+    fn field(&mut self) -> Option<(&'a str, &'a str)> {
+        self.skip_spaces();
+        let colon = self.rest.find(':')?;
+        let key = &self.rest[..colon];
+        let is_key = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !is_key {
+            return None;
+        }
+        self.rest = &self.rest[colon + 1..];
+        let value = if self.rest.starts_with('"') {
+            self.delimited('"', '"')?
+        } else {
+            self.word().unwrap_or("")
+        };
+        Some((key, value))
+    }
+
+    fn is_field(&self) -> bool {
+        Tokens { rest: self.rest }.field().is_some()
+    }
+}
+
+/// Position of `close` in `text`, skipping the ones escaped with a backslash
+fn closing(text: &str, close: char) -> Option<usize> {
+    let mut escaped = false;
+    for (position, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c if c == close && !escaped => return Some(position),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn optional(value: &str) -> Option<&str> {
+    if value == "-" || value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Parse the message of a RTR entry, `None` when it is not an access log
+pub fn parse_rtr_access_log(message: &str) -> Option<RtrAccessLog<'_>> {
+    let mut tokens = Tokens { rest: message };
+    let host = tokens.word()?;
+    if tokens.word()? != "-" {
+        return None;
+    }
+    let time = tokens.delimited('[', ']')?;
+    let request = tokens.delimited('"', '"')?;
+    let (method, request) = request.split_once(' ')?;
+    let (path, protocol) = request.rsplit_once(' ')?;
+    let status = tokens.word()?.parse().ok()?;
+    let bytes_received = tokens.word()?.parse().ok()?;
+    let bytes_sent = tokens.word()?.parse().ok()?;
+    let referer = tokens.delimited('"', '"')?;
+    let user_agent = tokens.delimited('"', '"')?;
+
+    let mut addresses = Vec::with_capacity(2);
+    let mut fields = Vec::new();
+    loop {
+        tokens.skip_spaces();
+        if tokens.rest.is_empty() {
+            break;
+        }
+        if tokens.is_field() {
+            fields.push(tokens.field()?);
+        } else if fields.is_empty() && addresses.len() < 2 {
+            addresses.push(tokens.value()?);
+        } else {
+            // not a key:value field, skip it
+            tokens.value()?;
+        }
+    }
+
+    Some(RtrAccessLog {
+        host,
+        time,
+        method,
+        path,
+        protocol,
+        status,
+        bytes_received,
+        bytes_sent,
+        referer: optional(referer),
+        user_agent: optional(user_agent),
+        remote_addr: addresses.first().copied().and_then(optional),
+        backend_addr: addresses.get(1).copied().and_then(optional),
+        fields,
+    })
+}
+
+/// Access log of an entry, `None` when it is not a RTR entry or not an access log
+pub fn rtr_access_log<'a>(entry: &CfAppLogEntry<'a>) -> Option<RtrAccessLog<'a>> {
+    match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::ROUTER => {
+            parse_rtr_access_log(entry.message?)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rtr_access_log() {
+        let log = parse_rtr_access_log(
+            r#"my-app.example.com - [2021-09-28T08:00:10.102+0000] "GET /health?full=1 HTTP/1.1" 200 0 15 "-" "curl/7.64.1" "10.0.0.1:51234" "10.0.1.5:61001" x_forwarded_for:"203.0.113.10, 10.0.0.1" x_forwarded_proto:"https" vcap_request_id:"3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d" response_time:0.012345 gorouter_time:0.000321 app_id:"79bb58ab-3737-43be-ac70-39a2843b5177" app_index:"0" x_b3_traceid:"-""#,
+        )
+        .unwrap();
+        assert_eq!(log.host, "my-app.example.com");
+        assert_eq!(log.time, "2021-09-28T08:00:10.102+0000");
+        assert_eq!(
+            log.received_at(),
+            DateTime::parse_from_rfc3339("2021-09-28T08:00:10.102Z").ok()
+        );
+        assert_eq!(
+            (log.method, log.path, log.protocol),
+            ("GET", "/health?full=1", "HTTP/1.1")
+        );
+        assert_eq!(
+            (log.status, log.bytes_received, log.bytes_sent),
+            (200, 0, 15)
+        );
+        assert_eq!(log.referer, None);
+        assert_eq!(log.user_agent, Some("curl/7.64.1"));
+        assert_eq!(log.remote_addr, Some("10.0.0.1:51234"));
+        assert_eq!(log.backend_addr, Some("10.0.1.5:61001"));
+        assert_eq!(log.x_forwarded_for(), Some("203.0.113.10, 10.0.0.1"));
+        assert_eq!(log.x_forwarded_proto(), Some("https"));
+        assert_eq!(
+            log.vcap_request_id(),
+            Some("3e5c7b4a-1d2f-4c3b-6a5d-7e8f9a0b1c2d")
+        );
+        assert_eq!(log.response_time(), Some(0.012345));
+        assert_eq!(log.gorouter_time(), Some(0.000321));
+        assert_eq!(log.app_index(), Some(0));
+        assert_eq!(log.field("x_b3_traceid"), None);
+        assert_eq!(log.b3_trace_id(), None);
+    }
+
+    #[test]
+    fn test_b3_trace_id() {
+        let log = parse_rtr_access_log(
+            r#"my-app.example.com - [2021-09-28T08:00:09.102+0000] "GET / HTTP/1.1" 200 0 15 "-" "curl" "10.0.0.1:1" "10.0.1.5:2" x_b3_traceid:"6152cb8077136e53942078a29eb7d0d8""#,
+        )
+        .unwrap();
+        assert_eq!(log.b3_trace_id(), Some("6152cb8077136e53942078a29eb7d0d8"));
+        let log = parse_rtr_access_log(
+            r#"my-app.example.com - [2021-09-28T08:00:09.102+0000] "GET / HTTP/1.1" 200 0 15 "-" "curl" "10.0.0.1:1" "10.0.1.5:2" b3:"6152cb8077136e53942078a29eb7d0d8-942078a29eb7d0d8""#,
+        )
+        .unwrap();
+        assert_eq!(log.b3_trace_id(), Some("6152cb8077136e53942078a29eb7d0d8"));
+    }
+
+    #[test]
+    fn test_parse_older_rtr_access_log() {
+        let log = parse_rtr_access_log(
+            r#"www.example.com - [14/06/2016:22:16:12.582 +0000] "GET / HTTP/1.1" 200 0 103455 "-" "Mozilla/5.0 (Macintosh) \"quoted\"" 192.0.2.206:27743 x_forwarded_for:"203.0.113.222" vcap_request_id:bd3e6ed1-5dd0-43ab-70ed-5d232b577b09 response_time:0.12050583 app_id:79bb58ab-3737-43be-ac70-39a2843b5177"#,
+        )
+        .unwrap();
+        assert_eq!(log.time, "14/06/2016:22:16:12.582 +0000");
+        assert_eq!(
+            log.received_at(),
+            DateTime::parse_from_rfc3339("2016-06-14T22:16:12.582Z").ok()
+        );
+        assert_eq!(
+            log.user_agent,
+            Some(r#"Mozilla/5.0 (Macintosh) \"quoted\""#)
+        );
+        assert_eq!(log.remote_addr, Some("192.0.2.206:27743"));
+        assert_eq!(log.backend_addr, None);
+        assert_eq!(
+            log.vcap_request_id(),
+            Some("bd3e6ed1-5dd0-43ab-70ed-5d232b577b09")
+        );
+        assert_eq!(log.response_time(), Some(0.12050583));
+    }
+
+    #[test]
+    fn test_not_rtr_access_log() {
+        assert_eq!(parse_rtr_access_log("Started GET /health"), None);
+        assert_eq!(parse_rtr_access_log(""), None);
+    }
+}
@@ -0,0 +1,59 @@
+//! SSH access to app instances, from the entries written by the SSH component
+
+use crate::{CfAppLogEntry, Component, ComponentInfoValid};
+
+/// What an SSH entry tells about a session, with the address of the client
+#[derive(Debug, PartialEq)]
+pub enum SshEvent<'a> {
+    /// `Successful remote access by 192.0.2.33:53422`
+    Start(&'a str),
+    /// `Remote access ended for 192.0.2.33:53422`
+    End(&'a str),
+}
+
+/// Instance index and SSH session event of an entry, `None` for other entries
+pub fn ssh_event<'a>(entry: &CfAppLogEntry<'a>) -> Option<(u32, SshEvent<'a>)> {
+    let index = match &entry.component {
+        ComponentInfoValid::Valid(info) if info.name == Component::SSH => info.index,
+        _ => return None,
+    };
+    let message = entry.message?.trim();
+    if let Some(address) = message.strip_prefix("Successful remote access by ") {
+        return Some((index, SshEvent::Start(address)));
+    }
+    if let Some(address) = message.strip_prefix("Remote access ended for ") {
+        return Some((index, SshEvent::End(address)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_ssh_event() {
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:00:00.00+0900 [SSH/1] OUT Successful remote access by 192.0.2.33:53422",
+        )
+        .unwrap();
+        assert_eq!(
+            ssh_event(&entry),
+            Some((1, SshEvent::Start("192.0.2.33:53422")))
+        );
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:05:00.00+0900 [SSH/1] OUT Remote access ended for 192.0.2.33:53422",
+        )
+        .unwrap();
+        assert_eq!(
+            ssh_event(&entry),
+            Some((1, SshEvent::End("192.0.2.33:53422")))
+        );
+        let (_, entry) = parse_cf_app_log(
+            "2021-09-28T17:05:00.00+0900 [APP/PROC/WEB/1] OUT Remote access ended for 192.0.2.33:53422",
+        )
+        .unwrap();
+        assert_eq!(ssh_event(&entry), None);
+    }
+}
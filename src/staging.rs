@@ -0,0 +1,234 @@
+//! Timeline of the staging of an app, from the entries written by the STG component
+
+use chrono::{DateTime, Duration, FixedOffset};
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::{CfAppLogEntry, Component, ComponentInfoValid};
+
+/// Phases of a staging, in the order they happen
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum StagingPhase {
+    /// creating the staging container
+    Setup,
+    /// downloading the app package, the buildpacks and the build cache
+    Download,
+    /// buildpacks detecting and compiling the app
+    Compile,
+    /// uploading the droplet and the build cache
+    Upload,
+}
+
+impl StagingPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StagingPhase::Setup => "setup",
+            StagingPhase::Download => "download",
+            StagingPhase::Compile => "compile",
+            StagingPhase::Upload => "upload",
+        }
+    }
+}
+
+/// Messages starting a phase, and ending the staging
+fn patterns() -> &'static ([(Regex, StagingPhase); 4], Regex) {
+    static PATTERNS: OnceLock<([(Regex, StagingPhase); 4], Regex)> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        (
+            [
+                (
+                    Regex::new(r"(?i)creating container").unwrap(),
+                    StagingPhase::Setup,
+                ),
+                (
+                    Regex::new(r"^Downloading ").unwrap(),
+                    StagingPhase::Download,
+                ),
+                // buildpack output, cloud native buildpacks writing `===> DETECTING`
+                (
+                    Regex::new(r"^(----->|===>) ").unwrap(),
+                    StagingPhase::Compile,
+                ),
+                (
+                    Regex::new(r"^Uploading droplet").unwrap(),
+                    StagingPhase::Upload,
+                ),
+            ],
+            Regex::new(r"^(Uploading complete|Staging complete)").unwrap(),
+        )
+    })
+}
+
+/// Buildpack a staging message tells is running: the banner of a buildpack, e.g. `-----> Java
+/// Buildpack v4.45 (offline)` or `-----> Nodejs Buildpack version 1.7.60`, or a cloud native
+/// buildpack taking part, e.g. `paketo-buildpacks/java 6.0.0`
+pub fn buildpack(message: &str) -> Option<&str> {
+    static BUILDPACK: OnceLock<[Regex; 2]> = OnceLock::new();
+    let patterns = BUILDPACK.get_or_init(|| {
+        [
+            Regex::new(r"^-----> ((?:[\w.+-]+ )+?[Bb]uildpack(?: (?:version |v)[\w.+-]+)?)")
+                .unwrap(),
+            Regex::new(r"^([\w.-]+/[\w.-]+ v?\d+\.\d+[\w.+-]*)$").unwrap(),
+        ]
+    });
+    patterns
+        .iter()
+        .find_map(|pattern| pattern.captures(message.trim()))
+        .and_then(|captures| captures.get(1))
+        .map(|buildpack| buildpack.as_str())
+}
+
+/// A staging, phases being told by the first entry of each one
+#[derive(Debug, PartialEq)]
+pub struct Staging {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    /// phases which happened with the time they started at, in order
+    pub phases: Vec<(StagingPhase, DateTime<FixedOffset>)>,
+    /// whether the staging told it completed, a capture may end before
+    pub complete: bool,
+    /// buildpacks which detected the app and compiled it, e.g. `Java Buildpack v4.45`, in the
+    /// order they ran
+    pub buildpacks: Vec<String>,
+}
+
+impl Staging {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Phases with how long each one lasted, until the next one or the end of the staging
+    pub fn phase_durations(&self) -> Vec<(StagingPhase, Duration)> {
+        self.phases
+            .iter()
+            .enumerate()
+            .map(|(i, (phase, start))| {
+                let end = self.phases.get(i + 1).map_or(self.end, |(_, next)| *next);
+                (*phase, end - *start)
+            })
+            .collect()
+    }
+}
+
+/// Stagings told by STG entries, fed in the order they were logged
+#[derive(Debug, Default)]
+pub struct StagingTimeline {
+    pub stagings: Vec<Staging>,
+}
+
+impl StagingTimeline {
+    pub fn new() -> StagingTimeline {
+        StagingTimeline::default()
+    }
+
+    /// Add the entry to the timeline when it is a STG one. A staging starts with the first
+    /// entry after the previous one completed, or with a phase going back, e.g. a download after
+    /// an upload.
+    pub fn record(&mut self, entry: &CfAppLogEntry) {
+        match &entry.component {
+            ComponentInfoValid::Valid(info) if info.name == Component::STAGING => {}
+            _ => return,
+        }
+        let message = entry.message.unwrap_or("").trim();
+        let (phases, end) = patterns();
+        let phase = phases
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(message))
+            .map(|(_, phase)| *phase);
+
+        let new_staging = match self.stagings.last() {
+            Some(staging) if !staging.complete => match (phase, staging.phases.last()) {
+                (Some(phase), Some((current, _))) => phase < *current,
+                _ => false,
+            },
+            _ => true,
+        };
+        if new_staging {
+            self.stagings.push(Staging {
+                start: entry.timestamp,
+                end: entry.timestamp,
+                phases: Vec::new(),
+                complete: false,
+                buildpacks: Vec::new(),
+            });
+        }
+        let staging = self.stagings.last_mut().unwrap();
+        staging.end = staging.end.max(entry.timestamp);
+        // phases only move forward, e.g. a buildpack downloading its dependencies is compiling
+        if let Some(phase) = phase {
+            if staging
+                .phases
+                .last()
+                .is_none_or(|(current, _)| phase > *current)
+            {
+                staging.phases.push((phase, entry.timestamp));
+            }
+        }
+        if end.is_match(message) {
+            staging.complete = true;
+        }
+        if let Some(buildpack) = buildpack(message) {
+            if !staging.buildpacks.iter().any(|known| known == buildpack) {
+                staging.buildpacks.push(buildpack.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cf_app_log;
+
+    #[test]
+    fn test_staging_timeline() {
+        let mut timeline = StagingTimeline::new();
+        for line in &[
+            "2021-09-28T17:00:00.00+0900 [STG/0] OUT Cell 8f4a creating container for instance 5e3f",
+            "2021-09-28T17:00:02.00+0900 [STG/0] OUT Downloading app package...",
+            "2021-09-28T17:00:05.00+0900 [STG/0] OUT -----> Java Buildpack v4.45",
+            "2021-09-28T17:00:06.00+0900 [STG/0] OUT -----> Downloading Open Jdk JRE 11.0.12",
+            "2021-09-28T17:00:40.00+0900 [APP/PROC/WEB/0] OUT Started",
+            "2021-09-28T17:00:45.00+0900 [STG/0] OUT Uploading droplet...",
+            "2021-09-28T17:00:50.00+0900 [STG/0] OUT Uploading complete",
+            "2021-09-28T18:00:00.00+0900 [STG/0] OUT Downloading app package...",
+        ] {
+            let (_, entry) = parse_cf_app_log(line).unwrap();
+            timeline.record(&entry);
+        }
+
+        assert_eq!(timeline.stagings.len(), 2);
+        let staging = &timeline.stagings[0];
+        assert!(staging.complete);
+        assert_eq!(staging.duration(), Duration::seconds(50));
+        assert_eq!(
+            staging.phase_durations(),
+            vec![
+                (StagingPhase::Setup, Duration::seconds(2)),
+                (StagingPhase::Download, Duration::seconds(3)),
+                (StagingPhase::Compile, Duration::seconds(40)),
+                (StagingPhase::Upload, Duration::seconds(5)),
+            ]
+        );
+        assert_eq!(staging.buildpacks, vec!["Java Buildpack v4.45"]);
+        assert!(!timeline.stagings[1].complete);
+    }
+
+    #[test]
+    fn test_buildpack() {
+        assert_eq!(
+            buildpack("-----> Nodejs Buildpack version 1.7.60"),
+            Some("Nodejs Buildpack version 1.7.60")
+        );
+        assert_eq!(
+            buildpack("-----> Java Buildpack v4.45 (offline) | https://github.com/cloudfoundry/java-buildpack#5c6b9fd"),
+            Some("Java Buildpack v4.45")
+        );
+        assert_eq!(
+            buildpack("  paketo-buildpacks/java 6.0.0"),
+            Some("paketo-buildpacks/java 6.0.0")
+        );
+        assert_eq!(buildpack("-----> Downloading Open Jdk JRE 11.0.12"), None);
+        assert_eq!(buildpack("-----> Installing python 3.9.7"), None);
+    }
+}
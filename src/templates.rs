@@ -0,0 +1,62 @@
+//! Grouping of similar messages: what varies between occurrences of a message (ids, numbers,
+//! addresses) is masked so that they share a template
+
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// Masks applied in that order, the most specific ones first
+fn masks() -> &'static [(Regex, &'static str)] {
+    static MASKS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    MASKS.get_or_init(|| {
+        [
+            (
+                r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b",
+                "<uuid>",
+            ),
+            (r"\b\d{1,3}(\.\d{1,3}){3}(:\d+)?\b", "<ip>"),
+            (r"(?i)\b(0x)?[0-9a-f]*\d[0-9a-f]*[a-f][0-9a-f]*\b", "<hex>"),
+            (r"(?i)\b(0x)?[0-9a-f]*[a-f][0-9a-f]*\d[0-9a-f]*\b", "<hex>"),
+            (r"[-+]?\b\d+(\.\d+)?", "<num>"),
+        ]
+        .iter()
+        .map(|(pattern, mask)| (Regex::new(pattern).unwrap(), *mask))
+        .collect()
+    })
+}
+
+/// Template of a message: UUIDs, IP addresses, hexadecimal ids and numbers replaced by
+/// `<uuid>`, `<ip>`, `<hex>` and `<num>`, e.g. `user 42 logged in from 10.0.0.1` becomes
+/// `user <num> logged in from <ip>`
+pub fn message_template(message: &str) -> String {
+    let mut template = message.to_string();
+    for (regex, mask) in masks() {
+        if let Cow::Owned(masked) = regex.replace_all(&template, *mask) {
+            template = masked;
+        }
+    }
+    template
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_template() {
+        assert_eq!(
+            message_template("user 42 logged in from 10.0.0.1:5123 in 0.25s"),
+            "user <num> logged in from <ip> in <num>s"
+        );
+        assert_eq!(
+            message_template("request bd3e6ed1-5dd0-43ab-70ed-5d232b577b09 failed"),
+            "request <uuid> failed"
+        );
+        assert_eq!(
+            message_template("trace 6152cb8077136e53942078a29eb7d0d8 done"),
+            "trace <hex> done"
+        );
+        // words which happen to be made of hexadecimal letters are kept
+        assert_eq!(message_template("added a cafe"), "added a cafe");
+    }
+}
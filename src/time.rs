@@ -0,0 +1,220 @@
+//! Rendering of entry timestamps in another time zone, or relative to another entry, and
+//! timestamps written by apps at the beginning of their messages
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use chrono_tz::Tz;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// Same instant in `tz`, with the offset `tz` has at that instant
+pub fn in_timezone(timestamp: &DateTime<FixedOffset>, tz: Tz) -> DateTime<FixedOffset> {
+    timestamp.with_timezone(&tz).fixed_offset()
+}
+
+/// Line whose leading entry timestamp is rewritten in `tz`, in the same format and with as many
+/// fractional digits as the original one, e.g. `2021-09-28T08:00:09.36+0000`. Lines not starting
+/// with a timestamp are returned as they are.
+pub fn rewrite_timestamp(line: &str, tz: Tz) -> Cow<'_, str> {
+    replace_timestamp(line, |timestamp, raw| {
+        let timestamp = in_timezone(timestamp, tz);
+        let digits = raw.find('.').map_or(0, |dot| {
+            raw[dot + 1..]
+                .bytes()
+                .take_while(u8::is_ascii_digit)
+                .count()
+        });
+        let mut rewritten = timestamp.format("%Y-%m-%dT%H:%M:%S").to_string();
+        if digits > 0 {
+            let fraction = format!("{:09}", timestamp.nanosecond() % 1_000_000_000);
+            rewritten.push('.');
+            rewritten.push_str(&fraction[..digits.min(9)]);
+        }
+        rewritten.push_str(&timestamp.format("%z").to_string());
+        rewritten
+    })
+}
+
+/// Line whose leading entry timestamp is replaced by the time elapsed since `start`, see
+/// `format_elapsed`. Lines not starting with a timestamp are returned as they are.
+pub fn rewrite_relative(line: &str, start: DateTime<FixedOffset>) -> Cow<'_, str> {
+    replace_timestamp(line, |timestamp, _| {
+        format_elapsed(timestamp.signed_duration_since(start))
+    })
+}
+
+/// Signed elapsed time with a millisecond precision, hours going past 24, e.g. `+00:03:12.480`
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let sign = if elapsed < Duration::zero() { '-' } else { '+' };
+    let milliseconds = elapsed.num_milliseconds().unsigned_abs();
+    format!(
+        "{}{:02}:{:02}:{:02}.{:03}",
+        sign,
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1000 % 60,
+        milliseconds % 1000
+    )
+}
+
+/// Timestamp an app wrote at the beginning of `message`, logged at `envelope`:
+///
+/// - a date and time, e.g. `2021-09-28 08:00:09.360` or `2021-09-28T08:00:09.360Z`, taken in
+///   UTC, the time zone of app containers, unless an offset follows
+/// - a time alone, e.g. `08:00:09.360` of the Logback default pattern, on the day (in UTC) it is
+///   the closest to `envelope`
+///
+/// Timestamps may be enclosed in brackets, and prefixed with the severity letter of the Ruby
+/// `Logger`, e.g. `I, [2021-09-28T08:00:09.360123 #1]`.
+pub fn inner_timestamp(
+    message: &str,
+    envelope: &DateTime<FixedOffset>,
+) -> Option<DateTime<FixedOffset>> {
+    static INNER: OnceLock<Regex> = OnceLock::new();
+    let inner = INNER.get_or_init(|| {
+        Regex::new(concat!(
+            r"^(?:[DIWEFA], )?\[?",
+            r"(?:(\d{4}-\d{2}-\d{2})[ T])?(\d{2}:\d{2}:\d{2})(?:[.,](\d{1,9}))?",
+            r"(?: ?(Z|[+-]\d{2}:?\d{2})\b)?",
+        ))
+        .unwrap()
+    });
+    let captures = inner.captures(message)?;
+    let time = NaiveTime::parse_from_str(&captures[2], "%H:%M:%S").ok()?;
+    let nanoseconds = captures.get(3).map_or(0, |fraction| {
+        let digits = fraction.as_str();
+        digits.parse::<u32>().unwrap_or(0) * 10u32.pow(9 - digits.len() as u32)
+    });
+    let time = time.with_nanosecond(nanoseconds)?;
+    let offset = match captures.get(4).map(|offset| offset.as_str()) {
+        None | Some("Z") => FixedOffset::east_opt(0)?,
+        Some(offset) => {
+            let offset = offset.replace(':', "");
+            let seconds =
+                offset[1..3].parse::<i32>().ok()? * 3600 + offset[3..5].parse::<i32>().ok()? * 60;
+            if offset.starts_with('-') {
+                FixedOffset::west_opt(seconds)?
+            } else {
+                FixedOffset::east_opt(seconds)?
+            }
+        }
+    };
+    match captures.get(1) {
+        Some(date) => {
+            let date = NaiveDate::parse_from_str(date.as_str(), "%Y-%m-%d").ok()?;
+            NaiveDateTime::new(date, time)
+                .and_local_timezone(offset)
+                .single()
+        }
+        None => {
+            let day = envelope.with_timezone(&offset).date_naive();
+            [day.pred_opt()?, day, day.succ_opt()?]
+                .iter()
+                .filter_map(|day| {
+                    NaiveDateTime::new(*day, time)
+                        .and_local_timezone(offset)
+                        .single()
+                })
+                .min_by_key(|timestamp| {
+                    timestamp
+                        .signed_duration_since(*envelope)
+                        .num_milliseconds()
+                        .abs()
+                })
+        }
+    }
+}
+
+/// Line whose leading entry timestamp is replaced by what `render` makes of it, given the parsed
+/// and raw timestamp
+fn replace_timestamp<F>(line: &str, render: F) -> Cow<'_, str>
+where
+    F: FnOnce(&DateTime<FixedOffset>, &str) -> String,
+{
+    let start = line.len() - line.trim_start_matches(' ').len();
+    let end = line[start..]
+        .find(' ')
+        .map_or(line.len(), |end| start + end);
+    let raw = &line[start..end];
+    match DateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        Ok(timestamp) => Cow::Owned(format!(
+            "{}{}{}",
+            &line[..start],
+            render(&timestamp, raw),
+            &line[end..]
+        )),
+        Err(_) => Cow::Borrowed(line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_timezone() {
+        let timestamp = DateTime::parse_from_rfc3339("2021-09-28T17:00:09.36+09:00").unwrap();
+        assert_eq!(
+            in_timezone(&timestamp, Tz::UTC).to_rfc3339(),
+            "2021-09-28T08:00:09.360+00:00"
+        );
+        assert_eq!(
+            in_timezone(&timestamp, Tz::Europe__Paris).to_rfc3339(),
+            "2021-09-28T10:00:09.360+02:00"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_timestamp() {
+        assert_eq!(
+            rewrite_timestamp("   2021-09-28T17:00:09.36+0900 [RTR/1] OUT ok", Tz::UTC),
+            "   2021-09-28T08:00:09.36+0000 [RTR/1] OUT ok"
+        );
+        assert_eq!(
+            rewrite_timestamp(
+                "2021-09-28T17:00:09+0900 [RTR/1] OUT",
+                Tz::America__New_York
+            ),
+            "2021-09-28T04:00:09-0400 [RTR/1] OUT"
+        );
+        assert_eq!(rewrite_timestamp("not a log", Tz::UTC), "not a log");
+    }
+
+    #[test]
+    fn test_rewrite_relative() {
+        let start = DateTime::parse_from_rfc3339("2021-09-28T08:00:00Z").unwrap();
+        assert_eq!(
+            rewrite_relative("2021-09-28T17:03:12.48+0900 [RTR/1] OUT ok", start),
+            "+00:03:12.480 [RTR/1] OUT ok"
+        );
+        assert_eq!(
+            format_elapsed(Duration::milliseconds(-(26 * 3_600_000 + 1))),
+            "-26:00:00.001"
+        );
+    }
+
+    #[test]
+    fn test_inner_timestamp() {
+        let envelope = DateTime::parse_from_rfc3339("2021-09-28T17:00:09.500+09:00").unwrap();
+        let inner =
+            |message| inner_timestamp(message, &envelope).map(|timestamp| timestamp.to_rfc3339());
+        assert_eq!(
+            inner("2021-09-28 08:00:09.360  INFO 1 --- [main] c.e.App : Started"),
+            Some("2021-09-28T08:00:09.360+00:00".to_string())
+        );
+        assert_eq!(
+            inner("[2021-09-28T17:00:09.1+09:00] hello"),
+            Some("2021-09-28T17:00:09.100+09:00".to_string())
+        );
+        assert_eq!(
+            inner("I, [2021-09-28T08:00:09.360123 #1]  INFO -- : Started"),
+            Some("2021-09-28T08:00:09.360123+00:00".to_string())
+        );
+        // the envelope is on 2021-09-28 in UTC, the time is the closest
+        assert_eq!(
+            inner("23:59:59.000 [main] INFO c.e.App - Started"),
+            Some("2021-09-27T23:59:59+00:00".to_string())
+        );
+        assert_eq!(inner("Listening on port 8080"), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Consistency of the timestamps of entries: those going backwards tell sources got interleaved
+//! or clocks misbehaved, which breaks merging logs by timestamp, long gaps between them tell a
+//! source went quiet, and those of the entries of a same request tell the clocks of the routers
+//! and cells disagree
+
+use chrono::{DateTime, Duration, FixedOffset};
+
+/// Entry whose timestamp is earlier than the one of the entry before it in the same input
+#[derive(Debug, PartialEq)]
+pub struct Regression {
+    pub filename: String,
+    pub line_number: u64,
+    /// how far back its timestamp goes
+    pub duration: Duration,
+}
+
+/// Regressions of the timestamps of entries, recorded in the order of the inputs
+#[derive(Default)]
+pub struct Regressions {
+    /// input and timestamp of the previous entry
+    previous: Option<(String, DateTime<FixedOffset>)>,
+    pub count: u64,
+    /// the largest regression, the first one on ties
+    pub largest: Option<Regression>,
+}
+
+impl Regressions {
+    pub fn record(&mut self, filename: &str, line_number: u64, timestamp: DateTime<FixedOffset>) {
+        match &mut self.previous {
+            Some((previous_filename, previous)) if previous_filename == filename => {
+                let duration = previous.signed_duration_since(timestamp);
+                *previous = timestamp;
+                if duration <= Duration::zero() {
+                    return;
+                }
+                self.count += 1;
+                if self
+                    .largest
+                    .as_ref()
+                    .is_none_or(|largest| duration > largest.duration)
+                {
+                    self.largest = Some(Regression {
+                        filename: filename.to_string(),
+                        line_number,
+                        duration,
+                    });
+                }
+            }
+            _ => self.previous = Some((filename.to_string(), timestamp)),
+        }
+    }
+}
+
+/// Period without entries
+#[derive(Debug, PartialEq)]
+pub struct Gap {
+    /// timestamp of the last entry before it
+    pub start: DateTime<FixedOffset>,
+    /// timestamp of the first entry after it
+    pub end: DateTime<FixedOffset>,
+}
+
+impl Gap {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Periods longer than `longer_than` between consecutive timestamps, given sorted ones
+pub fn gaps(timestamps: &[DateTime<FixedOffset>], longer_than: Duration) -> Vec<Gap> {
+    timestamps
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > longer_than)
+        .map(|pair| Gap {
+            start: pair[0],
+            end: pair[1],
+        })
+        .collect()
+}
+
+/// Skew of the clock of the cell which logged the app entries of a request, from `first` to
+/// `last`, against the one of the router which received it at `received` and answered at
+/// `answered`: how far they fall outside of that period, positive when the cell is ahead. Zero
+/// when they fall in it, or last longer than it, the skew being too small to tell.
+pub fn clock_skew(
+    received: DateTime<FixedOffset>,
+    answered: DateTime<FixedOffset>,
+    first: DateTime<FixedOffset>,
+    last: DateTime<FixedOffset>,
+) -> Duration {
+    if first < received && last <= answered {
+        first - received
+    } else if last > answered && first >= received {
+        last - answered
+    } else {
+        Duration::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regressions() {
+        let mut regressions = Regressions::default();
+        let lines = [
+            ("a.log", "2021-09-28T17:00:09.36+09:00"),
+            ("a.log", "2021-09-28T17:00:08.86+09:00"),
+            ("a.log", "2021-09-28T08:00:10.00+00:00"),
+            ("a.log", "2021-09-28T17:00:07.00+09:00"),
+            // a new input starts over
+            ("b.log", "2021-09-28T17:00:00.00+09:00"),
+            ("b.log", "2021-09-28T17:00:00.00+09:00"),
+        ];
+        for (line_number, (filename, timestamp)) in lines.iter().enumerate() {
+            let timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap();
+            regressions.record(filename, line_number as u64 + 1, timestamp);
+        }
+        assert_eq!(regressions.count, 2);
+        assert_eq!(
+            regressions.largest,
+            Some(Regression {
+                filename: "a.log".to_string(),
+                line_number: 4,
+                duration: Duration::seconds(3),
+            })
+        );
+    }
+
+    #[test]
+    fn test_gaps() {
+        let timestamps: Vec<_> = [
+            "2021-09-28T17:00:00.00+09:00",
+            "2021-09-28T17:00:30.00+09:00",
+            "2021-09-28T08:02:30.00+00:00",
+            "2021-09-28T17:03:30.00+09:00",
+        ]
+        .iter()
+        .map(|timestamp| DateTime::parse_from_rfc3339(timestamp).unwrap())
+        .collect();
+        let found = gaps(&timestamps, Duration::minutes(1));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].start, timestamps[1]);
+        assert_eq!(found[0].duration(), Duration::minutes(2));
+        assert!(gaps(&timestamps, Duration::minutes(2)).is_empty());
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let at = |seconds: &str| {
+            DateTime::parse_from_rfc3339(&format!("2021-09-28T08:00:{}+00:00", seconds)).unwrap()
+        };
+        let skew = |first, last| clock_skew(at("10.00"), at("10.50"), at(first), at(last));
+        assert_eq!(skew("10.10", "10.40"), Duration::zero());
+        assert_eq!(skew("09.70", "09.90"), Duration::milliseconds(-300));
+        assert_eq!(skew("10.20", "11.00"), Duration::milliseconds(500));
+        assert_eq!(skew("09.90", "10.60"), Duration::zero());
+    }
+}
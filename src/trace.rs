@@ -0,0 +1,69 @@
+//! Tracing ids written by apps in their messages, to join entries with a tracing backend
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Ids of the trace and span an entry was logged in
+#[derive(Debug, PartialEq)]
+pub struct TraceContext<'a> {
+    /// 16 or 32 hexadecimal digits
+    pub trace_id: &'a str,
+    pub span_id: &'a str,
+}
+
+/// Patterns capturing the trace and span ids, tried in that order
+fn patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // Spring Cloud Sleuth, `[app,trace,span]` or `[app,trace,span,exportable]`
+            r"\[[^\],\s]*,([0-9a-f]{32}|[0-9a-f]{16}),([0-9a-f]{16})(,[^\]]*)?\]",
+            // W3C traceparent, `version-trace-span-flags`
+            r"\b[0-9a-f]{2}-([0-9a-f]{32})-([0-9a-f]{16})-[0-9a-f]{2}\b",
+        ]
+        .iter()
+        .map(|pattern| Regex::new(pattern).unwrap())
+        .collect()
+    })
+}
+
+/// Trace context of a message, from a Spring Cloud Sleuth `[app,trace,span]` prefix, e.g.
+/// `[orders,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8]`, or a W3C traceparent, e.g.
+/// `00-6152cb8077136e53942078a29eb7d0d8-942078a29eb7d0d8-01`
+pub fn trace_context(message: &str) -> Option<TraceContext<'_>> {
+    patterns().iter().find_map(|pattern| {
+        let captures = pattern.captures(message)?;
+        Some(TraceContext {
+            trace_id: captures.get(1)?.as_str(),
+            span_id: captures.get(2)?.as_str(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_context() {
+        let expected = Some(TraceContext {
+            trace_id: "6152cb8077136e53942078a29eb7d0d8",
+            span_id: "942078a29eb7d0d8",
+        });
+        assert_eq!(
+            trace_context("2021-09-28 08:00:09.361 DEBUG [,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8] 15 --- [   scheduling-1] result"),
+            expected
+        );
+        assert_eq!(
+            trace_context(
+                "INFO [orders,6152cb8077136e53942078a29eb7d0d8,942078a29eb7d0d8,true] created"
+            ),
+            expected
+        );
+        assert_eq!(
+            trace_context("traceparent=00-6152cb8077136e53942078a29eb7d0d8-942078a29eb7d0d8-01"),
+            expected
+        );
+        assert_eq!(trace_context("[   scheduling-1] result ===> false"), None);
+    }
+}